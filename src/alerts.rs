@@ -0,0 +1,912 @@
+use crate::api::{ApiError, Category, ControllerStatus, TelldusApi};
+use crate::cache;
+use crate::changes::Changes;
+use crate::config::{ConfigError, config_dir};
+use crate::daemon::JobAction;
+use crate::filter::{self, Filter};
+use crate::notify::{self, NotifyError, SmtpConfig};
+use crate::output;
+use crate::schedule::{
+    ScheduleTime, describe_age, format_duration, local_time, parse_duration, unix_now,
+};
+use crate::service;
+use crate::sun::{self, Location};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+const ALERTS_FILE: &str = "alerts.yaml";
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_SUBJECT: &str = "telltales: {rule}";
+const DEFAULT_BODY: &str = "{rule}: {sensor} {reading} is {value} ({condition})";
+const DEFAULT_CONTROLLER_RULE: &str = "Controller offline";
+const DEFAULT_CONTROLLER_BODY: &str = "{rule}: {controller} {event}, last seen {last_seen}";
+
+#[derive(Debug, Error)]
+pub enum AlertsError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("failed to read alert rules {0}: {1}")]
+    ReadFailed(String, #[source] io::Error),
+    #[error("failed to parse alert rules {0}: {1}")]
+    ParseFailed(String, #[source] serde_yaml::Error),
+    #[error("alert '{0}': {1}")]
+    Invalid(String, String),
+}
+
+#[derive(Debug, Error)]
+enum ActionError {
+    #[error(transparent)]
+    Api(#[from] ApiError),
+    #[error(transparent)]
+    Notify(#[from] NotifyError),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct AlertsFile {
+    interval: Option<String>,
+    smtp: Option<SmtpConfig>,
+    location: Option<Location>,
+    rules: Vec<RuleConfig>,
+    controllers: Option<ControllerWatchConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleConfig {
+    name: Option<String>,
+    sensor: String,
+    when: String,
+    #[serde(rename = "for")]
+    duration: Option<String>,
+    #[serde(default)]
+    hysteresis: f64,
+    /// Only alert between two times, e.g. `sunset..sunrise` or `22:00..06:30`.
+    active: Option<String>,
+    #[serde(default)]
+    actions: Vec<Action>,
+    #[serde(default)]
+    clear_actions: Vec<Action>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ControllerWatchConfig {
+    name: Option<String>,
+    /// Also alert when a controller has not been heard from for this long.
+    stale_after: Option<String>,
+    #[serde(default)]
+    only: Vec<String>,
+    #[serde(default)]
+    actions: Vec<Action>,
+    #[serde(default)]
+    clear_actions: Vec<Action>,
+}
+
+/// What to do when a rule fires or clears; messages may use `{event}`, `{rule}`,
+/// `{sensor}`, `{reading}`, `{value}`, `{threshold}`, and `{condition}` placeholders.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Action {
+    Device {
+        device: String,
+        command: JobAction,
+        level: Option<u8>,
+    },
+    Webhook {
+        webhook: String,
+        /// JSON body template; defaults to an object with every alert field.
+        body: Option<Value>,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    Push {
+        push: String,
+        #[serde(default)]
+        phones: Vec<String>,
+    },
+    Notify {
+        notify: String,
+        title: Option<String>,
+    },
+    Email {
+        email: Vec<String>,
+        subject: Option<String>,
+        body: Option<String>,
+    },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Device {
+                device,
+                command,
+                level: Some(level),
+            } if *command == JobAction::Dim => write!(f, "dim {device} to {level}"),
+            Action::Device {
+                device, command, ..
+            } => write!(f, "{} {device}", format!("{command:?}").to_lowercase()),
+            Action::Webhook { webhook, .. } => write!(f, "POST {webhook}"),
+            Action::Push { .. } => f.write_str("push notification"),
+            Action::Notify { .. } => f.write_str("desktop notification"),
+            Action::Email { email, .. } => write!(f, "email {}", email.join(", ")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Above,
+    AtLeast,
+    Below,
+    AtMost,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Above => ">",
+            Comparison::AtLeast => ">=",
+            Comparison::Below => "<",
+            Comparison::AtMost => "<=",
+        }
+    }
+
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Above => value > threshold,
+            Comparison::AtLeast => value >= threshold,
+            Comparison::Below => value < threshold,
+            Comparison::AtMost => value <= threshold,
+        }
+    }
+}
+
+/// A validated threshold rule from `alerts.yaml`.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub sensor: String,
+    pub reading: String,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    pub duration: Duration,
+    pub hysteresis: f64,
+    /// Only evaluated from the first time until the second, wrapping past midnight.
+    pub active: Option<(ScheduleTime, ScheduleTime)>,
+    pub actions: Vec<Action>,
+    pub clear_actions: Vec<Action>,
+}
+
+impl Rule {
+    /// An event of this rule with the fields every `--filter` can name.
+    fn record(&self, event: Value) -> Value {
+        filter::with_context(event, "sensor", &self.sensor, &self.sensor)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "{} {} {}",
+            self.reading,
+            self.comparison.symbol(),
+            self.threshold
+        );
+        if !self.duration.is_zero() {
+            text.push_str(&format!(" for {}", format_duration(self.duration)));
+        }
+        if self.hysteresis > 0.0 {
+            text.push_str(&format!(" (hysteresis {})", self.hysteresis));
+        }
+        if let Some((start, end)) = self.active {
+            text.push_str(&format!(" from {start} to {end}"));
+        }
+        text
+    }
+
+    /// Whether the rule is watched at `timestamp`. A sunrise or sunset that does not
+    /// happen that day, as in polar summer, leaves the rule active.
+    fn is_active(&self, location: Option<Location>, timestamp: u64) -> bool {
+        let Some((start, end)) = self.active else {
+            return true;
+        };
+        let minute_of_day = |time| sun::minute_of_day(time, location, timestamp);
+        let (Some(start), Some(end)) = (minute_of_day(start), minute_of_day(end)) else {
+            return true;
+        };
+        let time = local_time(timestamp);
+        let now = time.hour * 60 + time.minute;
+        if start <= end {
+            (start..end).contains(&now)
+        } else {
+            now >= start || now < end
+        }
+    }
+
+    /// Whether an alert that has fired should stay active: the value must move past the
+    /// threshold by the hysteresis margin before the alert clears, which avoids flapping.
+    fn still_active(&self, value: f64) -> bool {
+        let margin = match self.comparison {
+            Comparison::Above | Comparison::AtLeast => -self.hysteresis,
+            Comparison::Below | Comparison::AtMost => self.hysteresis,
+        };
+        self.comparison.holds(value, self.threshold + margin)
+    }
+}
+
+/// Watches controllers for dropping off Telldus Live, from the `controllers:` section.
+#[derive(Debug, Clone)]
+pub struct ControllerWatch {
+    pub name: String,
+    /// A controller silent for longer counts as offline even while Telldus Live still
+    /// reports it online.
+    pub stale_after: Option<Duration>,
+    /// Controller ids or names to watch; every controller when empty.
+    pub only: Vec<String>,
+    pub actions: Vec<Action>,
+    pub clear_actions: Vec<Action>,
+}
+
+impl ControllerWatch {
+    pub fn describe(&self) -> String {
+        match self.stale_after {
+            Some(stale_after) => {
+                format!("offline or not seen for {}", format_duration(stale_after))
+            }
+            None => "offline".into(),
+        }
+    }
+
+    fn watches(&self, controller: &ControllerStatus) -> bool {
+        self.only.is_empty()
+            || self
+                .only
+                .iter()
+                .any(|only| *only == controller.id || only.eq_ignore_ascii_case(&controller.name))
+    }
+
+    /// Whether `controller` counts as offline at `now`.
+    fn is_offline(&self, controller: &ControllerStatus, now: u64) -> bool {
+        controller.online == Some(false)
+            || self.stale_after.is_some_and(|stale_after| {
+                controller
+                    .last_seen
+                    .is_some_and(|last_seen| now.saturating_sub(last_seen) > stale_after.as_secs())
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RuleState {
+    Normal,
+    Pending(Instant),
+    Firing,
+}
+
+pub struct Config {
+    pub interval: Duration,
+    pub smtp: Option<SmtpConfig>,
+    /// Where sunrise and sunset are computed for; the controller's location when unset.
+    pub location: Option<Location>,
+    pub rules: Vec<Rule>,
+    pub controllers: Option<ControllerWatch>,
+}
+
+impl Config {
+    /// Whether some rule is active relative to sunrise or sunset and so needs a location.
+    pub fn needs_location(&self) -> bool {
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.active)
+            .any(|(start, end)| sun::needs_location(start) || sun::needs_location(end))
+    }
+}
+
+pub fn default_path() -> Result<PathBuf, AlertsError> {
+    Ok(config_dir()?.join(ALERTS_FILE))
+}
+
+pub fn load(path: &Path) -> Result<Config, AlertsError> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| AlertsError::ReadFailed(path.display().to_string(), err))?;
+    let file: AlertsFile = serde_yaml::from_str(&text)
+        .map_err(|err| AlertsError::ParseFailed(path.display().to_string(), err))?;
+
+    let interval = match file.interval {
+        Some(text) => parse_duration(&text)
+            .map_err(|err| AlertsError::Invalid("interval".into(), err))?
+            .max(Duration::from_secs(1)),
+        None => DEFAULT_INTERVAL,
+    };
+    let location = file
+        .location
+        .map(Location::validate)
+        .transpose()
+        .map_err(|err| AlertsError::Invalid("location".into(), err))?;
+    if let Some(smtp) = &file.smtp {
+        smtp.validate()
+            .map_err(|err| AlertsError::Invalid("smtp".into(), err))?;
+    }
+    let has_smtp = file.smtp.is_some();
+    let rules = file
+        .rules
+        .into_iter()
+        .enumerate()
+        .map(|(index, config)| parse_rule(index, config, has_smtp))
+        .collect::<Result<_, _>>()?;
+    let controllers = file
+        .controllers
+        .map(|config| parse_controller_watch(config, has_smtp))
+        .transpose()?;
+    Ok(Config {
+        interval,
+        smtp: file.smtp,
+        location,
+        rules,
+        controllers,
+    })
+}
+
+fn parse_rule(index: usize, config: RuleConfig, has_smtp: bool) -> Result<Rule, AlertsError> {
+    let name = config.name.unwrap_or_else(|| format!("rule {}", index + 1));
+    let invalid = |message: String| AlertsError::Invalid(name.clone(), message);
+    let (reading, comparison, threshold) = parse_condition(&config.when).map_err(invalid)?;
+    let duration = match &config.duration {
+        Some(text) => parse_duration(text).map_err(invalid)?,
+        None => Duration::ZERO,
+    };
+    if config.hysteresis < 0.0 {
+        return Err(invalid("hysteresis must not be negative".into()));
+    }
+    let active = match &config.active {
+        Some(text) => {
+            let (start, end) = text.split_once("..").ok_or_else(|| {
+                invalid(format!("active '{text}' must look like 'sunset..sunrise'"))
+            })?;
+            Some((
+                ScheduleTime::parse(start).map_err(invalid)?,
+                ScheduleTime::parse(end).map_err(invalid)?,
+            ))
+        }
+        None => None,
+    };
+    check_actions(&config.actions, &config.clear_actions, has_smtp).map_err(invalid)?;
+    Ok(Rule {
+        name,
+        sensor: config.sensor,
+        reading,
+        comparison,
+        threshold,
+        duration,
+        hysteresis: config.hysteresis,
+        active,
+        actions: config.actions,
+        clear_actions: config.clear_actions,
+    })
+}
+
+fn parse_controller_watch(
+    config: ControllerWatchConfig,
+    has_smtp: bool,
+) -> Result<ControllerWatch, AlertsError> {
+    let name = config
+        .name
+        .unwrap_or_else(|| DEFAULT_CONTROLLER_RULE.to_string());
+    let invalid = |message: String| AlertsError::Invalid(name.clone(), message);
+    let stale_after = config
+        .stale_after
+        .as_deref()
+        .map(parse_duration)
+        .transpose()
+        .map_err(invalid)?;
+    check_actions(&config.actions, &config.clear_actions, has_smtp).map_err(invalid)?;
+    Ok(ControllerWatch {
+        name,
+        stale_after,
+        only: config.only,
+        actions: config.actions,
+        clear_actions: config.clear_actions,
+    })
+}
+
+fn check_actions(
+    actions: &[Action],
+    clear_actions: &[Action],
+    has_smtp: bool,
+) -> Result<(), String> {
+    if actions.is_empty() {
+        return Err("no actions configured".into());
+    }
+    let sends_email = actions
+        .iter()
+        .chain(clear_actions)
+        .any(|action| matches!(action, Action::Email { .. }));
+    if sends_email && !has_smtp {
+        return Err("email actions need an smtp section".into());
+    }
+    Ok(())
+}
+
+/// Parse conditions such as `temp > 28` or `humidity <= 30`.
+fn parse_condition(text: &str) -> Result<(String, Comparison, f64), String> {
+    let usage = || format!("condition '{text}' must look like 'temp > 28'");
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    let [reading, operator, threshold] = parts[..] else {
+        return Err(usage());
+    };
+    let comparison = match operator {
+        ">" => Comparison::Above,
+        ">=" => Comparison::AtLeast,
+        "<" => Comparison::Below,
+        "<=" => Comparison::AtMost,
+        _ => return Err(usage()),
+    };
+    let threshold = threshold.parse().map_err(|_| usage())?;
+    Ok((reading.to_string(), comparison, threshold))
+}
+
+/// Poll every rule's sensor, and the controllers when watched, at the configured interval
+/// until shutdown is requested.
+///
+/// With a `filter`, only matching events are printed; every rule's actions still run.
+pub fn run(api: &TelldusApi, client: &Client, config: &Config, filter: Option<&Filter>) {
+    let mut states = vec![RuleState::Normal; config.rules.len()];
+    let mut offline: BTreeMap<String, bool> = BTreeMap::new();
+    let mut failures = Changes::new(None);
+    loop {
+        let now = unix_now();
+        let time = local_time(now);
+        for (rule, state) in config.rules.iter().zip(states.iter_mut()) {
+            if !rule.is_active(config.location, now) {
+                // A rule waiting out its `for` period starts over next time it is active.
+                if let RuleState::Pending(_) = state {
+                    *state = RuleState::Normal;
+                }
+                continue;
+            }
+            let value = match read_value(api, rule) {
+                Ok(value) => {
+                    failures.forget(&rule.name);
+                    value
+                }
+                Err(err) => {
+                    // Report a failure once, not on every poll until the sensor is back.
+                    let err = err.to_string();
+                    if !failures.update(&rule.name, err.clone()) {
+                        continue;
+                    }
+                    let record = rule.record(json!({
+                        "timestamp": now,
+                        "event": "failed",
+                        "rule": rule.name,
+                        "sensor": rule.sensor,
+                        "error": err,
+                    }));
+                    if filter.is_some_and(|filter| !filter.matches(&record)) {
+                        continue;
+                    }
+                    if output::is_ndjson() {
+                        output::print(&record);
+                    } else {
+                        eprintln!("[{time}] {}: cannot read sensor: {err}", rule.name);
+                    }
+                    continue;
+                }
+            };
+            let triggered = rule.comparison.holds(value, rule.threshold);
+            let fire = |event| {
+                let alert = Alert {
+                    subject: Subject::Reading { rule, value },
+                    event,
+                };
+                report(api, client, config, &alert, now, filter);
+            };
+            *state = match *state {
+                RuleState::Normal if !triggered => RuleState::Normal,
+                RuleState::Normal if rule.duration.is_zero() => {
+                    fire(Event::Fired);
+                    RuleState::Firing
+                }
+                RuleState::Normal => RuleState::Pending(Instant::now()),
+                RuleState::Pending(_) if !triggered => RuleState::Normal,
+                RuleState::Pending(since) if since.elapsed() >= rule.duration => {
+                    fire(Event::Fired);
+                    RuleState::Firing
+                }
+                RuleState::Pending(since) => RuleState::Pending(since),
+                RuleState::Firing if rule.still_active(value) => RuleState::Firing,
+                RuleState::Firing => {
+                    fire(Event::Cleared);
+                    RuleState::Normal
+                }
+            };
+        }
+        if let Some(watch) = &config.controllers {
+            watch_controllers(
+                api,
+                client,
+                config,
+                watch,
+                &mut offline,
+                &mut failures,
+                filter,
+            );
+        }
+        if !service::sleep(config.interval) {
+            return;
+        }
+    }
+}
+
+/// Fire or clear the controller alert for each watched controller whose state changed
+/// since the last poll; `offline` holds the state per controller id.
+fn watch_controllers(
+    api: &TelldusApi,
+    client: &Client,
+    config: &Config,
+    watch: &ControllerWatch,
+    offline: &mut BTreeMap<String, bool>,
+    failures: &mut Changes<String>,
+    filter: Option<&Filter>,
+) {
+    let now = unix_now();
+    let controllers = match api.controller_statuses() {
+        Ok(controllers) => {
+            failures.forget(&watch.name);
+            controllers
+        }
+        Err(err) => {
+            let err = err.to_string();
+            if !failures.update(&watch.name, err.clone()) {
+                return;
+            }
+            let record = json!({
+                "timestamp": now,
+                "event": "failed",
+                "rule": watch.name,
+                "error": err,
+            });
+            if filter.is_some_and(|filter| !filter.matches(&record)) {
+                return;
+            }
+            if output::is_ndjson() {
+                output::print(&record);
+            } else {
+                eprintln!(
+                    "[{}] {}: cannot list controllers: {err}",
+                    local_time(now),
+                    watch.name
+                );
+            }
+            return;
+        }
+    };
+    for controller in controllers
+        .iter()
+        .filter(|controller| watch.watches(controller))
+    {
+        let is_offline = watch.is_offline(controller, now);
+        let was_offline = offline.insert(controller.id.clone(), is_offline);
+        let event = match (was_offline.unwrap_or(false), is_offline) {
+            (false, true) => Event::Fired,
+            (true, false) => Event::Cleared,
+            _ => continue,
+        };
+        let alert = Alert {
+            subject: Subject::Controller {
+                watch,
+                controller,
+                now,
+            },
+            event,
+        };
+        report(api, client, config, &alert, now, filter);
+    }
+}
+
+/// Print an alert event, unless `filter` hides it, and run its actions.
+fn report(
+    api: &TelldusApi,
+    client: &Client,
+    config: &Config,
+    alert: &Alert,
+    now: u64,
+    filter: Option<&Filter>,
+) {
+    let record = alert.record(now);
+    let shown = filter.is_none_or(|filter| filter.matches(&record));
+    if shown && !output::is_ndjson() {
+        println!("[{}] {}", local_time(now), alert.headline());
+    }
+    let outcomes = perform(api, client, config, alert);
+    if !shown {
+        return;
+    }
+    if output::is_ndjson() {
+        let mut record = record;
+        record["actions"] = Value::Array(outcomes);
+        output::print(&record);
+        return;
+    }
+    for outcome in &outcomes {
+        let action = outcome["action"].as_str().unwrap_or_default();
+        match outcome["error"].as_str() {
+            Some(err) => eprintln!("  {action} failed: {err}"),
+            None => println!("  {action}"),
+        }
+    }
+}
+
+fn read_value(api: &TelldusApi, rule: &Rule) -> Result<f64, ApiError> {
+    let id = cache::resolve(api, Category::Sensor, &rule.sensor)?
+        .ok_or_else(|| ApiError::Unexpected(format!("no sensor named '{}'", rule.sensor)))?;
+    api.sensor_value(&id, &rule.reading)?.ok_or_else(|| {
+        ApiError::Unexpected(format!("sensor reports no '{}' reading", rule.reading))
+    })
+}
+
+/// Run the actions for an alert event, a failure not stopping the remaining ones, and
+/// return each action's `{action, error}` outcome for the caller to report.
+fn perform(api: &TelldusApi, client: &Client, config: &Config, alert: &Alert) -> Vec<Value> {
+    let actions = alert.actions();
+    let mut outcomes = Vec::new();
+    for action in actions {
+        outcomes.push(match perform_one(api, client, config, alert, action) {
+            Ok(()) => json!({ "action": action.to_string() }),
+            Err(err) => json!({ "action": action.to_string(), "error": err.to_string() }),
+        });
+    }
+    outcomes
+}
+
+fn perform_one(
+    api: &TelldusApi,
+    client: &Client,
+    config: &Config,
+    alert: &Alert,
+    action: &Action,
+) -> Result<(), ActionError> {
+    match action {
+        Action::Device {
+            device,
+            command,
+            level,
+        } => {
+            let id = cache::resolve(api, Category::Device, device)?
+                .ok_or_else(|| ApiError::Unexpected(format!("no device named '{device}'")))?;
+            match command {
+                JobAction::On => api.device_turn_on(&id)?,
+                JobAction::Off => api.device_turn_off(&id)?,
+                JobAction::Dim => api.device_dim(&id, level.unwrap_or(u8::MAX))?,
+                JobAction::Bell => api.device_bell(&id)?,
+                JobAction::Up => api.device_up(&id)?,
+                JobAction::Down => api.device_down(&id)?,
+                JobAction::Stop => api.device_stop(&id)?,
+            }
+            Ok(())
+        }
+        Action::Webhook {
+            webhook,
+            body,
+            headers,
+        } => {
+            let body = match body {
+                Some(template) => alert.expand_json(template),
+                None => alert.to_json(),
+            };
+            let headers: Vec<(String, String)> = headers
+                .iter()
+                .map(|(name, value)| (name.clone(), alert.expand(value)))
+                .collect();
+            notify::webhook(client, webhook, &headers, &body)?;
+            Ok(())
+        }
+        Action::Push { push, phones } => {
+            let message = alert.expand(push);
+            let targets = if phones.is_empty() {
+                api.list_phones()?
+                    .into_iter()
+                    .map(|phone| phone.id)
+                    .collect()
+            } else {
+                phones.clone()
+            };
+            for phone in &targets {
+                api.send_push(phone, &message)?;
+            }
+            Ok(())
+        }
+        Action::Notify { notify, title } => {
+            let title = title.as_deref().unwrap_or("telltales");
+            notify::desktop(&alert.expand(title), &alert.expand(notify))?;
+            Ok(())
+        }
+        Action::Email {
+            email,
+            subject,
+            body,
+        } => {
+            let smtp = config.smtp.as_ref().expect("validated when loading");
+            let subject = alert.expand(subject.as_deref().unwrap_or(DEFAULT_SUBJECT));
+            let body = alert.expand(body.as_deref().unwrap_or(alert.default_body()));
+            notify::email(smtp, email, &subject, &body)?;
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    Fired,
+    Cleared,
+}
+
+impl Event {
+    fn as_str(self) -> &'static str {
+        match self {
+            Event::Fired => "fired",
+            Event::Cleared => "cleared",
+        }
+    }
+}
+
+/// What an alert is about.
+enum Subject<'a> {
+    /// A rule's sensor reading crossing its threshold.
+    Reading { rule: &'a Rule, value: f64 },
+    /// A controller dropping off Telldus Live or coming back, as of `now`.
+    Controller {
+        watch: &'a ControllerWatch,
+        controller: &'a ControllerStatus,
+        now: u64,
+    },
+}
+
+/// An alert event, as seen by the actions it triggers.
+struct Alert<'a> {
+    subject: Subject<'a>,
+    event: Event,
+}
+
+impl Alert<'_> {
+    fn actions(&self) -> &[Action] {
+        let (actions, clear_actions) = match &self.subject {
+            Subject::Reading { rule, .. } => (&rule.actions, &rule.clear_actions),
+            Subject::Controller { watch, .. } => (&watch.actions, &watch.clear_actions),
+        };
+        match self.event {
+            Event::Fired => actions,
+            Event::Cleared => clear_actions,
+        }
+    }
+
+    fn default_body(&self) -> &'static str {
+        match self.subject {
+            Subject::Reading { .. } => DEFAULT_BODY,
+            Subject::Controller { .. } => DEFAULT_CONTROLLER_BODY,
+        }
+    }
+
+    fn headline(&self) -> String {
+        match &self.subject {
+            Subject::Reading { rule, value } => {
+                format!("{}: {} at {value}", rule.name, self.event.as_str())
+            }
+            Subject::Controller {
+                watch, controller, ..
+            } => format!(
+                "{}: {} for {} (last seen {})",
+                watch.name,
+                self.event.as_str(),
+                controller.name,
+                self.last_seen()
+            ),
+        }
+    }
+
+    /// How long ago the controller was last heard from.
+    fn last_seen(&self) -> String {
+        match self.subject {
+            Subject::Controller {
+                controller:
+                    ControllerStatus {
+                        last_seen: Some(last_seen),
+                        ..
+                    },
+                now,
+                ..
+            } => describe_age(now.saturating_sub(*last_seen)),
+            _ => "never".into(),
+        }
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let event = ("event", self.event.as_str().to_string());
+        match &self.subject {
+            Subject::Reading { rule, value } => vec![
+                event,
+                ("rule", rule.name.clone()),
+                ("sensor", rule.sensor.clone()),
+                ("reading", rule.reading.clone()),
+                ("value", value.to_string()),
+                ("threshold", rule.threshold.to_string()),
+                ("condition", rule.describe()),
+            ],
+            Subject::Controller {
+                watch, controller, ..
+            } => vec![
+                event,
+                ("rule", watch.name.clone()),
+                ("controller", controller.name.clone()),
+                ("controller_id", controller.id.clone()),
+                ("last_seen", self.last_seen()),
+                ("condition", watch.describe()),
+            ],
+        }
+    }
+
+    fn expand(&self, template: &str) -> String {
+        self.fields()
+            .iter()
+            .fold(template.to_string(), |text, (name, value)| {
+                text.replace(&format!("{{{name}}}"), value)
+            })
+    }
+
+    /// Expand placeholders in every string of a user-supplied JSON body.
+    fn expand_json(&self, template: &Value) -> Value {
+        match template {
+            Value::String(text) => Value::String(self.expand(text)),
+            Value::Array(items) => items.iter().map(|item| self.expand_json(item)).collect(),
+            Value::Object(map) => map
+                .iter()
+                .map(|(key, item)| (key.clone(), self.expand_json(item)))
+                .collect(),
+            other => other.clone(),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match &self.subject {
+            Subject::Reading { rule, value } => json!({
+                "event": self.event.as_str(),
+                "rule": rule.name,
+                "sensor": rule.sensor,
+                "reading": rule.reading,
+                "value": value,
+                "threshold": rule.threshold,
+                "condition": rule.describe(),
+            }),
+            Subject::Controller {
+                watch, controller, ..
+            } => json!({
+                "event": self.event.as_str(),
+                "rule": watch.name,
+                "controller": controller.name,
+                "controller_id": controller.id,
+                "online": controller.online,
+                "last_seen": controller.last_seen,
+                "condition": watch.describe(),
+            }),
+        }
+    }
+
+    /// The event as printed with `--output ndjson` and matched by `--filter`.
+    fn record(&self, now: u64) -> Value {
+        let mut record = self.to_json();
+        record["timestamp"] = json!(now);
+        match &self.subject {
+            Subject::Reading { rule, .. } => rule.record(record),
+            Subject::Controller { controller, .. } => {
+                filter::with_context(record, "controller", &controller.id, &controller.name)
+            }
+        }
+    }
+}
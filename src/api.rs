@@ -1,14 +1,48 @@
+use crate::audit;
 use crate::config::TelldusCredentials;
-use reqwest::blocking::Client;
-use reqwest_oauth1::{OAuthClientProvider, Secrets};
+use crate::fixtures;
+use crate::otlp;
+use crate::response_cache;
+use crate::schedule::ScheduleTime;
+use crate::service;
+use crate::sun::Location;
+use crate::timing;
+use crate::transport::{Conditional, Method, Request, Transport};
 use serde_json::Value;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::sync::{Mutex, OnceLock};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
 const BASE_URL: &str = "https://pa-api.telldus.com";
 const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+/// Span of history requested per page by the history iterators.
+const HISTORY_PAGE_SECS: i64 = 24 * 60 * 60;
+
+/// List endpoints probed for compatibility: collection keys, id keys, and name keys
+/// the parsers in this module understand.
+type Shape = (
+    &'static str,
+    &'static [&'static str],
+    &'static [&'static str],
+    &'static [&'static str],
+);
+const KNOWN_SHAPES: [Shape; 2] = [
+    (
+        "/json/clients/list",
+        &["client", "clients"],
+        &["id", "clientId"],
+        &["name", "clientName"],
+    ),
+    (
+        "/json/devices/list",
+        &["device", "devices"],
+        &["id", "deviceId"],
+        &["name"],
+    ),
+];
 
 #[derive(Debug, Error)]
 pub enum ApiError {
@@ -16,15 +50,31 @@ pub enum ApiError {
     Http(#[from] reqwest::Error),
     #[error("OAuth request failed: {0}")]
     OAuth(#[from] reqwest_oauth1::Error),
+    #[error(
+        "Telldus Live rejected the access token; run `telltales auth validate` to re-authorize"
+    )]
+    Unauthorized,
     #[error("unexpected Telldus response: {0}")]
     Unexpected(String),
+    #[error("cannot build API client: {0}")]
+    Build(String),
+    #[error("re-authorization failed: {0}")]
+    Reauthorize(String),
+    #[error("interrupted")]
+    Interrupted,
 }
 
+/// Replaces credentials Telldus Live rejected, given the rejected ones.
+pub type Reauthorize<'a> =
+    Box<dyn Fn(&TelldusCredentials) -> Result<TelldusCredentials, ApiError> + 'a>;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Category {
     Controller,
     Device,
     Sensor,
+    Event,
+    Phone,
 }
 
 impl Category {
@@ -33,6 +83,8 @@ impl Category {
             Category::Controller => "controller",
             Category::Device => "device",
             Category::Sensor => "sensor",
+            Category::Event => "event",
+            Category::Phone => "phone",
         }
     }
 }
@@ -43,11 +95,213 @@ pub struct Entry {
     pub id: String,
     pub name: String,
     pub details: Option<String>,
+    /// Last command method for devices (see [`method_name`]).
+    pub state: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryLevel {
+    Percent(u8),
+    Ok,
+    Low,
+    Unknown,
+}
+
+impl BatteryLevel {
+    /// Normalize the assorted encodings Telldus uses for battery state.
+    ///
+    /// Percentages arrive as plain numbers, while 253/254/255 are magic values for
+    /// OK/unknown/low on devices that only report a flag.
+    pub fn parse(raw: &str) -> Self {
+        let trimmed = raw.trim();
+        match trimmed.to_ascii_lowercase().as_str() {
+            "ok" | "good" => return BatteryLevel::Ok,
+            "low" => return BatteryLevel::Low,
+            _ => {}
+        }
+        match trimmed.parse::<f64>().map(|value| value.round() as i64) {
+            Ok(253) => BatteryLevel::Ok,
+            Ok(255) => BatteryLevel::Low,
+            Ok(value @ 0..=100) => BatteryLevel::Percent(value as u8),
+            _ => BatteryLevel::Unknown,
+        }
+    }
+
+    pub fn is_low(self, threshold: u8) -> bool {
+        match self {
+            BatteryLevel::Percent(value) => value <= threshold,
+            BatteryLevel::Low => true,
+            BatteryLevel::Ok | BatteryLevel::Unknown => false,
+        }
+    }
+}
+
+impl std::fmt::Display for BatteryLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatteryLevel::Percent(value) => write!(f, "{value}%"),
+            BatteryLevel::Ok => f.write_str("ok"),
+            BatteryLevel::Low => f.write_str("low"),
+            BatteryLevel::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatteryStatus {
+    pub category: Category,
+    pub id: String,
+    pub name: String,
+    pub level: BatteryLevel,
+}
+
+/// Whether a controller is connected to Telldus Live, and when it was last heard from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerStatus {
+    pub id: String,
+    pub name: String,
+    /// `None` when Telldus Live does not say.
+    pub online: Option<bool>,
+    /// Unix timestamp, when reported.
+    pub last_seen: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FirmwareStatus {
+    pub id: String,
+    pub name: String,
+    pub current: Option<String>,
+    pub available: Option<String>,
+}
+
+impl FirmwareStatus {
+    pub fn upgrade_available(&self) -> bool {
+        match (&self.current, &self.available) {
+            (Some(current), Some(available)) => current != available,
+            _ => false,
+        }
+    }
 }
 
 pub struct TelldusApi<'a> {
-    client: &'a Client,
-    credentials: &'a TelldusCredentials,
+    transport: Box<dyn Transport + 'a>,
+    credentials: RefCell<Cow<'a, TelldusCredentials>>,
+    base_url: String,
+    rate_limit: RateLimit,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+    reauthorize: Option<Reauthorize<'a>>,
+    reauthorized: Cell<bool>,
+}
+
+/// Configures a [`TelldusApi`]; only the credentials are required.
+pub struct TelldusApiBuilder<'a> {
+    transport: Option<Box<dyn Transport + 'a>>,
+    credentials: Option<&'a TelldusCredentials>,
+    base_url: String,
+    rate_limit: RateLimit,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+    reauthorize: Option<Reauthorize<'a>>,
+}
+
+/// History entries fetched lazily one page (a day of history) at a time, oldest first.
+///
+/// Iteration stops after the first error.
+pub struct HistoryIter<'api, 'a> {
+    api: &'api TelldusApi<'a>,
+    path: &'static str,
+    params: Vec<(String, String)>,
+    next_from: i64,
+    to: i64,
+    page: std::vec::IntoIter<Value>,
+    failed: bool,
+}
+
+impl Iterator for HistoryIter<'_, '_> {
+    type Item = Result<Value, ApiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.page.next() {
+                return Some(Ok(entry));
+            }
+            if self.failed || self.next_from > self.to {
+                return None;
+            }
+            // Ctrl-C ends a long export after the pages already returned.
+            if service::shutdown_requested() {
+                self.failed = true;
+                return Some(Err(ApiError::Interrupted));
+            }
+            // Telldus treats both bounds as inclusive, so pages must not share a second.
+            let from = self.next_from;
+            let to = from.saturating_add(HISTORY_PAGE_SECS - 1).min(self.to);
+            self.next_from = to.saturating_add(1);
+
+            let mut params = self.params.clone();
+            params.push(("from".into(), from.to_string()));
+            params.push(("to".into(), to.to_string()));
+            match self.api.get_json_owned(self.path, params) {
+                Ok(payload) => self.page = array_from(&payload, &["history"]).into_iter(),
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// Minimum spacing between requests.
+///
+/// The last request time is shared by every client in the process, since Telldus Live
+/// throttles per account rather than per connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub min_interval: Duration,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            min_interval: MIN_REQUEST_INTERVAL,
+        }
+    }
+}
+
+/// How failed requests are re-sent: up to `max_attempts` in total, doubling `backoff`
+/// after each failure. Only timeouts, connection failures, HTTP 429, and 5xx responses
+/// are retried.
+///
+/// Commands such as `turnOn` or `bell` may have reached the device even when the response
+/// was lost, so they are only retried with `retry_unsafe`; reads are always safe to repeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: Duration,
+    pub retry_unsafe: bool,
+}
+
+impl RetryPolicy {
+    pub const fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+            retry_unsafe: false,
+        }
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
 }
 
 pub struct AddDeviceRequest<'a> {
@@ -62,11 +316,433 @@ pub struct SensorUpdateRequest<'a> {
     pub ignored: bool,
 }
 
-impl<'a> TelldusApi<'a> {
-    pub fn new(client: &'a Client, credentials: &'a TelldusCredentials) -> Self {
+#[derive(Debug, Clone)]
+pub struct SchedulerJob {
+    pub id: String,
+    pub device_id: String,
+    pub method: u32,
+    pub method_value: Option<u8>,
+    pub time: ScheduleTime,
+    pub weekdays: Vec<u8>,
+    pub active: bool,
+    pub next_run: Option<i64>,
+}
+
+impl SchedulerJob {
+    /// Human-readable action, e.g. `on` or `dim 128`.
+    pub fn describe_action(&self) -> String {
+        match (self.method, self.method_value) {
+            (16, Some(level)) => format!("dim {level}"),
+            (method @ (1 | 2 | 16), _) => method_name(method).into(),
+            (method, _) => format!("method {method}"),
+        }
+    }
+}
+
+pub struct SchedulerJobRequest<'a> {
+    pub device_id: &'a str,
+    pub method: u32,
+    pub method_value: Option<u8>,
+    pub time: ScheduleTime,
+    pub weekdays: &'a [u8],
+}
+
+pub enum EventTrigger {
+    Device {
+        device_id: String,
+        method: u32,
+    },
+    Sensor {
+        sensor_id: String,
+        value_type: String,
+        scale: i32,
+        value: f64,
+        /// 1 when the value rises above the threshold, -1 when it falls below.
+        edge: i8,
+    },
+    Time {
+        hour: u8,
+        minute: u8,
+    },
+}
+
+pub enum EventCondition {
+    Device { device_id: String, state: u32 },
+    Time { from: (u8, u8), to: (u8, u8) },
+}
+
+pub enum EventAction {
+    Device {
+        device_id: String,
+        method: u32,
+        value: Option<u8>,
+    },
+    Push {
+        phone_id: String,
+        message: String,
+    },
+    Sms {
+        to: String,
+        message: String,
+    },
+}
+
+/// Last command Telldus recorded for a device, as a method number (1 = on, 2 = off,
+/// 16 = dim, 128 = up, 256 = down, 512 = stop) with the dim level where relevant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceState {
+    pub method: u32,
+    pub level: Option<u8>,
+}
+
+/// How a device is registered: the controller it belongs to, its protocol and model,
+/// and the parameters (such as house and unit codes) it transmits with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSettings {
+    pub client_id: String,
+    pub protocol: String,
+    pub model: String,
+    pub parameters: Vec<(String, String)>,
+}
+
+impl DeviceSettings {
+    /// Read the settings from a `device/info` payload of device `id`.
+    pub fn from_info(id: &str, info: &Value) -> Result<Self, ApiError> {
+        let field = |keys: &[&str]| {
+            pick_string(info, keys).ok_or_else(|| {
+                ApiError::Unexpected(format!("device {id} info lacks {}", keys.join("/")))
+            })
+        };
+        let parameters = array_from(info, &["parameter", "parameters"])
+            .iter()
+            .filter_map(|parameter| {
+                Some((
+                    pick_string(parameter, &["name"])?,
+                    pick_string(parameter, &["value"]).unwrap_or_default(),
+                ))
+            })
+            .collect();
+        Ok(DeviceSettings {
+            client_id: field(&["client", "clientId"])?,
+            protocol: field(&["protocol"])?,
+            model: field(&["model"])?,
+            parameters,
+        })
+    }
+}
+
+/// What a sensor reading measures, derived from the Telldus data `name` (e.g. `temp`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadingKind {
+    Temperature,
+    Humidity,
+    RainRate,
+    RainTotal,
+    WindDirection,
+    WindAverage,
+    WindGust,
+    Uv,
+    Power,
+    Luminance,
+    Pressure,
+    DewPoint,
+    Other,
+}
+
+impl ReadingKind {
+    pub const ALL: [ReadingKind; 13] = [
+        ReadingKind::Temperature,
+        ReadingKind::Humidity,
+        ReadingKind::RainRate,
+        ReadingKind::RainTotal,
+        ReadingKind::WindDirection,
+        ReadingKind::WindAverage,
+        ReadingKind::WindGust,
+        ReadingKind::Uv,
+        ReadingKind::Power,
+        ReadingKind::Luminance,
+        ReadingKind::Pressure,
+        ReadingKind::DewPoint,
+        ReadingKind::Other,
+    ];
+
+    /// Parse a kind by its readable name (`rain-rate`) or Telldus name (`rrate`).
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase().replace(['_', ' '], "-");
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == name)
+            .or_else(|| Some(Self::from_name(&name)).filter(|kind| *kind != ReadingKind::Other))
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "temp" => ReadingKind::Temperature,
+            "humidity" => ReadingKind::Humidity,
+            "rrate" => ReadingKind::RainRate,
+            "rtot" => ReadingKind::RainTotal,
+            "wdir" => ReadingKind::WindDirection,
+            "wavg" => ReadingKind::WindAverage,
+            "wgust" => ReadingKind::WindGust,
+            "uv" => ReadingKind::Uv,
+            "watt" => ReadingKind::Power,
+            "lum" => ReadingKind::Luminance,
+            "barpress" => ReadingKind::Pressure,
+            "dewp" => ReadingKind::DewPoint,
+            _ => ReadingKind::Other,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadingKind::Temperature => "temperature",
+            ReadingKind::Humidity => "humidity",
+            ReadingKind::RainRate => "rain-rate",
+            ReadingKind::RainTotal => "rain-total",
+            ReadingKind::WindDirection => "wind-direction",
+            ReadingKind::WindAverage => "wind-average",
+            ReadingKind::WindGust => "wind-gust",
+            ReadingKind::Uv => "uv",
+            ReadingKind::Power => "power",
+            ReadingKind::Luminance => "luminance",
+            ReadingKind::Pressure => "pressure",
+            ReadingKind::DewPoint => "dew-point",
+            ReadingKind::Other => "other",
+        }
+    }
+
+    /// Unit of a value of this kind reported with the given Telldus scale.
+    pub fn unit(self, scale: u32) -> &'static str {
+        match (self, scale) {
+            (ReadingKind::Temperature | ReadingKind::DewPoint, 1) => "°F",
+            (ReadingKind::Temperature | ReadingKind::DewPoint, _) => "°C",
+            (ReadingKind::Humidity, _) => "%",
+            (ReadingKind::RainRate, _) => "mm/h",
+            (ReadingKind::RainTotal, _) => "mm",
+            (ReadingKind::WindDirection, _) => "°",
+            (ReadingKind::WindAverage | ReadingKind::WindGust, _) => "m/s",
+            (ReadingKind::Power, 0) => "kWh",
+            (ReadingKind::Power, 1) => "kVAh",
+            (ReadingKind::Power, 2) => "W",
+            (ReadingKind::Power, 3) => "pulses",
+            (ReadingKind::Power, 4) => "V",
+            (ReadingKind::Power, 5) => "A",
+            (ReadingKind::Luminance, 1) => "lx",
+            (ReadingKind::Luminance, _) => "%",
+            (ReadingKind::Pressure, _) => "hPa",
+            _ => "",
+        }
+    }
+}
+
+/// One numeric value from a sensor's `data` list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorReading {
+    /// Telldus name of the value, such as `temp` or `watt`.
+    pub name: String,
+    pub kind: ReadingKind,
+    pub value: f64,
+    pub scale: u32,
+}
+
+impl SensorReading {
+    fn from_value(entry: &Value) -> Option<Self> {
+        let name = pick_string(entry, &["name"])?;
+        Some(Self {
+            kind: ReadingKind::from_name(&name),
+            value: pick_string(entry, &["value"])?.parse().ok()?,
+            scale: pick_string(entry, &["scale"])
+                .and_then(|scale| scale.parse().ok())
+                .unwrap_or_default(),
+            name,
+        })
+    }
+
+    pub fn unit(&self) -> &'static str {
+        self.kind.unit(self.scale)
+    }
+}
+
+impl std::fmt::Display for SensorReading {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}{}", self.name, self.value, self.unit())
+    }
+}
+
+/// Readings in a sensor list, info, or history entry; values that are not numbers are skipped.
+pub fn sensor_readings(value: &Value) -> Vec<SensorReading> {
+    value
+        .get("data")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(SensorReading::from_value)
+        .collect()
+}
+
+/// Add `kind` and `unit` next to each raw `data` entry so printed JSON names what the numbers mean.
+pub fn annotate_readings(value: &mut Value) {
+    let Some(data) = value.get_mut("data").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for entry in data {
+        if let Some(reading) = SensorReading::from_value(entry)
+            && let Some(entry) = entry.as_object_mut()
+        {
+            entry.insert("kind".into(), reading.kind.as_str().into());
+            entry.insert("unit".into(), reading.unit().into());
+        }
+    }
+}
+
+/// Drop `data` entries of other kinds, returning whether any are left.
+pub fn retain_readings(value: &mut Value, kind: ReadingKind) -> bool {
+    let Some(data) = value.get_mut("data").and_then(Value::as_array_mut) else {
+        return false;
+    };
+    data.retain(|entry| {
+        pick_string(entry, &["name"]).is_some_and(|name| ReadingKind::from_name(&name) == kind)
+    });
+    !data.is_empty()
+}
+
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    pub id: String,
+    pub name: String,
+    pub model: Option<String>,
+    pub protocol: Option<String>,
+    pub ignored: bool,
+    pub readings: Vec<SensorReading>,
+}
+
+impl Sensor {
+    fn from_value(sensor: &Value) -> Self {
         Self {
-            client,
-            credentials,
+            id: pick_string(sensor, &["id", "sensorId"]).unwrap_or_else(|| "?".into()),
+            name: pick_string(sensor, &["name"]).unwrap_or_else(|| "(unnamed sensor)".into()),
+            model: pick_string(sensor, &["model"]),
+            protocol: pick_string(sensor, &["protocol"]),
+            ignored: pick_string(sensor, &["ignored"])
+                .is_some_and(|flag| matches!(flag.as_str(), "1" | "true")),
+            readings: sensor_readings(sensor),
+        }
+    }
+
+    pub fn has_kind(&self, kind: ReadingKind) -> bool {
+        self.readings.iter().any(|reading| reading.kind == kind)
+    }
+
+    pub fn to_entry(&self) -> Entry {
+        let mut details = Vec::new();
+        if self.ignored {
+            details.push("ignored".into());
+        }
+        details.extend(self.model.clone());
+        if let Some(protocol) = &self.protocol {
+            details.push(format!("protocol={protocol}"));
+        }
+        if !self.readings.is_empty() {
+            let values: Vec<String> = self.readings.iter().map(ToString::to_string).collect();
+            details.push(values.join(", "));
+        }
+        Entry {
+            category: Category::Sensor,
+            id: self.id.clone(),
+            name: self.name.clone(),
+            details: details_to_string(details),
+            state: None,
+        }
+    }
+}
+
+/// Command name for a Telldus method number, e.g. `dim` for 16.
+pub fn method_name(method: u32) -> &'static str {
+    match method {
+        1 => "on",
+        2 => "off",
+        4 => "bell",
+        16 => "dim",
+        128 => "up",
+        256 => "down",
+        512 => "stop",
+        _ => "unknown",
+    }
+}
+
+pub struct ZwaveConfigRequest<'a> {
+    pub id: &'a str,
+    pub parameter: u8,
+    pub size: u8,
+    pub value: i64,
+}
+
+impl<'a> TelldusApiBuilder<'a> {
+    pub fn credentials(mut self, credentials: &'a TelldusCredentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Send requests through `transport`, such as a preconfigured reqwest client.
+    ///
+    /// Defaults to the client the CLI shares between the OAuth flow and every API call.
+    pub fn client(mut self, transport: impl Transport + 'a) -> Self {
+        self.transport = Some(Box::new(transport));
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Give each request this long instead of the client's own timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Replace the credentials through `reauthorize` when Telldus Live rejects them, and
+    /// resend only the rejected request. This happens at most once per client.
+    pub fn reauthorize(
+        mut self,
+        reauthorize: impl Fn(&TelldusCredentials) -> Result<TelldusCredentials, ApiError> + 'a,
+    ) -> Self {
+        self.reauthorize = Some(Box::new(reauthorize));
+        self
+    }
+
+    pub fn build(self) -> Result<TelldusApi<'a>, ApiError> {
+        let credentials = self
+            .credentials
+            .ok_or_else(|| ApiError::Build("credentials are required".into()))?;
+        let transport = match self.transport {
+            Some(transport) => transport,
+            None => default_transport()?,
+        };
+        Ok(TelldusApi {
+            transport,
+            credentials: RefCell::new(Cow::Borrowed(credentials)),
+            base_url: self.base_url,
+            rate_limit: self.rate_limit,
+            retry: self.retry,
+            timeout: self.timeout,
+            reauthorize: self.reauthorize,
+            reauthorized: Cell::new(false),
+        })
+    }
+}
+
+impl<'a> TelldusApi<'a> {
+    pub fn builder() -> TelldusApiBuilder<'a> {
+        TelldusApiBuilder {
+            transport: None,
+            credentials: None,
+            base_url: BASE_URL.to_string(),
+            rate_limit: RateLimit::default(),
+            retry: RetryPolicy::default(),
+            timeout: None,
+            reauthorize: None,
         }
     }
 
@@ -87,10 +763,11 @@ impl<'a> TelldusApi<'a> {
                         details.push("offline".into());
                     }
                 }
-                if let Some(last_seen) = pick_string(&client, &["lastSeen", "lastseen"]) {
-                    if !last_seen.is_empty() && last_seen != "0" {
-                        details.push(format!("lastSeen={last_seen}"));
-                    }
+                if let Some(last_seen) = pick_string(&client, &["lastSeen", "lastseen"])
+                    && !last_seen.is_empty()
+                    && last_seen != "0"
+                {
+                    details.push(format!("lastSeen={last_seen}"));
                 }
                 if let Some(firmware) = pick_string(&client, &["firmware", "firmwareVersion"]) {
                     details.push(format!("fw={firmware}"));
@@ -100,92 +777,194 @@ impl<'a> TelldusApi<'a> {
                     id,
                     name,
                     details: details_to_string(details),
+                    state: None,
                 }
             })
             .collect())
     }
 
     pub fn list_devices(&self) -> Result<Vec<Entry>, ApiError> {
+        let payload = self.get_json("/json/devices/list", &[])?;
+        let items = array_from(&payload, &["device", "devices"]);
+        Ok(items.iter().map(device_entry).collect())
+    }
+
+    pub fn list_groups(&self) -> Result<Vec<Entry>, ApiError> {
         let payload = self.get_json("/json/devices/list", &[])?;
         let items = array_from(&payload, &["device", "devices"]);
         Ok(items
-            .into_iter()
-            .map(|device| {
-                let id = pick_string(&device, &["id", "deviceId"]).unwrap_or_else(|| "?".into());
-                let name =
-                    pick_string(&device, &["name"]).unwrap_or_else(|| "(unnamed device)".into());
-                let mut details = Vec::new();
-                if let Some(model) = pick_string(&device, &["model", "deviceType", "type"]) {
-                    details.push(model);
-                }
-                if let Some(state) = pick_string(&device, &["statevalue", "state", "stateValue"]) {
-                    if !state.is_empty() {
-                        details.push(format!("state={state}"));
-                    }
-                }
-                if let Some(client_name) = pick_string(&device, &["clientName"]) {
-                    details.push(format!("client={client_name}"));
-                }
-                Entry {
-                    category: Category::Device,
-                    id,
-                    name,
-                    details: details_to_string(details),
-                }
-            })
+            .iter()
+            .filter(|device| is_group(device))
+            .map(device_entry)
+            .collect())
+    }
+
+    /// Ids of the devices in group `id`, or `None` when it is not a group.
+    pub fn group_members(&self, id: &str) -> Result<Option<Vec<String>>, ApiError> {
+        let info = self.device_info(id)?;
+        Ok(is_group(&info).then(|| member_ids(&info)))
+    }
+
+    pub fn list_sensors(&self, include_ignored: bool) -> Result<Vec<Entry>, ApiError> {
+        Ok(self
+            .sensors(include_ignored)?
+            .iter()
+            .map(Sensor::to_entry)
             .collect())
     }
 
-    pub fn list_sensors(&self) -> Result<Vec<Entry>, ApiError> {
+    /// Sensors with their latest readings parsed into numbers.
+    pub fn sensors(&self, include_ignored: bool) -> Result<Vec<Sensor>, ApiError> {
         let payload = self.get_json(
             "/json/sensors/list",
             &[
-                ("includeIgnored", "0"),
+                ("includeIgnored", if include_ignored { "1" } else { "0" }),
                 ("includeValues", "1"),
                 ("includeScale", "1"),
             ],
         )?;
         let items = array_from(&payload, &["sensor", "sensors"]);
+        Ok(items.iter().map(Sensor::from_value).collect())
+    }
+
+    pub fn list_firmware(&self) -> Result<Vec<FirmwareStatus>, ApiError> {
+        let payload = self.get_json("/json/clients/list", &[("extras", "firmware")])?;
+        let items = array_from(&payload, &["client", "clients"]);
         Ok(items
             .into_iter()
-            .map(|sensor| {
-                let id = pick_string(&sensor, &["id", "sensorId"]).unwrap_or_else(|| "?".into());
-                let name =
-                    pick_string(&sensor, &["name"]).unwrap_or_else(|| "(unnamed sensor)".into());
-                let mut details = Vec::new();
-                if let Some(model) = pick_string(&sensor, &["model"]) {
-                    details.push(model);
-                }
-                if let Some(protocol) = pick_string(&sensor, &["protocol"]) {
-                    details.push(format!("protocol={protocol}"));
-                }
-                if let Some(data) = sensor.get("data").and_then(Value::as_array) {
-                    let mut values = Vec::new();
-                    for entry in data {
-                        if let Some(name) = pick_string(entry, &["name"]) {
-                            let value = pick_string(entry, &["value"]).unwrap_or_default();
-                            let scale = pick_string(entry, &["scale"]).unwrap_or_default();
-                            let mut sample = format!("{name}={value}");
-                            if !scale.is_empty() {
-                                sample.push_str(&format!("@{scale}"));
-                            }
-                            values.push(sample);
-                        }
-                    }
-                    if !values.is_empty() {
-                        details.push(values.join(", "));
+            .map(|client| FirmwareStatus {
+                id: pick_string(&client, &["id", "clientId"]).unwrap_or_else(|| "?".into()),
+                name: pick_string(&client, &["name", "clientName"])
+                    .unwrap_or_else(|| "(controller)".into()),
+                current: pick_string(&client, &["firmware", "firmwareVersion", "version"]),
+                available: pick_string(
+                    &client,
+                    &["availableFirmware", "latestFirmware", "firmwareAvailable"],
+                ),
+            })
+            .collect())
+    }
+
+    /// Online flag and last contact of every controller.
+    pub fn controller_statuses(&self) -> Result<Vec<ControllerStatus>, ApiError> {
+        let payload = self.get_json("/json/clients/list", &[])?;
+        let items = array_from(&payload, &["client", "clients"]);
+        Ok(items
+            .iter()
+            .map(|client| ControllerStatus {
+                id: pick_string(client, &["id", "clientId"]).unwrap_or_else(|| "?".into()),
+                name: pick_string(client, &["name", "clientName"])
+                    .unwrap_or_else(|| "(controller)".into()),
+                online: pick_string(client, &["online"]).and_then(|online| {
+                    match online.to_ascii_lowercase().as_str() {
+                        "1" | "true" => Some(true),
+                        "0" | "false" => Some(false),
+                        _ => None,
                     }
-                }
-                Entry {
-                    category: Category::Sensor,
-                    id,
-                    name,
-                    details: details_to_string(details),
-                }
+                }),
+                last_seen: pick_string(client, &["lastSeen", "lastseen"])
+                    .and_then(|last_seen| last_seen.parse().ok())
+                    .filter(|last_seen| *last_seen > 0),
             })
             .collect())
     }
 
+    /// Coordinates of the first controller that has them, for computing sunrise and sunset
+    /// locally; controllers without a location report 0, 0.
+    pub fn controller_location(&self) -> Result<Option<Location>, ApiError> {
+        let payload = self.get_json("/json/clients/list", &[("extras", "coordinate")])?;
+        let items = array_from(&payload, &["client", "clients"]);
+        Ok(items.iter().find_map(|client| {
+            let degrees = |key| pick_string(client, &[key])?.parse::<f64>().ok();
+            let location = Location {
+                latitude: degrees("latitude")?,
+                longitude: degrees("longitude")?,
+            };
+            (location.latitude != 0.0 || location.longitude != 0.0).then_some(location)
+        }))
+    }
+
+    pub fn register_controller(
+        &self,
+        code: &str,
+        name: Option<&str>,
+    ) -> Result<Option<String>, ApiError> {
+        let mut params = vec![("code".into(), code.into())];
+        if let Some(name) = name {
+            params.push(("name".into(), name.into()));
+        }
+        let payload = self.get_json_owned("/json/client/register", params)?;
+        ensure_success(&payload)?;
+        Ok(pick_string(&payload, &["id", "clientId"]))
+    }
+
+    pub fn remove_controller(&self, client_id: &str) -> Result<(), ApiError> {
+        let payload = self.get_json("/json/client/remove", &[("id", client_id)])?;
+        ensure_success(&payload)
+    }
+
+    pub fn upgrade_firmware(&self, client_id: &str) -> Result<(), ApiError> {
+        let payload = self.get_json("/json/client/upgradeFirmware", &[("id", client_id)])?;
+        ensure_success(&payload)
+    }
+
+    /// Battery state of every device and sensor that reports one.
+    pub fn list_batteries(&self) -> Result<Vec<BatteryStatus>, ApiError> {
+        let devices = self.get_json("/json/devices/list", &[("extras", "battery")])?;
+        let sensors = self.get_json(
+            "/json/sensors/list",
+            &[("includeIgnored", "0"), ("includeValues", "1")],
+        )?;
+
+        let mut statuses = Vec::new();
+        for (category, payload, keys) in [
+            (Category::Device, &devices, ["device", "devices"]),
+            (Category::Sensor, &sensors, ["sensor", "sensors"]),
+        ] {
+            for item in array_from(payload, &keys) {
+                let Some(raw) = pick_string(&item, &["battery", "batteryLevel", "batteryStatus"])
+                else {
+                    continue;
+                };
+                statuses.push(BatteryStatus {
+                    category,
+                    id: pick_string(&item, &["id", "deviceId", "sensorId"])
+                        .unwrap_or_else(|| "?".into()),
+                    name: pick_string(&item, &["name"]).unwrap_or_else(|| "(unnamed)".into()),
+                    level: BatteryLevel::parse(&raw),
+                });
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// Detect response shapes the parsers do not recognize, such as renamed fields.
+    ///
+    /// Returns one human-readable warning per endpoint whose payload no longer matches.
+    pub fn probe_compatibility(&self) -> Result<Vec<String>, ApiError> {
+        let mut warnings = Vec::new();
+        for (path, collection_keys, id_keys, name_keys) in KNOWN_SHAPES {
+            let payload = self.get_json(path, &[])?;
+            if let Some(warning) =
+                shape_warning(path, &payload, collection_keys, id_keys, name_keys)
+            {
+                warnings.push(warning);
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Items of a list endpoint exactly as Telldus returned them.
+    pub fn raw_list(
+        &self,
+        path: &str,
+        params: &[(&str, &str)],
+        keys: &[&str],
+    ) -> Result<Vec<Value>, ApiError> {
+        let payload = self.get_json(path, params)?;
+        Ok(array_from(&payload, keys))
+    }
+
     pub fn device_turn_on(&self, id: &str) -> Result<(), ApiError> {
         self.device_action("/json/device/turnOn", id, Vec::new())
     }
@@ -320,6 +1099,22 @@ impl<'a> TelldusApi<'a> {
         self.get_json("/json/device/info", &[("id", id)])
     }
 
+    pub fn device_state(&self, id: &str) -> Result<DeviceState, ApiError> {
+        let info = self.device_info(id)?;
+        let method = pick_string(&info, &["state"])
+            .and_then(|state| state.parse().ok())
+            .ok_or_else(|| ApiError::Unexpected(format!("device {id} reports no state")))?;
+        Ok(DeviceState {
+            method,
+            level: pick_string(&info, &["statevalue", "stateValue"])
+                .and_then(|value| value.parse().ok()),
+        })
+    }
+
+    pub fn device_settings(&self, id: &str) -> Result<DeviceSettings, ApiError> {
+        DeviceSettings::from_info(id, &self.device_info(id)?)
+    }
+
     pub fn device_history(&self, id: &str, limit: Option<u32>) -> Result<Vec<Value>, ApiError> {
         let mut params = vec![("id".into(), id.into())];
         if let Some(limit) = limit {
@@ -337,6 +1132,25 @@ impl<'a> TelldusApi<'a> {
         self.get_json_owned("/json/sensor/info", params)
     }
 
+    /// Current numeric value of the named reading (e.g. `temp`), if the sensor reports it.
+    pub fn sensor_value(&self, id: &str, name: &str) -> Result<Option<f64>, ApiError> {
+        let info = self.sensor_info(id, None)?;
+        Ok(sensor_readings(&info)
+            .into_iter()
+            .find(|reading| reading.name == name)
+            .map(|reading| reading.value))
+    }
+
+    /// Device events between the `from` and `to` Unix timestamps, fetched page by page.
+    pub fn device_history_iter(&self, id: &str, from: i64, to: i64) -> HistoryIter<'_, 'a> {
+        self.history_iter(
+            "/json/device/history",
+            vec![("id".into(), id.into())],
+            from,
+            to,
+        )
+    }
+
     pub fn sensor_history(
         &self,
         id: &str,
@@ -354,6 +1168,39 @@ impl<'a> TelldusApi<'a> {
         Ok(array_from(&payload, &["history"]))
     }
 
+    /// Sensor readings between the `from` and `to` Unix timestamps, fetched page by page.
+    pub fn sensor_history_iter(
+        &self,
+        id: &str,
+        scale: i32,
+        from: i64,
+        to: i64,
+    ) -> HistoryIter<'_, 'a> {
+        let params = vec![
+            ("id".into(), id.into()),
+            ("scale".into(), scale.to_string()),
+        ];
+        self.history_iter("/json/sensor/history", params, from, to)
+    }
+
+    fn history_iter(
+        &self,
+        path: &'static str,
+        params: Vec<(String, String)>,
+        from: i64,
+        to: i64,
+    ) -> HistoryIter<'_, 'a> {
+        HistoryIter {
+            api: self,
+            path,
+            params,
+            next_from: from,
+            to,
+            page: Vec::new().into_iter(),
+            failed: false,
+        }
+    }
+
     pub fn sensor_set_ignored(&self, request: SensorUpdateRequest<'_>) -> Result<(), ApiError> {
         let payload = self.post_form(
             "/json/sensor/setIgnored",
@@ -372,18 +1219,300 @@ impl<'a> TelldusApi<'a> {
         ensure_success(&payload)
     }
 
-    fn get_json(&self, path: &str, params: &[(&str, &str)]) -> Result<Value, ApiError> {
-        let url = format!("{BASE_URL}{path}");
-        let secrets = Secrets::new(&self.credentials.public_key, &self.credentials.private_key)
-            .token(&self.credentials.token, &self.credentials.token_secret);
+    pub fn list_jobs(&self) -> Result<Vec<SchedulerJob>, ApiError> {
+        let payload = self.get_json("/json/scheduler/jobList", &[])?;
+        let items = array_from(&payload, &["job", "jobs"]);
+        Ok(items.iter().map(scheduler_job).collect())
+    }
 
-        let mut request = self.client.clone().oauth1(secrets).get(&url);
-        if !params.is_empty() {
-            request = request.query(&params);
-        }
-        wait_for_rate_limit();
-        let response = request.send()?.error_for_status()?.text()?;
-        serde_json::from_str(&response).map_err(|err| ApiError::Unexpected(err.to_string()))
+    pub fn set_job(&self, request: SchedulerJobRequest<'_>) -> Result<String, ApiError> {
+        let weekdays = request
+            .weekdays
+            .iter()
+            .map(u8::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let payload = self.get_json_owned(
+            "/json/scheduler/setJob",
+            vec![
+                ("id".into(), "0".into()),
+                ("deviceId".into(), request.device_id.into()),
+                ("method".into(), request.method.to_string()),
+                (
+                    "methodValue".into(),
+                    request.method_value.unwrap_or_default().to_string(),
+                ),
+                ("type".into(), request.time.kind().into()),
+                ("hour".into(), request.time.hour().to_string()),
+                ("minute".into(), request.time.minute().to_string()),
+                ("offset".into(), request.time.offset().to_string()),
+                ("randomInterval".into(), "0".into()),
+                ("retries".into(), "3".into()),
+                ("retryInterval".into(), "5".into()),
+                ("reps".into(), "1".into()),
+                ("active".into(), "1".into()),
+                ("weekdays".into(), weekdays),
+            ],
+        )?;
+        ensure_success(&payload)?;
+        pick_string(&payload, &["id"])
+            .ok_or_else(|| ApiError::Unexpected("scheduler/setJob did not return an id".into()))
+    }
+
+    pub fn remove_job(&self, id: &str) -> Result<(), ApiError> {
+        let payload = self.get_json("/json/scheduler/removeJob", &[("id", id)])?;
+        ensure_success(&payload)
+    }
+
+    pub fn list_events(&self) -> Result<Vec<Entry>, ApiError> {
+        let payload = self.get_json("/json/events/list", &[])?;
+        let items = array_from(&payload, &["event", "events"]);
+        Ok(items
+            .into_iter()
+            .map(|event| {
+                let id = pick_string(&event, &["id"]).unwrap_or_else(|| "?".into());
+                let name = pick_string(&event, &["description", "name"])
+                    .unwrap_or_else(|| "(unnamed event)".into());
+                let mut details = Vec::new();
+                if pick_string(&event, &["active"]).is_some_and(|active| active == "0") {
+                    details.push("inactive".into());
+                }
+                if let Some(interval) = pick_string(&event, &["minRepeatInterval"]) {
+                    details.push(format!("minRepeat={interval}s"));
+                }
+                Entry {
+                    category: Category::Event,
+                    id,
+                    name,
+                    details: details_to_string(details),
+                    state: None,
+                }
+            })
+            .collect())
+    }
+
+    pub fn create_event(
+        &self,
+        description: &str,
+        min_repeat_interval: u32,
+    ) -> Result<String, ApiError> {
+        let payload = self.get_json_owned(
+            "/json/event/setEvent",
+            vec![
+                ("id".into(), "0".into()),
+                ("description".into(), description.into()),
+                ("minRepeatInterval".into(), min_repeat_interval.to_string()),
+                ("active".into(), "1".into()),
+            ],
+        )?;
+        ensure_success(&payload)?;
+        pick_string(&payload, &["id"])
+            .ok_or_else(|| ApiError::Unexpected("event/setEvent did not return an id".into()))
+    }
+
+    pub fn remove_event(&self, id: &str) -> Result<(), ApiError> {
+        let payload = self.get_json("/json/event/removeEvent", &[("id", id)])?;
+        ensure_success(&payload)
+    }
+
+    pub fn set_event_trigger(
+        &self,
+        event_id: &str,
+        trigger: &EventTrigger,
+    ) -> Result<(), ApiError> {
+        let mut params = vec![
+            ("eventId".into(), event_id.into()),
+            ("id".into(), "0".into()),
+        ];
+        let path = match trigger {
+            EventTrigger::Device { device_id, method } => {
+                params.push(("deviceId".into(), device_id.clone()));
+                params.push(("method".into(), method.to_string()));
+                "/json/event/setDeviceTrigger"
+            }
+            EventTrigger::Sensor {
+                sensor_id,
+                value_type,
+                scale,
+                value,
+                edge,
+            } => {
+                params.push(("sensorId".into(), sensor_id.clone()));
+                params.push(("valueType".into(), value_type.clone()));
+                params.push(("scale".into(), scale.to_string()));
+                params.push(("value".into(), value.to_string()));
+                params.push(("edge".into(), edge.to_string()));
+                "/json/event/setSensorTrigger"
+            }
+            EventTrigger::Time { hour, minute } => {
+                params.push(("hour".into(), hour.to_string()));
+                params.push(("minute".into(), minute.to_string()));
+                "/json/event/setTimeTrigger"
+            }
+        };
+        let payload = self.get_json_owned(path, params)?;
+        ensure_success(&payload)
+    }
+
+    pub fn set_event_condition(
+        &self,
+        event_id: &str,
+        condition: &EventCondition,
+    ) -> Result<(), ApiError> {
+        let mut params = vec![
+            ("eventId".into(), event_id.into()),
+            ("id".into(), "0".into()),
+            ("group".into(), "1".into()),
+        ];
+        let path = match condition {
+            EventCondition::Device { device_id, state } => {
+                params.push(("deviceId".into(), device_id.clone()));
+                params.push(("state".into(), state.to_string()));
+                "/json/event/setDeviceCondition"
+            }
+            EventCondition::Time { from, to } => {
+                params.push(("fromHour".into(), from.0.to_string()));
+                params.push(("fromMinute".into(), from.1.to_string()));
+                params.push(("toHour".into(), to.0.to_string()));
+                params.push(("toMinute".into(), to.1.to_string()));
+                "/json/event/setTimeCondition"
+            }
+        };
+        let payload = self.get_json_owned(path, params)?;
+        ensure_success(&payload)
+    }
+
+    pub fn set_event_action(&self, event_id: &str, action: &EventAction) -> Result<(), ApiError> {
+        let mut params = vec![
+            ("eventId".into(), event_id.into()),
+            ("id".into(), "0".into()),
+            ("delay".into(), "0".into()),
+        ];
+        let path = match action {
+            EventAction::Device {
+                device_id,
+                method,
+                value,
+            } => {
+                params.push(("deviceId".into(), device_id.clone()));
+                params.push(("method".into(), method.to_string()));
+                params.push(("value".into(), value.unwrap_or_default().to_string()));
+                params.push(("repeats".into(), "1".into()));
+                "/json/event/setDeviceAction"
+            }
+            EventAction::Push { phone_id, message } => {
+                params.push(("phoneId".into(), phone_id.clone()));
+                params.push(("message".into(), message.clone()));
+                "/json/event/setPushAction"
+            }
+            EventAction::Sms { to, message } => {
+                params.push(("to".into(), to.clone()));
+                params.push(("message".into(), message.clone()));
+                params.push(("flash".into(), "0".into()));
+                "/json/event/setSMSAction"
+            }
+        };
+        let payload = self.get_json_owned(path, params)?;
+        ensure_success(&payload)
+    }
+
+    pub fn list_phones(&self) -> Result<Vec<Entry>, ApiError> {
+        let payload = self.get_json("/json/user/listPhones", &[])?;
+        let items = array_from(&payload, &["phone", "phones"]);
+        Ok(items
+            .into_iter()
+            .map(|phone| {
+                let id = pick_string(&phone, &["id", "phoneId"]).unwrap_or_else(|| "?".into());
+                let name = pick_string(&phone, &["name", "model"])
+                    .unwrap_or_else(|| "(unnamed phone)".into());
+                let mut details = Vec::new();
+                if let Some(os) = pick_string(&phone, &["os", "platform"]) {
+                    details.push(os);
+                }
+                if let Some(version) = pick_string(&phone, &["appVersion", "version"]) {
+                    details.push(format!("app={version}"));
+                }
+                Entry {
+                    category: Category::Phone,
+                    id,
+                    name,
+                    details: details_to_string(details),
+                    state: None,
+                }
+            })
+            .collect())
+    }
+
+    pub fn send_push_test(&self, phone_id: &str) -> Result<(), ApiError> {
+        let payload = self.get_json("/json/user/sendPushTest", &[("phoneId", phone_id)])?;
+        ensure_success(&payload)
+    }
+
+    pub fn send_push(&self, phone_id: &str, message: &str) -> Result<(), ApiError> {
+        let payload = self.get_json(
+            "/json/user/sendPush",
+            &[("phoneId", phone_id), ("message", message)],
+        )?;
+        ensure_success(&payload)
+    }
+
+    pub fn list_zwave_nodes(&self, client_id: &str) -> Result<Vec<Entry>, ApiError> {
+        let payload = self.get_json("/json/zwave/nodes", &[("clientId", client_id)])?;
+        let items = array_from(&payload, &["node", "nodes"]);
+        Ok(items
+            .into_iter()
+            .map(|node| {
+                let id = pick_string(&node, &["id", "deviceId"]).unwrap_or_else(|| "?".into());
+                let name = pick_string(&node, &["name"]).unwrap_or_else(|| "(unnamed node)".into());
+                let mut details = Vec::new();
+                if let Some(node_id) = pick_string(&node, &["nodeId", "node"]) {
+                    details.push(format!("node={node_id}"));
+                }
+                if let Some(manufacturer) =
+                    pick_string(&node, &["manufacturer", "manufacturerName"])
+                {
+                    details.push(manufacturer);
+                }
+                if let Some(product) = pick_string(&node, &["product", "productName"]) {
+                    details.push(product);
+                }
+                Entry {
+                    category: Category::Device,
+                    id,
+                    name,
+                    details: details_to_string(details),
+                    state: None,
+                }
+            })
+            .collect())
+    }
+
+    pub fn get_zwave_config(&self, id: &str, parameter: u8) -> Result<Option<String>, ApiError> {
+        let payload = self.get_json_owned(
+            "/json/zwave/getConfig",
+            vec![
+                ("id".into(), id.into()),
+                ("parameter".into(), parameter.to_string()),
+            ],
+        )?;
+        Ok(pick_string(&payload, &["value"]))
+    }
+
+    pub fn set_zwave_config(&self, request: ZwaveConfigRequest<'_>) -> Result<(), ApiError> {
+        let payload = self.get_json_owned(
+            "/json/zwave/setConfig",
+            vec![
+                ("id".into(), request.id.into()),
+                ("parameter".into(), request.parameter.to_string()),
+                ("size".into(), request.size.to_string()),
+                ("value".into(), request.value.to_string()),
+            ],
+        )?;
+        ensure_success(&payload)
+    }
+
+    fn get_json(&self, path: &str, params: &[(&str, &str)]) -> Result<Value, ApiError> {
+        self.execute(Method::Get, path, params)
     }
 
     fn get_json_owned(&self, path: &str, params: Vec<(String, String)>) -> Result<Value, ApiError> {
@@ -392,22 +1521,148 @@ impl<'a> TelldusApi<'a> {
     }
 
     fn post_form(&self, path: &str, params: Vec<(String, String)>) -> Result<Value, ApiError> {
-        let url = format!("{BASE_URL}{path}");
-        let secrets = Secrets::new(&self.credentials.public_key, &self.credentials.private_key)
-            .token(&self.credentials.token, &self.credentials.token_secret);
-
         let pairs = params_to_slice(&params);
-        wait_for_rate_limit();
-        let response = self
-            .client
-            .clone()
-            .oauth1(secrets)
-            .post(&url)
-            .form(&pairs)
-            .send()?
-            .error_for_status()?
-            .text()?;
-        serde_json::from_str(&response).map_err(|err| ApiError::Unexpected(err.to_string()))
+        self.execute(Method::Post, path, &pairs)
+    }
+
+    fn execute(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Value, ApiError> {
+        match self.execute_once(method, path, params) {
+            // A 401 means Telldus Live did not act on the request, so resending it is safe.
+            Err(ApiError::Unauthorized) if !self.reauthorized.get() => {
+                let Some(reauthorize) = &self.reauthorize else {
+                    return Err(ApiError::Unauthorized);
+                };
+                self.reauthorized.set(true);
+                let credentials = reauthorize(&self.credentials.borrow())?;
+                *self.credentials.borrow_mut() = Cow::Owned(credentials);
+                self.execute_once(method, path, params)
+            }
+            result => result,
+        }
+    }
+
+    fn execute_once(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Value, ApiError> {
+        if let Some(replayed) = fixtures::replay(method.as_str(), path, params) {
+            return parse_replayed(replayed);
+        }
+        let is_read = is_idempotent_read(path);
+        // Fetched before borrowing the credentials, which this request may replace.
+        let before = if !is_read && audit::is_enabled() && audit::keeps_previous(path) {
+            params
+                .iter()
+                .find(|(key, _)| *key == "id")
+                .and_then(|(_, id)| self.device_info(id).ok())
+        } else {
+            None
+        };
+        let credentials = self.credentials.borrow();
+        let cacheable = method == Method::Get && response_cache::is_cacheable(path);
+        let cached = cacheable
+            .then(|| response_cache::load(&credentials, path, params))
+            .flatten();
+        if let Some(entry) = &cached
+            && response_cache::is_fresh(path, entry)
+        {
+            response_cache::record_hit();
+            return serde_json::from_str(&entry.body)
+                .map_err(|err| ApiError::Unexpected(err.to_string()));
+        }
+        let request = Request {
+            method,
+            url: format!("{}{path}", self.base_url),
+            params,
+            credentials: &credentials,
+            timeout: self.timeout,
+            etag: cached.as_ref().and_then(|entry| entry.etag.as_deref()),
+            last_modified: cached
+                .as_ref()
+                .and_then(|entry| entry.last_modified.as_deref()),
+        };
+        let may_retry = self.retry.retry_unsafe || is_read;
+        let mut attempt = 1;
+        let response = loop {
+            let waited = wait_for_rate_limit(self.rate_limit.min_interval);
+            let (started, started_at) = (Instant::now(), SystemTime::now());
+            let response = if cacheable {
+                self.execute_cached(&request, path, params, cached.as_ref())
+            } else {
+                self.transport.execute(&request)
+            };
+            timing::record(method.as_str(), path, waited, started.elapsed());
+            otlp::record_call(
+                method.as_str(),
+                path,
+                started_at,
+                started.elapsed(),
+                response.is_ok(),
+            );
+            match response {
+                Err(err)
+                    if may_retry && attempt < self.retry.max_attempts && is_retryable(&err) =>
+                {
+                    // Ctrl-C during the backoff gives up with the error instead.
+                    if !service::sleep(self.retry.delay(attempt)) {
+                        break Err(err);
+                    }
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        let value = response.and_then(|response| {
+            fixtures::record(method.as_str(), path, params, &response);
+            serde_json::from_str(&response).map_err(|err| ApiError::Unexpected(err.to_string()))
+        });
+        if !is_read && audit::is_enabled() {
+            audit_request(&credentials, path, params, &value, before);
+        }
+        if !is_read {
+            response_cache::clear(&credentials);
+        }
+        value
+    }
+
+    /// Send a cacheable read with the stored validators, answering a 304 with the stored
+    /// body, and keep the result when it can be revalidated or served within the TTL.
+    fn execute_cached(
+        &self,
+        request: &Request<'_>,
+        path: &str,
+        params: &[(&str, &str)],
+        cached: Option<&response_cache::Entry>,
+    ) -> Result<String, ApiError> {
+        let entry = match self.transport.execute_conditional(request)? {
+            Conditional::NotModified => {
+                let cached = cached.ok_or_else(|| {
+                    ApiError::Unexpected(format!("{path} answered 304 to an unconditional request"))
+                })?;
+                // Confirmed current, so the TTL starts over.
+                response_cache::Entry::new(
+                    cached.body.clone(),
+                    cached.etag.clone(),
+                    cached.last_modified.clone(),
+                )
+            }
+            Conditional::Modified {
+                body,
+                etag,
+                last_modified,
+            } => response_cache::Entry::new(body, etag, last_modified),
+        };
+        if response_cache::is_worth_keeping(path, &entry) {
+            response_cache::store(&self.credentials.borrow(), path, params, &entry);
+        }
+        Ok(entry.body)
     }
 
     fn device_action(
@@ -422,18 +1677,211 @@ impl<'a> TelldusApi<'a> {
     }
 }
 
-fn wait_for_rate_limit() {
+/// Log a mutating request with its outcome, including requests Telldus refused.
+fn audit_request(
+    credentials: &TelldusCredentials,
+    path: &str,
+    params: &[(&str, &str)],
+    value: &Result<Value, ApiError>,
+    previous: Option<Value>,
+) {
+    let result = match value {
+        Ok(value) => match ensure_success(value) {
+            Ok(()) => "success".to_string(),
+            Err(err) => err.to_string(),
+        },
+        Err(err) => err.to_string(),
+    };
+    audit::append(
+        credentials,
+        &audit::Record {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            endpoint: path.to_string(),
+            parameters: params
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            result,
+            previous,
+            undoes: audit::undoing(),
+        },
+    );
+}
+
+fn parse_replayed(body: std::io::Result<String>) -> Result<Value, ApiError> {
+    let body = body.map_err(|err| ApiError::Unexpected(err.to_string()))?;
+    serde_json::from_str(&body).map_err(|err| ApiError::Unexpected(err.to_string()))
+}
+
+fn device_entry(device: &Value) -> Entry {
+    let id = pick_string(device, &["id", "deviceId"]).unwrap_or_else(|| "?".into());
+    let name = pick_string(device, &["name"]).unwrap_or_else(|| "(unnamed device)".into());
+    let mut details = Vec::new();
+    if is_group(device) {
+        details.push(format!("group of {}", member_ids(device).len()));
+    } else if let Some(model) = pick_string(device, &["model", "deviceType", "type"]) {
+        details.push(model);
+    }
+    if let Some(state) = pick_string(device, &["statevalue", "state", "stateValue"])
+        && !state.is_empty()
+    {
+        details.push(format!("state={state}"));
+    }
+    if let Some(client_name) = pick_string(device, &["clientName"]) {
+        details.push(format!("client={client_name}"));
+    }
+    Entry {
+        category: Category::Device,
+        id,
+        name,
+        details: details_to_string(details),
+        state: pick_string(device, &["state"]).and_then(|state| state.parse().ok()),
+    }
+}
+
+fn scheduler_job(job: &Value) -> SchedulerJob {
+    let number = |keys: &[&str]| pick_string(job, keys).and_then(|text| text.parse::<i64>().ok());
+    let kind = pick_string(job, &["type"]).unwrap_or_else(|| "time".into());
+    let time = ScheduleTime::from_parts(
+        &kind,
+        number(&["hour"]).unwrap_or_default() as u8,
+        number(&["minute"]).unwrap_or_default() as u8,
+        number(&["offset"]).unwrap_or_default() as i32,
+    );
+    let weekdays = pick_string(job, &["weekdays"])
+        .map(|days| {
+            days.split(',')
+                .filter_map(|day| day.trim().parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    SchedulerJob {
+        id: pick_string(job, &["id"]).unwrap_or_else(|| "?".into()),
+        device_id: pick_string(job, &["deviceId"]).unwrap_or_else(|| "?".into()),
+        method: number(&["method"]).unwrap_or_default() as u32,
+        method_value: number(&["methodValue"])
+            .filter(|value| *value > 0)
+            .map(|value| value as u8),
+        time,
+        weekdays,
+        active: number(&["active"]).is_none_or(|active| active != 0),
+        next_run: number(&["nextRunTime"]).filter(|ts| *ts > 0),
+    }
+}
+
+fn is_group(device: &Value) -> bool {
+    pick_string(device, &["type", "deviceType"])
+        .is_some_and(|kind| kind.eq_ignore_ascii_case("group"))
+}
+
+/// Member ids of a group, which Telldus lists comma-separated under `devices`.
+fn member_ids(device: &Value) -> Vec<String> {
+    pick_string(device, &["devices"])
+        .map(|list| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn shape_warning(
+    path: &str,
+    payload: &Value,
+    collection_keys: &[&str],
+    id_keys: &[&str],
+    name_keys: &[&str],
+) -> Option<String> {
+    let has_collection = payload.is_array()
+        || collection_keys
+            .iter()
+            .any(|key| payload.get(*key).is_some_and(Value::is_array));
+    if !has_collection {
+        return Some(format!(
+            "{path}: expected a list under {} but found keys [{}]",
+            collection_keys.join("/"),
+            object_keys(payload)
+        ));
+    }
+    array_from(payload, collection_keys)
+        .iter()
+        .find(|item| pick_string(item, id_keys).is_none() || pick_string(item, name_keys).is_none())
+        .map(|item| {
+            format!(
+                "{path}: item lacks {} or {}; found keys [{}]",
+                id_keys.join("/"),
+                name_keys.join("/"),
+                object_keys(item)
+            )
+        })
+}
+
+fn object_keys(value: &Value) -> String {
+    match value.as_object() {
+        Some(map) => map.keys().cloned().collect::<Vec<_>>().join(", "),
+        None => format!("<{}>", value_kind(value)),
+    }
+}
+
+fn value_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn default_transport<'a>() -> Result<Box<dyn Transport + 'a>, ApiError> {
+    Ok(Box::new(crate::http_client::shared_client()?))
+}
+
+/// Endpoints that only read state; everything else changes devices, settings, or sends
+/// notifications.
+fn is_idempotent_read(path: &str) -> bool {
+    let action = path.rsplit('/').next().unwrap_or_default();
+    matches!(
+        action,
+        "list" | "info" | "history" | "jobList" | "nodes" | "listPhones" | "profile"
+    ) || action.starts_with("get")
+}
+
+fn is_retryable(err: &ApiError) -> bool {
+    match err {
+        ApiError::Http(err) => {
+            err.is_timeout()
+                || err.is_connect()
+                || err.status().is_some_and(|status| {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Block until `min_interval` has passed since the previous request, returning the time slept.
+fn wait_for_rate_limit(min_interval: Duration) -> Duration {
     static LAST_REQUEST: OnceLock<Mutex<Option<Instant>>> = OnceLock::new();
     let lock = LAST_REQUEST.get_or_init(|| Mutex::new(None));
 
     let mut guard = lock.lock().expect("rate limiter poisoned");
+    let mut waited = Duration::ZERO;
     if let Some(last) = *guard {
         let elapsed = last.elapsed();
-        if elapsed < MIN_REQUEST_INTERVAL {
-            thread::sleep(MIN_REQUEST_INTERVAL - elapsed);
+        if elapsed < min_interval {
+            waited = min_interval - elapsed;
+            thread::sleep(waited);
         }
     }
     *guard = Some(Instant::now());
+    waited
 }
 
 fn array_from(value: &Value, keys: &[&str]) -> Vec<Value> {
@@ -450,12 +1898,11 @@ fn array_from(value: &Value, keys: &[&str]) -> Vec<Value> {
 
 fn pick_string(value: &Value, keys: &[&str]) -> Option<String> {
     for key in keys {
-        if let Some(found) = value.get(*key) {
-            if let Some(text) = value_as_string(found) {
-                if !text.is_empty() {
-                    return Some(text);
-                }
-            }
+        if let Some(found) = value.get(*key)
+            && let Some(text) = value_as_string(found)
+            && !text.is_empty()
+        {
+            return Some(text);
         }
     }
     None
@@ -506,3 +1953,6 @@ fn params_to_slice(params: &[(String, String)]) -> Vec<(&str, &str)> {
         .map(|(k, v)| (k.as_str(), v.as_str()))
         .collect()
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,589 @@
+use super::*;
+use reqwest::blocking::Client;
+use serde_json::json;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+
+impl RateLimit {
+    const fn none() -> Self {
+        Self {
+            min_interval: Duration::ZERO,
+        }
+    }
+}
+
+impl TelldusApiBuilder<'_> {
+    /// Send requests to the mock server instead of Telldus Live.
+    fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+}
+
+/// A canned response served when the path matches, every listed query pair is present
+/// and, if given, the request is signed with `token`.
+///
+/// Payloads mirror what the live API actually sends: numbers as strings, singular and
+/// plural collection keys, and alternate field names across firmware.
+struct Route {
+    path: &'static str,
+    query: Vec<(&'static str, &'static str)>,
+    token: Option<&'static str>,
+    status: u16,
+    body: String,
+}
+
+impl Route {
+    fn json(path: &'static str, body: Value) -> Self {
+        Self {
+            path,
+            query: Vec::new(),
+            token: None,
+            status: 200,
+            body: body.to_string(),
+        }
+    }
+
+    fn with_query(mut self, key: &'static str, value: &'static str) -> Self {
+        self.query.push((key, value));
+        self
+    }
+
+    fn with_token(mut self, token: &'static str) -> Self {
+        self.token = Some(token);
+        self
+    }
+}
+
+/// Minimal HTTP/1.1 server answering one request per connection from a fixed route table.
+struct MockServer {
+    base_url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    fn start(routes: Vec<Route>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock server");
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                let mut authorization = String::new();
+                let mut header = String::new();
+                while reader.read_line(&mut header).is_ok_and(|read| read > 2) {
+                    if header.to_ascii_lowercase().starts_with("authorization:") {
+                        authorization = header.clone();
+                    }
+                    header.clear();
+                }
+
+                let target = request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+                let pairs: Vec<(String, String)> = url::form_urlencoded::parse(query.as_bytes())
+                    .into_owned()
+                    .collect();
+                let route = routes.iter().find(|route| {
+                    route.path == path
+                        && route
+                            .query
+                            .iter()
+                            .all(|(key, value)| pairs.iter().any(|(k, v)| k == key && v == value))
+                        && route.token.is_none_or(|token| {
+                            authorization.contains(&format!("oauth_token=\"{token}\""))
+                        })
+                });
+                let (status, body) = match route {
+                    Some(route) => (route.status, route.body.as_str()),
+                    None => (404, ""),
+                };
+                seen.lock().unwrap().push(target.clone());
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        Self { base_url, requests }
+    }
+
+    /// Request targets (path and query) received so far, in order.
+    fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn with_api<T>(server: &MockServer, test: impl FnOnce(&TelldusApi<'_>) -> T) -> T {
+    with_retry(server, RetryPolicy::none(), test)
+}
+
+fn with_retry<T>(
+    server: &MockServer,
+    retry: RetryPolicy,
+    test: impl FnOnce(&TelldusApi<'_>) -> T,
+) -> T {
+    let credentials = TelldusCredentials::default();
+    let api = TelldusApi::builder()
+        .credentials(&credentials)
+        .client(Client::new())
+        .base_url(server.base_url.clone())
+        .rate_limit(RateLimit::none())
+        .retry(retry)
+        .build()
+        .unwrap();
+    test(&api)
+}
+
+#[test]
+fn controllers_accept_string_flags_and_alternate_keys() {
+    let server = MockServer::start(vec![Route::json(
+        "/json/clients/list",
+        json!({
+            "client": [
+                { "id": "101", "name": "Hall", "online": "1", "version": "1.3.2" },
+                { "clientId": 102, "clientName": "Garage", "online": "0", "lastSeen": "0" }
+            ]
+        }),
+    )]);
+
+    let controllers = with_api(&server, |api| api.list_controllers()).unwrap();
+
+    assert_eq!(controllers.len(), 2);
+    assert_eq!(controllers[0].id, "101");
+    assert_eq!(controllers[0].details.as_deref(), Some("online"));
+    assert_eq!(controllers[1].id, "102");
+    assert_eq!(controllers[1].name, "Garage");
+    assert_eq!(controllers[1].details.as_deref(), Some("offline"));
+}
+
+#[test]
+fn devices_parse_groups_and_plural_collection_key() {
+    let server = MockServer::start(vec![Route::json(
+        "/json/devices/list",
+        json!({
+            "devices": [
+                { "id": "7", "name": "Lamp", "model": "selflearning-switch", "statevalue": "", "clientName": "Hall" },
+                { "deviceId": 8, "name": "Downstairs", "type": "group", "devices": "7,9," }
+            ]
+        }),
+    )]);
+
+    let devices = with_api(&server, |api| api.list_devices()).unwrap();
+
+    assert_eq!(devices[0].id, "7");
+    assert_eq!(
+        devices[0].details.as_deref(),
+        Some("selflearning-switch, client=Hall")
+    );
+    assert_eq!(devices[1].id, "8");
+    assert_eq!(devices[1].details.as_deref(), Some("group of 2"));
+}
+
+#[test]
+fn group_members_come_from_device_info() {
+    let server = MockServer::start(vec![
+        Route::json(
+            "/json/device/info",
+            json!({ "id": "8", "name": "Downstairs", "type": "group", "devices": "7, 9," }),
+        )
+        .with_query("id", "8"),
+        Route::json(
+            "/json/device/info",
+            json!({ "id": "7", "name": "Lamp", "model": "selflearning-switch" }),
+        )
+        .with_query("id", "7"),
+    ]);
+
+    let (group, device) = with_api(&server, |api| {
+        (
+            api.group_members("8").unwrap(),
+            api.group_members("7").unwrap(),
+        )
+    });
+
+    assert_eq!(group, Some(vec!["7".to_string(), "9".to_string()]));
+    assert_eq!(device, None);
+}
+
+#[test]
+fn sensors_send_flags_and_parse_typed_readings() {
+    let server = MockServer::start(vec![
+        Route::json(
+            "/json/sensors/list",
+            json!({
+                "sensor": [{
+                    "id": "1534643827",
+                    "name": "Outdoor",
+                    "ignored": 1,
+                    "model": "temperaturehumidity",
+                    "data": [
+                        { "name": "temp", "value": "-3.5", "scale": "0" },
+                        { "name": "humidity", "value": 81, "scale": 0 }
+                    ]
+                }]
+            }),
+        )
+        .with_query("includeIgnored", "1")
+        .with_query("includeValues", "1"),
+    ]);
+
+    let sensors = with_api(&server, |api| api.sensors(true)).unwrap();
+
+    assert_eq!(server.requests().len(), 1);
+    assert!(sensors[0].ignored);
+    let temp = &sensors[0].readings[0];
+    assert_eq!(temp.kind, ReadingKind::Temperature);
+    assert_eq!(temp.value, -3.5);
+    assert_eq!(temp.unit(), "°C");
+    assert_eq!(sensors[0].readings[1].kind, ReadingKind::Humidity);
+    assert_eq!(
+        sensors[0].to_entry().details.as_deref(),
+        Some("ignored, temperaturehumidity, temp=-3.5°C, humidity=81%")
+    );
+}
+
+#[test]
+fn batteries_decode_status_codes_from_both_endpoints() {
+    let server = MockServer::start(vec![
+        Route::json(
+            "/json/devices/list",
+            json!({
+                "device": [
+                    { "id": "7", "name": "Remote", "battery": "255" },
+                    { "id": "8", "name": "Mains powered" }
+                ]
+            }),
+        )
+        .with_query("extras", "battery"),
+        Route::json(
+            "/json/sensors/list",
+            json!({
+                "sensor": [{ "sensorId": "42", "name": "Outdoor", "batteryLevel": 64 }]
+            }),
+        ),
+    ]);
+
+    let batteries = with_api(&server, |api| api.list_batteries()).unwrap();
+
+    assert_eq!(batteries.len(), 2);
+    assert_eq!(batteries[0].level, BatteryLevel::Low);
+    assert_eq!(batteries[1].id, "42");
+    assert_eq!(batteries[1].level, BatteryLevel::Percent(64));
+}
+
+#[test]
+fn scheduler_jobs_parse_string_numbers() {
+    let server = MockServer::start(vec![Route::json(
+        "/json/scheduler/jobList",
+        json!({
+            "job": [{
+                "id": "55",
+                "deviceId": "7",
+                "method": "16",
+                "methodValue": "128",
+                "type": "sunset",
+                "hour": "0",
+                "minute": "0",
+                "offset": "-30",
+                "weekdays": "1,2,3,4,5",
+                "active": "1",
+                "nextRunTime": "1767225600"
+            }]
+        }),
+    )]);
+
+    let jobs = with_api(&server, |api| api.list_jobs()).unwrap();
+
+    let job = &jobs[0];
+    assert_eq!(job.device_id, "7");
+    assert_eq!(job.method, 16);
+    assert_eq!(job.method_value, Some(128));
+    assert_eq!(job.time, ScheduleTime::Sunset { offset: -30 });
+    assert_eq!(job.weekdays, vec![1, 2, 3, 4, 5]);
+    assert!(job.active);
+    assert_eq!(job.next_run, Some(1_767_225_600));
+}
+
+#[test]
+fn device_actions_surface_error_payloads() {
+    let server = MockServer::start(vec![
+        Route::json("/json/device/turnOn", json!({ "status": "success" })).with_query("id", "7"),
+        Route::json(
+            "/json/device/turnOff",
+            json!({ "error": "The device is not available" }),
+        )
+        .with_query("id", "7"),
+    ]);
+
+    with_api(&server, |api| {
+        api.device_turn_on("7").unwrap();
+        let err = api.device_turn_off("7").unwrap_err();
+        assert!(err.to_string().contains("not available"), "{err}");
+    });
+}
+
+#[test]
+fn http_errors_and_malformed_bodies_are_reported() {
+    let mut unavailable = Route::json("/json/clients/list", Value::Null);
+    unavailable.status = 503;
+    let mut maintenance = Route::json("/json/devices/list", Value::Null);
+    maintenance.body = "<html>maintenance</html>".into();
+    let server = MockServer::start(vec![unavailable, maintenance]);
+
+    with_api(&server, |api| {
+        assert!(matches!(api.list_controllers(), Err(ApiError::Http(_))));
+        assert!(matches!(api.list_devices(), Err(ApiError::Unexpected(_))));
+    });
+}
+
+#[test]
+fn retry_policy_resends_server_errors_only() {
+    let mut unavailable = Route::json("/json/clients/list", Value::Null);
+    unavailable.status = 503;
+    let mut missing = Route::json("/json/devices/list", Value::Null);
+    missing.status = 404;
+    let server = MockServer::start(vec![unavailable, missing]);
+    let retry = RetryPolicy {
+        max_attempts: 3,
+        backoff: Duration::from_millis(1),
+        retry_unsafe: false,
+    };
+
+    with_retry(&server, retry, |api| {
+        assert!(api.list_controllers().is_err());
+        assert!(api.list_devices().is_err());
+    });
+
+    let requests = server.requests();
+    assert_eq!(
+        requests
+            .iter()
+            .filter(|target| *target == "/json/clients/list")
+            .count(),
+        3
+    );
+    assert_eq!(
+        requests
+            .iter()
+            .filter(|target| *target == "/json/devices/list")
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn rejected_tokens_are_replaced_and_only_the_rejected_request_resent() {
+    let mut rejected = Route::json("/json/device/turnOn", Value::Null);
+    rejected.status = 401;
+    let server = MockServer::start(vec![
+        Route::json("/json/device/turnOn", json!({ "status": "success" })).with_token("fresh"),
+        Route::json("/json/device/turnOff", json!({ "status": "success" })).with_token("fresh"),
+        rejected,
+    ]);
+    let credentials = TelldusCredentials {
+        token: "stale".into(),
+        token_secret: "secret".into(),
+        ..TelldusCredentials::default()
+    };
+    let calls = Cell::new(0);
+    let api = TelldusApi::builder()
+        .credentials(&credentials)
+        .client(Client::new())
+        .base_url(server.base_url.clone())
+        .rate_limit(RateLimit::none())
+        .reauthorize(|rejected| {
+            calls.set(calls.get() + 1);
+            assert_eq!(rejected.token, "stale");
+            Ok(TelldusCredentials {
+                token: "fresh".into(),
+                ..rejected.clone()
+            })
+        })
+        .build()
+        .unwrap();
+
+    api.device_turn_on("7").unwrap();
+    api.device_turn_off("7").unwrap();
+
+    assert_eq!(calls.get(), 1);
+    assert_eq!(
+        server.requests(),
+        [
+            "/json/device/turnOn?id=7",
+            "/json/device/turnOn?id=7",
+            "/json/device/turnOff?id=7"
+        ]
+    );
+}
+
+#[test]
+fn history_iterator_requests_one_day_per_page() {
+    let day = HISTORY_PAGE_SECS;
+    let server = MockServer::start(vec![
+        Route::json(
+            "/json/device/history",
+            json!({ "history": [{ "ts": 100, "state": 1 }, { "ts": 200, "state": 2 }] }),
+        )
+        .with_query("from", "0"),
+        Route::json("/json/device/history", json!({ "history": [] })).with_query("from", "86400"),
+        Route::json(
+            "/json/device/history",
+            json!({ "history": [{ "ts": 180000, "state": 1 }] }),
+        )
+        .with_query("from", "172800"),
+    ]);
+
+    let timestamps: Vec<i64> = with_api(&server, |api| {
+        api.device_history_iter("7", 0, 3 * day - 1)
+            .map(|entry| entry.unwrap()["ts"].as_i64().unwrap())
+            .collect()
+    });
+
+    assert_eq!(timestamps, vec![100, 200, 180_000]);
+    assert_eq!(
+        server.requests(),
+        vec![
+            "/json/device/history?id=7&from=0&to=86399",
+            "/json/device/history?id=7&from=86400&to=172799",
+            "/json/device/history?id=7&from=172800&to=259199",
+        ]
+    );
+}
+
+#[test]
+fn commands_are_retried_only_when_unsafe_retries_are_allowed() {
+    let mut unavailable = Route::json("/json/device/bell", Value::Null);
+    unavailable.status = 503;
+    let server = MockServer::start(vec![unavailable]);
+    let mut retry = RetryPolicy {
+        max_attempts: 3,
+        backoff: Duration::from_millis(1),
+        retry_unsafe: false,
+    };
+
+    with_retry(&server, retry, |api| assert!(api.device_bell("7").is_err()));
+    assert_eq!(server.requests().len(), 1);
+
+    retry.retry_unsafe = true;
+    with_retry(&server, retry, |api| assert!(api.device_bell("7").is_err()));
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn device_state_reads_string_method_and_level() {
+    let server = MockServer::start(vec![
+        Route::json(
+            "/json/device/info",
+            json!({ "id": "7", "state": "16", "statevalue": "128" }),
+        )
+        .with_query("id", "7"),
+        Route::json(
+            "/json/device/info",
+            json!({ "id": "8", "state": 2, "statevalue": "" }),
+        )
+        .with_query("id", "8"),
+    ]);
+
+    with_api(&server, |api| {
+        assert_eq!(
+            api.device_state("7").unwrap(),
+            DeviceState {
+                method: 16,
+                level: Some(128)
+            }
+        );
+        assert_eq!(
+            api.device_state("8").unwrap(),
+            DeviceState {
+                method: 2,
+                level: None
+            }
+        );
+    });
+}
+
+#[test]
+fn device_settings_collect_client_model_and_parameters() {
+    let server = MockServer::start(vec![
+        Route::json(
+            "/json/device/info",
+            json!({
+                "id": "7",
+                "client": 367974,
+                "protocol": "arctech",
+                "model": "selflearning-switch:nexa",
+                "parameter": [
+                    { "name": "house", "value": "12345" },
+                    { "name": "unit", "value": 3 }
+                ]
+            }),
+        )
+        .with_query("id", "7"),
+        Route::json("/json/device/info", json!({ "id": "8", "protocol": "x10" }))
+            .with_query("id", "8"),
+    ]);
+
+    with_api(&server, |api| {
+        assert_eq!(
+            api.device_settings("7").unwrap(),
+            DeviceSettings {
+                client_id: "367974".into(),
+                protocol: "arctech".into(),
+                model: "selflearning-switch:nexa".into(),
+                parameters: vec![
+                    ("house".into(), "12345".into()),
+                    ("unit".into(), "3".into())
+                ],
+            }
+        );
+        assert!(api.device_settings("8").is_err());
+    });
+}
+
+#[test]
+fn request_timeout_overrides_the_client_timeout() {
+    // Accept connections but never answer them.
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind silent server");
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let held: Vec<_> = listener.incoming().collect();
+        drop(held);
+    });
+    let credentials = TelldusCredentials::default();
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .unwrap();
+    let api = TelldusApi::builder()
+        .credentials(&credentials)
+        .client(client)
+        .base_url(base_url)
+        .rate_limit(RateLimit::none())
+        .timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+
+    let started = Instant::now();
+    let result = api.list_controllers();
+    assert!(result.is_err());
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_secs(5));
+}
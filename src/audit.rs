@@ -0,0 +1,259 @@
+use crate::api::DeviceSettings;
+use crate::config::{self, ConfigError, TelldusCredentials};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+const AUDIT_FILE: &str = "audit.log";
+
+/// Endpoints logged with the device's previous `device/info`.
+const KEEPS_PREVIOUS: [&str; 5] = [
+    "/json/device/setName",
+    "/json/device/setDeviceParameter",
+    "/json/device/setProtocol",
+    "/json/device/setModel",
+    "/json/device/remove",
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static UNDOING: OnceLock<usize> = OnceLock::new();
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("failed to read audit log {0}: {1}")]
+    ReadFailed(String, #[source] io::Error),
+    #[error("audit log {0} line {1} is not a valid record: {2}")]
+    ParseFailed(String, usize, #[source] serde_json::Error),
+}
+
+/// One mutating request and how Telldus Live answered it, whether it came from a
+/// command, the daemon, or the REST API.
+///
+/// Renames, parameter, protocol and model changes, and removals also keep the device's
+/// `device/info` from just before the change, which is what `undo` restores.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// Unix timestamp of the response.
+    pub timestamp: u64,
+    /// API path such as `/json/device/turnOff`.
+    pub endpoint: String,
+    pub parameters: BTreeMap<String, String>,
+    /// `success`, or the error the request failed with.
+    pub result: String,
+    /// `device/info` of the device before the change, for endpoints that overwrite or
+    /// remove something.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<Value>,
+    /// Position in the log of the record this request was sent to undo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<usize>,
+}
+
+impl Record {
+    pub fn succeeded(&self) -> bool {
+        self.result == "success"
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.get(name).map(String::as_str)
+    }
+}
+
+/// Start logging; library users and tests leave the log alone.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn keeps_previous(path: &str) -> bool {
+    KEEPS_PREVIOUS.contains(&path)
+}
+
+/// Mark the requests that follow as undoing the record at `index`.
+pub fn set_undoing(index: usize) {
+    let _ = UNDOING.set(index);
+}
+
+pub fn undoing() -> Option<usize> {
+    UNDOING.get().copied()
+}
+
+pub fn log_path(credentials: &TelldusCredentials) -> Result<PathBuf, ConfigError> {
+    config::account_file(credentials, AUDIT_FILE)
+}
+
+/// Append `record` to the log of the account `credentials` belong to.
+///
+/// Failures are reported but do not fail the request being logged, which has already
+/// been sent.
+pub fn append(credentials: &TelldusCredentials, record: &Record) {
+    if !is_enabled() {
+        return;
+    }
+    let path = match log_path(credentials) {
+        Ok(path) => path,
+        Err(err) => return eprintln!("Warning: cannot write the audit log: {err}"),
+    };
+    let result = serde_json::to_string(record)
+        .map_err(io::Error::other)
+        .and_then(|line| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut options = fs::OpenOptions::new();
+            options.create(true).append(true);
+            // Parameters include phone numbers and notification texts.
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options
+                .open(&path)?
+                .write_all(format!("{line}\n").as_bytes())
+        });
+    if let Err(err) = result {
+        eprintln!("Warning: failed to write {}: {err}", path.display());
+    }
+}
+
+/// Every record logged for the account, oldest first; an absent log has none.
+pub fn read(credentials: &TelldusCredentials) -> Result<Vec<Record>, AuditError> {
+    let path = log_path(credentials)?;
+    let display = path.display().to_string();
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(AuditError::ReadFailed(display, err)),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|err| AuditError::ParseFailed(display.clone(), index + 1, err))
+        })
+        .collect()
+}
+
+/// The request that reverses a logged change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reversal {
+    TurnOn(String),
+    TurnOff(String),
+    Rename {
+        id: String,
+        name: String,
+    },
+    SetParameter {
+        id: String,
+        parameter: String,
+        value: String,
+    },
+    SetProtocol {
+        id: String,
+        protocol: String,
+    },
+    SetModel {
+        id: String,
+        model: String,
+    },
+    /// Add the removed device again; it gets a new id.
+    Recreate {
+        name: String,
+        settings: DeviceSettings,
+    },
+}
+
+impl Reversal {
+    pub fn describe(&self) -> String {
+        match self {
+            Reversal::TurnOn(id) => format!("turn device {id} back on"),
+            Reversal::TurnOff(id) => format!("turn device {id} back off"),
+            Reversal::Rename { id, name } => format!("rename device {id} back to '{name}'"),
+            Reversal::SetParameter {
+                id,
+                parameter,
+                value,
+            } => format!("set parameter {parameter} of device {id} back to '{value}'"),
+            Reversal::SetProtocol { id, protocol } => {
+                format!("set the protocol of device {id} back to {protocol}")
+            }
+            Reversal::SetModel { id, model } => {
+                format!("set the model of device {id} back to {model}")
+            }
+            Reversal::Recreate { name, settings } => format!(
+                "re-create '{name}' ({} {}) on client {} with {} parameter(s)",
+                settings.protocol,
+                settings.model,
+                settings.client_id,
+                settings.parameters.len()
+            ),
+        }
+    }
+}
+
+/// How to reverse `record`, if it succeeded and is one of the changes `undo` knows.
+pub fn reversal(record: &Record) -> Option<Reversal> {
+    if !record.succeeded() {
+        return None;
+    }
+    let id = record.parameter("id")?.to_string();
+    let previous = record.previous.as_ref();
+    let previous_field = |key: &str| previous?.get(key)?.as_str().map(str::to_string);
+    Some(match record.endpoint.as_str() {
+        "/json/device/turnOn" => Reversal::TurnOff(id),
+        "/json/device/turnOff" => Reversal::TurnOn(id),
+        "/json/device/setName" => Reversal::Rename {
+            name: previous_field("name")?,
+            id,
+        },
+        "/json/device/setProtocol" => Reversal::SetProtocol {
+            protocol: previous_field("protocol")?,
+            id,
+        },
+        "/json/device/setModel" => Reversal::SetModel {
+            model: previous_field("model")?,
+            id,
+        },
+        "/json/device/setDeviceParameter" => {
+            let parameter = record.parameter("parameter")?.to_string();
+            let settings = DeviceSettings::from_info(&id, previous?).ok()?;
+            let value = settings
+                .parameters
+                .into_iter()
+                .find(|(name, _)| *name == parameter)?
+                .1;
+            Reversal::SetParameter {
+                id,
+                parameter,
+                value,
+            }
+        }
+        "/json/device/remove" => Reversal::Recreate {
+            name: previous_field("name")?,
+            settings: DeviceSettings::from_info(&id, previous?).ok()?,
+        },
+        _ => return None,
+    })
+}
+
+/// The newest change that can be undone and has not been yet, with its position in
+/// `records`. Requests sent by `undo` itself are never undone in turn.
+pub fn last_undoable(records: &[Record]) -> Option<(usize, Reversal)> {
+    records
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, record)| record.undoes.is_none())
+        .filter(|(index, _)| !records.iter().any(|record| record.undoes == Some(*index)))
+        .find_map(|(index, record)| Some((index, reversal(record)?)))
+}
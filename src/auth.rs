@@ -1,15 +1,18 @@
-use crate::{config::TelldusCredentials, http_client::build_http_client};
+use crate::{certificate, config::TelldusCredentials, http_client::shared_client, service};
 use dialoguer::Input;
+use native_tls::TlsAcceptor;
 use reqwest::StatusCode;
 use reqwest::blocking::Client;
 use reqwest_oauth1::{Error as OAuth1Error, OAuthClientProvider, Secrets};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::io::{Read, Write};
-use std::net::TcpListener;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, OnceLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use url::Url;
 
@@ -18,6 +21,15 @@ const AUTHORIZE_URL: &str = "https://pa-api.telldus.com/oauth/authorize";
 const ACCESS_TOKEN_URL: &str = "https://pa-api.telldus.com/oauth/accessToken";
 const PROFILE_URL: &str = "https://pa-api.telldus.com/json/user/profile";
 
+/// Record a successful verification at most this often, so commands don't rewrite the file each run.
+const VERIFIED_RECORD_INTERVAL: u64 = 3600;
+/// Tokens at least this old get a re-authorization hint when Telldus Live rejects them.
+const OLD_TOKEN_AGE: u64 = 365 * 86_400;
+/// How often the OAuth redirect wait checks for Ctrl-C.
+const CALLBACK_POLL: Duration = Duration::from_millis(100);
+
+static CALLBACK: OnceLock<CallbackOptions> = OnceLock::new();
+
 #[derive(Debug, Error)]
 pub enum AuthError {
     #[error("consumer keys are required before authenticating")]
@@ -46,6 +58,24 @@ pub enum AuthError {
     Io(#[from] std::io::Error),
     #[error("prompt failed")]
     Prompt(#[from] dialoguer::Error),
+    #[error("interrupted while waiting for the authorization")]
+    Interrupted,
+}
+
+/// How the local listener receives the OAuth redirect.
+#[derive(Debug, Clone, Default)]
+pub struct CallbackOptions {
+    /// Serve TLS with an ephemeral self-signed certificate.
+    pub https: bool,
+    /// Address to bind; defaults to a random port on 127.0.0.1.
+    pub listen: Option<SocketAddr>,
+    /// Public URL Telldus Live redirects to, such as a reverse proxy or tunnel that
+    /// forwards to `listen`; defaults to the listener's own address.
+    pub url: Option<Url>,
+}
+
+pub fn configure_callback(options: CallbackOptions) {
+    let _ = CALLBACK.set(options);
 }
 
 pub struct AuthOutcome {
@@ -54,7 +84,7 @@ pub struct AuthOutcome {
 }
 
 pub fn validate(credentials: &mut TelldusCredentials) -> Result<AuthOutcome, AuthError> {
-    let client = build_http_client()?;
+    let client = shared_client()?;
     validate_with_client(&client, credentials)
 }
 
@@ -68,31 +98,87 @@ pub fn validate_with_client(
     let mut refreshed = false;
 
     if credentials.token.trim().is_empty() || credentials.token_secret.trim().is_empty() {
-        let (token, secret) = oauth_dance(client, credentials)?;
-        credentials.token = token;
-        credentials.token_secret = secret;
+        authorize(client, credentials)?;
         refreshed = true;
     }
 
-    match verify_profile(client, credentials) {
-        Ok(name) => Ok(AuthOutcome {
-            tokens_refreshed: refreshed,
-            account_name: name,
-        }),
+    let name = match verify_profile(client, credentials) {
+        Ok(name) => name,
         Err(AuthError::Unauthorized) => {
-            println!("Stored tokens were rejected by Telldus Live; starting OAuth flow.");
-            let (token, secret) = oauth_dance(client, credentials)?;
-            credentials.token = token;
-            credentials.token_secret = secret;
+            eprintln!("Stored tokens were rejected by Telldus Live; starting OAuth flow.");
+            if let Some(hint) = old_token_hint(credentials, unix_now()) {
+                eprintln!("{hint}");
+            }
+            authorize(client, credentials)?;
             refreshed = true;
-            let name = verify_profile(client, credentials)?;
-            Ok(AuthOutcome {
-                tokens_refreshed: refreshed,
-                account_name: name,
-            })
+            verify_profile(client, credentials)?
         }
-        Err(err) => Err(err),
+        Err(err) => return Err(err),
+    };
+    let now = unix_now();
+    if credentials
+        .last_verified
+        .is_none_or(|at| now.saturating_sub(at) >= VERIFIED_RECORD_INTERVAL)
+    {
+        credentials.last_verified = Some(now);
     }
+    Ok(AuthOutcome {
+        tokens_refreshed: refreshed,
+        account_name: name,
+    })
+}
+
+/// Check the stored token against Telldus Live without starting the OAuth flow,
+/// recording the time when it is accepted.
+pub fn verify(
+    client: &Client,
+    credentials: &mut TelldusCredentials,
+) -> Result<Option<String>, AuthError> {
+    let name = verify_profile(client, credentials)?;
+    credentials.last_verified = Some(unix_now());
+    Ok(name)
+}
+
+/// Replace a token Telldus Live rejected by running the OAuth flow with the stored consumer keys.
+pub fn reauthorize(
+    client: &Client,
+    credentials: &mut TelldusCredentials,
+) -> Result<Option<String>, AuthError> {
+    if credentials.public_key.trim().is_empty() || credentials.private_key.trim().is_empty() {
+        return Err(AuthError::MissingConsumerKeys);
+    }
+    if let Some(hint) = old_token_hint(credentials, unix_now()) {
+        eprintln!("{hint}");
+    }
+    authorize(client, credentials)?;
+    verify(client, credentials)
+}
+
+/// A hint that a rejected token may simply have aged out.
+pub fn old_token_hint(credentials: &TelldusCredentials, now: u64) -> Option<String> {
+    let age = now.saturating_sub(credentials.token_created?);
+    (age >= OLD_TOKEN_AGE).then(|| {
+        format!(
+            "The access token is {} days old; Telldus Live may have revoked it, and re-authorizing issues a new one.",
+            age / 86_400
+        )
+    })
+}
+
+fn authorize(client: &Client, credentials: &mut TelldusCredentials) -> Result<(), AuthError> {
+    let (token, secret) = oauth_dance(client, credentials)?;
+    credentials.token = token;
+    credentials.token_secret = secret;
+    credentials.token_created = Some(unix_now());
+    credentials.last_verified = None;
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
 }
 
 fn oauth_dance(
@@ -107,9 +193,9 @@ fn oauth_dance(
         &callback.callback_url,
     )?;
     let authorize_url = format!("{AUTHORIZE_URL}?oauth_token={}", temp.token);
-    println!("Open this URL in your browser to authorize Telldus Live access:");
-    println!("{authorize_url}");
-    println!(
+    eprintln!("Open this URL in your browser to authorize Telldus Live access:");
+    eprintln!("{authorize_url}");
+    eprintln!(
         "After approving, Telldus Live redirects to {}.\n\
 If the CLI captures the redirect automatically, you can close the browser tab.\n\
 Otherwise, copy the full redirect URL (or the code shown) and paste it below.",
@@ -180,6 +266,15 @@ fn verify_profile(
     client: &Client,
     credentials: &TelldusCredentials,
 ) -> Result<Option<String>, AuthError> {
+    let value = fetch_profile(client, credentials)?;
+    Ok(account_name(&value))
+}
+
+/// Fetch the raw `user/profile` payload, treating Telldus error statuses as failures.
+pub fn fetch_profile(
+    client: &Client,
+    credentials: &TelldusCredentials,
+) -> Result<Value, AuthError> {
     let secrets = Secrets::new(&credentials.public_key, &credentials.private_key)
         .token(&credentials.token, &credentials.token_secret);
     let response = client.clone().oauth1(secrets).get(PROFILE_URL).send()?;
@@ -190,16 +285,13 @@ fn verify_profile(
 
     let value: Value = response.error_for_status()?.json()?;
     let status = value.get("status").and_then(|v| v.as_str());
-    let success = match status {
-        Some("success") | None => true,
-        _ => false,
-    };
+    let success = matches!(status, Some("success") | None);
 
     if !success {
         let extract = |key: &str| -> Option<String> {
-            value.get(key).and_then(|v| match v {
-                Value::String(s) => Some(s.clone()),
-                _ => Some(v.to_string()),
+            value.get(key).map(|v| match v {
+                Value::String(s) => s.clone(),
+                _ => v.to_string(),
             })
         };
 
@@ -213,8 +305,13 @@ fn verify_profile(
         )));
     }
 
-    let user_obj = value.get("user").unwrap_or(&value);
-    let account = user_obj.as_object().and_then(|map| {
+    Ok(value)
+}
+
+/// Compose a display name from a `user/profile` payload.
+pub fn account_name(value: &Value) -> Option<String> {
+    let user_obj = value.get("user").unwrap_or(value);
+    user_obj.as_object().and_then(|map| {
         let first = map
             .get("firstname")
             .and_then(|v| v.as_str())
@@ -243,9 +340,7 @@ fn verify_profile(
         } else {
             Some(composed)
         }
-    });
-
-    Ok(account)
+    })
 }
 
 #[derive(Clone)]
@@ -257,17 +352,55 @@ struct TempToken {
 struct CallbackServer {
     callback_url: String,
     receiver: mpsc::Receiver<Result<String, AuthError>>,
+    /// Tells the listener thread to stop once the redirect is no longer awaited.
+    stop: Arc<AtomicBool>,
 }
 
 impl CallbackServer {
     fn start() -> Result<Self, AuthError> {
-        let listener = TcpListener::bind(("127.0.0.1", 0))?;
-        let port = listener.local_addr()?.port();
-        let callback_url = format!("http://127.0.0.1:{port}/telltales/callback");
+        let options = CALLBACK.get().cloned().unwrap_or_default();
+        let listen = options
+            .listen
+            .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], 0)));
+        let listener = TcpListener::bind(listen).map_err(|err| {
+            AuthError::CallbackListener(format!("cannot listen on {listen}: {err}"))
+        })?;
+        let mut local = listener.local_addr()?;
+        if local.ip().is_unspecified() {
+            // Listening on every interface; the browser still reaches it on loopback.
+            local.set_ip(if local.is_ipv4() {
+                Ipv4Addr::LOCALHOST.into()
+            } else {
+                Ipv6Addr::LOCALHOST.into()
+            });
+        }
+        let acceptor = if options.https {
+            let host = match &options.url {
+                Some(url) => url.host_str().unwrap_or("localhost").to_string(),
+                None => local.ip().to_string(),
+            };
+            let identity = certificate::self_signed(&host).map_err(AuthError::CallbackListener)?;
+            Some(
+                TlsAcceptor::new(identity).map_err(|err| {
+                    AuthError::CallbackListener(format!("cannot set up TLS: {err}"))
+                })?,
+            )
+        } else {
+            None
+        };
+        let callback_url = match options.url {
+            Some(url) => url.to_string(),
+            None => {
+                let scheme = if acceptor.is_some() { "https" } else { "http" };
+                format!("{scheme}://{local}/telltales/callback")
+            }
+        };
         let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
 
+        let stopped = Arc::clone(&stop);
         thread::spawn(move || {
-            if let Err(err) = wait_for_callback(listener, tx.clone()) {
+            if let Err(err) = wait_for_callback(listener, acceptor, tx.clone(), &stopped) {
                 let _ = tx.send(Err(err));
             }
         });
@@ -275,68 +408,116 @@ impl CallbackServer {
         Ok(Self {
             callback_url,
             receiver: rx,
+            stop,
         })
     }
 
+    /// Wait up to `timeout` for the redirect, giving up early on Ctrl-C.
     fn wait_for_verifier(self, timeout: Duration) -> Result<Option<String>, AuthError> {
-        match self.receiver.recv_timeout(timeout) {
-            Ok(Ok(v)) => Ok(Some(v)),
-            Ok(Err(e)) => Err(e),
-            Err(RecvTimeoutError::Timeout) => Ok(None),
-            Err(RecvTimeoutError::Disconnected) => {
-                Err(AuthError::CallbackListener("channel closed".into()))
+        let deadline = Instant::now() + timeout;
+        loop {
+            if service::shutdown_requested() {
+                return Err(AuthError::Interrupted);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            match self.receiver.recv_timeout(remaining.min(CALLBACK_POLL)) {
+                Ok(Ok(v)) => return Ok(Some(v)),
+                Ok(Err(e)) => return Err(e),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(AuthError::CallbackListener("channel closed".into()));
+                }
             }
         }
     }
 }
 
+impl Drop for CallbackServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
 fn wait_for_callback(
     listener: TcpListener,
+    acceptor: Option<TlsAcceptor>,
     tx: Sender<Result<String, AuthError>>,
+    stop: &AtomicBool,
 ) -> Result<(), AuthError> {
-    match listener.accept() {
-        Ok((mut stream, _)) => {
-            let mut buffer = [0u8; 4096];
-            let read = stream.read(&mut buffer)?;
-            let request = String::from_utf8_lossy(&buffer[..read]);
-            let first_line = request.lines().next().unwrap_or_default();
-            let path = first_line.split_whitespace().nth(1).unwrap_or("/");
-            let parsed_url = Url::parse(&format!("http://localhost{path}")).map_err(|err| {
-                AuthError::CallbackListener(format!("failed to parse redirect URL: {err}"))
-            })?;
-
-            if let Some((_, value)) = parsed_url
-                .query_pairs()
-                .find(|(key, _)| key == "oauth_verifier")
-            {
-                let verifier = value.into_owned();
-                let response_body = "<html><body><h2>Telldus Live authorization complete.</h2>\
-<p>You can close this window and return to the Telltales CLI.</p></body></html>";
-                let response = format!(
-                    "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-                    response_body.len(),
-                    response_body
-                );
-                stream.write_all(response.as_bytes())?;
-                stream.flush()?;
-                let _ = tx.send(Ok(verifier));
-                Ok(())
-            } else {
-                let response_body = "<html><body><h2>Authorization error</h2>\
-<p>Missing oauth_verifier parameter. Please return to the CLI and try again.</p></body></html>";
-                let response = format!(
-                    "HTTP/1.1 400 Bad Request\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-                    response_body.len(),
-                    response_body
-                );
-                stream.write_all(response.as_bytes())?;
-                stream.flush()?;
-                Err(AuthError::VerifierNotFound)
+    // Non-blocking accepts let the thread notice `stop` and release the port.
+    listener.set_nonblocking(true)?;
+    loop {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                if stop.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+                thread::sleep(CALLBACK_POLL);
+                continue;
+            }
+            Err(err) => {
+                return Err(AuthError::CallbackListener(format!(
+                    "failed to accept connection: {err}"
+                )));
             }
+        };
+        stream.set_nonblocking(false)?;
+        match &acceptor {
+            None => return answer_callback(stream, &tx),
+            Some(acceptor) => match acceptor.accept(stream) {
+                Ok(stream) => return answer_callback(stream, &tx),
+                // Browsers drop the first connection to a self-signed certificate and
+                // reconnect once the user accepts it.
+                Err(_) => continue,
+            },
         }
-        Err(err) => Err(AuthError::CallbackListener(format!(
-            "failed to accept connection: {err}"
-        ))),
+    }
+}
+
+fn answer_callback(
+    mut stream: impl Read + Write,
+    tx: &Sender<Result<String, AuthError>>,
+) -> Result<(), AuthError> {
+    let mut buffer = [0u8; 4096];
+    let read = stream.read(&mut buffer)?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let first_line = request.lines().next().unwrap_or_default();
+    let path = first_line.split_whitespace().nth(1).unwrap_or("/");
+    let parsed_url = Url::parse(&format!("http://localhost{path}")).map_err(|err| {
+        AuthError::CallbackListener(format!("failed to parse redirect URL: {err}"))
+    })?;
+
+    if let Some((_, value)) = parsed_url
+        .query_pairs()
+        .find(|(key, _)| key == "oauth_verifier")
+    {
+        let verifier = value.into_owned();
+        let response_body = "<html><body><h2>Telldus Live authorization complete.</h2>\
+<p>You can close this window and return to the Telltales CLI.</p></body></html>";
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response_body.len(),
+            response_body
+        );
+        stream.write_all(response.as_bytes())?;
+        stream.flush()?;
+        let _ = tx.send(Ok(verifier));
+        Ok(())
+    } else {
+        let response_body = "<html><body><h2>Authorization error</h2>\
+<p>Missing oauth_verifier parameter. Please return to the CLI and try again.</p></body></html>";
+        let response = format!(
+            "HTTP/1.1 400 Bad Request\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response_body.len(),
+            response_body
+        );
+        stream.write_all(response.as_bytes())?;
+        stream.flush()?;
+        Err(AuthError::VerifierNotFound)
     }
 }
 
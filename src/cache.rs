@@ -0,0 +1,107 @@
+use crate::api::{ApiError, Category, TelldusApi};
+use crate::response_cache;
+use std::time::Duration;
+
+/// How long name resolution, `--match` and completions trust the account's cached device
+/// and sensor lists. Any change telltales sends drops them sooner.
+pub const NAME_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Resolve a numeric id or a device or sensor name to an id.
+///
+/// Names are looked up in the lists the response cache holds, when younger than
+/// [`NAME_TTL`]; a name missing from a cached list is looked up again on Telldus Live in
+/// case it belongs to something created since.
+pub fn resolve(
+    api: &TelldusApi,
+    kind: Category,
+    reference: &str,
+) -> Result<Option<String>, ApiError> {
+    let reference = reference.trim();
+    if !reference.is_empty() && reference.chars().all(|ch| ch.is_ascii_digit()) {
+        return Ok(Some(reference.to_string()));
+    }
+
+    let find = || -> Result<Option<String>, ApiError> {
+        Ok(entries(api, kind)?
+            .into_iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(reference))
+            .map(|(id, _)| id))
+    };
+    let hits = response_cache::hits();
+    let found = response_cache::with_ttl(NAME_TTL, find)?;
+    if found.is_some() || response_cache::hits() == hits {
+        return Ok(found);
+    }
+    response_cache::refreshing(find)
+}
+
+/// Names of the account's devices or sensors, from a cached list when one is recent enough.
+pub fn names(api: &TelldusApi, kind: Category) -> Result<Vec<String>, ApiError> {
+    let entries = response_cache::with_ttl(NAME_TTL, || entries(api, kind))?;
+    Ok(entries.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Whether device `id` is a group, going by the cached device list.
+pub fn is_group(api: &TelldusApi, id: &str) -> Result<bool, ApiError> {
+    let groups = response_cache::with_ttl(NAME_TTL, || api.list_groups())?;
+    Ok(groups.iter().any(|group| group.id == id))
+}
+
+/// `ids` paired with their device names from a cached list; unknown ids get no name.
+pub fn device_names(
+    api: &TelldusApi,
+    ids: Vec<String>,
+) -> Result<Vec<(String, Option<String>)>, ApiError> {
+    let entries = response_cache::with_ttl(NAME_TTL, || entries(api, Category::Device))?;
+    Ok(ids
+        .into_iter()
+        .map(|id| {
+            let name = entries
+                .iter()
+                .find(|(entry, _)| *entry == id)
+                .map(|(_, name)| name.clone());
+            (id, name)
+        })
+        .collect())
+}
+
+/// Match `text` against a shell-style pattern where `*` matches any run of characters
+/// and `?` matches exactly one; comparison ignores case.
+pub fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(ch) if *ch == '?' || *ch == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|ch| *ch == '*')
+}
+
+fn entries(api: &TelldusApi, kind: Category) -> Result<Vec<(String, String)>, ApiError> {
+    let entries = match kind {
+        Category::Sensor => api.list_sensors(false)?,
+        _ => api.list_devices()?,
+    };
+    Ok(entries
+        .into_iter()
+        .map(|entry| (entry.id, entry.name))
+        .collect())
+}
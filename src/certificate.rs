@@ -0,0 +1,169 @@
+use crate::schedule::utc_time;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use native_tls::Identity;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair};
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OID: u8 = 0x06;
+const UTF8_STRING: u8 = 0x0c;
+const UTC_TIME: u8 = 0x17;
+
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// Margin either side of now, so small clock differences don't invalidate the certificate.
+const VALIDITY: u64 = 86_400;
+
+/// A fresh key pair and self-signed certificate valid for `host`, a DNS name or IP address,
+/// for the local OAuth callback listener.
+///
+/// The certificate is built by hand as DER: an ECDSA P-256 key from ring, a subject
+/// alternative name for the callback host, and a validity of a day either side of now.
+pub fn self_signed(host: &str) -> Result<Identity, String> {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+        .map_err(|_| "cannot generate a key pair".to_string())?;
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+        .map_err(|_| "cannot load the generated key pair".to_string())?;
+
+    let mut serial = [0u8; 16];
+    rng.fill(&mut serial)
+        .map_err(|_| "cannot generate a serial number".to_string())?;
+    // Serial numbers are positive integers.
+    serial[0] &= 0x7f;
+    serial[0] |= 0x01;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let name = tlv(
+        SEQUENCE,
+        &tlv(
+            SET,
+            &tlv(
+                SEQUENCE,
+                &[tlv(OID, OID_COMMON_NAME), tlv(UTF8_STRING, host.as_bytes())].concat(),
+            ),
+        ),
+    );
+    let algorithm = tlv(SEQUENCE, &tlv(OID, OID_ECDSA_SHA256));
+    let public_key = tlv(
+        SEQUENCE,
+        &[
+            tlv(
+                SEQUENCE,
+                &[tlv(OID, OID_EC_PUBLIC_KEY), tlv(OID, OID_P256)].concat(),
+            ),
+            bit_string(key.public_key().as_ref()),
+        ]
+        .concat(),
+    );
+    let alt_name = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => tlv(0x87, &ip.octets()),
+        Ok(IpAddr::V6(ip)) => tlv(0x87, &ip.octets()),
+        Err(_) => tlv(0x82, host.as_bytes()),
+    };
+    let extensions = tlv(
+        0xa3,
+        &tlv(
+            SEQUENCE,
+            &tlv(
+                SEQUENCE,
+                &[
+                    tlv(OID, OID_SUBJECT_ALT_NAME),
+                    tlv(OCTET_STRING, &tlv(SEQUENCE, &alt_name)),
+                ]
+                .concat(),
+            ),
+        ),
+    );
+    let tbs = tlv(
+        SEQUENCE,
+        &[
+            // Version 3, needed for extensions.
+            tlv(0xa0, &tlv(INTEGER, &[2])),
+            tlv(INTEGER, &serial),
+            algorithm.clone(),
+            name.clone(),
+            tlv(
+                SEQUENCE,
+                &[utc(now.saturating_sub(VALIDITY)), utc(now + VALIDITY)].concat(),
+            ),
+            name,
+            public_key,
+            extensions,
+        ]
+        .concat(),
+    );
+    let signature = key
+        .sign(&rng, &tbs)
+        .map_err(|_| "cannot sign the certificate".to_string())?;
+    let certificate = tlv(
+        SEQUENCE,
+        &[tbs, algorithm, bit_string(signature.as_ref())].concat(),
+    );
+
+    Identity::from_pkcs8(
+        pem("CERTIFICATE", &certificate).as_bytes(),
+        pem("PRIVATE KEY", pkcs8.as_ref()).as_bytes(),
+    )
+    .map_err(|err| format!("cannot load the certificate: {err}"))
+}
+
+/// DER encoding of one value: tag, definite length, contents.
+fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    let length = contents.len();
+    if length < 0x80 {
+        encoded.push(length as u8);
+    } else {
+        let bytes = length.to_be_bytes();
+        let significant = &bytes[bytes.iter().take_while(|byte| **byte == 0).count()..];
+        encoded.push(0x80 | significant.len() as u8);
+        encoded.extend_from_slice(significant);
+    }
+    encoded.extend_from_slice(contents);
+    encoded
+}
+
+fn bit_string(bytes: &[u8]) -> Vec<u8> {
+    // No unused bits in the last byte.
+    tlv(BIT_STRING, &[&[0], bytes].concat())
+}
+
+fn utc(timestamp: u64) -> Vec<u8> {
+    let time = utc_time(timestamp);
+    let text = format!(
+        "{:02}{:02}{:02}{:02}{:02}{:02}Z",
+        time.year % 100,
+        time.month,
+        time.day,
+        time.hour,
+        time.minute,
+        timestamp % 60
+    );
+    tlv(UTC_TIME, text.as_bytes())
+}
+
+fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = BASE64.encode(der);
+    let mut text = format!("-----BEGIN {label}-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        text.push_str(&String::from_utf8_lossy(line));
+        text.push('\n');
+    }
+    text.push_str(&format!("-----END {label}-----\n"));
+    text
+}
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The last value printed per device, sensor or rule, with when it was printed, so
+/// polling commands stay quiet until something changes.
+#[derive(Debug)]
+pub struct Changes<V> {
+    last: HashMap<String, (V, Instant)>,
+    heartbeat: Option<Duration>,
+}
+
+impl<V: PartialEq> Changes<V> {
+    /// Track printed values; with a `heartbeat`, unchanged values are printed again
+    /// once that long has passed, to show the poller is still alive.
+    pub fn new(heartbeat: Option<Duration>) -> Self {
+        Self {
+            last: HashMap::new(),
+            heartbeat,
+        }
+    }
+
+    /// Whether `value` should be printed for `key`, remembering it if so: it is the
+    /// first value, differs from the last printed one, or the heartbeat is due.
+    pub fn update(&mut self, key: &str, value: V) -> bool {
+        let now = Instant::now();
+        if let Some((last, printed)) = self.last.get(key)
+            && *last == value
+            && self
+                .heartbeat
+                .is_none_or(|heartbeat| now.duration_since(*printed) < heartbeat)
+        {
+            return false;
+        }
+        self.last.insert(key.to_string(), (value, now));
+        true
+    }
+
+    /// Forget `key`, so that whatever it reports next is printed.
+    pub fn forget(&mut self, key: &str) {
+        self.last.remove(key);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::api::Category;
+use clap::{Arg, Command, ValueEnum};
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+const BASH: &str = r#"_telltales() {
+    local IFS=$'\n'
+    COMPREPLY=($(telltales __complete -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+    COMPREPLY=("${COMPREPLY[@]// /\\ }")
+}
+complete -o default -F _telltales telltales
+"#;
+
+const ZSH: &str = r#"_telltales() {
+    local -a candidates
+    candidates=("${(@f)$(telltales __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null)}")
+    compadd -a candidates
+}
+compdef _telltales telltales
+"#;
+
+const FISH: &str = r#"complete -c telltales -f -a '(telltales __complete -- (commandline -opc)[2..-1] (commandline -ct) 2>/dev/null)'
+"#;
+
+/// A script that makes `shell` ask `telltales __complete` for candidates.
+pub fn script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+    }
+}
+
+/// Candidates for the last of `words`, the command line after `telltales`.
+///
+/// `command` must be built so global options show up on every subcommand. Values of
+/// `--id` options naming devices or sensors come from `names`, which is only called then.
+pub fn candidates(
+    command: &Command,
+    words: &[String],
+    names: impl FnOnce(Category) -> Vec<String>,
+) -> Vec<String> {
+    let (current, done) = match words.split_last() {
+        Some((current, done)) => (current.as_str(), done),
+        None => ("", words),
+    };
+    let mut command = command;
+    let mut expecting: Option<&Arg> = None;
+    for word in done {
+        if expecting.take().is_some() {
+            continue;
+        }
+        if let Some(long) = word.strip_prefix("--") {
+            expecting = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(long))
+                .filter(|arg| arg.get_action().takes_values());
+        } else if let Some(short) = word
+            .strip_prefix('-')
+            .and_then(|short| short.chars().next())
+        {
+            expecting = command
+                .get_arguments()
+                .find(|arg| arg.get_short() == Some(short))
+                .filter(|arg| arg.get_action().takes_values() && word.len() == 2);
+        } else if let Some(subcommand) = command.find_subcommand(word) {
+            command = subcommand;
+        }
+    }
+
+    let candidates: Vec<String> = match expecting {
+        Some(arg) => match arg.get_id().as_str() {
+            "device_id" => names(Category::Device),
+            "sensor_id" => names(Category::Sensor),
+            _ => arg
+                .get_possible_values()
+                .iter()
+                .filter(|value| !value.is_hide_set())
+                .map(|value| value.get_name().to_string())
+                .collect(),
+        },
+        None if current.starts_with('-') => command
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .filter_map(|arg| arg.get_long().map(|long| format!("--{long}")))
+            .collect(),
+        None => command
+            .get_subcommands()
+            .filter(|subcommand| !subcommand.is_hide_set())
+            .map(|subcommand| subcommand.get_name().to_string())
+            .collect(),
+    };
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(current))
+        .collect()
+}
@@ -1,13 +1,34 @@
+use crate::fixtures;
+use crate::lock::FileLock;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use dialoguer::{Input, Password};
 use dirs::home_dir;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io;
+use std::io::{self, Write};
+use std::num::NonZeroU32;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use thiserror::Error;
 
 const CONFIG_SUBDIR: &str = ".config/telltales";
 const CONFIG_FILE: &str = "credentials.yaml";
+const PROFILES_DIR: &str = "profiles";
+const ACCOUNTS_DIR: &str = "accounts";
+const KDF: &str = "pbkdf2-sha256";
+const CIPHER: &str = "aes-256-gcm";
+/// PBKDF2 work factor for newly encrypted files; stored alongside so it can be raised later.
+const KDF_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+
+static CREDENTIALS_FILE: OnceLock<PathBuf> = OnceLock::new();
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+static ALLOW_INSECURE: AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug, Error)]
 pub enum ConfigError {
@@ -25,15 +46,39 @@ pub enum ConfigError {
     WriteFailed(String, #[source] io::Error),
     #[error(transparent)]
     PromptFailed(#[from] dialoguer::Error),
+    #[error(
+        "{0} is readable by other users (mode {1:o}); run `chmod 600 {0}` or pass --insecure-config"
+    )]
+    Insecure(String, u32),
+    #[error("cannot decrypt {0}: wrong passphrase or damaged file")]
+    DecryptFailed(String),
+    #[error("{0} uses unsupported encryption {1}")]
+    UnsupportedEncryption(String, String),
+    #[error("failed to encrypt credentials")]
+    EncryptFailed,
+    #[error("no credentials file at {0}")]
+    NoCredentials(String),
+    #[error("{0} is already encrypted")]
+    AlreadyEncrypted(String),
+    #[error("{0} is not encrypted")]
+    NotEncrypted(String),
+    #[error("cannot lock {0}: {1}")]
+    LockFailed(String, #[source] io::Error),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct TelldusCredentials {
     pub public_key: String,
     pub private_key: String,
     pub token: String,
     pub token_secret: String,
+    /// When the OAuth access token was issued, as Unix seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_created: Option<u64>,
+    /// When Telldus Live last accepted the token, as Unix seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_verified: Option<u64>,
 }
 
 impl TelldusCredentials {
@@ -53,40 +98,340 @@ impl TelldusCredentials {
     }
 }
 
+/// On-disk form of credentials protected by a passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedFile {
+    encrypted: Sealed,
+}
+
+/// Credentials YAML sealed with a key derived from the passphrase; binary fields are base64.
+#[derive(Debug, Serialize, Deserialize)]
+struct Sealed {
+    kdf: String,
+    iterations: u32,
+    cipher: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
 pub fn ensure_credentials() -> Result<TelldusCredentials, ConfigError> {
-    let mut creds = load_credentials()?.unwrap_or_default();
-    if !creds.is_complete() {
-        prompt_for_missing(&mut creds)?;
-        save_credentials(&creds)?;
-    }
+    let (creds, ()) = update_credentials_at(&credentials_path_internal()?, |creds| {
+        if !creds.is_complete() {
+            prompt_for_missing(creds)?;
+        }
+        Ok::<_, ConfigError>(())
+    })?;
     Ok(creds)
 }
 
-fn load_credentials() -> Result<Option<TelldusCredentials>, ConfigError> {
-    let path = credentials_path_internal()?;
+/// Read the credentials at `path`, let `update` change them, and write them back if it
+/// did, all while holding the file's lock.
+///
+/// Callers that refresh tokens must go through here rather than load and save
+/// separately: another telltales process may store a new token in between, and saving
+/// an older copy would throw it away. Nothing is written when `update` fails.
+pub fn update_credentials_at<T, E: From<ConfigError>>(
+    path: &Path,
+    update: impl FnOnce(&mut TelldusCredentials) -> Result<T, E>,
+) -> Result<(TelldusCredentials, T), E> {
+    let _lock = lock_credentials(path)?;
+    let original = load_credentials_from(path)?.unwrap_or_default();
+    let mut credentials = original.clone();
+    let value = update(&mut credentials)?;
+    if credentials != original {
+        write_credentials(path, &credentials)?;
+    }
+    Ok((credentials, value))
+}
+
+/// Like [`update_credentials_at`], for updates that may run the interactive OAuth flow.
+///
+/// `update` works on a copy without holding the lock, since authorizing in a browser can
+/// take minutes; the lock is only taken to write the resulting token into whatever the
+/// file holds by then.
+pub fn refresh_credentials_at<T, E: From<ConfigError>>(
+    path: &Path,
+    update: impl FnOnce(&mut TelldusCredentials) -> Result<T, E>,
+) -> Result<(TelldusCredentials, T), E> {
+    let original = load_credentials_from(path)?.unwrap_or_default();
+    let mut credentials = original.clone();
+    let value = update(&mut credentials)?;
+    if credentials == original {
+        return Ok((credentials, value));
+    }
+    let (stored, ()) = update_credentials_at(path, |stored| {
+        stored.token = credentials.token;
+        stored.token_secret = credentials.token_secret;
+        stored.token_created = credentials.token_created;
+        stored.last_verified = credentials.last_verified;
+        Ok::<_, E>(())
+    })?;
+    Ok((stored, value))
+}
+
+pub fn load_credentials() -> Result<Option<TelldusCredentials>, ConfigError> {
+    load_credentials_from(&credentials_path_internal()?)
+}
+
+pub fn load_credentials_from(path: &Path) -> Result<Option<TelldusCredentials>, ConfigError> {
     if !path.exists() {
         return Ok(None);
     }
+    check_permissions(path)?;
 
-    let contents = fs::read_to_string(&path)
-        .map_err(|err| ConfigError::ReadFailed(display_path(&path), err))?;
-    let parsed = serde_yaml::from_str(&contents)
-        .map_err(|err| ConfigError::ParseFailed(display_path(&path), err))?;
+    let contents =
+        fs::read_to_string(path).map_err(|err| ConfigError::ReadFailed(display_path(path), err))?;
+    let parsed = if is_encrypted(&contents) {
+        let file: EncryptedFile = serde_yaml::from_str(&contents)
+            .map_err(|err| ConfigError::ParseFailed(display_path(path), err))?;
+        let plaintext = open(path, &file.encrypted)?;
+        serde_yaml::from_slice(&plaintext)
+    } else {
+        serde_yaml::from_str(&contents)
+    }
+    .map_err(|err| ConfigError::ParseFailed(display_path(path), err))?;
     Ok(Some(parsed))
 }
 
-pub fn save_credentials(credentials: &TelldusCredentials) -> Result<(), ConfigError> {
-    let dir = config_dir()?;
-    fs::create_dir_all(&dir)
-        .map_err(|err| ConfigError::CreateDirFailed(display_path(&dir), err))?;
+fn write_credentials(path: &Path, credentials: &TelldusCredentials) -> Result<(), ConfigError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|err| ConfigError::CreateDirFailed(display_path(dir), err))?;
+    }
+
+    let mut yaml = serde_yaml::to_string(credentials).map_err(ConfigError::SerializeFailed)?;
+    // Keep an encrypted file encrypted when refreshed tokens are written back.
+    let encrypted = fs::read_to_string(path).is_ok_and(|contents| is_encrypted(&contents));
+    if encrypted {
+        yaml = seal_file(path, yaml.as_bytes(), false)?;
+    }
+    write_private(path, yaml.as_bytes())
+        .map_err(|err| ConfigError::WriteFailed(display_path(path), err))?;
+
+    Ok(())
+}
+
+/// Rewrite the plaintext credentials file at `path` encrypted with a passphrase.
+pub fn encrypt_credentials_file(path: &Path) -> Result<(), ConfigError> {
+    let _lock = lock_credentials(path)?;
+    let contents = read_existing(path)?;
+    if is_encrypted(&contents) {
+        return Err(ConfigError::AlreadyEncrypted(display_path(path)));
+    }
+    check_permissions(path)?;
+    let sealed = seal_file(path, contents.as_bytes(), true)?;
+    write_private(path, sealed.as_bytes())
+        .map_err(|err| ConfigError::WriteFailed(display_path(path), err))
+}
+
+/// Rewrite the encrypted credentials file at `path` as plaintext YAML.
+pub fn decrypt_credentials_file(path: &Path) -> Result<(), ConfigError> {
+    let _lock = lock_credentials(path)?;
+    let contents = read_existing(path)?;
+    if !is_encrypted(&contents) {
+        return Err(ConfigError::NotEncrypted(display_path(path)));
+    }
+    let credentials = load_credentials_from(path)?
+        .ok_or_else(|| ConfigError::NoCredentials(display_path(path)))?;
+    let yaml = serde_yaml::to_string(&credentials).map_err(ConfigError::SerializeFailed)?;
+    write_private(path, yaml.as_bytes())
+        .map_err(|err| ConfigError::WriteFailed(display_path(path), err))
+}
+
+/// Use `passphrase` for encrypted credentials instead of prompting for it.
+pub fn set_passphrase(passphrase: String) {
+    let _ = PASSPHRASE.set(passphrase);
+}
+
+fn read_existing(path: &Path) -> Result<String, ConfigError> {
+    fs::read_to_string(path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => ConfigError::NoCredentials(display_path(path)),
+        _ => ConfigError::ReadFailed(display_path(path), err),
+    })
+}
+
+fn is_encrypted(contents: &str) -> bool {
+    serde_yaml::from_str::<serde_yaml::Value>(contents)
+        .is_ok_and(|value| value.get("encrypted").is_some())
+}
+
+/// The passphrase given up front, or one prompted for once per run.
+fn passphrase(path: &Path, confirm: bool) -> Result<&'static str, ConfigError> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase);
+    }
+    let mut prompt = Password::new().with_prompt(format!("Passphrase for {}", display_path(path)));
+    if confirm {
+        prompt = prompt.with_confirmation("Confirm passphrase", "Passphrases do not match.");
+    }
+    let entered = prompt.interact()?;
+    Ok(PASSPHRASE.get_or_init(|| entered))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: NonZeroU32) -> LessSafeKey {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("AES-256 keys are 32 bytes"))
+}
+
+/// Encrypt `plaintext` into the YAML of an encrypted credentials file.
+fn seal_file(path: &Path, plaintext: &[u8], confirm: bool) -> Result<String, ConfigError> {
+    let passphrase = passphrase(path, confirm)?;
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|()| rng.fill(&mut nonce))
+        .map_err(|_| ConfigError::EncryptFailed)?;
+    let iterations = NonZeroU32::new(KDF_ITERATIONS).expect("iteration count is non-zero");
+    let mut ciphertext = plaintext.to_vec();
+    derive_key(passphrase, &salt, iterations)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut ciphertext,
+        )
+        .map_err(|_| ConfigError::EncryptFailed)?;
+    let file = EncryptedFile {
+        encrypted: Sealed {
+            kdf: KDF.into(),
+            iterations: KDF_ITERATIONS,
+            cipher: CIPHER.into(),
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        },
+    };
+    serde_yaml::to_string(&file).map_err(ConfigError::SerializeFailed)
+}
+
+fn open(path: &Path, sealed: &Sealed) -> Result<Vec<u8>, ConfigError> {
+    if sealed.kdf != KDF || sealed.cipher != CIPHER {
+        return Err(ConfigError::UnsupportedEncryption(
+            display_path(path),
+            format!("{}/{}", sealed.kdf, sealed.cipher),
+        ));
+    }
+    let damaged = || ConfigError::DecryptFailed(display_path(path));
+    let iterations = NonZeroU32::new(sealed.iterations).ok_or_else(damaged)?;
+    let salt = BASE64.decode(&sealed.salt).map_err(|_| damaged())?;
+    let nonce = BASE64
+        .decode(&sealed.nonce)
+        .ok()
+        .and_then(|nonce| Nonce::try_assume_unique_for_key(&nonce).ok())
+        .ok_or_else(damaged)?;
+    let mut ciphertext = BASE64.decode(&sealed.ciphertext).map_err(|_| damaged())?;
+    let passphrase = passphrase(path, false)?;
+    let plaintext = derive_key(passphrase, &salt, iterations)
+        .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+        .map_err(|_| damaged())?;
+    Ok(plaintext.to_vec())
+}
+
+/// Lock the credentials at `path` against other telltales processes writing them, using
+/// `<file>.lock` beside it. This is separate from the `--exclusive` run lock, which
+/// the same process may already hold.
+fn lock_credentials(path: &Path) -> Result<FileLock, ConfigError> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    let lock_path = path.with_file_name(name);
+    FileLock::acquire(&lock_path, || {
+        eprintln!(
+            "Waiting for another telltales process to finish updating {}...",
+            display_path(path)
+        );
+    })
+    .map_err(|err| ConfigError::LockFailed(display_path(&lock_path), err))
+}
+
+/// Replace `path` with `contents`, readable only by its owner. The data goes to a
+/// temporary file that is renamed into place, so readers never see a partial file.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(name);
 
-    let path = dir.join(CONFIG_FILE);
-    let yaml = serde_yaml::to_string(credentials).map_err(ConfigError::SerializeFailed)?;
-    fs::write(&path, yaml).map_err(|err| ConfigError::WriteFailed(display_path(&path), err))?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let result = options
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
 
+/// Load credentials even when other users can read the file.
+pub fn allow_insecure(allow: bool) {
+    ALLOW_INSECURE.store(allow, Ordering::Relaxed);
+}
+
+/// Refuse a world-readable credentials file and warn about a group-readable one.
+fn check_permissions(path: &Path) -> Result<(), ConfigError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)
+            .map_err(|err| ConfigError::ReadFailed(display_path(path), err))?
+            .permissions()
+            .mode()
+            & 0o777;
+        if mode & 0o007 != 0 && !ALLOW_INSECURE.load(Ordering::Relaxed) {
+            return Err(ConfigError::Insecure(display_path(path), mode));
+        }
+        if mode & 0o077 != 0 {
+            eprintln!(
+                "warning: {} is mode {mode:o}; run `chmod 600 {}` to keep secrets private",
+                display_path(path),
+                display_path(path)
+            );
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
     Ok(())
 }
 
+/// Additional accounts, one credentials file per profile in `profiles/<name>.yaml`,
+/// sorted by name.
+pub fn profiles() -> Result<Vec<(String, PathBuf)>, ConfigError> {
+    let dir = config_dir()?.join(PROFILES_DIR);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(ConfigError::ReadFailed(display_path(&dir), err)),
+    };
+    let mut profiles: Vec<(String, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "yaml" || extension == "yml")
+        })
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            Some((name, path))
+        })
+        .collect();
+    profiles.sort();
+    Ok(profiles)
+}
+
 fn prompt_for_missing(creds: &mut TelldusCredentials) -> Result<(), ConfigError> {
     println!(
         "Telldus Live credentials are required. Values are stored in {}.",
@@ -150,15 +495,51 @@ pub fn credentials_path() -> Result<PathBuf, ConfigError> {
     credentials_path_internal()
 }
 
-fn config_dir() -> Result<PathBuf, ConfigError> {
+pub fn config_dir() -> Result<PathBuf, ConfigError> {
     let home = home_dir().ok_or(ConfigError::MissingHomeDir)?;
     Ok(home.join(CONFIG_SUBDIR))
 }
 
+/// Directory for what telltales remembers about the account `credentials` belong to,
+/// so switching credentials files or profiles never mixes two accounts' state.
+///
+/// Accounts are told apart by their consumer key rather than the access token, which
+/// changes whenever the account is re-authorized.
+pub fn account_dir(credentials: &TelldusCredentials) -> Result<PathBuf, ConfigError> {
+    let account = fixtures::fnv1a(credentials.public_key.trim().as_bytes());
+    Ok(config_dir()?
+        .join(ACCOUNTS_DIR)
+        .join(format!("{account:016x}")))
+}
+
+/// The file `name` in the account's directory.
+///
+/// A file of that name left directly in the config directory by an older telltales is
+/// moved there the first time an account asks for it.
+pub fn account_file(credentials: &TelldusCredentials, name: &str) -> Result<PathBuf, ConfigError> {
+    let path = account_dir(credentials)?.join(name);
+    let legacy = config_dir()?.join(name);
+    if !path.exists()
+        && legacy.is_file()
+        && let Some(dir) = path.parent()
+    {
+        let _ = fs::create_dir_all(dir).and_then(|()| fs::rename(&legacy, &path));
+    }
+    Ok(path)
+}
+
 fn display_path(path: &Path) -> String {
     path.to_string_lossy().into_owned()
 }
 
+/// Read and write credentials at `path` instead of `~/.config/telltales/credentials.yaml`.
+pub fn set_credentials_file(path: PathBuf) {
+    let _ = CREDENTIALS_FILE.set(path);
+}
+
 fn credentials_path_internal() -> Result<PathBuf, ConfigError> {
+    if let Some(path) = CREDENTIALS_FILE.get() {
+        return Ok(path.clone());
+    }
     Ok(config_dir()?.join(CONFIG_FILE))
 }
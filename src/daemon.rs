@@ -0,0 +1,564 @@
+use crate::api::{ApiError, Category, TelldusApi};
+use crate::cache;
+use crate::config::{ConfigError, config_dir};
+use crate::filter::{self, Filter};
+use crate::metrics::{Sink, SinkConfig};
+use crate::output;
+use crate::schedule::{ExceptDate, LocalTime, ScheduleTime, Weekdays, local_time, unix_now};
+use crate::service;
+use crate::sun::{self, Location};
+use crate::vacation::{Plan, Vacation, VacationConfig};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+const SCHEDULES_FILE: &str = "schedules.yaml";
+/// Most minutes run late after jobs or a busy system held up the loop; older ones are
+/// skipped so a resumed laptop does not replay hours of switching.
+const MAX_CATCH_UP_MINUTES: u64 = 15;
+
+#[derive(Debug, Error)]
+pub enum DaemonError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("failed to read schedules {0}: {1}")]
+    ReadFailed(String, #[source] io::Error),
+    #[error("failed to parse schedules {0}: {1}")]
+    ParseFailed(String, #[source] serde_yaml::Error),
+    #[error("schedule '{0}': {1}")]
+    Invalid(String, String),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SchedulesFile {
+    jobs: Vec<JobConfig>,
+    metrics: Option<SinkConfig>,
+    vacation: Option<VacationConfig>,
+    location: Option<Location>,
+}
+
+/// Everything `schedules.yaml` asks the daemon to do.
+#[derive(Debug, Clone)]
+pub struct Schedules {
+    pub jobs: Vec<Job>,
+    /// Also poll sensors and send their readings here.
+    pub metrics: Option<Sink>,
+    /// Also switch these lights at random times to look occupied.
+    pub vacation: Option<Vacation>,
+    /// Where sunrise and sunset are computed for; the controller's location when unset.
+    pub location: Option<Location>,
+}
+
+impl Schedules {
+    /// Whether some job or vacation window follows sunrise or sunset and so needs a
+    /// location.
+    pub fn needs_location(&self) -> bool {
+        self.jobs
+            .iter()
+            .any(|job| matches!(job.trigger, Trigger::At(time) if sun::needs_location(time)))
+            || self.vacation.as_ref().is_some_and(Vacation::needs_location)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct JobConfig {
+    name: Option<String>,
+    cron: Option<String>,
+    /// A time of day instead of `cron`: `22:30`, `sunset-30m`, `30 minutes before sunset`.
+    at: Option<String>,
+    /// Only run on these days of the week, e.g. `weekdays` or `mon,wed,fri`.
+    days: Option<String>,
+    /// Skip these dates: `2024-12-24`, or `12-24` for every year.
+    #[serde(default)]
+    except: Vec<String>,
+    device: String,
+    action: JobAction,
+    level: Option<u8>,
+    only_if: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobAction {
+    On,
+    Off,
+    Dim,
+    Bell,
+    Up,
+    Down,
+    Stop,
+}
+
+/// Only run a job while another device is in the given state.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Condition {
+    pub device: String,
+    pub state: ConditionState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConditionState {
+    On,
+    Off,
+}
+
+/// A validated schedule entry from `schedules.yaml`.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: String,
+    /// The `cron` expression or `at` time as written.
+    pub when: String,
+    pub device: String,
+    pub action: JobAction,
+    pub level: Option<u8>,
+    pub only_if: Option<Condition>,
+    pub days: Option<Weekdays>,
+    pub except: Vec<ExceptDate>,
+    trigger: Trigger,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Trigger {
+    Cron(CronSchedule),
+    /// Every day at a clock time, or relative to sunrise or sunset.
+    At(ScheduleTime),
+}
+
+impl Job {
+    /// Whether the job runs at the local minute `time`, which starts at `timestamp`.
+    fn is_due(&self, time: &LocalTime, timestamp: u64, location: Option<Location>) -> bool {
+        let at_time = match self.trigger {
+            Trigger::Cron(schedule) => schedule.matches(time),
+            Trigger::At(at) => {
+                sun::minute_of_day(at, location, timestamp) == Some(time.hour * 60 + time.minute)
+            }
+        };
+        at_time
+            && self.days.as_ref().is_none_or(|days| days.includes(time))
+            && !self.except.iter().any(|date| date.matches(time))
+    }
+
+    /// When the job runs in words, e.g. `at sunset-30m on Mon-Fri, except 12-24`.
+    pub fn describe_when(&self) -> String {
+        let mut text = self.when.clone();
+        if let Some(days) = &self.days {
+            text.push_str(&format!(" {}", days.describe()));
+        }
+        if !self.except.is_empty() {
+            let dates: Vec<String> = self.except.iter().map(ToString::to_string).collect();
+            text.push_str(&format!(", except {}", dates.join(", ")));
+        }
+        text
+    }
+
+    /// Human-readable action, e.g. `on` or `dim 128`.
+    pub fn describe_action(&self) -> String {
+        match (self.action, self.level) {
+            (JobAction::Dim, Some(level)) => format!("dim {level}"),
+            (action, _) => format!("{action:?}").to_lowercase(),
+        }
+    }
+}
+
+/// Cron fields as bit sets; day of week uses 0 = Sunday.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "cron expression '{expression}' needs five fields: minute hour day month weekday"
+            ));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            // Both 0 and 7 mean Sunday.
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    fn matches(&self, time: &LocalTime) -> bool {
+        let has = |set: u64, value: u32| set & (1 << value) != 0;
+        let day = has(self.days, time.day);
+        let weekday = has(self.weekdays, time.weekday);
+        // As in cron, a restricted day of month and day of week match if either does.
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        };
+        has(self.minutes, time.minute)
+            && has(self.hours, time.hour)
+            && has(self.months, time.month)
+            && day_matches
+    }
+}
+
+/// Parse one cron field of comma-separated values, `a-b` ranges, `*`, and `/step` suffixes.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("invalid step in '{part}'"))?,
+            ),
+            None => (part, 1),
+        };
+        let value = |text: &str| {
+            text.parse::<u32>()
+                .ok()
+                .filter(|value| (min..=max).contains(value))
+                .ok_or_else(|| format!("'{text}' is outside {min}-{max}"))
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let single = value(range)?;
+                    (single, single)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("range '{range}' runs backwards"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+pub fn default_path() -> Result<PathBuf, DaemonError> {
+    Ok(config_dir()?.join(SCHEDULES_FILE))
+}
+
+/// Load and validate every job so mistakes surface before the daemon starts.
+pub fn load(path: &Path) -> Result<Schedules, DaemonError> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| DaemonError::ReadFailed(path.display().to_string(), err))?;
+    let file: SchedulesFile = serde_yaml::from_str(&text)
+        .map_err(|err| DaemonError::ParseFailed(path.display().to_string(), err))?;
+    let location = file
+        .location
+        .map(Location::validate)
+        .transpose()
+        .map_err(|err| DaemonError::Invalid("location".into(), err))?;
+
+    let metrics = file
+        .metrics
+        .map(Sink::from_config)
+        .transpose()
+        .map_err(|err| DaemonError::Invalid("metrics".into(), err))?;
+    let vacation = file
+        .vacation
+        .map(Vacation::from_config)
+        .transpose()
+        .map_err(|err| DaemonError::Invalid("vacation".into(), err))?;
+    let jobs = file
+        .jobs
+        .into_iter()
+        .enumerate()
+        .map(|(index, config)| {
+            let name = config
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("job {}", index + 1));
+            let invalid = |err| DaemonError::Invalid(name.clone(), err);
+            let (when, trigger) = match (config.cron, config.at) {
+                (Some(cron), None) => {
+                    let schedule = CronSchedule::parse(&cron).map_err(invalid)?;
+                    (cron, Trigger::Cron(schedule))
+                }
+                (None, Some(at)) => {
+                    let time = ScheduleTime::parse(&at).map_err(invalid)?;
+                    (format!("at {time}"), Trigger::At(time))
+                }
+                _ => return Err(invalid("set either cron or at".into())),
+            };
+            let days = config
+                .days
+                .as_deref()
+                .map(Weekdays::parse)
+                .transpose()
+                .map_err(invalid)?;
+            let except = config
+                .except
+                .iter()
+                .map(|date| ExceptDate::parse(date))
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?;
+            if config.action == JobAction::Dim && config.level.is_none() {
+                return Err(DaemonError::Invalid(
+                    name,
+                    "dim needs a level between 0 and 255".into(),
+                ));
+            }
+            Ok(Job {
+                name,
+                when,
+                device: config.device,
+                action: config.action,
+                level: config.level,
+                only_if: config.only_if,
+                days,
+                except,
+                trigger,
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Schedules {
+        jobs,
+        metrics,
+        vacation,
+        location,
+    })
+}
+
+/// A job run as printed by `daemon run --output ndjson`.
+#[derive(Debug, Serialize)]
+struct JobRun<'a> {
+    /// Unix timestamp in seconds of the minute the job was due.
+    timestamp: u64,
+    job: &'a str,
+    device: &'a str,
+    action: String,
+    /// `ran`, `skipped` (its condition was not met), or `failed`.
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<'a> JobRun<'a> {
+    fn new(
+        job: &'a str,
+        device: &'a str,
+        action: String,
+        timestamp: u64,
+        result: &Result<bool, ApiError>,
+    ) -> Self {
+        let (result, error) = match result {
+            Ok(true) => ("ran", None),
+            Ok(false) => ("skipped", None),
+            Err(err) => ("failed", Some(err.to_string())),
+        };
+        Self {
+            timestamp,
+            job,
+            device,
+            action,
+            result,
+            error,
+        }
+    }
+
+    /// The run with the fields every `--filter` can name.
+    fn record(&self) -> serde_json::Value {
+        let record = serde_json::to_value(self).unwrap_or_default();
+        filter::with_context(record, "device", self.device, self.device)
+    }
+}
+
+/// Run jobs whose schedule matches each local minute until shutdown is requested,
+/// sending sensor readings to the metrics sink in between when one is configured.
+///
+/// Minutes that pass while jobs run are caught up, up to [`MAX_CATCH_UP_MINUTES`]; a
+/// longer gap, such as after a suspend, is reported and skipped. A failing job or
+/// metrics send is reported and does not stop the others. With a `filter`, only matching
+/// job runs are printed.
+pub fn run(api: &TelldusApi, schedules: &Schedules, filter: Option<&Filter>) {
+    let mut last_minute = unix_now() / 60;
+    let mut next_metrics = unix_now();
+    let mut plan = Plan::default();
+    loop {
+        let now = unix_now();
+        if let Some(vacation) = &schedules.vacation {
+            run_vacation(api, vacation, &mut plan, schedules.location, now, filter);
+        }
+        let minute = now / 60;
+        if minute < last_minute {
+            // The clock was set back; carry on from the new time.
+            last_minute = minute;
+        }
+        let (due_minutes, skipped) = minutes_to_run(last_minute, minute);
+        if skipped > 0 {
+            eprintln!(
+                "[{}] skipped jobs due in the {skipped} minute(s) since {}",
+                local_time(now),
+                local_time((last_minute + 1) * 60)
+            );
+        }
+        for due in due_minutes {
+            run_jobs(api, schedules, filter, due * 60);
+        }
+        last_minute = minute;
+        if let Some(sink) = &schedules.metrics
+            && now >= next_metrics
+        {
+            next_metrics = now + sink.interval.as_secs();
+            let sent = api
+                .sensors(false)
+                .map_err(|err| err.to_string())
+                .and_then(|sensors| sink.send(&sensors, now).map_err(|err| err.to_string()));
+            if let Err(err) = sent {
+                eprintln!("[{}] metrics: {err}", local_time(now));
+            }
+        }
+        let mut wait = 60 - unix_now() % 60;
+        if schedules.metrics.is_some() {
+            wait = wait.min(next_metrics.saturating_sub(unix_now()).max(1));
+        }
+        if !service::sleep(Duration::from_secs(wait)) {
+            break;
+        }
+    }
+}
+
+/// The minutes after `last` up to `current` whose jobs still run, and how many minutes
+/// before them are skipped.
+fn minutes_to_run(last: u64, current: u64) -> (RangeInclusive<u64>, u64) {
+    let first = (last + 1).max(current.saturating_sub(MAX_CATCH_UP_MINUTES - 1));
+    (first..=current, first - (last + 1))
+}
+
+/// Run the jobs due in the local minute starting at `timestamp`.
+fn run_jobs(api: &TelldusApi, schedules: &Schedules, filter: Option<&Filter>, timestamp: u64) {
+    let time = local_time(timestamp);
+    for job in schedules
+        .jobs
+        .iter()
+        .filter(|job| job.is_due(&time, timestamp, schedules.location))
+    {
+        let result = run_job(api, job);
+        let record = JobRun::new(
+            &job.name,
+            &job.device,
+            job.describe_action(),
+            timestamp,
+            &result,
+        )
+        .record();
+        if filter.is_some_and(|filter| !filter.matches(&record)) {
+            continue;
+        }
+        if output::is_ndjson() {
+            output::print(&record);
+            continue;
+        }
+        match result {
+            Ok(true) => println!("[{time}] {}: {}", job.name, job.describe_action()),
+            Ok(false) => println!("[{time}] {}: skipped, condition not met", job.name),
+            Err(err) => eprintln!("[{time}] {}: failed: {err}", job.name),
+        }
+    }
+}
+
+/// Plan the day's vacation switches when it starts, and make those that are due.
+fn run_vacation(
+    api: &TelldusApi,
+    vacation: &Vacation,
+    plan: &mut Plan,
+    location: Option<Location>,
+    now: u64,
+    filter: Option<&Filter>,
+) {
+    let time = local_time(now);
+    if let Some(switches) = plan.update(vacation, location, now)
+        && !output::is_ndjson()
+    {
+        let planned: Vec<String> = switches.iter().map(ToString::to_string).collect();
+        if planned.is_empty() {
+            println!("[{time}] vacation: nothing left to switch today");
+        } else {
+            println!("[{time}] vacation: {}", planned.join(", "));
+        }
+    }
+    for switch in plan.due(now) {
+        let result = resolve(api, &switch.device).and_then(|id| {
+            if switch.on {
+                api.device_turn_on(&id)?;
+            } else {
+                api.device_turn_off(&id)?;
+            }
+            Ok(true)
+        });
+        let action = if switch.on { "on" } else { "off" };
+        let record = JobRun::new("vacation", &switch.device, action.into(), now, &result).record();
+        if filter.is_some_and(|filter| !filter.matches(&record)) {
+            continue;
+        }
+        if output::is_ndjson() {
+            output::print(&record);
+            continue;
+        }
+        match result {
+            Ok(_) => println!("[{time}] vacation: {} {action}", switch.device),
+            Err(err) => eprintln!(
+                "[{time}] vacation: {} {action} failed: {err}",
+                switch.device
+            ),
+        }
+    }
+}
+
+fn run_job(api: &TelldusApi, job: &Job) -> Result<bool, ApiError> {
+    if let Some(condition) = &job.only_if {
+        let id = resolve(api, &condition.device)?;
+        let on = api.device_state(&id)?.method != 2;
+        if on != (condition.state == ConditionState::On) {
+            return Ok(false);
+        }
+    }
+
+    let id = resolve(api, &job.device)?;
+    match job.action {
+        JobAction::On => api.device_turn_on(&id)?,
+        JobAction::Off => api.device_turn_off(&id)?,
+        JobAction::Dim => api.device_dim(&id, job.level.unwrap_or_default())?,
+        JobAction::Bell => api.device_bell(&id)?,
+        JobAction::Up => api.device_up(&id)?,
+        JobAction::Down => api.device_down(&id)?,
+        JobAction::Stop => api.device_stop(&id)?,
+    }
+    Ok(true)
+}
+
+fn resolve(api: &TelldusApi, reference: &str) -> Result<String, ApiError> {
+    cache::resolve(api, Category::Device, reference)?
+        .ok_or_else(|| ApiError::Unexpected(format!("no device named '{reference}'")))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,132 @@
+use super::*;
+
+/// Thursday 15 October 2026 at `hour:minute`.
+fn thursday(hour: u32, minute: u32) -> LocalTime {
+    LocalTime {
+        year: 2026,
+        month: 10,
+        day: 15,
+        hour,
+        minute,
+        weekday: 4,
+    }
+}
+
+fn bits(values: &[u32]) -> u64 {
+    values.iter().fold(0, |set, value| set | 1 << value)
+}
+
+#[test]
+fn fields_accept_single_values_lists_and_ranges() {
+    assert_eq!(parse_field("5", 0, 59), Ok(bits(&[5])));
+    assert_eq!(parse_field("1,15,30", 0, 59), Ok(bits(&[1, 15, 30])));
+    assert_eq!(parse_field("9-12", 0, 23), Ok(bits(&[9, 10, 11, 12])));
+    assert_eq!(parse_field("1-3,5", 1, 12), Ok(bits(&[1, 2, 3, 5])));
+    assert_eq!(
+        parse_field("*", 1, 12),
+        Ok(bits(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]))
+    );
+}
+
+#[test]
+fn steps_apply_to_stars_ranges_and_starting_values() {
+    assert_eq!(parse_field("*/15", 0, 59), Ok(bits(&[0, 15, 30, 45])));
+    assert_eq!(parse_field("8-18/4", 0, 23), Ok(bits(&[8, 12, 16])));
+    assert_eq!(parse_field("50/5", 0, 59), Ok(bits(&[50, 55])));
+    assert_eq!(parse_field("*/1", 0, 6), Ok(bits(&[0, 1, 2, 3, 4, 5, 6])));
+}
+
+#[test]
+fn invalid_fields_are_rejected() {
+    for (field, min, max, message) in [
+        ("60", 0, 59, "'60' is outside 0-59"),
+        ("0", 1, 31, "'0' is outside 1-31"),
+        ("x", 0, 59, "'x' is outside 0-59"),
+        ("", 0, 59, "'' is outside 0-59"),
+        ("5-", 0, 59, "'' is outside 0-59"),
+        ("10-5", 0, 59, "range '10-5' runs backwards"),
+        ("*/0", 0, 59, "invalid step in '*/0'"),
+        ("*/x", 0, 59, "invalid step in '*/x'"),
+    ] {
+        let err = parse_field(field, min, max).unwrap_err();
+        assert!(err.contains(message), "{field}: {err}");
+    }
+}
+
+#[test]
+fn expressions_need_five_fields() {
+    let err = CronSchedule::parse("0 7 * *").unwrap_err();
+    assert!(err.contains("needs five fields"), "{err}");
+    assert!(CronSchedule::parse("0 7 * * * *").is_err());
+    assert!(CronSchedule::parse("0 24 * * *").is_err());
+}
+
+#[test]
+fn schedules_match_minute_hour_and_month() {
+    let schedule = CronSchedule::parse("*/15 7-9 * 10 *").unwrap();
+
+    assert!(schedule.matches(&thursday(7, 0)));
+    assert!(schedule.matches(&thursday(9, 45)));
+    assert!(!schedule.matches(&thursday(9, 46)));
+    assert!(!schedule.matches(&thursday(10, 0)));
+    assert!(!schedule.matches(&LocalTime {
+        month: 11,
+        ..thursday(7, 0)
+    }));
+}
+
+#[test]
+fn sunday_is_both_zero_and_seven() {
+    let sunday = LocalTime {
+        day: 18,
+        weekday: 0,
+        ..thursday(8, 0)
+    };
+
+    assert!(CronSchedule::parse("0 8 * * 7").unwrap().matches(&sunday));
+    assert!(CronSchedule::parse("0 8 * * 0").unwrap().matches(&sunday));
+    assert!(CronSchedule::parse("0 8 * * 5-7").unwrap().matches(&sunday));
+    assert!(!CronSchedule::parse("0 8 * * 1-5").unwrap().matches(&sunday));
+}
+
+#[test]
+fn restricted_day_and_weekday_match_if_either_does() {
+    // The 1st of the month or any Thursday, as in cron.
+    let schedule = CronSchedule::parse("0 8 1 * 4").unwrap();
+
+    assert!(schedule.matches(&thursday(8, 0)));
+    assert!(schedule.matches(&LocalTime {
+        day: 1,
+        weekday: 4,
+        ..thursday(8, 0)
+    }));
+    assert!(!schedule.matches(&LocalTime {
+        day: 16,
+        weekday: 5,
+        ..thursday(8, 0)
+    }));
+    // With the weekday left open only the day of month counts.
+    assert!(
+        !CronSchedule::parse("0 8 1 * *")
+            .unwrap()
+            .matches(&thursday(8, 0))
+    );
+}
+
+#[test]
+fn minutes_held_up_by_slow_jobs_are_caught_up() {
+    let (minutes, skipped) = minutes_to_run(100, 100);
+    assert!(minutes.is_empty());
+    assert_eq!(skipped, 0);
+    assert_eq!(minutes_to_run(100, 101), (101..=101, 0));
+    assert_eq!(minutes_to_run(100, 103), (101..=103, 0));
+}
+
+#[test]
+fn long_gaps_are_skipped_beyond_the_catch_up_limit() {
+    let (minutes, skipped) = minutes_to_run(100, 200);
+
+    assert_eq!(minutes.clone().count() as u64, MAX_CATCH_UP_MINUTES);
+    assert_eq!(minutes.last(), Some(200));
+    assert_eq!(skipped, 100 - MAX_CATCH_UP_MINUTES);
+}
@@ -0,0 +1,263 @@
+use crate::api::TelldusApi;
+use crate::auth;
+use crate::config::{self, TelldusCredentials};
+use crate::http_client::shared_client;
+use std::fmt;
+use std::net::ToSocketAddrs;
+use std::time::Instant;
+
+const API_HOST: &str = "pa-api.telldus.com";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        })
+    }
+}
+
+pub struct Check {
+    pub status: Status,
+    pub label: &'static str,
+    pub detail: String,
+}
+
+impl Check {
+    fn pass(label: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status: Status::Pass,
+            label,
+            detail: detail.into(),
+        }
+    }
+
+    fn warn(label: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status: Status::Warn,
+            label,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(label: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status: Status::Fail,
+            label,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Run every diagnostic, stopping early only where later checks cannot succeed.
+pub fn run() -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let credentials = match check_config(&mut checks) {
+        Some(credentials) => credentials,
+        None => return checks,
+    };
+    if !credentials.is_complete() {
+        checks.push(Check::fail(
+            "consumer keys",
+            format!(
+                "missing {}; run `telltales auth validate` to enter them",
+                credentials.missing_fields().join(", ")
+            ),
+        ));
+        return checks;
+    }
+    checks.push(Check::pass(
+        "consumer keys",
+        "public and private keys present",
+    ));
+
+    match (API_HOST, 443).to_socket_addrs() {
+        Ok(mut addrs) => match addrs.next() {
+            Some(addr) => checks.push(Check::pass("dns", format!("{API_HOST} -> {}", addr.ip()))),
+            None => {
+                checks.push(Check::fail("dns", format!("{API_HOST} has no addresses")));
+                return checks;
+            }
+        },
+        Err(err) => {
+            checks.push(Check::fail(
+                "dns",
+                format!("cannot resolve {API_HOST}: {err}; check network and resolver"),
+            ));
+            return checks;
+        }
+    }
+
+    let client = match shared_client() {
+        Ok(client) => client,
+        Err(err) => {
+            checks.push(Check::fail("http client", err.to_string()));
+            return checks;
+        }
+    };
+
+    if credentials.token.trim().is_empty() || credentials.token_secret.trim().is_empty() {
+        checks.push(Check::fail(
+            "oauth",
+            "no access token stored; run `telltales auth validate` to authorize",
+        ));
+        return checks;
+    }
+    match auth::fetch_profile(&client, &credentials) {
+        Ok(profile) => checks.push(Check::pass(
+            "oauth",
+            match auth::account_name(&profile) {
+                Some(name) => format!("authenticated as {name}"),
+                None => "access token accepted".into(),
+            },
+        )),
+        Err(auth::AuthError::Unauthorized) => {
+            checks.push(Check::fail(
+                "oauth",
+                "access token rejected; run `telltales auth validate` to re-authorize",
+            ));
+            return checks;
+        }
+        Err(err) => {
+            checks.push(Check::fail("oauth", err.to_string()));
+            return checks;
+        }
+    }
+
+    let api = match TelldusApi::builder()
+        .client(&client)
+        .credentials(&credentials)
+        .build()
+    {
+        Ok(api) => api,
+        Err(err) => {
+            checks.push(Check::fail("api client", err.to_string()));
+            return checks;
+        }
+    };
+    let started = Instant::now();
+    let controllers = match api.list_controllers() {
+        Ok(controllers) => {
+            checks.push(Check::pass(
+                "rate-limited request",
+                format!(
+                    "clients/list answered in {} ms including limiter wait",
+                    started.elapsed().as_millis()
+                ),
+            ));
+            controllers
+        }
+        Err(err) => {
+            checks.push(Check::fail("rate-limited request", err.to_string()));
+            return checks;
+        }
+    };
+
+    match api.probe_compatibility() {
+        Ok(warnings) if warnings.is_empty() => checks.push(Check::pass(
+            "api compatibility",
+            "list responses use recognized field names",
+        )),
+        Ok(warnings) => {
+            for warning in warnings {
+                checks.push(Check::warn("api compatibility", warning));
+            }
+        }
+        Err(err) => checks.push(Check::warn("api compatibility", err.to_string())),
+    }
+
+    let online = controllers
+        .iter()
+        .filter(|entry| {
+            entry
+                .details
+                .as_deref()
+                .is_some_and(|details| details.split(", ").any(|part| part == "online"))
+        })
+        .count();
+    if controllers.is_empty() {
+        checks.push(Check::fail(
+            "controllers",
+            "no controllers registered on this account",
+        ));
+    } else if online == 0 {
+        checks.push(Check::fail(
+            "controllers",
+            format!(
+                "none of {} controller(s) online; check power and network of your TellStick",
+                controllers.len()
+            ),
+        ));
+    } else {
+        checks.push(Check::pass(
+            "controllers",
+            format!("{online} of {} controller(s) online", controllers.len()),
+        ));
+    }
+
+    checks
+}
+
+fn check_config(checks: &mut Vec<Check>) -> Option<TelldusCredentials> {
+    let path = match config::credentials_path() {
+        Ok(path) => path,
+        Err(err) => {
+            checks.push(Check::fail("config file", err.to_string()));
+            return None;
+        }
+    };
+    if !path.exists() {
+        checks.push(Check::fail(
+            "config file",
+            format!(
+                "{} not found; run `telltales auth validate` to create it",
+                path.display()
+            ),
+        ));
+        return None;
+    }
+
+    let credentials = match config::load_credentials() {
+        Ok(Some(credentials)) => credentials,
+        Ok(None) => TelldusCredentials::default(),
+        Err(err) => {
+            checks.push(Check::fail("config file", err.to_string()));
+            return None;
+        }
+    };
+    checks.push(Check::pass(
+        "config file",
+        format!("{} readable", path.display()),
+    ));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            let mode = metadata.permissions().mode() & 0o777;
+            if mode & 0o077 != 0 {
+                checks.push(Check::warn(
+                    "config permissions",
+                    format!(
+                        "{} is mode {mode:o}; run `chmod 600 {}` to keep secrets private",
+                        path.display(),
+                        path.display()
+                    ),
+                ));
+            } else {
+                checks.push(Check::pass("config permissions", format!("mode {mode:o}")));
+            }
+        }
+    }
+
+    Some(credentials)
+}
@@ -0,0 +1,156 @@
+use crate::api::{ReadingKind, Sensor, sensor_readings};
+use crate::filter;
+use crate::history::timestamp;
+use crate::schedule::local_date as date;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Power samples further apart than this are a gap in the data, not a steady load.
+const MAX_SAMPLE_GAP: i64 = 3600;
+
+/// Telldus scale of a `watt` reading holding a cumulative meter value in kWh.
+pub const SCALE_KWH: u32 = 0;
+/// Telldus scale of a `watt` reading holding the momentary load in W.
+pub const SCALE_WATT: u32 = 2;
+
+/// Energy one sensor used on one local calendar day.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyEnergy {
+    pub id: String,
+    pub sensor: String,
+    /// Local date as `YYYY-MM-DD`.
+    pub date: String,
+    pub kwh: f64,
+}
+
+/// Energy used per local day, in kWh, from one sensor's history entries.
+///
+/// Meter readings (kWh) are preferred and counted as the increase between samples;
+/// a meter that goes backwards was reset, so its new value is all new usage.
+/// Sensors that only report the load (W) are integrated over time instead.
+pub fn daily_usage(entries: &[Value]) -> BTreeMap<String, f64> {
+    let mut meter = Vec::new();
+    let mut load = Vec::new();
+    for entry in entries {
+        let Some(timestamp) = timestamp(entry) else {
+            continue;
+        };
+        for reading in sensor_readings(entry) {
+            match (reading.kind, reading.scale) {
+                (ReadingKind::Power, SCALE_KWH) => meter.push((timestamp, reading.value)),
+                (ReadingKind::Power, SCALE_WATT) => load.push((timestamp, reading.value)),
+                _ => {}
+            }
+        }
+    }
+    meter.sort_by_key(|(timestamp, _)| *timestamp);
+    load.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let mut days = BTreeMap::new();
+    if !meter.is_empty() {
+        for pair in meter.windows(2) {
+            let ((_, before), (timestamp, after)) = (pair[0], pair[1]);
+            let used = if after >= before {
+                after - before
+            } else {
+                after
+            };
+            *days.entry(date(timestamp)).or_default() += used;
+        }
+    } else {
+        for pair in load.windows(2) {
+            let ((start, watts_before), (end, watts_after)) = (pair[0], pair[1]);
+            let seconds = end - start;
+            if seconds <= 0 || seconds > MAX_SAMPLE_GAP {
+                continue;
+            }
+            let kwh = (watts_before + watts_after) / 2.0 * seconds as f64 / 3_600_000.0;
+            *days.entry(date(end)).or_default() += kwh;
+        }
+    }
+    days
+}
+
+/// Live load of one sensor while `energy watch` runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct Load {
+    pub id: String,
+    pub sensor: String,
+    pub watts: f64,
+    /// Mean of every load seen since watching started.
+    pub average_watts: f64,
+    /// Energy used since watching started, integrating the load between polls.
+    pub kwh: f64,
+    /// The load is more than the spike margin above its average.
+    pub spike: bool,
+    #[serde(skip)]
+    samples: u32,
+    /// Unix timestamp in seconds of the poll the load comes from.
+    #[serde(rename = "timestamp")]
+    polled_at: u64,
+}
+
+impl Load {
+    /// The load with the fields every `--filter` can name.
+    pub fn record(&self) -> Value {
+        let record = serde_json::to_value(self).unwrap_or_default();
+        filter::with_context(record, "sensor", &self.id, &self.sensor)
+    }
+}
+
+/// Tracks the current load of power sensors across polls.
+pub struct Monitor {
+    spike_margin: f64,
+    loads: Vec<Load>,
+}
+
+impl Monitor {
+    /// `spike_percent` is how far above its running average a load must jump to count as a spike.
+    pub fn new(spike_percent: f64) -> Self {
+        Self {
+            spike_margin: 1.0 + spike_percent / 100.0,
+            loads: Vec::new(),
+        }
+    }
+
+    /// Record the W readings from one poll of the sensor list taken at `now` (Unix seconds).
+    pub fn update(&mut self, sensors: &[Sensor], now: u64) {
+        for sensor in sensors {
+            let Some(watts) = sensor
+                .readings
+                .iter()
+                .find(|reading| reading.kind == ReadingKind::Power && reading.scale == SCALE_WATT)
+                .map(|reading| reading.value)
+            else {
+                continue;
+            };
+            let Some(load) = self.loads.iter_mut().find(|load| load.id == sensor.id) else {
+                self.loads.push(Load {
+                    id: sensor.id.clone(),
+                    sensor: sensor.name.clone(),
+                    watts,
+                    average_watts: watts,
+                    kwh: 0.0,
+                    spike: false,
+                    samples: 1,
+                    polled_at: now,
+                });
+                continue;
+            };
+            let seconds = now.saturating_sub(load.polled_at) as i64;
+            if seconds <= MAX_SAMPLE_GAP {
+                load.kwh += (load.watts + watts) / 2.0 * seconds as f64 / 3_600_000.0;
+            }
+            load.spike = load.samples > 1 && watts > load.average_watts * self.spike_margin;
+            load.samples += 1;
+            load.average_watts += (watts - load.average_watts) / f64::from(load.samples);
+            load.watts = watts;
+            load.polled_at = now;
+        }
+    }
+
+    pub fn loads(&self) -> &[Load] {
+        &self.loads
+    }
+}
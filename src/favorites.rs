@@ -0,0 +1,82 @@
+use crate::config::{self, ConfigError, TelldusCredentials};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+const FAVORITES_FILE: &str = "favorites.yaml";
+
+#[derive(Debug, Error)]
+pub enum FavoritesError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("failed to read favorites {0}: {1}")]
+    ReadFailed(String, #[source] io::Error),
+    #[error("failed to parse favorites {0}: {1}")]
+    ParseFailed(String, #[source] serde_yaml::Error),
+    #[error("failed to write favorites {0}: {1}")]
+    WriteFailed(String, #[source] io::Error),
+}
+
+/// Ids of the starred devices, in the order they were starred, so listings can hide the
+/// stale entries accounts collect from auto-learned remotes and sensors.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Favorites {
+    pub devices: Vec<String>,
+}
+
+impl Favorites {
+    pub fn contains(&self, id: &str) -> bool {
+        self.devices.iter().any(|favorite| favorite == id)
+    }
+
+    /// Star `id`; returns false if it already was.
+    pub fn add(&mut self, id: &str) -> bool {
+        if self.contains(id) {
+            return false;
+        }
+        self.devices.push(id.to_string());
+        true
+    }
+
+    /// Unstar `id`; returns false if it was not starred.
+    pub fn remove(&mut self, id: &str) -> bool {
+        let before = self.devices.len();
+        self.devices.retain(|favorite| favorite != id);
+        self.devices.len() != before
+    }
+}
+
+pub fn path(credentials: &TelldusCredentials) -> Result<PathBuf, FavoritesError> {
+    Ok(config::account_file(credentials, FAVORITES_FILE)?)
+}
+
+/// The account's starred devices; none when the file does not exist yet.
+pub fn load(credentials: &TelldusCredentials) -> Result<Favorites, FavoritesError> {
+    let path = path(credentials)?;
+    if !path.exists() {
+        return Ok(Favorites::default());
+    }
+    let text = fs::read_to_string(&path)
+        .map_err(|err| FavoritesError::ReadFailed(path.display().to_string(), err))?;
+    serde_yaml::from_str(&text)
+        .map_err(|err| FavoritesError::ParseFailed(path.display().to_string(), err))
+}
+
+pub fn save(
+    credentials: &TelldusCredentials,
+    favorites: &Favorites,
+) -> Result<PathBuf, FavoritesError> {
+    let path = path(credentials)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|err| FavoritesError::WriteFailed(dir.display().to_string(), err))?;
+    }
+    let yaml = serde_yaml::to_string(favorites)
+        .map_err(|err| FavoritesError::ParseFailed(path.display().to_string(), err))?;
+    fs::write(&path, yaml)
+        .map_err(|err| FavoritesError::WriteFailed(path.display().to_string(), err))?;
+    Ok(path)
+}
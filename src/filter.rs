@@ -0,0 +1,258 @@
+use serde_json::Value;
+use std::cmp::Ordering;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FilterError {
+    #[error("invalid filter '{0}': {1}")]
+    Syntax(String, &'static str),
+}
+
+/// A parsed `--filter` expression, such as `device==42 || kind==sensor && name~"outdoor"`.
+///
+/// A filter compares fields of an event's JSON record with literals and combines the
+/// comparisons with `&&`, `||`, `!` and parentheses; `&&` binds tighter than `||`.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(String, Comparison, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    /// The field contains the text, ignoring case.
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Compare(Comparison),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Filter {
+    pub fn parse(text: &str) -> Result<Self, FilterError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            text,
+            tokens: &tokens,
+            position: 0,
+        };
+        let expr = parser.or()?;
+        if parser.position < tokens.len() {
+            return Err(parser.error("unexpected text after the expression"));
+        }
+        Ok(Self { expr })
+    }
+
+    /// Whether `record` passes the filter; a field the record lacks matches only `!=`.
+    pub fn matches(&self, record: &Value) -> bool {
+        self.expr.matches(record)
+    }
+}
+
+impl Expr {
+    fn matches(&self, record: &Value) -> bool {
+        match self {
+            Expr::Or(left, right) => left.matches(record) || right.matches(record),
+            Expr::And(left, right) => left.matches(record) && right.matches(record),
+            Expr::Not(inner) => !inner.matches(record),
+            Expr::Compare(path, comparison, literal) => {
+                compare(lookup(record, path), *comparison, literal)
+            }
+        }
+    }
+}
+
+/// Fill in the `kind`, `id` and `name` fields a filter may name for every kind of event,
+/// where `record` does not already have them.
+pub fn with_context(mut record: Value, kind: &str, id: &str, name: &str) -> Value {
+    if let Value::Object(map) = &mut record {
+        for (key, value) in [("kind", kind), ("id", id), ("name", name), (kind, id)] {
+            map.entry(key)
+                .or_insert_with(|| Value::String(value.to_string()));
+        }
+    }
+    record
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, FilterError> {
+    let syntax = |message| FilterError::Syntax(text.to_string(), message);
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, ch)) = chars.next() {
+        let mut next_is = |expected| chars.next_if(|&(_, ch)| ch == expected).is_some();
+        let token = match ch {
+            _ if ch.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '~' => Token::Compare(Comparison::Contains),
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '=' if next_is('=') => Token::Compare(Comparison::Equal),
+            '!' if next_is('=') => Token::Compare(Comparison::NotEqual),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Compare(Comparison::LessOrEqual),
+            '<' => Token::Compare(Comparison::Less),
+            '>' if next_is('=') => Token::Compare(Comparison::GreaterOrEqual),
+            '>' => Token::Compare(Comparison::Greater),
+            '"' => {
+                let mut literal = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => literal.push(escaped),
+                            None => return Err(syntax("unterminated string")),
+                        },
+                        Some((_, ch)) => literal.push(ch),
+                        None => return Err(syntax("unterminated string")),
+                    }
+                }
+                Token::Text(literal)
+            }
+            _ if is_word_char(ch) => {
+                let mut word = ch.to_string();
+                while let Some((_, ch)) = chars.next_if(|&(_, ch)| is_word_char(ch)) {
+                    word.push(ch);
+                }
+                Token::Word(word)
+            }
+            _ => return Err(syntax("expected a field, value or operator")),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || matches!(ch, '_' | '.' | '-' | ':')
+}
+
+/// Recursive descent over the tokens: `or := and ('||' and)*`, `and := unary ('&&' unary)*`,
+/// `unary := '!' unary | '(' or ')' | FIELD OP VALUE`.
+struct Parser<'a> {
+    text: &'a str,
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::Open) {
+            let expr = self.or()?;
+            if !self.eat(&Token::Close) {
+                return Err(self.error("expected ')'"));
+            }
+            return Ok(expr);
+        }
+        let Some(Token::Word(field)) = self.next() else {
+            return Err(self.error("expected a field name such as name or kind"));
+        };
+        let Some(Token::Compare(comparison)) = self.next() else {
+            return Err(self.error("expected ==, !=, <, <=, >, >= or ~ after the field"));
+        };
+        match self.next() {
+            Some(Token::Word(literal) | Token::Text(literal)) => {
+                Ok(Expr::Compare(field, comparison, literal))
+            }
+            _ => Err(self.error("expected a value such as 42, sensor or \"outdoor\"")),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        let found = self.tokens.get(self.position) == Some(expected);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn error(&self, message: &'static str) -> FilterError {
+        FilterError::Syntax(self.text.to_string(), message)
+    }
+}
+
+/// Follow a dotted path such as `readings.0.value` into the record.
+fn lookup<'a>(record: &'a Value, path: &str) -> &'a Value {
+    path.split('.').fold(record, |value, key| match value {
+        Value::Array(items) => key
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| items.get(index))
+            .unwrap_or(&Value::Null),
+        _ => value.get(key).unwrap_or(&Value::Null),
+    })
+}
+
+/// Compare numerically when both sides are numbers, and as text otherwise, so `device==42`
+/// matches the string ids Telldus uses.
+fn compare(value: &Value, comparison: Comparison, literal: &str) -> bool {
+    let text = match value {
+        Value::Null => return comparison == Comparison::NotEqual,
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    let ordering = match (text.parse::<f64>(), literal.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b),
+        _ => Some(text.as_str().cmp(literal)),
+    };
+    match comparison {
+        Comparison::Equal => ordering == Some(Ordering::Equal),
+        Comparison::NotEqual => ordering != Some(Ordering::Equal),
+        Comparison::Less => ordering == Some(Ordering::Less),
+        Comparison::LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        Comparison::Greater => ordering == Some(Ordering::Greater),
+        Comparison::GreaterOrEqual => {
+            matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+        }
+        Comparison::Contains => text.to_lowercase().contains(&literal.to_lowercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests;
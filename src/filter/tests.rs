@@ -0,0 +1,139 @@
+use super::*;
+use serde_json::json;
+
+fn matches(filter: &str, record: &Value) -> bool {
+    Filter::parse(filter)
+        .unwrap_or_else(|err| panic!("{filter}: {err}"))
+        .matches(record)
+}
+
+fn syntax_error(filter: &str) -> &'static str {
+    match Filter::parse(filter) {
+        Ok(_) => panic!("{filter} should not parse"),
+        Err(FilterError::Syntax(_, message)) => message,
+    }
+}
+
+#[test]
+fn numbers_compare_numerically_even_as_strings() {
+    let record = json!({"device": "42", "temp": 21.5});
+
+    assert!(matches("device==42", &record));
+    assert!(matches("device==42.0", &record));
+    assert!(matches("device!=7", &record));
+    assert!(matches("device>9", &record));
+    assert!(matches("temp<=21.5", &record));
+    assert!(matches("temp>-5", &record));
+    assert!(!matches("temp>=22", &record));
+}
+
+#[test]
+fn other_values_compare_as_text() {
+    let record = json!({"name": "Outdoor Lamp", "state": "on", "online": true});
+
+    assert!(matches(r#"name=="Outdoor Lamp""#, &record));
+    assert!(!matches("name==outdoor", &record));
+    assert!(matches("state<sensor", &record));
+    assert!(matches("online==true", &record));
+}
+
+#[test]
+fn contains_ignores_case() {
+    let record = json!({"name": "Outdoor Lamp"});
+
+    assert!(matches("name~outdoor", &record));
+    assert!(matches(r#"name~"DOOR L""#, &record));
+    assert!(!matches("name~kitchen", &record));
+}
+
+#[test]
+fn missing_fields_only_match_not_equal() {
+    let record = json!({"name": "Lamp"});
+
+    assert!(matches("room!=Kitchen", &record));
+    for filter in ["room==Kitchen", "room<z", "room>=a", "room~K"] {
+        assert!(!matches(filter, &record), "{filter}");
+    }
+}
+
+#[test]
+fn dotted_paths_walk_into_objects_and_arrays() {
+    let record = json!({"readings": [{"name": "temp", "value": "18.5"}], "meta": {"room": "Hall"}});
+
+    assert!(matches("readings.0.value<20", &record));
+    assert!(matches("meta.room==Hall", &record));
+    assert!(!matches("readings.1.value<20", &record));
+    assert!(!matches("readings.x.value<20", &record));
+}
+
+#[test]
+fn and_binds_tighter_than_or() {
+    let record = json!({"a": "1", "b": "0", "c": "0"});
+
+    assert!(matches("a==1 || b==1 && c==1", &record));
+    assert!(!matches("(a==1 || b==1) && c==1", &record));
+    assert!(matches("b==1 && c==1 || a==1", &record));
+}
+
+#[test]
+fn not_applies_to_the_next_comparison_or_group() {
+    let record = json!({"kind": "sensor", "id": "3"});
+
+    assert!(matches("!kind==device", &record));
+    assert!(!matches("!kind==sensor && id==3", &record));
+    assert!(matches("!(kind==sensor && id==4)", &record));
+    assert!(matches("!!kind==sensor", &record));
+}
+
+#[test]
+fn string_literals_may_hold_operators_and_escaped_quotes() {
+    let record = json!({"name": r#"Lamp "A" && (B)"#});
+
+    assert!(matches(r#"name=="Lamp \"A\" && (B)""#, &record));
+    assert!(matches(r#"name~"&& (""#, &record));
+}
+
+#[test]
+fn malformed_filters_are_rejected() {
+    for (filter, message) in [
+        ("", "expected a field name such as name or kind"),
+        ("&& name==a", "expected a field name such as name or kind"),
+        ("name", "expected ==, !=, <, <=, >, >= or ~ after the field"),
+        (
+            "name lamp",
+            "expected ==, !=, <, <=, >, >= or ~ after the field",
+        ),
+        (
+            "name==",
+            "expected a value such as 42, sensor or \"outdoor\"",
+        ),
+        (
+            "name==(",
+            "expected a value such as 42, sensor or \"outdoor\"",
+        ),
+        ("(name==a", "expected ')'"),
+        ("name==a)", "unexpected text after the expression"),
+        ("name==a id==1", "unexpected text after the expression"),
+        (r#"name=="lamp"#, "unterminated string"),
+        (r#"name=="lamp\"#, "unterminated string"),
+        ("name=a", "expected a field, value or operator"),
+        ("name==a & id==1", "expected a field, value or operator"),
+    ] {
+        assert_eq!(syntax_error(filter), message, "{filter}");
+    }
+}
+
+#[test]
+fn context_fills_in_fields_the_record_lacks() {
+    let record = with_context(json!({"name": "Porch", "value": 3}), "sensor", "17", "Hall");
+
+    assert_eq!(
+        record,
+        json!({"kind": "sensor", "id": "17", "sensor": "17", "name": "Porch", "value": 3})
+    );
+    assert!(matches(
+        "sensor==17 && kind==sensor && name==Porch",
+        &record
+    ));
+    assert_eq!(with_context(json!(5), "sensor", "17", "Hall"), json!(5));
+}
@@ -0,0 +1,101 @@
+use crate::api::{ReadingKind, SensorReading, sensor_readings};
+use crate::schedule::local_date;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Unix timestamp of a history entry, which Telldus sends as a number or a string.
+pub fn timestamp(entry: &Value) -> Option<i64> {
+    match entry.get("ts")? {
+        Value::Number(number) => number.as_i64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Lowest, highest, and mean value of one reading over one local day.
+#[derive(Debug, Clone, Serialize)]
+pub struct DaySummary {
+    /// Local date as `YYYY-MM-DD`.
+    pub date: String,
+    pub name: String,
+    pub kind: &'static str,
+    pub unit: &'static str,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub samples: u32,
+}
+
+/// Summarize history entries per local day and reading, optionally only readings of `kind`.
+pub fn daily_summary(entries: &[Value], kind: Option<ReadingKind>) -> Vec<DaySummary> {
+    let mut days: BTreeMap<(String, String), DaySummary> = BTreeMap::new();
+    for entry in entries {
+        let Some(timestamp) = timestamp(entry) else {
+            continue;
+        };
+        let date = local_date(timestamp);
+        for reading in sensor_readings(entry) {
+            if kind.is_some_and(|kind| reading.kind != kind) {
+                continue;
+            }
+            let summary = days
+                .entry((date.clone(), reading.name.clone()))
+                .or_insert_with(|| DaySummary {
+                    date: date.clone(),
+                    name: reading.name.clone(),
+                    kind: reading.kind.as_str(),
+                    unit: reading.unit(),
+                    min: reading.value,
+                    max: reading.value,
+                    mean: 0.0,
+                    samples: 0,
+                });
+            summary.min = summary.min.min(reading.value);
+            summary.max = summary.max.max(reading.value);
+            summary.samples += 1;
+            summary.mean += (reading.value - summary.mean) / f64::from(summary.samples);
+        }
+    }
+    days.into_values().collect()
+}
+
+/// One time slot of `sensors compare`: each sensor's value, in `--id` order.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparedSlot {
+    /// Start of the slot as a Unix timestamp.
+    pub timestamp: i64,
+    pub values: Vec<Option<f64>>,
+}
+
+/// Mean of the readings `select` accepts per `step`-second bucket, keyed by bucket start,
+/// together with the unit of those readings.
+pub fn bucket_means(
+    entries: &[Value],
+    step: i64,
+    select: impl Fn(&SensorReading) -> bool,
+) -> (BTreeMap<i64, f64>, Option<&'static str>) {
+    let mut buckets: BTreeMap<i64, (f64, u32)> = BTreeMap::new();
+    let mut unit = None;
+    for entry in entries {
+        let Some(timestamp) = timestamp(entry) else {
+            continue;
+        };
+        if let Some(reading) = sensor_readings(entry)
+            .into_iter()
+            .find(|reading| select(reading))
+        {
+            unit.get_or_insert(reading.unit());
+            let (sum, count) = buckets
+                .entry(timestamp - timestamp.rem_euclid(step))
+                .or_default();
+            *sum += reading.value;
+            *count += 1;
+        }
+    }
+    let means = buckets
+        .into_iter()
+        .map(|(start, (sum, count))| (start, sum / f64::from(count)))
+        .collect();
+    (means, unit)
+}
@@ -1,8 +1,41 @@
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, ClientBuilder};
+use std::sync::OnceLock;
+use std::time::Duration;
 
-pub fn build_http_client() -> Result<Client, reqwest::Error> {
-    Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .user_agent("telltales-cli/0.1 (+https://github.com/niklasha/telltales)")
-        .build()
+/// How long a Telldus Live request may take unless `--timeout` says otherwise.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_USER_AGENT: &str = concat!(
+    "telltales-cli/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/niklasha/telltales)"
+);
+
+static USER_AGENT: OnceLock<String> = OnceLock::new();
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Send `user_agent` instead of the telltales default; call it before any client is built.
+pub fn set_user_agent(user_agent: String) {
+    let _ = USER_AGENT.set(user_agent);
+}
+
+pub fn user_agent() -> &'static str {
+    USER_AGENT.get().map_or(DEFAULT_USER_AGENT, String::as_str)
+}
+
+/// A client builder carrying the shared settings, so every HTTP client telltales uses
+/// identifies itself alike; callers add their own timeout.
+pub fn builder() -> ClientBuilder {
+    Client::builder().user_agent(user_agent())
+}
+
+/// The client for Telldus Live, built on first use and shared by the whole process.
+///
+/// Clones share one connection pool, so the OAuth check at startup and the API calls
+/// after it reuse the same keep-alive connection.
+pub fn shared_client() -> Result<Client, reqwest::Error> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let client = builder().timeout(REQUEST_TIMEOUT).build()?;
+    Ok(CLIENT.get_or_init(|| client).clone())
 }
@@ -0,0 +1,57 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+/// An exclusive advisory lock on a file next to the credentials, held until dropped.
+///
+/// The locks are `flock(2)` locks: they only keep out other processes that take the same
+/// lock, and the kernel releases them when the holder exits, however it exits.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Lock `path`, creating it if needed. When another process holds the lock,
+    /// `waiting` is called once before blocking until it is released.
+    pub fn acquire(path: &Path, waiting: impl FnOnce()) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.create(true).truncate(false).write(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(path)?;
+        lock(&file, waiting)?;
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(unix)]
+fn lock(file: &File, waiting: impl FnOnce()) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let flock = |operation| loop {
+        // SAFETY: flock only operates on the descriptor, which `file` keeps open.
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    };
+    match flock(libc::LOCK_EX | libc::LOCK_NB) {
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+            waiting();
+            flock(libc::LOCK_EX)
+        }
+        result => result,
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_file: &File, _waiting: impl FnOnce()) -> io::Result<()> {
+    Ok(())
+}
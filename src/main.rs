@@ -1,19 +1,167 @@
+mod alerts;
 mod api;
+mod audit;
 mod auth;
+mod cache;
+mod certificate;
+mod changes;
+mod completions;
 mod config;
+mod daemon;
+mod doctor;
+mod energy;
+mod favorites;
+mod filter;
+mod fixtures;
+mod history;
 mod http_client;
+mod lock;
+mod metrics;
+mod notify;
+mod otlp;
+mod output;
+mod protocols;
+mod provision;
+mod query;
+mod recent;
+mod response_cache;
+mod rooms;
+mod schedule;
+mod serve;
+mod service;
+mod snapshot;
+mod stats;
+mod substitute;
+mod sun;
+mod timing;
+mod transport;
+mod vacation;
 
-use api::{AddDeviceRequest, SensorUpdateRequest, TelldusApi};
-use clap::{Parser, Subcommand, ValueEnum, builder::BoolishValueParser};
-use config::{TelldusCredentials, credentials_path, ensure_credentials, save_credentials};
-use http_client::build_http_client;
-use serde_json::to_string_pretty;
+use api::{
+    AddDeviceRequest, BatteryLevel, Category, DeviceSettings, DeviceState, Entry, EventAction,
+    EventCondition, EventTrigger, ReadingKind, RetryPolicy, SchedulerJobRequest, Sensor,
+    SensorUpdateRequest, TelldusApi, ZwaveConfigRequest,
+};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, builder::BoolishValueParser};
+use config::{TelldusCredentials, credentials_path, ensure_credentials};
+use dialoguer::{Confirm, Input, Select};
+use http_client::shared_client;
+use output::{Cell, ColorChoice, OutputFormat, Style, Table};
+use schedule::{
+    ExceptDate, ScheduleTime, WEEKDAY_NAMES, Weekdays, describe_age, format_duration,
+    parse_duration,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use substitute::Substitution;
 use thiserror::Error;
+use url::Url;
+
+/// Delay before the first retry; doubles with each further attempt.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Time given to a device to act on a command before `--confirm` reads its state back.
+const CONFIRM_DELAY: Duration = Duration::from_secs(2);
+
+/// Seconds a receiver listens for the learn signal unless told otherwise.
+const LEARN_WINDOW: u64 = 10;
+
+const SERVE_TOKEN_VAR: &str = "TELLTALES_SERVE_TOKEN";
+const OTLP_ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+const CONFIG_VAR: &str = "TELLTALES_CONFIG";
+const PASSPHRASE_VAR: &str = "TELLTALES_PASSPHRASE";
+const USER_AGENT_VAR: &str = "TELLTALES_USER_AGENT";
+const CACHE_TTL_VAR: &str = "TELLTALES_CACHE_TTL";
+const FAVORITES_VAR: &str = "TELLTALES_FAVORITES";
+
+/// Retry behaviour chosen on the command line, applied to every API client.
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+/// Per-request timeout chosen with `--timeout`, applied to every API client.
+static REQUEST_TIMEOUT: OnceLock<Duration> = OnceLock::new();
 
 #[derive(Parser)]
 #[command(name = "telltales", version, about = "Telldus Live CLI")]
 struct Cli {
+    /// Ask Telldus Live even for lists the local cache could answer, including names
+    #[arg(long, global = true)]
+    refresh: bool,
+    /// Serve list responses younger than this from the local cache, e.g. 30s (or set TELLTALES_CACHE_TTL)
+    #[arg(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    cache_ttl: Option<Duration>,
+    /// Neither use nor update the local response cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+    /// Print per-request latency and rate-limiter wait times after the command
+    #[arg(long, global = true)]
+    timing: bool,
+    /// Retry failed reads up to this many times on timeouts, 429 and 5xx responses
+    #[arg(long, global = true, default_value_t = 0)]
+    retries: u32,
+    /// Also retry commands such as on/off/bell, which may then run more than once
+    #[arg(long, global = true, requires = "retries")]
+    retry_unsafe: bool,
+    /// Color tables and states: auto (terminals without NO_COLOR), always, or never
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Show table cells in full instead of truncating them to fit the terminal
+    #[arg(long, global = true)]
+    wide: bool,
+    /// Print output as a table, as JSON (see `telltales schema`), or as one JSON object per line
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+    /// Extract values from JSON output with a jq-style filter, e.g. '.[] | .name'
+    #[arg(long, global = true, value_name = "FILTER", value_parser = parse_query)]
+    query: Option<query::Query>,
+    /// Print each record through a template such as '{id}\t{name}\t{state}'
+    #[arg(long, global = true, value_name = "TEMPLATE")]
+    format: Option<String>,
+    // Not global: `devices wait-for` has its own `--timeout`.
+    /// Give each Telldus Live request this long before failing, e.g. 5s or 2m (default 30s)
+    #[arg(long, value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+    /// Use this credentials file instead of ~/.config/telltales/credentials.yaml (or set TELLTALES_CONFIG)
+    #[arg(long, global = true, value_name = "PATH")]
+    credentials_file: Option<PathBuf>,
+    /// Load the credentials file even when other users can read it
+    #[arg(long, global = true)]
+    insecure_config: bool,
+    /// Identify as this User-Agent in every HTTP request (or set TELLTALES_USER_AGENT)
+    #[arg(long, global = true, value_name = "AGENT", value_parser = parse_user_agent)]
+    user_agent: Option<String>,
+    /// Export API calls as traces to this OTLP/HTTP collector (or set OTEL_EXPORTER_OTLP_ENDPOINT)
+    #[arg(long, global = true, value_name = "URL")]
+    otlp_endpoint: Option<String>,
+    /// Serve the OAuth callback over HTTPS with a throwaway self-signed certificate
+    #[arg(long, global = true)]
+    callback_https: bool,
+    /// Bind the OAuth callback listener here instead of a random port on 127.0.0.1
+    #[arg(long, global = true, value_name = "ADDR")]
+    callback_listen: Option<SocketAddr>,
+    /// Redirect OAuth to this public URL (a reverse proxy or tunnel forwarding to --callback-listen)
+    #[arg(long, global = true, value_name = "URL", requires = "callback_listen", value_parser = parse_callback_url)]
+    callback_url: Option<Url>,
+    /// Wait for other --exclusive runs using the same credentials, and keep them waiting until this one ends
+    #[arg(long, global = true)]
+    exclusive: bool,
+    /// Save every API response under this directory
+    #[arg(
+        long,
+        global = true,
+        hide = true,
+        value_name = "DIR",
+        conflicts_with = "replay"
+    )]
+    record: Option<PathBuf>,
+    /// Serve API responses from a directory written by --record instead of Telldus Live
+    #[arg(long, global = true, hide = true, value_name = "DIR")]
+    replay: Option<PathBuf>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -25,6 +173,16 @@ enum Commands {
         #[command(subcommand)]
         command: Option<AuthCommand>,
     },
+    /// Encrypt or decrypt the local credentials file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Manage TellStick controllers
+    Controllers {
+        #[command(subcommand)]
+        command: Option<ControllerCommand>,
+    },
     /// Interact with Telldus Live devices
     Devices {
         #[command(subcommand)]
@@ -35,12 +193,217 @@ enum Commands {
         #[command(subcommand)]
         command: Option<SensorCommand>,
     },
+    /// Control Telldus Live device groups
+    Groups {
+        #[command(subcommand)]
+        command: Option<GroupCommand>,
+    },
+    /// Manage Telldus Live events
+    Events {
+        #[command(subcommand)]
+        command: Option<EventCommand>,
+    },
+    /// Send notifications through Telldus Live
+    Notify {
+        #[command(subcommand)]
+        command: NotifyCommand,
+    },
+    /// Manage Telldus Live scheduler jobs
+    Scheduler {
+        #[command(subcommand)]
+        command: Option<SchedulerCommand>,
+    },
+    /// Report devices and sensors with low or unknown battery
+    Battery {
+        /// Show every battery-powered resource, not only problems
+        #[arg(long)]
+        all: bool,
+        /// Percentage at or below which a battery counts as low
+        #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u8).range(0..=100))]
+        threshold: u8,
+    },
+    /// Capture and compare local snapshots of the account state
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+    /// Serve a small authenticated REST API for other local apps
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+        /// Bearer token clients must send; prefer the TELLTALES_SERVE_TOKEN variable
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Summarize energy use per power sensor and day from sensor history
+    #[command(args_conflicts_with_subcommands = true)]
+    Energy {
+        #[command(subcommand)]
+        command: Option<EnergyCommand>,
+        /// How far back to summarize, e.g. `7d` or `36h`
+        #[arg(long, value_parser = parse_duration, default_value = "7d")]
+        since: Duration,
+        /// Only these sensors (repeatable); defaults to every sensor reporting power
+        #[arg(long = "id")]
+        sensor_ids: Vec<String>,
+    },
+    /// Print the JSON Schema of `--output json` for a command family
+    Schema {
+        #[arg(value_enum)]
+        model: SchemaModel,
+    },
+    /// Diagnose configuration, connectivity, and authentication problems
+    Doctor,
+    /// Run cron-like schedules from schedules.yaml locally
+    Daemon {
+        #[command(subcommand)]
+        command: Option<DaemonCommand>,
+    },
+    /// Watch sensors and act when alert thresholds from alerts.yaml are crossed
+    Alerts {
+        #[command(subcommand)]
+        command: Option<AlertsCommand>,
+    },
+    /// Inspect the Telldus Live account
+    User {
+        #[command(subcommand)]
+        command: Option<UserCommand>,
+    },
+    /// Configure Z-Wave nodes
+    Zwave {
+        #[command(subcommand)]
+        command: Option<ZwaveCommand>,
+    },
+    /// Show the changes telltales made on Telldus Live, newest last
+    Log {
+        /// Number of most recent entries to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Only entries for this device, sensor, event or other resource ID
+        #[arg(long)]
+        id: Option<String>,
+        /// Only entries from this long ago until now, e.g. `12h`
+        #[arg(long, value_parser = parse_duration)]
+        since: Option<Duration>,
+    },
+    /// Summarize the account: controllers, devices, sensors, schedules and events
+    Stats,
+    /// Turn the device most recently commanded from telltales on or off again
+    Last {
+        #[arg(value_enum)]
+        action: LastAction,
+    },
+    /// Reverse the most recent logged change that can be reversed
+    Undo {
+        /// Undo without asking for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Show the protocol/model combinations devices can be added with
+    Protocols {
+        #[command(subcommand)]
+        command: Option<ProtocolsCommand>,
+    },
+    /// Print a shell completion script, e.g. `source <(telltales completions bash)`
+    Completions {
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+    /// Print the candidates for completing a command line; used by the completion scripts
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(raw = true)]
+        words: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProtocolsCommand {
+    /// List known protocols, models and their parameters, with those in use on the account
+    List {
+        /// Only the built-in catalog, without reading the account's devices
+        #[arg(long)]
+        builtin: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum EnergyCommand {
+    /// Poll power sensors and show their current load until interrupted
+    Watch {
+        /// Time between polls
+        #[arg(long, value_parser = parse_duration, default_value = "10s")]
+        interval: Duration,
+        /// Highlight a load this many percent above its running average
+        #[arg(long, default_value_t = 50.0)]
+        spike: f64,
+        /// When piped, also repeat unchanged loads this often, e.g. 5m
+        #[arg(long, value_parser = parse_duration)]
+        heartbeat: Option<Duration>,
+        /// Only these sensors (repeatable); defaults to every sensor reporting power
+        #[arg(long = "id")]
+        sensor_ids: Vec<String>,
+        /// Only print loads matching this expression, e.g. 'name~"outdoor" && watts>100'
+        #[arg(long, value_name = "EXPR", value_parser = parse_filter)]
+        filter: Option<filter::Filter>,
+    },
 }
 
 #[derive(Subcommand)]
 enum AuthCommand {
     /// Ensure credentials are present and valid locally
     Validate,
+    /// Show when the access token was issued and last accepted, then check it without re-authorizing
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Encrypt the credentials file with a passphrase (prompted, or from TELLTALES_PASSPHRASE)
+    Encrypt,
+    /// Store the credentials file as plaintext again
+    Decrypt,
+}
+
+#[derive(Subcommand)]
+enum ControllerCommand {
+    /// List controllers
+    List,
+    /// Attach a new controller to the account using its activation code
+    Register {
+        #[arg(long)]
+        code: String,
+        /// Display name for the controller
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Remove a controller from the account
+    Remove {
+        #[arg(long = "client-id")]
+        client_id: String,
+        /// Skip the confirmation prompt
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Show current and available firmware per controller
+    Firmware {
+        /// Request a firmware upgrade where a newer version is available
+        #[arg(long)]
+        upgrade: bool,
+        /// Limit to a single controller
+        #[arg(long = "client-id")]
+        client_id: Option<String>,
+    },
+    /// Check that a controller is online and when it was last heard from
+    Ping {
+        #[arg(long = "client-id")]
+        client_id: String,
+        /// Also resend this device's current state through the controller, timing the
+        /// round trip; use a lamp that is fine to switch
+        #[arg(long)]
+        test_device: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -50,6 +413,24 @@ enum DeviceCommand {
         /// Filter to a specific resource category
         #[arg(short, long, value_enum, default_value_t = DeviceKind::All)]
         kind: DeviceKind,
+        /// Only show entries whose name matches a pattern (`*` and `?` wildcards)
+        #[arg(long = "match")]
+        pattern: Option<String>,
+        /// Also list every account in ~/.config/telltales/profiles/, tagging rows by profile
+        #[arg(long)]
+        all_profiles: bool,
+        /// Group rows by the rooms in ~/.config/telltales/rooms.yaml
+        #[arg(long, conflicts_with = "all_profiles")]
+        by_room: bool,
+        /// Hide devices not starred with `devices favorite` (default with TELLTALES_FAVORITES=1)
+        #[arg(
+            long,
+            num_args = 0..=1,
+            default_missing_value = "true",
+            value_parser = BoolishValueParser::new(),
+            conflicts_with = "all_profiles"
+        )]
+        favorites: Option<bool>,
     },
     /// Update Telldus Live device metadata
     Edit {
@@ -62,20 +443,73 @@ enum DeviceCommand {
         #[arg(long)]
         model: Option<String>,
     },
+    /// Rename every matching device with a sed-style substitution, after a preview
+    Rename {
+        /// Only rename devices whose name matches this pattern (`*` and `?` wildcards)
+        #[arg(long = "match")]
+        pattern: Option<String>,
+        /// Substitution applied to each name, e.g. `s/Lamp/Light/` (flags: g, i)
+        #[arg(long, value_parser = Substitution::parse)]
+        replace: Substitution,
+        /// Rename without asking for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+    /// List the devices most recently commanded from telltales, newest first
+    Recent,
+    /// Star a device, so `devices list --favorites` shows it
+    Favorite {
+        #[arg(long = "id")]
+        device_id: String,
+        /// Unstar the device instead
+        #[arg(long)]
+        remove: bool,
+    },
     /// Register a new Telldus Live device
     Add {
-        #[arg(long = "client-id")]
-        client_id: String,
+        /// Controller for the new device; with --from, the default for rows without one
+        #[arg(long = "client-id", required_unless_present_any = ["wizard", "from"])]
+        client_id: Option<String>,
+        #[arg(long, required_unless_present_any = ["wizard", "from"])]
+        name: Option<String>,
+        #[arg(long, required_unless_present_any = ["wizard", "from", "preset"])]
+        protocol: Option<String>,
+        #[arg(long, required_unless_present_any = ["wizard", "from", "preset"])]
+        model: Option<String>,
+        /// Take the protocol and model from a preset such as `nexa-selflearning` and
+        /// generate its parameters; --parameter overrides them
+        #[arg(long, value_parser = parse_preset, conflicts_with_all = ["protocol", "model", "wizard", "from"])]
+        preset: Option<&'static protocols::Preset>,
+        #[arg(long = "parameter", value_parser = parse_key_value)]
+        parameters: Vec<KeyValue>,
         #[arg(long)]
-        name: String,
+        learn: bool,
+        /// Pick a random house code no other device with this protocol uses, and its first unit
+        #[arg(long = "generate-codes")]
+        generate_codes: bool,
+        /// Create the device even if its parameters do not fit the protocol catalog
         #[arg(long)]
-        protocol: String,
+        force: bool,
+        /// Pick the controller, protocol and model from lists and get prompted for parameters
+        #[arg(long, conflicts_with_all = ["client_id", "name", "protocol", "model", "parameters", "learn", "generate_codes", "force"])]
+        wizard: bool,
+        /// Create every device listed in a CSV or YAML file
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["name", "protocol", "model", "parameters", "learn", "generate_codes", "wizard"])]
+        from: Option<PathBuf>,
+    },
+    /// Create a new device on the same controller with another device's protocol, model and parameters
+    Clone {
+        #[arg(long = "id")]
+        device_id: String,
+        /// Name of the new device
         #[arg(long)]
-        model: String,
+        name: String,
+        /// Use this value instead of the copied one (repeatable), e.g. `unit=4`
         #[arg(long = "parameter", value_parser = parse_key_value)]
         parameters: Vec<KeyValue>,
+        /// Create the copy even if its parameters do not fit the protocol catalog
         #[arg(long)]
-        learn: bool,
+        force: bool,
     },
     /// Remove a device from Telldus Live
     Remove {
@@ -84,20 +518,50 @@ enum DeviceCommand {
     },
     /// Turn on a device
     On {
-        #[arg(long = "id")]
-        device_id: String,
+        #[arg(long = "id", required_unless_present = "room")]
+        device_id: Option<String>,
+        /// Re-read the device state afterwards and resend once if it did not change
+        #[arg(long)]
+        confirm: bool,
+        /// Turn the device off again after this long, e.g. 15m; waits in the foreground
+        #[arg(long = "for", value_parser = parse_duration, conflicts_with = "confirm")]
+        duration: Option<Duration>,
+        /// Every device in this room of ~/.config/telltales/rooms.yaml instead of one
+        #[arg(long, conflicts_with_all = ["device_id", "confirm", "duration"])]
+        room: Option<String>,
+        /// With --room, list the devices that would be switched without switching them
+        #[arg(long, requires = "room")]
+        dry_run: bool,
     },
     /// Turn off a device
     Off {
-        #[arg(long = "id")]
-        device_id: String,
+        #[arg(long = "id", required_unless_present = "room")]
+        device_id: Option<String>,
+        /// Re-read the device state afterwards and resend once if it did not change
+        #[arg(long)]
+        confirm: bool,
+        /// Every device in this room of ~/.config/telltales/rooms.yaml instead of one
+        #[arg(long, conflicts_with_all = ["device_id", "confirm"])]
+        room: Option<String>,
+        /// With --room, list the devices that would be switched without switching them
+        #[arg(long, requires = "room")]
+        dry_run: bool,
     },
     /// Dim a device to a level (0-255)
     Dim {
-        #[arg(long = "id")]
-        device_id: String,
+        #[arg(long = "id", required_unless_present = "room")]
+        device_id: Option<String>,
         #[arg(long, value_parser = clap::value_parser!(u8).range(0..=255))]
         level: u8,
+        /// Re-read the device state afterwards and resend once if it did not change
+        #[arg(long)]
+        confirm: bool,
+        /// Every device in this room of ~/.config/telltales/rooms.yaml instead of one
+        #[arg(long, conflicts_with_all = ["device_id", "confirm"])]
+        room: Option<String>,
+        /// With --room, list the devices that would be switched without switching them
+        #[arg(long, requires = "room")]
+        dry_run: bool,
     },
     /// Trigger a doorbell action
     Bell {
@@ -126,10 +590,40 @@ enum DeviceCommand {
         #[arg(long = "id")]
         device_id: String,
     },
+    /// Flash a device on and off to find which physical lamp it is
+    Blink {
+        #[arg(long = "id")]
+        device_id: String,
+        /// Number of on/off cycles
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..=20))]
+        times: u32,
+        /// Time between toggles, e.g. 1s or 2s
+        #[arg(long, value_parser = parse_duration, default_value = "1s")]
+        interval: Duration,
+    },
+    /// Poll a device until it reports the given state
+    WaitFor {
+        #[arg(long = "id")]
+        device_id: String,
+        #[arg(long, value_enum)]
+        state: WaitState,
+        /// Give up after this long, e.g. 60s, 2m or 1h30m
+        #[arg(long, value_parser = parse_duration, default_value = "60s")]
+        timeout: Duration,
+        /// Time between polls
+        #[arg(long, value_parser = parse_duration, default_value = "2s")]
+        interval: Duration,
+    },
     /// Put device into learning mode
     Learn {
         #[arg(long = "id")]
         device_id: String,
+        /// Guide pairing interactively with a countdown and retries
+        #[arg(long)]
+        wizard: bool,
+        /// Seconds the receiver listens for the learn signal
+        #[arg(long, default_value_t = LEARN_WINDOW, requires = "wizard")]
+        window: u64,
     },
     /// Inspect device details
     Info {
@@ -142,6 +636,21 @@ enum DeviceCommand {
         device_id: String,
         #[arg(long)]
         limit: Option<u32>,
+        /// Stream history from this Unix timestamp onwards, a day at a time
+        #[arg(long)]
+        from: Option<i64>,
+        /// End of the streamed range as a Unix timestamp (defaults to now)
+        #[arg(long, requires = "from")]
+        to: Option<i64>,
+        /// Keep polling and print new events as they arrive, like `tail -f`
+        #[arg(long, conflicts_with = "from")]
+        follow: bool,
+        /// Time between polls with --follow
+        #[arg(long, value_parser = parse_duration, default_value = "30s", requires = "follow")]
+        interval: Duration,
+        /// With --follow, only print events matching this expression, e.g. 'state==on'
+        #[arg(long, value_name = "EXPR", value_parser = parse_filter, requires = "follow")]
+        filter: Option<filter::Filter>,
     },
     /// Persist a device parameter key/value
     SetParameter {
@@ -151,6 +660,9 @@ enum DeviceCommand {
         parameter: String,
         #[arg(long)]
         value: String,
+        /// Set the value even if it does not fit the device's protocol
+        #[arg(long)]
+        force: bool,
     },
     /// Retrieve a device parameter value
     GetParameter {
@@ -161,7 +673,7 @@ enum DeviceCommand {
     },
 }
 
-#[derive(Copy, Clone, Debug, ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
 enum DeviceKind {
     All,
     Controllers,
@@ -171,21 +683,85 @@ enum DeviceKind {
 
 #[derive(Subcommand)]
 enum SensorCommand {
+    /// List sensors
+    List {
+        /// Include sensors hidden with `sensors ignore`
+        #[arg(long)]
+        include_ignored: bool,
+        /// Only show sensors whose name matches a pattern (`*` and `?` wildcards)
+        #[arg(long = "match")]
+        pattern: Option<String>,
+        /// Only show sensors whose model matches a pattern, e.g. `temperature*`
+        #[arg(long)]
+        model: Option<String>,
+        /// Only show sensors whose protocol matches a pattern, e.g. `fineoffset`
+        #[arg(long)]
+        protocol: Option<String>,
+        /// Only show sensors reporting this kind of reading, e.g. `temperature` or `power`
+        #[arg(long, value_parser = parse_reading_kind)]
+        kind: Option<ReadingKind>,
+    },
     /// Show sensor metadata
     Info {
         #[arg(long = "id")]
         sensor_id: String,
-        #[arg(long)]
-        scale: Option<i32>,
+        /// Telldus scale number, or a reading kind such as `temperature` to show only that value
+        #[arg(long, value_parser = parse_scale)]
+        scale: Option<Scale>,
     },
     /// Show historic sensor readings
     History {
         #[arg(long = "id")]
         sensor_id: String,
-        #[arg(long)]
-        scale: i32,
+        /// Telldus scale number, or a reading kind such as `temperature` to show only that value
+        #[arg(long, value_parser = parse_scale)]
+        scale: Scale,
         #[arg(long)]
         limit: Option<u32>,
+        /// Stream readings from this Unix timestamp onwards, a day at a time
+        #[arg(long)]
+        from: Option<i64>,
+        /// End of the streamed range as a Unix timestamp (defaults to now)
+        #[arg(long, requires = "from")]
+        to: Option<i64>,
+    },
+    /// Show the lowest, highest and mean reading per day
+    Summary {
+        #[arg(long = "id")]
+        sensor_id: String,
+        /// Number of days to cover, counting today
+        #[arg(long, default_value_t = 7, value_parser = clap::value_parser!(u32).range(1..=366))]
+        days: u32,
+        /// Telldus scale number, or a reading kind such as `temperature` to show only that value
+        #[arg(long, value_parser = parse_scale)]
+        scale: Option<Scale>,
+    },
+    /// Show readings from several sensors side by side on a common time axis
+    Compare {
+        /// Sensors to compare (repeat for each, at least two)
+        #[arg(long = "id", required = true, num_args = 1)]
+        sensor_ids: Vec<String>,
+        /// Reading kind such as `temperature`, or a Telldus scale number
+        #[arg(long, value_parser = parse_scale)]
+        scale: Scale,
+        /// How far back to compare, e.g. `24h` or `7d`
+        #[arg(long, value_parser = parse_duration, default_value = "24h")]
+        since: Duration,
+        /// Width of each time slot; readings within a slot are averaged
+        #[arg(long, value_parser = parse_duration, default_value = "1h")]
+        step: Duration,
+        /// Show the other sensors as differences from the first one
+        #[arg(long)]
+        delta: bool,
+    },
+    /// Push current readings to a Prometheus Pushgateway once, e.g. from cron
+    Push {
+        /// Pushgateway group URL such as http://host:9091/metrics/job/telldus
+        #[arg(long)]
+        pushgateway: String,
+        /// Include sensors hidden with `sensors ignore`
+        #[arg(long)]
+        include_ignored: bool,
     },
     /// Toggle ignore flag for a sensor
     Ignore {
@@ -196,6 +772,290 @@ enum SensorCommand {
     },
 }
 
+#[derive(Subcommand)]
+enum GroupCommand {
+    /// List group devices
+    List,
+    /// Turn on every member of a group with one command
+    On {
+        #[arg(long = "id")]
+        group_id: String,
+    },
+    /// Turn off every member of a group with one command
+    Off {
+        #[arg(long = "id")]
+        group_id: String,
+    },
+    /// Dim every member of a group to a level (0-255)
+    Dim {
+        #[arg(long = "id")]
+        group_id: String,
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=255))]
+        level: u8,
+    },
+}
+
+#[derive(Subcommand)]
+enum EventCommand {
+    /// List configured events
+    List,
+    /// Create an event
+    Create {
+        /// Build the event interactively
+        #[arg(long)]
+        wizard: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum NotifyCommand {
+    /// Send a push notification to registered phones
+    Push {
+        #[arg(long)]
+        message: String,
+        /// Limit delivery to specific phones (defaults to every registered phone)
+        #[arg(long = "phone-id")]
+        phone_ids: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SchedulerCommand {
+    /// List scheduler jobs
+    List,
+    /// Render scheduler jobs as a timeline
+    Show {
+        /// Show all seven weekdays instead of only today
+        #[arg(long)]
+        week: bool,
+    },
+    /// Export scheduler jobs for use in other tools
+    Export {
+        /// Calendar format to export as; `--format ics` is accepted too
+        #[arg(long, value_name = "FORMAT", value_enum, default_value_t = ExportFormat::Ics)]
+        to: ExportFormat,
+        /// Write to a file instead of standard output
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+    /// Create a scheduler job
+    Set {
+        #[arg(long = "id")]
+        device_id: String,
+        #[arg(long, value_enum)]
+        action: ScheduleAction,
+        /// Dim level (0-255) used with `--action dim`
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=255))]
+        level: Option<u8>,
+        /// Trigger time: 22:30, 10:30pm, noon, sunset-30m or "30 minutes before sunset"
+        #[arg(long, value_parser = ScheduleTime::parse)]
+        at: ScheduleTime,
+        /// Days to run on: daily, weekdays, weekends, or days such as mon,wed,fri or mon-fri
+        #[arg(long, value_parser = Weekdays::parse, default_value = "daily")]
+        days: Weekdays,
+        /// Skip a date, as 2024-12-24 or 12-24 for every year; repeatable. Telldus Live
+        /// cannot skip dates, so this prints a job for `daemon run` instead
+        #[arg(long, value_parser = ExceptDate::parse)]
+        except: Vec<ExceptDate>,
+        /// Create the job without asking to confirm the parsed schedule
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Remove a scheduler job
+    Remove {
+        #[arg(long = "job-id")]
+        job_id: String,
+    },
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum ExportFormat {
+    Ics,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum ScheduleAction {
+    On,
+    Off,
+    Dim,
+}
+
+impl ScheduleAction {
+    /// Telldus method number submitted with the job.
+    fn method(self) -> u32 {
+        match self {
+            ScheduleAction::On => 1,
+            ScheduleAction::Off => 2,
+            ScheduleAction::Dim => 16,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ScheduleAction::On => "on",
+            ScheduleAction::Off => "off",
+            ScheduleAction::Dim => "dim",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum LastAction {
+    On,
+    Off,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum WaitState {
+    On,
+    Off,
+    Dim,
+    Up,
+    Down,
+    Stop,
+}
+
+impl WaitState {
+    /// Telldus method number reported as the device state.
+    fn method(self) -> u32 {
+        match self {
+            WaitState::On => 1,
+            WaitState::Off => 2,
+            WaitState::Dim => 16,
+            WaitState::Up => 128,
+            WaitState::Down => 256,
+            WaitState::Stop => 512,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SchemaModel {
+    /// `devices list`
+    Devices,
+    /// `sensors list`
+    Sensors,
+    /// `devices history` and `sensors history`
+    History,
+}
+
+#[derive(Subcommand)]
+enum AlertsCommand {
+    /// Evaluate alert rules in the foreground until stopped
+    Run {
+        /// Alert rules file (defaults to ~/.config/telltales/alerts.yaml)
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Only print alert events matching this expression, e.g. 'event==fired'
+        #[arg(long, value_name = "EXPR", value_parser = parse_filter)]
+        filter: Option<filter::Filter>,
+    },
+    /// Validate the alert rules file and list its rules
+    Check {
+        /// Alert rules file (defaults to ~/.config/telltales/alerts.yaml)
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonCommand {
+    /// Execute schedules in the foreground until stopped
+    Run {
+        /// Schedules file (defaults to ~/.config/telltales/schedules.yaml)
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Write the process id here while running
+        #[arg(long, value_name = "PATH")]
+        pid_file: Option<PathBuf>,
+        /// Only print job runs matching this expression, e.g. 'result!=skipped'
+        #[arg(long, value_name = "EXPR", value_parser = parse_filter)]
+        filter: Option<filter::Filter>,
+    },
+    /// Validate the schedules file and list its jobs
+    Check {
+        /// Schedules file (defaults to ~/.config/telltales/schedules.yaml)
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+    /// Print a systemd user unit that runs the daemon at login and after reboots
+    InstallService {
+        /// Schedules file (defaults to ~/.config/telltales/schedules.yaml)
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Write the unit here instead of printing it
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommand {
+    /// Save the current controllers, devices, and sensors under a name
+    Save {
+        name: String,
+        /// Also capture per-device details such as parameters (one request per device)
+        #[arg(long)]
+        deep: bool,
+    },
+    /// Show what changed between two snapshots
+    Diff { before: String, after: String },
+    /// List saved snapshots
+    List,
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    /// Show the account profile
+    Profile,
+    /// Manage phones registered for push notifications
+    Phones {
+        #[command(subcommand)]
+        command: Option<PhoneCommand>,
+    },
+    /// Show remaining SMS credits
+    SmsCredits,
+    /// Send a test push notification to a phone
+    PushTest {
+        #[arg(long = "phone-id")]
+        phone_id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PhoneCommand {
+    /// List registered phones
+    List,
+}
+
+#[derive(Subcommand)]
+enum ZwaveCommand {
+    /// List Z-Wave nodes on a controller
+    Nodes {
+        #[arg(long = "client-id")]
+        client_id: String,
+    },
+    /// Read a node configuration parameter
+    GetConfig {
+        #[arg(long = "id")]
+        device_id: String,
+        #[arg(long)]
+        parameter: u8,
+    },
+    /// Write a node configuration parameter
+    SetConfig {
+        #[arg(long = "id")]
+        device_id: String,
+        #[arg(long)]
+        parameter: u8,
+        /// Parameter size in bytes (1, 2 or 4)
+        #[arg(long, value_parser = parse_zwave_size)]
+        size: u8,
+        #[arg(long, allow_negative_numbers = true)]
+        value: i64,
+    },
+}
+
 #[derive(Debug, Error)]
 enum AppError {
     #[error(transparent)]
@@ -205,68 +1065,444 @@ enum AppError {
     #[error(transparent)]
     Api(#[from] api::ApiError),
     #[error(transparent)]
+    Snapshot(#[from] snapshot::SnapshotError),
+    #[error(transparent)]
+    Daemon(#[from] daemon::DaemonError),
+    #[error(transparent)]
+    Alerts(#[from] alerts::AlertsError),
+    #[error(transparent)]
+    Rooms(#[from] rooms::RoomsError),
+    #[error(transparent)]
+    Serve(#[from] serve::ServeError),
+    #[error(transparent)]
+    Metrics(#[from] metrics::MetricsError),
+    #[error(transparent)]
+    Provision(#[from] provision::ProvisionError),
+    #[error(transparent)]
+    Favorites(#[from] favorites::FavoritesError),
+    #[error(transparent)]
+    Audit(#[from] audit::AuditError),
+    #[error(transparent)]
     Http(#[from] reqwest::Error),
+    #[error("prompt failed: {0}")]
+    Prompt(#[from] dialoguer::Error),
+    #[error("failed to write {0}: {1}")]
+    WriteFailed(String, #[source] io::Error),
+    #[error("cannot lock {0}: {1}")]
+    LockFailed(String, #[source] io::Error),
+    #[error("{0} diagnostic check(s) failed")]
+    ChecksFailed(usize),
+    #[error("controller {0} is offline")]
+    ControllerOffline(String),
+    #[error("device {0} did not report the requested state, even after resending")]
+    Unconfirmed(String),
+    #[error("device {0} did not reach state '{1}' within {2}s")]
+    StateTimeout(String, String, u64),
+    #[error("{0} problem(s) in the device definition(s); fix them or pass --force")]
+    InvalidDefinition(usize),
+    #[error("{0} of {1} devices could not be created")]
+    ProvisionIncomplete(usize, usize),
+    #[error("{0} of {1} devices in {2} could not be switched")]
+    RoomIncomplete(usize, usize, String),
     #[error("{0}")]
     Usage(String),
+    #[error("interrupted")]
+    Interrupted,
 }
 
-#[derive(Clone, Debug)]
-struct KeyValue {
-    key: String,
+impl AppError {
+    /// Whether the command stopped because of Ctrl-C rather than a failure.
+    fn is_interrupted(&self) -> bool {
+        matches!(
+            self,
+            AppError::Interrupted
+                | AppError::Api(api::ApiError::Interrupted)
+                | AppError::Auth(auth::AuthError::Interrupted)
+        )
+    }
+}
+
+/// Stop a loop between requests once Ctrl-C was pressed.
+fn check_interrupted() -> Result<(), AppError> {
+    if service::shutdown_requested() {
+        return Err(AppError::Interrupted);
+    }
+    Ok(())
+}
+
+/// Flags of `devices add` that apply to however the device is described.
+#[derive(Clone, Copy, Debug)]
+struct AddOptions {
+    learn: bool,
+    generate_codes: bool,
+    force: bool,
+}
+
+#[derive(Clone, Debug)]
+struct KeyValue {
+    key: String,
     value: String,
 }
 
 fn main() -> ExitCode {
-    let cli = Cli::parse();
-    if let Err(err) = run(cli) {
-        eprintln!("Error: {err}");
-        ExitCode::FAILURE
-    } else {
-        ExitCode::SUCCESS
+    let mut cli = Cli::parse();
+    if cli.timing {
+        timing::enable();
+    }
+    output::set_format(cli.output);
+    output::set_color(cli.color);
+    output::set_wide(cli.wide);
+    if let Some(query) = cli.query.clone() {
+        output::set_query(query);
+    }
+    // `scheduler export --format ics` names the calendar format, not an output template.
+    if let Some(Commands::Scheduler {
+        command: Some(SchedulerCommand::Export { to, .. }),
+    }) = &mut cli.command
+        && let Some(format) = cli.format.take()
+    {
+        match ExportFormat::from_str(&format, true) {
+            Ok(format) => *to = format,
+            Err(_) => {
+                eprintln!(
+                    "Error: scheduler export cannot write '{format}'; choose a calendar format with --to ics"
+                );
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if let Some(template) = &cli.format {
+        output::set_template(template);
+    }
+    let _ = RETRY_POLICY.set(RetryPolicy {
+        max_attempts: cli.retries.saturating_add(1),
+        backoff: RETRY_BACKOFF,
+        retry_unsafe: cli.retry_unsafe,
+    });
+    if let Some(timeout) = cli.timeout {
+        let _ = REQUEST_TIMEOUT.set(timeout);
+    }
+    if let Some(dir) = cli.record.clone() {
+        fixtures::set_mode(fixtures::Mode::Record(dir));
+    } else if let Some(dir) = cli.replay.clone() {
+        fixtures::set_mode(fixtures::Mode::Replay(dir));
+    }
+    config::allow_insecure(cli.insecure_config);
+    audit::enable();
+    if !cli.no_cache {
+        response_cache::enable();
+    }
+    match cli.cache_ttl.map(Ok).or_else(|| {
+        env::var(CACHE_TTL_VAR)
+            .ok()
+            .filter(|ttl| !ttl.trim().is_empty())
+            .map(|ttl| parse_duration(&ttl))
+    }) {
+        Some(Ok(ttl)) => response_cache::set_ttl(ttl),
+        Some(Err(err)) => {
+            eprintln!("Error: {CACHE_TTL_VAR}: {err}");
+            return ExitCode::FAILURE;
+        }
+        None => {}
+    }
+    service::handle_signals();
+    auth::configure_callback(auth::CallbackOptions {
+        https: cli.callback_https,
+        listen: cli.callback_listen,
+        url: cli.callback_url.clone(),
+    });
+    if let Some(path) = cli
+        .credentials_file
+        .clone()
+        .or_else(|| env::var_os(CONFIG_VAR).map(PathBuf::from))
+        .filter(|path| !path.as_os_str().is_empty())
+    {
+        config::set_credentials_file(path);
+    }
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR)
+        && !passphrase.is_empty()
+    {
+        config::set_passphrase(passphrase);
+    }
+    if let Some(agent) = cli
+        .user_agent
+        .clone()
+        .or_else(|| env::var(USER_AGENT_VAR).ok())
+        .filter(|agent| !agent.trim().is_empty())
+    {
+        match parse_user_agent(&agent) {
+            Ok(agent) => http_client::set_user_agent(agent),
+            Err(err) => {
+                eprintln!("Error: {USER_AGENT_VAR}: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
     }
+    if let Some(endpoint) = cli
+        .otlp_endpoint
+        .clone()
+        .or_else(|| env::var(OTLP_ENDPOINT_VAR).ok())
+        .filter(|endpoint| !endpoint.trim().is_empty())
+        && let Err(err) = otlp::enable(&endpoint)
+    {
+        eprintln!("warning: cannot export traces: {err}");
+    }
+    // Held until main returns.
+    let _lock = match cli.exclusive.then(lock_account).transpose() {
+        Ok(lock) => lock,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = run(cli);
+    let _ = io::stdout().flush();
+    otlp::flush();
+    timing::print_summary();
+    match result {
+        Err(err) if err.is_interrupted() => {
+            eprintln!("Interrupted.");
+            ExitCode::from(service::INTERRUPTED_EXIT)
+        }
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::FAILURE
+        }
+        Ok(()) => ExitCode::SUCCESS,
+    }
+}
+
+/// Take the `--exclusive` lock, a file next to the credentials it protects.
+fn lock_account() -> Result<lock::FileLock, AppError> {
+    let path = credentials_path()?.with_extension("lock");
+    lock::FileLock::acquire(&path, || {
+        eprintln!("Waiting for another telltales --exclusive run to finish...");
+    })
+    .map_err(|err| AppError::LockFailed(path.display().to_string(), err))
 }
 
 fn run(cli: Cli) -> Result<(), AppError> {
+    response_cache::set_refresh(cli.refresh);
     match cli.command.unwrap_or(Commands::Auth {
         command: Some(AuthCommand::Validate),
     }) {
         Commands::Auth { command } => match command.unwrap_or(AuthCommand::Validate) {
             AuthCommand::Validate => handle_validate(),
+            AuthCommand::Status => handle_auth_status(),
+        },
+        Commands::Config { command } => handle_config(command),
+        Commands::Controllers { command } => match command.unwrap_or(ControllerCommand::List) {
+            ControllerCommand::List => {
+                handle_devices_list(DeviceKind::Controllers, None, false, FavoritesFilter::All)
+            }
+            ControllerCommand::Register { code, name } => {
+                handle_controller_register(&code, name.as_deref())
+            }
+            ControllerCommand::Remove { client_id, yes } => {
+                handle_controller_remove(&client_id, yes)
+            }
+            ControllerCommand::Firmware { upgrade, client_id } => {
+                handle_controller_firmware(upgrade, client_id.as_deref())
+            }
+            ControllerCommand::Ping {
+                client_id,
+                test_device,
+            } => handle_controller_ping(&client_id, test_device.as_deref()),
         },
         Commands::Devices { command } => match command.unwrap_or(DeviceCommand::List {
             kind: DeviceKind::All,
+            pattern: None,
+            all_profiles: false,
+            by_room: false,
+            favorites: None,
         }) {
-            DeviceCommand::List { kind } => handle_devices_list(kind),
+            DeviceCommand::List {
+                kind,
+                pattern,
+                all_profiles: false,
+                by_room,
+                favorites,
+            } => handle_devices_list(kind, pattern.as_deref(), by_room, favorites.into()),
+            DeviceCommand::List {
+                kind,
+                pattern,
+                all_profiles: true,
+                ..
+            } => handle_devices_list_all_profiles(kind, pattern.as_deref()),
             DeviceCommand::Edit {
                 device_id,
                 name,
                 protocol,
                 model,
             } => handle_devices_edit(&device_id, name, protocol, model),
+            DeviceCommand::Rename {
+                pattern,
+                replace,
+                yes,
+            } => handle_devices_rename(pattern.as_deref(), &replace, yes),
+            DeviceCommand::Recent => handle_devices_recent(),
+            DeviceCommand::Favorite { device_id, remove } => {
+                handle_devices_favorite(&device_id, remove)
+            }
+            DeviceCommand::Add { wizard: true, .. } => handle_device_add_wizard(),
             DeviceCommand::Add {
+                from: Some(path),
                 client_id,
-                name,
-                protocol,
-                model,
+                force,
+                ..
+            } => handle_device_add_from(&path, client_id.as_deref(), force),
+            DeviceCommand::Add {
+                preset: Some(preset),
+                client_id: Some(client_id),
+                name: Some(name),
+                parameters,
+                learn,
+                generate_codes,
+                force,
+                ..
+            } => handle_device_add_preset(
+                &client_id,
+                &name,
+                preset,
+                parameters,
+                AddOptions {
+                    learn,
+                    generate_codes,
+                    force,
+                },
+            ),
+            DeviceCommand::Add {
+                client_id: Some(client_id),
+                name: Some(name),
+                protocol: Some(protocol),
+                model: Some(model),
                 parameters,
                 learn,
-            } => handle_device_add(&client_id, &name, &protocol, &model, parameters, learn),
+                generate_codes,
+                force,
+                ..
+            } => handle_device_add(
+                &client_id,
+                &name,
+                &protocol,
+                &model,
+                parameters,
+                AddOptions {
+                    learn,
+                    generate_codes,
+                    force,
+                },
+            ),
+            DeviceCommand::Add { .. } => Err(AppError::Usage(
+                "devices add needs --client-id, --name and either --protocol and --model or --preset, or --wizard."
+                    .into(),
+            )),
+            DeviceCommand::Clone {
+                device_id,
+                name,
+                parameters,
+                force,
+            } => handle_device_clone(&device_id, &name, parameters, force),
             DeviceCommand::Remove { device_id } => handle_device_remove(&device_id),
-            DeviceCommand::On { device_id } => handle_device_simple(
+            DeviceCommand::On {
+                room: Some(room),
+                dry_run,
+                ..
+            } => handle_room_action(
+                &room,
+                dry_run,
+                "turn on",
+                |api, id| api.device_turn_on(id),
+                "turned on",
+            ),
+            DeviceCommand::Off {
+                room: Some(room),
+                dry_run,
+                ..
+            } => handle_room_action(
+                &room,
+                dry_run,
+                "turn off",
+                |api, id| api.device_turn_off(id),
+                "turned off",
+            ),
+            DeviceCommand::Dim {
+                room: Some(room),
+                level,
+                dry_run,
+                ..
+            } => handle_room_action(
+                &room,
+                dry_run,
+                &format!("dim to level {level}"),
+                move |api, id| api.device_dim(id, level),
+                &format!("dimmed to level {level}"),
+            ),
+            DeviceCommand::On {
+                device_id: Some(device_id),
+                duration: Some(duration),
+                ..
+            } => handle_device_on_for(&device_id, duration),
+            DeviceCommand::On {
+                device_id: Some(device_id),
+                confirm: true,
+                ..
+            } => handle_device_confirmed(
                 &device_id,
                 |api, id| api.device_turn_on(id),
-                || "Turned device on.".into(),
+                DeviceState {
+                    method: WaitState::On.method(),
+                    level: None,
+                },
+                "Turned device on.",
             ),
-            DeviceCommand::Off { device_id } => handle_device_simple(
+            DeviceCommand::On {
+                device_id: Some(device_id),
+                ..
+            } => handle_device_switch(&device_id, true),
+            DeviceCommand::Off {
+                device_id: Some(device_id),
+                confirm: true,
+                ..
+            } => handle_device_confirmed(
                 &device_id,
                 |api, id| api.device_turn_off(id),
-                || "Turned device off.".into(),
+                DeviceState {
+                    method: WaitState::Off.method(),
+                    level: None,
+                },
+                "Turned device off.",
+            ),
+            DeviceCommand::Off {
+                device_id: Some(device_id),
+                ..
+            } => handle_device_switch(&device_id, false),
+            DeviceCommand::Dim {
+                device_id: Some(device_id),
+                level,
+                confirm: true,
+                ..
+            } => handle_device_confirmed(
+                &device_id,
+                |api, id| api.device_dim(id, level),
+                DeviceState {
+                    method: WaitState::Dim.method(),
+                    level: Some(level),
+                },
+                &format!("Dimmed device to level {level}."),
             ),
-            DeviceCommand::Dim { device_id, level } => handle_device_simple(
+            DeviceCommand::Dim {
+                device_id: Some(device_id),
+                level,
+                ..
+            } => handle_device_simple(
                 &device_id,
                 move |api, id| api.device_dim(id, level),
                 move || format!("Dimmed device to level {level}."),
             ),
+            DeviceCommand::On { .. } | DeviceCommand::Off { .. } | DeviceCommand::Dim { .. } => {
+                Err(AppError::Usage("Give a device with --id or a room with --room.".into()))
+            }
             DeviceCommand::Bell { device_id } => handle_device_simple(
                 &device_id,
                 |api, id| api.device_bell(id),
@@ -292,48 +1528,252 @@ fn run(cli: Cli) -> Result<(), AppError> {
                 |api, id| api.device_down(id),
                 || "Sent down command.".into(),
             ),
-            DeviceCommand::Learn { device_id } => handle_device_simple(
+            DeviceCommand::Blink {
+                device_id,
+                times,
+                interval,
+            } => handle_device_blink(&device_id, times, interval),
+            DeviceCommand::WaitFor {
+                device_id,
+                state,
+                timeout,
+                interval,
+            } => handle_device_wait_for(&device_id, state, timeout, interval),
+            DeviceCommand::Learn {
+                device_id,
+                wizard: true,
+                window,
+            } => handle_device_learn_wizard(&device_id, window),
+            DeviceCommand::Learn { device_id, .. } => handle_device_simple(
                 &device_id,
                 |api, id| api.device_learn(id),
                 || "Device put into learn mode.".into(),
             ),
             DeviceCommand::Info { device_id } => handle_device_info(&device_id),
-            DeviceCommand::History { device_id, limit } => handle_device_history(&device_id, limit),
+            DeviceCommand::History {
+                device_id,
+                limit,
+                follow: true,
+                interval,
+                filter,
+                ..
+            } => handle_device_follow(&device_id, limit, interval, filter.as_ref()),
+            DeviceCommand::History {
+                device_id,
+                limit,
+                from,
+                to,
+                ..
+            } => handle_device_history(&device_id, limit, from.map(|from| (from, to))),
             DeviceCommand::SetParameter {
                 device_id,
                 parameter,
                 value,
-            } => handle_device_set_parameter(&device_id, &parameter, &value),
+                force,
+            } => handle_device_set_parameter(&device_id, &parameter, &value, force),
             DeviceCommand::GetParameter {
                 device_id,
                 parameter,
             } => handle_device_get_parameter(&device_id, &parameter),
         },
         Commands::Sensors { command } => match command {
+            Some(SensorCommand::List {
+                include_ignored,
+                pattern,
+                model,
+                protocol,
+                kind,
+            }) => handle_sensors_list(
+                include_ignored,
+                &SensorFilter {
+                    pattern,
+                    model,
+                    protocol,
+                    kind,
+                },
+            ),
             Some(SensorCommand::Info { sensor_id, scale }) => handle_sensor_info(&sensor_id, scale),
             Some(SensorCommand::History {
                 sensor_id,
                 scale,
                 limit,
-            }) => handle_sensor_history(&sensor_id, scale, limit),
+                from,
+                to,
+            }) => handle_sensor_history(&sensor_id, scale, limit, from.map(|from| (from, to))),
+            Some(SensorCommand::Summary {
+                sensor_id,
+                days,
+                scale,
+            }) => handle_sensor_summary(&sensor_id, days, scale),
+            Some(SensorCommand::Compare {
+                sensor_ids,
+                scale,
+                since,
+                step,
+                delta,
+            }) => handle_sensor_compare(&sensor_ids, scale, since, step, delta),
+            Some(SensorCommand::Push {
+                pushgateway,
+                include_ignored,
+            }) => handle_sensors_push(&pushgateway, include_ignored),
             Some(SensorCommand::Ignore { sensor_id, ignored }) => {
                 handle_sensor_ignore(&sensor_id, ignored)
             }
             None => Err(AppError::Usage(
-                "Specify a sensors subcommand (info/history/ignore).".into(),
+                "Specify a sensors subcommand (list/info/history/summary/compare/push/ignore)."
+                    .into(),
+            )),
+        },
+        Commands::Groups { command } => match command.unwrap_or(GroupCommand::List) {
+            GroupCommand::List => handle_groups_list(),
+            GroupCommand::On { group_id } => handle_group_action(
+                &group_id,
+                |api, id| api.device_turn_on(id),
+                || "turned on".into(),
+            ),
+            GroupCommand::Off { group_id } => handle_group_action(
+                &group_id,
+                |api, id| api.device_turn_off(id),
+                || "turned off".into(),
+            ),
+            GroupCommand::Dim { group_id, level } => handle_group_action(
+                &group_id,
+                move |api, id| api.device_dim(id, level),
+                move || format!("dimmed to level {level}"),
+            ),
+        },
+        Commands::Events { command } => match command.unwrap_or(EventCommand::List) {
+            EventCommand::List => handle_events_list(),
+            EventCommand::Create { wizard: true } => handle_event_wizard(),
+            EventCommand::Create { wizard: false } => Err(AppError::Usage(
+                "Events are built interactively; rerun with --wizard.".into(),
+            )),
+        },
+        Commands::Notify { command } => match command {
+            NotifyCommand::Push { message, phone_ids } => handle_notify_push(&message, phone_ids),
+        },
+        Commands::Scheduler { command } => match command.unwrap_or(SchedulerCommand::List) {
+            SchedulerCommand::List => handle_scheduler_list(),
+            SchedulerCommand::Show { week } => handle_scheduler_show(week),
+            SchedulerCommand::Export { to, out } => handle_scheduler_export(to, out.as_deref()),
+            SchedulerCommand::Set {
+                device_id,
+                action,
+                level,
+                at,
+                days,
+                except,
+                yes,
+            } => handle_scheduler_set(&device_id, action, level, at, &days, &except, yes),
+            SchedulerCommand::Remove { job_id } => handle_scheduler_remove(&job_id),
+        },
+        Commands::Battery { all, threshold } => handle_battery(all, threshold),
+        Commands::Snapshot { command } => match command {
+            SnapshotCommand::Save { name, deep } => handle_snapshot_save(&name, deep),
+            SnapshotCommand::Diff { before, after } => handle_snapshot_diff(&before, &after),
+            SnapshotCommand::List => handle_snapshot_list(),
+        },
+        Commands::Serve { listen, token } => handle_serve(listen, token),
+        Commands::Energy {
+            command:
+                Some(EnergyCommand::Watch {
+                    interval,
+                    spike,
+                    heartbeat,
+                    sensor_ids,
+                    filter,
+                }),
+            ..
+        } => handle_energy_watch(interval, spike, heartbeat, &sensor_ids, filter.as_ref()),
+        Commands::Energy {
+            command: None,
+            since,
+            sensor_ids,
+        } => handle_energy(since, &sensor_ids),
+        Commands::Schema { model } => {
+            handle_schema(model);
+            Ok(())
+        }
+        Commands::Doctor => handle_doctor(),
+        Commands::Alerts { command } => {
+            match command.unwrap_or(AlertsCommand::Run {
+                file: None,
+                filter: None,
+            }) {
+                AlertsCommand::Run { file, filter } => handle_alerts_run(file, filter.as_ref()),
+                AlertsCommand::Check { file } => handle_alerts_check(file),
+            }
+        }
+        Commands::Daemon { command } => {
+            match command.unwrap_or(DaemonCommand::Run {
+                file: None,
+                pid_file: None,
+                filter: None,
+            }) {
+                DaemonCommand::Run {
+                    file,
+                    pid_file,
+                    filter,
+                } => handle_daemon_run(file, pid_file, filter.as_ref()),
+                DaemonCommand::Check { file } => handle_daemon_check(file),
+                DaemonCommand::InstallService { file, out } => {
+                    handle_daemon_install_service(file, out)
+                }
+            }
+        }
+        Commands::Log { limit, id, since } => handle_log(limit, id.as_deref(), since),
+        Commands::Stats => handle_stats(),
+        Commands::Last { action } => handle_last(action),
+        Commands::Undo { yes } => handle_undo(yes),
+        Commands::Completions { shell } => {
+            print!("{}", completions::script(shell));
+            Ok(())
+        }
+        Commands::Complete { words } => {
+            handle_complete(&words);
+            Ok(())
+        }
+        Commands::Protocols { command } => {
+            match command.unwrap_or(ProtocolsCommand::List { builtin: false }) {
+                ProtocolsCommand::List { builtin } => handle_protocols_list(builtin),
+            }
+        }
+        Commands::User { command } => match command.unwrap_or(UserCommand::Profile) {
+            UserCommand::Profile => handle_user_profile(),
+            UserCommand::Phones { command } => match command.unwrap_or(PhoneCommand::List) {
+                PhoneCommand::List => handle_phones_list(),
+            },
+            UserCommand::SmsCredits => handle_sms_credits(),
+            UserCommand::PushTest { phone_id } => handle_push_test(&phone_id),
+        },
+        Commands::Zwave { command } => match command {
+            Some(ZwaveCommand::Nodes { client_id }) => handle_zwave_nodes(&client_id),
+            Some(ZwaveCommand::GetConfig {
+                device_id,
+                parameter,
+            }) => handle_zwave_get_config(&device_id, parameter),
+            Some(ZwaveCommand::SetConfig {
+                device_id,
+                parameter,
+                size,
+                value,
+            }) => handle_zwave_set_config(&device_id, parameter, size, value),
+            None => Err(AppError::Usage(
+                "Specify a zwave subcommand (nodes/get-config/set-config).".into(),
             )),
         },
     }
 }
 
 fn handle_validate() -> Result<(), AppError> {
-    let mut credentials = ensure_credentials()?;
+    ensure_credentials()?;
     let location = credentials_path()?;
-    println!("Using credentials file at {}", location.to_string_lossy());
+    eprintln!("Using credentials file at {}", location.to_string_lossy());
 
-    let outcome = auth::validate(&mut credentials)?;
+    let (_, outcome) = config::refresh_credentials_at(&location, |credentials| {
+        auth::validate(credentials).map_err(AppError::from)
+    })?;
     if outcome.tokens_refreshed {
-        save_credentials(&credentials)?;
         println!("Stored refreshed OAuth access token.");
     }
 
@@ -345,48 +1785,475 @@ fn handle_validate() -> Result<(), AppError> {
     Ok(())
 }
 
-fn handle_devices_list(kind: DeviceKind) -> Result<(), AppError> {
+fn handle_auth_status() -> Result<(), AppError> {
+    let location = credentials_path()?;
+    println!("{:<14} {}", "Credentials", location.to_string_lossy());
+    let Some(mut credentials) = config::load_credentials()? else {
+        println!("{:<14} missing; run `telltales auth validate`", "Status");
+        return Ok(());
+    };
+    let now = unix_now().max(0) as u64;
+    let when = |timestamp: Option<u64>, unknown: &str| match timestamp {
+        Some(timestamp) => format!(
+            "{} ({})",
+            schedule::local_time(timestamp),
+            describe_age(now.saturating_sub(timestamp))
+        ),
+        None => unknown.to_string(),
+    };
+    let has_token =
+        !credentials.token.trim().is_empty() && !credentials.token_secret.trim().is_empty();
+    println!(
+        "{:<14} {}",
+        "Consumer keys",
+        if credentials.is_complete() {
+            "present"
+        } else {
+            "missing"
+        }
+    );
+    println!(
+        "{:<14} {}",
+        "Access token",
+        if has_token { "present" } else { "missing" }
+    );
+    println!(
+        "{:<14} {}",
+        "Issued",
+        when(credentials.token_created, "unknown")
+    );
+    println!(
+        "{:<14} {}",
+        "Last verified",
+        when(credentials.last_verified, "never")
+    );
+    if !credentials.is_complete() || !has_token {
+        println!(
+            "{:<14} not authorized; run `telltales auth validate`",
+            "Status"
+        );
+        return Ok(());
+    }
+
+    let client = shared_client()?;
+    let verified = config::update_credentials_at(&location, |stored| {
+        let name = auth::verify(&client, stored);
+        // A rejected token is reported below rather than failing the update.
+        credentials = stored.clone();
+        Ok::<_, config::ConfigError>(name)
+    })?
+    .1;
+    match verified {
+        Ok(name) => {
+            match name {
+                Some(name) => println!("{:<14} valid, authenticated as {name}", "Status"),
+                None => println!("{:<14} valid", "Status"),
+            }
+            Ok(())
+        }
+        Err(auth::AuthError::Unauthorized) => {
+            println!("{:<14} rejected by Telldus Live", "Status");
+            if let Some(hint) = auth::old_token_hint(&credentials, now) {
+                println!("{hint}");
+            }
+            println!("Run `telltales auth validate` to re-authorize.");
+            Err(auth::AuthError::Unauthorized.into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn handle_config(command: ConfigCommand) -> Result<(), AppError> {
+    let location = credentials_path()?;
+    match command {
+        ConfigCommand::Encrypt => {
+            config::encrypt_credentials_file(&location)?;
+            println!("Encrypted {}.", location.to_string_lossy());
+        }
+        ConfigCommand::Decrypt => {
+            config::decrypt_credentials_file(&location)?;
+            println!("Decrypted {}.", location.to_string_lossy());
+        }
+    }
+    Ok(())
+}
+
+/// Which devices `devices list` keeps, from its `--favorites` flag.
+#[derive(Clone, Copy)]
+enum FavoritesFilter {
+    /// Every device, whatever TELLTALES_FAVORITES says.
+    All,
+    /// Only starred devices; an error while none are.
+    Only,
+    /// Starred devices when TELLTALES_FAVORITES is set, otherwise every device.
+    FromEnvironment,
+}
+
+impl From<Option<bool>> for FavoritesFilter {
+    fn from(flag: Option<bool>) -> Self {
+        match flag {
+            Some(true) => Self::Only,
+            Some(false) => Self::All,
+            None => Self::FromEnvironment,
+        }
+    }
+}
+
+fn handle_devices_list(
+    kind: DeviceKind,
+    pattern: Option<&str>,
+    by_room: bool,
+    favorites_filter: FavoritesFilter,
+) -> Result<(), AppError> {
+    let rooms = by_room.then(load_rooms).transpose()?;
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    let mut entries = match kind {
-        DeviceKind::All => {
-            let mut combined = Vec::new();
-            combined.extend(api.list_controllers()?);
-            combined.extend(api.list_devices()?);
-            combined.extend(api.list_sensors()?);
-            combined
+    // Favorites from TELLTALES_FAVORITES only apply once some device is starred.
+    let favorites = match favorites_filter {
+        FavoritesFilter::Only => {
+            let favorites = favorites::load(&session.credentials)?;
+            if favorites.devices.is_empty() {
+                return Err(AppError::Usage(
+                    "No favorite devices yet; star one with `devices favorite --id <ID>`.".into(),
+                ));
+            }
+            Some(favorites)
         }
-        DeviceKind::Controllers => api.list_controllers()?,
-        DeviceKind::Devices => api.list_devices()?,
-        DeviceKind::Sensors => api.list_sensors()?,
+        FavoritesFilter::All => None,
+        FavoritesFilter::FromEnvironment
+            if env::var(FAVORITES_VAR)
+                .is_ok_and(|value| matches!(value.trim(), "1" | "true" | "yes" | "on")) =>
+        {
+            Some(favorites::load(&session.credentials)?)
+                .filter(|favorites| !favorites.devices.is_empty())
+        }
+        FavoritesFilter::FromEnvironment => None,
     };
+    let api = session.api()?;
+    // Like name resolution, `--match` is answered from recently cached lists.
+    let mut entries = match pattern {
+        Some(_) => response_cache::with_ttl(cache::NAME_TTL, || fetch_entries(&api, kind))?,
+        None => fetch_entries(&api, kind)?,
+    };
+    if let Some(pattern) = pattern {
+        entries.retain(|entry| cache::matches_pattern(pattern, &entry.name));
+    }
+    if let Some(favorites) = &favorites {
+        entries.retain(|entry| entry.category != Category::Device || favorites.contains(&entry.id));
+    }
+    sort_entries(&mut entries);
+    if let Some(rooms) = rooms {
+        return print_entries_by_room(&rooms, entries);
+    }
 
+    if output::is_json() {
+        return print_resources(&entries);
+    }
     if entries.is_empty() {
         println!("No resources returned for the selected filter.");
         return Ok(());
     }
+    print_entries(entries);
+    Ok(())
+}
 
-    entries.sort_by(|a, b| {
-        a.category
-            .as_str()
-            .cmp(b.category.as_str())
-            .then(a.name.cmp(&b.name))
-            .then(a.id.cmp(&b.id))
-    });
+fn handle_devices_recent() -> Result<(), AppError> {
+    let recent = recent::load(&stored_account()?).devices;
+    if recent.is_empty() {
+        println!("No devices have been commanded from telltales yet.");
+        return Ok(());
+    }
+    let session = authenticate()?;
+    let api = session.api()?;
+    let devices = api.list_devices()?;
+    let now = unix_now().max(0) as u64;
+    let rows: Vec<(&recent::RecentDevice, Option<&Entry>)> = recent
+        .iter()
+        .map(|item| (item, devices.iter().find(|device| device.id == item.id)))
+        .collect();
 
-    println!();
-    println!("{:<12} {:<12} {:<32} {}", "TYPE", "ID", "NAME", "DETAILS");
-    for entry in entries {
-        let details = entry.details.unwrap_or_else(|| "-".into());
-        println!(
-            "{:<12} {:<12} {:<32} {}",
-            entry.category.as_str(),
-            entry.id,
-            entry.name,
-            details
-        );
+    if output::is_json() {
+        let resources: Vec<serde_json::Value> = rows
+            .iter()
+            .map(|(item, device)| {
+                serde_json::json!({
+                    "id": item.id,
+                    "name": device.map(|device| device.name.as_str()),
+                    "state": device.and_then(|device| device.state).map(api::method_name),
+                    "commanded_at": item.commanded_at,
+                })
+            })
+            .collect();
+        output::print(&resources);
+        return Ok(());
+    }
+    let mut table = Table::new(&["ID", "NAME", "STATE", "COMMANDED"]);
+    for (item, device) in rows {
+        let (name, state) = match device {
+            Some(device) => (
+                device.name.clone(),
+                device.state.map_or("unknown", api::method_name),
+            ),
+            None => ("(removed)".to_string(), "-"),
+        };
+        table.row([
+            Cell::from(item.id.as_str()),
+            Cell::styled(name, Style::for_state(state)),
+            Cell::from(state),
+            Cell::from(describe_age(now.saturating_sub(item.commanded_at))),
+        ]);
+    }
+    table.print();
+    Ok(())
+}
+
+fn handle_last(action: LastAction) -> Result<(), AppError> {
+    let Some(last) = recent::load(&stored_account()?).devices.into_iter().next() else {
+        return Err(AppError::Usage(
+            "No device has been commanded from telltales yet.".into(),
+        ));
+    };
+    let id = last.id;
+    match action {
+        LastAction::On => handle_device_simple(
+            &id,
+            |api, id| api.device_turn_on(id),
+            || format!("Turned device {id} on."),
+        ),
+        LastAction::Off => handle_device_simple(
+            &id,
+            |api, id| api.device_turn_off(id),
+            || format!("Turned device {id} off."),
+        ),
+    }
+}
+
+fn handle_devices_favorite(device_id: &str, remove: bool) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = resolve_device(&api, device_id)?;
+    let mut favorites = favorites::load(&session.credentials)?;
+    if remove {
+        if !favorites.remove(&device_id) {
+            println!("Device {device_id} is not a favorite.");
+            return Ok(());
+        }
+        favorites::save(&session.credentials, &favorites)?;
+        println!("Removed device {device_id} from favorites.");
+    } else {
+        if !favorites.add(&device_id) {
+            println!("Device {device_id} is already a favorite.");
+            return Ok(());
+        }
+        favorites::save(&session.credentials, &favorites)?;
+        println!("Added device {device_id} to favorites.");
+    }
+    Ok(())
+}
+
+fn load_rooms() -> Result<rooms::Rooms, AppError> {
+    let path = rooms::default_path()?;
+    if !path.exists() {
+        return Err(AppError::Usage(format!(
+            "No rooms defined; list the devices in each room in {}.",
+            path.display()
+        )));
+    }
+    Ok(rooms::load(&path)?)
+}
+
+/// List entries room by room, with rooms in alphabetical order, then those in no room.
+fn print_entries_by_room(rooms: &rooms::Rooms, entries: Vec<Entry>) -> Result<(), AppError> {
+    let mut rows: Vec<(Option<&str>, Entry)> = entries
+        .into_iter()
+        .map(|entry| (rooms.room_of(&entry).map(|room| room.name.as_str()), entry))
+        .collect();
+    let position = |room: Option<&str>| {
+        room.and_then(|name| rooms.rooms.iter().position(|room| room.name == name))
+            .unwrap_or(rooms.rooms.len())
+    };
+    rows.sort_by_key(|(room, _)| position(*room));
+
+    if output::is_json() {
+        let resources: Vec<output::Resource> = rows
+            .iter()
+            .map(|(room, entry)| output::Resource {
+                room: room.map(str::to_string),
+                ..output::Resource::from(entry)
+            })
+            .collect();
+        output::print(&resources);
+        return Ok(());
+    }
+    if rows.is_empty() {
+        println!("No resources returned for the selected filter.");
+        return Ok(());
+    }
+    let mut table = Table::new(&["ROOM", "TYPE", "ID", "NAME", "DETAILS"]);
+    let mut previous = None;
+    for (room, entry) in rows {
+        // Name each room once, on its first row.
+        let label = match room {
+            _ if previous == Some(room) => "",
+            Some(name) => name,
+            None => "(no room)",
+        };
+        previous = Some(room);
+        let mut cells = vec![Cell::from(label)];
+        cells.extend(entry_cells(entry));
+        table.row(cells);
+    }
+    table.print();
+    Ok(())
+}
+
+fn handle_controller_register(code: &str, name: Option<&str>) -> Result<(), AppError> {
+    let code = code.trim();
+    if code.is_empty() {
+        return Err(AppError::Usage("Activation code must not be empty.".into()));
+    }
+
+    let session = authenticate()?;
+    let api = session.api()?;
+    match api.register_controller(code, name)? {
+        Some(client_id) => println!("Registered controller {client_id}."),
+        None => println!("Registered controller with activation code {code}."),
+    }
+    Ok(())
+}
+
+fn handle_controller_remove(client_id: &str, yes: bool) -> Result<(), AppError> {
+    if !yes
+        && !Confirm::new()
+            .with_prompt(format!(
+                "Remove controller {client_id} and detach all of its devices?"
+            ))
+            .default(false)
+            .interact()?
+    {
+        println!("Controller removal cancelled.");
+        return Ok(());
+    }
+
+    let session = authenticate()?;
+    let api = session.api()?;
+    api.remove_controller(client_id)?;
+    println!("Removed controller {client_id}.");
+    Ok(())
+}
+
+fn handle_controller_ping(client_id: &str, test_device: Option<&str>) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let controller = api
+        .controller_statuses()?
+        .into_iter()
+        .find(|controller| controller.id == client_id)
+        .ok_or_else(|| AppError::Usage(format!("No controller {client_id} on this account.")))?;
+    let state = match controller.online {
+        Some(true) => Cell::styled("online", Style::On),
+        Some(false) => Cell::styled("offline", Style::Problem),
+        None => Cell::styled("unknown", Style::Warning),
+    };
+    let last_seen = controller.last_seen.map_or_else(
+        || "not reported".to_string(),
+        |last_seen| describe_age((unix_now().max(0) as u64).saturating_sub(last_seen)),
+    );
+    let mut table = Table::new(&["ID", "NAME", "STATUS", "LAST SEEN"]);
+    table.row([
+        Cell::from(controller.id.as_str()),
+        Cell::from(controller.name.as_str()),
+        state,
+        Cell::from(last_seen),
+    ]);
+    table.print();
+    if controller.online == Some(false) {
+        return Err(AppError::ControllerOffline(controller.id));
+    }
+    let Some(test_device) = test_device else {
+        return Ok(());
+    };
+
+    let device_id = resolve_device(&api, test_device)?;
+    let settings = api.device_settings(&device_id)?;
+    if settings.client_id != controller.id {
+        return Err(AppError::Usage(format!(
+            "Device {device_id} belongs to controller {}, not {}.",
+            settings.client_id, controller.id
+        )));
+    }
+    // Resending the state the device is already in exercises the controller's radio
+    // without changing anything in the house.
+    let current = api.device_state(&device_id)?;
+    let started = Instant::now();
+    match (current.method, current.level) {
+        (1, _) => api.device_turn_on(&device_id)?,
+        (2, _) => api.device_turn_off(&device_id)?,
+        (16, Some(level)) => api.device_dim(&device_id, level)?,
+        (method, _) => {
+            return Err(AppError::Usage(format!(
+                "Device {device_id} was last sent '{}', which is not safe to repeat; \
+                 pick a lamp or switch as the test device.",
+                api::method_name(method)
+            )));
+        }
+    }
+    println!(
+        "Controller {} accepted '{}' for device {device_id} in {} ms.",
+        controller.id,
+        api::method_name(current.method),
+        started.elapsed().as_millis()
+    );
+    Ok(())
+}
+
+fn handle_controller_firmware(upgrade: bool, client_id: Option<&str>) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let mut controllers: Vec<_> = api
+        .list_firmware()?
+        .into_iter()
+        .filter(|status| client_id.is_none_or(|id| status.id == id))
+        .collect();
+    if controllers.is_empty() {
+        println!("No matching controllers found.");
+        return Ok(());
+    }
+    controllers.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+    let mut table = Table::new(&["ID", "NAME", "CURRENT", "AVAILABLE"]);
+    for status in &controllers {
+        let available = match &status.available {
+            Some(version) if status.upgrade_available() => {
+                Cell::styled(format!("{version} (upgrade)"), Style::Warning)
+            }
+            Some(version) => version.as_str().into(),
+            None => "-".into(),
+        };
+        table.row([
+            status.id.as_str().into(),
+            status.name.as_str().into(),
+            status.current.as_deref().unwrap_or("-").into(),
+            available,
+        ]);
     }
+    table.print();
 
+    if upgrade {
+        let pending: Vec<_> = controllers
+            .iter()
+            .filter(|status| status.upgrade_available())
+            .collect();
+        if pending.is_empty() {
+            println!("All controllers already run the latest firmware.");
+        }
+        for status in pending {
+            api.upgrade_firmware(&status.id)?;
+            println!(
+                "Requested firmware upgrade for controller {} ({}).",
+                status.id, status.name
+            );
+        }
+    }
     Ok(())
 }
 
@@ -403,7 +2270,8 @@ fn handle_devices_edit(
     }
 
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
+    let api = session.api()?;
+    let device_id = &resolve_device(&api, device_id)?;
 
     if let Some(ref new_name) = name {
         api.set_device_name(device_id, new_name)?;
@@ -422,17 +2290,106 @@ fn handle_devices_edit(
     Ok(())
 }
 
-fn handle_device_add(
-    client_id: &str,
-    name: &str,
-    protocol: &str,
-    model: &str,
-    parameters: Vec<KeyValue>,
-    learn: bool,
+fn handle_devices_rename(
+    pattern: Option<&str>,
+    substitution: &Substitution,
+    yes: bool,
 ) -> Result<(), AppError> {
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-
+    let api = session.api()?;
+    let mut renames = Vec::new();
+    for device in api.list_devices()? {
+        if pattern.is_some_and(|pattern| !cache::matches_pattern(pattern, &device.name)) {
+            continue;
+        }
+        let name = substitution.apply(&device.name).map_err(AppError::Usage)?;
+        if let Some(name) = name.filter(|name| *name != device.name) {
+            renames.push((device, name));
+        }
+    }
+    if renames.is_empty() {
+        println!("No device names would change.");
+        return Ok(());
+    }
+    renames.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+    let mut table = Table::new(&["ID", "OLD NAME", "NEW NAME"]);
+    for (device, name) in &renames {
+        table.row([device.id.as_str(), device.name.as_str(), name.as_str()]);
+    }
+    table.print();
+
+    if !yes
+        && !Confirm::new()
+            .with_prompt(format!("Rename {} device(s)?", renames.len()))
+            .default(false)
+            .interact()?
+    {
+        println!("Rename cancelled.");
+        return Ok(());
+    }
+
+    for (device, name) in &renames {
+        check_interrupted()?;
+        api.set_device_name(&device.id, name)?;
+        println!(
+            "Renamed device {}: '{}' -> '{}'.",
+            device.id, device.name, name
+        );
+    }
+    Ok(())
+}
+
+fn handle_device_add(
+    client_id: &str,
+    name: &str,
+    protocol: &str,
+    model: &str,
+    parameters: Vec<KeyValue>,
+    options: AddOptions,
+) -> Result<(), AppError> {
+    let known = if options.generate_codes {
+        let known = protocols::find(protocol, model)
+            .filter(|known| known.parameters.iter().any(|p| p.name == "house"))
+            .ok_or_else(|| {
+                AppError::Usage(format!(
+                    "--generate-codes needs a known protocol and model with a house code; {protocol} {model} is not one."
+                ))
+            })?;
+        Some(known)
+    } else {
+        None
+    };
+
+    let session = authenticate()?;
+    let api = session.api()?;
+
+    let parameters = match known {
+        Some(known) => {
+            let taken = taken_codes(&api, protocol)?;
+            let generated = known.unused_parameters(&taken).ok_or_else(|| {
+                AppError::Usage(format!(
+                    "Every {protocol} house and unit code is already in use."
+                ))
+            })?;
+            println!(
+                "Generated codes not used by the {} other {protocol} device(s).",
+                taken.len()
+            );
+            merge_parameters(generated, parameters)
+        }
+        None => parameters,
+    };
+    let pairs: Vec<_> = parameters
+        .iter()
+        .map(|kv| (kv.key.clone(), kv.value.clone()))
+        .collect();
+    check_definition(
+        None,
+        protocols::validate(protocol, model, &pairs),
+        options.force,
+    )?;
+
     let new_id = api.add_device(AddDeviceRequest {
         client_id,
         name,
@@ -450,148 +2407,3064 @@ fn handle_device_add(
         );
     }
 
-    if learn {
-        println!("Triggering learn mode for device {new_id}. Activate the remote now.");
-        api.device_learn(&new_id)?;
+    if options.learn {
+        println!("Triggering learn mode for device {new_id}. Activate the remote now.");
+        api.device_learn(&new_id)?;
+    }
+
+    Ok(())
+}
+
+/// The `(house, unit)` codes of every device on the account using `protocol`.
+fn taken_codes(api: &TelldusApi, protocol: &str) -> Result<Vec<(String, String)>, AppError> {
+    let mut taken = Vec::new();
+    for settings in all_device_settings(api)? {
+        if !settings.protocol.eq_ignore_ascii_case(protocol) {
+            continue;
+        }
+        let value = |name: &str| {
+            settings
+                .parameters
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        if let Some(house) = value("house") {
+            taken.push((house, value("unit").unwrap_or_default()));
+        }
+    }
+    Ok(taken)
+}
+
+/// Protocol, model and parameters of every device on the account, one request each.
+fn all_device_settings(api: &TelldusApi) -> Result<Vec<DeviceSettings>, AppError> {
+    let mut all = Vec::new();
+    for device in api.list_devices()? {
+        match api.device_settings(&device.id) {
+            Ok(settings) => all.push(settings),
+            // Groups and some Z-Wave devices have no protocol to compare.
+            Err(api::ApiError::Unexpected(_)) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(all)
+}
+
+/// `parameters` with each override replacing the value of the same key, or appended.
+fn merge_parameters(parameters: Vec<(String, String)>, overrides: Vec<KeyValue>) -> Vec<KeyValue> {
+    let mut merged: Vec<KeyValue> = parameters
+        .into_iter()
+        .map(|(key, value)| KeyValue { key, value })
+        .collect();
+    for kv in overrides {
+        match merged.iter_mut().find(|existing| existing.key == kv.key) {
+            Some(existing) => existing.value = kv.value,
+            None => merged.push(kv),
+        }
+    }
+    merged
+}
+
+/// Print what the catalog found wrong with a device definition, `subject` naming the
+/// device when there are several. Errors stop the command unless `force` is set.
+fn check_definition(
+    subject: Option<&str>,
+    findings: protocols::Findings,
+    force: bool,
+) -> Result<(), AppError> {
+    let prefix = subject
+        .map(|subject| format!("{subject}: "))
+        .unwrap_or_default();
+    for warning in &findings.warnings {
+        eprintln!("warning: {prefix}{warning}");
+    }
+    let level = if force { "warning" } else { "error" };
+    for error in &findings.errors {
+        eprintln!("{level}: {prefix}{error}");
+    }
+    if findings.errors.is_empty() || force {
+        Ok(())
+    } else {
+        Err(AppError::InvalidDefinition(findings.errors.len()))
+    }
+}
+
+fn handle_device_add_preset(
+    client_id: &str,
+    name: &str,
+    preset: &protocols::Preset,
+    overrides: Vec<KeyValue>,
+    options: AddOptions,
+) -> Result<(), AppError> {
+    // With --generate-codes the codes are picked once the existing devices are known.
+    let parameters = if options.generate_codes {
+        overrides
+    } else {
+        merge_parameters(preset.known().default_parameters(), overrides)
+    };
+    handle_device_add(
+        client_id,
+        name,
+        preset.protocol,
+        preset.model,
+        parameters,
+        options,
+    )
+}
+
+fn handle_log(limit: usize, id: Option<&str>, since: Option<Duration>) -> Result<(), AppError> {
+    let cutoff = since.map(|since| (unix_now() as u64).saturating_sub(since.as_secs()));
+    let mut records: Vec<_> = audit::read(&stored_account()?)?
+        .into_iter()
+        .filter(|record| id.is_none_or(|id| record.parameter("id") == Some(id)))
+        .filter(|record| cutoff.is_none_or(|cutoff| record.timestamp >= cutoff))
+        .collect();
+    let skipped = records.len().saturating_sub(limit);
+    records.drain(..skipped);
+
+    if output::is_json() {
+        output::print(&records);
+        return Ok(());
+    }
+    if records.is_empty() {
+        println!("No changes logged.");
+        return Ok(());
+    }
+    let mut table = Table::new(&["TIME", "ENDPOINT", "PARAMETERS", "RESULT"]);
+    for record in &records {
+        let parameters: Vec<_> = record
+            .parameters
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        let style = if record.succeeded() {
+            Style::Plain
+        } else {
+            Style::Problem
+        };
+        table.row([
+            schedule::local_time(record.timestamp).to_string().into(),
+            record.endpoint.trim_start_matches("/json/").into(),
+            parameters.join(" ").into(),
+            Cell::styled(record.result.as_str(), style),
+        ]);
+    }
+    table.print();
+    Ok(())
+}
+
+fn handle_undo(yes: bool) -> Result<(), AppError> {
+    // Read the log of the account the reversal will be sent to.
+    let session = authenticate()?;
+    let records = audit::read(&session.credentials)?;
+    let Some((index, reversal)) = audit::last_undoable(&records) else {
+        println!("Nothing in the audit log can be undone.");
+        return Ok(());
+    };
+    let record = &records[index];
+    let parameters: Vec<_> = record
+        .parameters
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect();
+    println!(
+        "Last reversible change: {} at {} ({}).",
+        record.endpoint.trim_start_matches("/json/"),
+        schedule::local_time(record.timestamp),
+        parameters.join(" ")
+    );
+    let undone = |position: usize| records.iter().any(|record| record.undoes == Some(position));
+    let newer = (index + 1..records.len())
+        .filter(|&position| {
+            let record = &records[position];
+            record.undoes.is_none() && record.succeeded() && !undone(position)
+        })
+        .count();
+    if newer > 0 {
+        println!("{newer} newer change(s) cannot be undone and are left as they are.");
+    }
+    if !yes
+        && !Confirm::new()
+            .with_prompt(format!("Undo it and {}?", reversal.describe()))
+            .default(false)
+            .interact()?
+    {
+        println!("Undo cancelled.");
+        return Ok(());
+    }
+
+    let api = session.api()?;
+    audit::set_undoing(index);
+    match &reversal {
+        audit::Reversal::TurnOn(id) => api.device_turn_on(id)?,
+        audit::Reversal::TurnOff(id) => api.device_turn_off(id)?,
+        audit::Reversal::Rename { id, name } => api.set_device_name(id, name)?,
+        audit::Reversal::SetParameter {
+            id,
+            parameter,
+            value,
+        } => api.set_device_parameter(id, parameter, value)?,
+        audit::Reversal::SetProtocol { id, protocol } => api.set_device_protocol(id, protocol)?,
+        audit::Reversal::SetModel { id, model } => api.set_device_model(id, model)?,
+        audit::Reversal::Recreate { name, settings } => {
+            let new_id = api.add_device(AddDeviceRequest {
+                client_id: &settings.client_id,
+                name,
+                protocol: &settings.protocol,
+                model: &settings.model,
+            })?;
+            for (key, value) in &settings.parameters {
+                api.set_device_parameter(&new_id, key, value)?;
+            }
+            println!("Re-created '{name}' as device {new_id}.");
+            return Ok(());
+        }
+    }
+    println!("Undone: {}.", reversal.describe());
+    Ok(())
+}
+
+fn handle_protocols_list(builtin: bool) -> Result<(), AppError> {
+    // Devices per catalog entry, and the models the catalog does not know keyed by
+    // protocol and model with the parameter names they use.
+    let mut counts: HashMap<(&str, &str), usize> = HashMap::new();
+    let mut unknown: BTreeMap<(String, String), (usize, Vec<String>)> = BTreeMap::new();
+    if !builtin {
+        let session = authenticate()?;
+        let api = session.api()?;
+        for settings in all_device_settings(&api)? {
+            if let Some(known) = protocols::find(&settings.protocol, &settings.model) {
+                *counts.entry((known.protocol, known.model)).or_default() += 1;
+                continue;
+            }
+            let (count, names) = unknown
+                .entry((settings.protocol.to_lowercase(), settings.model.clone()))
+                .or_default();
+            *count += 1;
+            for (name, _) in settings.parameters {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+
+    let headers: &[&str] = if builtin {
+        &["PROTOCOL", "MODEL", "PARAMETERS", "DESCRIPTION"]
+    } else {
+        &["PROTOCOL", "MODEL", "PARAMETERS", "DEVICES", "DESCRIPTION"]
+    };
+    let mut table = Table::new(headers);
+    for known in protocols::MODELS {
+        let count = counts
+            .get(&(known.protocol, known.model))
+            .copied()
+            .unwrap_or_default();
+        let parameters: Vec<_> = known
+            .parameters
+            .iter()
+            .map(|parameter| format!("{} {}", parameter.name, parameter.kind.describe()))
+            .collect();
+        let mut row: Vec<Cell> = vec![
+            known.protocol.into(),
+            known.model.into(),
+            parameters.join(", ").into(),
+        ];
+        if !builtin {
+            row.push(count.to_string().into());
+        }
+        row.push(known.description.into());
+        table.row(row);
+    }
+    for ((protocol, model), (count, names)) in &unknown {
+        table.row([
+            protocol.as_str().into(),
+            model.as_str().into(),
+            names.join(", ").into(),
+            count.to_string().into(),
+            Cell::styled("seen on this account", Style::Off),
+        ]);
+    }
+    table.print();
+
+    let presets: Vec<_> = protocols::PRESETS
+        .iter()
+        .map(|preset| preset.name)
+        .collect();
+    println!();
+    println!("Presets for `devices add --preset`: {}", presets.join(", "));
+    Ok(())
+}
+
+fn handle_device_add_from(
+    path: &Path,
+    default_client: Option<&str>,
+    force: bool,
+) -> Result<(), AppError> {
+    let devices = provision::load(path)?;
+    if let Some(device) = devices
+        .iter()
+        .find(|device| device.client_id.is_none() && default_client.is_none())
+    {
+        return Err(AppError::Usage(format!(
+            "Device '{}' has no client_id; add the column or pass --client-id.",
+            device.name
+        )));
+    }
+    // Report every row's problems before creating anything.
+    let mut problems = 0;
+    for device in &devices {
+        let findings = protocols::validate(&device.protocol, &device.model, &device.parameters);
+        if let Err(AppError::InvalidDefinition(count)) =
+            check_definition(Some(&device.name), findings, force)
+        {
+            problems += count;
+        }
+    }
+    if problems > 0 {
+        return Err(AppError::InvalidDefinition(problems));
+    }
+    let session = authenticate()?;
+    let api = session.api()?;
+
+    let mut failed = 0;
+    for device in &devices {
+        check_interrupted()?;
+        let client_id = device
+            .client_id
+            .as_deref()
+            .or(default_client)
+            .unwrap_or_default();
+        let new_id = match api.add_device(AddDeviceRequest {
+            client_id,
+            name: &device.name,
+            protocol: &device.protocol,
+            model: &device.model,
+        }) {
+            Ok(new_id) => new_id,
+            Err(err) => {
+                eprintln!("Failed to create '{}': {err}", device.name);
+                failed += 1;
+                continue;
+            }
+        };
+        match device
+            .parameters
+            .iter()
+            .try_for_each(|(key, value)| api.set_device_parameter(&new_id, key, value))
+        {
+            Ok(()) => println!(
+                "Created device {new_id} '{}' on client {client_id}.",
+                device.name
+            ),
+            Err(err) => {
+                eprintln!(
+                    "Created device {new_id} '{}', but setting its parameters failed: {err}",
+                    device.name
+                );
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(AppError::ProvisionIncomplete(failed, devices.len()));
+    }
+    println!("Created {} device(s).", devices.len());
+    Ok(())
+}
+
+fn handle_device_add_wizard() -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+
+    let controllers = api.list_controllers()?;
+    let controller = choose_entry("Controller", &controllers)?;
+    let mut labels: Vec<String> = protocols::MODELS
+        .iter()
+        .map(|known| {
+            format!(
+                "{:<13} {:<20} {}",
+                known.protocol, known.model, known.description
+            )
+        })
+        .collect();
+    labels.push("Other protocol and model".into());
+    let choice = Select::new()
+        .with_prompt("Device type")
+        .items(&labels)
+        .default(0)
+        .interact()?;
+    let known = protocols::MODELS.get(choice);
+    let (protocol, model) = match known {
+        Some(known) => (known.protocol.to_string(), known.model.to_string()),
+        None => (
+            Input::<String>::new()
+                .with_prompt("Protocol")
+                .interact_text()?
+                .trim()
+                .to_string(),
+            Input::<String>::new()
+                .with_prompt("Model")
+                .interact_text()?
+                .trim()
+                .to_string(),
+        ),
+    };
+    let name: String = Input::new().with_prompt("Device name").interact_text()?;
+
+    let mut parameters = Vec::new();
+    if let Some(known) = known {
+        for parameter in known.parameters {
+            let value: String = Input::new()
+                .with_prompt(format!(
+                    "{} ({})",
+                    parameter.name,
+                    parameter.kind.describe()
+                ))
+                .validate_with(|input: &String| parameter.kind.check(input).map(drop))
+                .interact_text()?;
+            parameters.push(KeyValue {
+                key: parameter.name.into(),
+                value: parameter.kind.check(&value).map_err(AppError::Usage)?,
+            });
+        }
+    } else {
+        while Confirm::new()
+            .with_prompt("Add a parameter?")
+            .default(parameters.is_empty())
+            .interact()?
+        {
+            let pair: String = Input::new()
+                .with_prompt("Parameter (key=value)")
+                .validate_with(|input: &String| parse_key_value(input).map(drop))
+                .interact_text()?;
+            parameters.push(parse_key_value(&pair).map_err(AppError::Usage)?);
+        }
+    }
+
+    println!();
+    println!("Controller  {} ({})", controller.name, controller.id);
+    println!("Name        {}", name.trim());
+    println!("Type        {protocol} {model}");
+    for kv in &parameters {
+        println!("Parameter   {}={}", kv.key, kv.value);
+    }
+    if !Confirm::new()
+        .with_prompt("Create this device?")
+        .default(true)
+        .interact()?
+    {
+        println!("Nothing created.");
+        return Ok(());
+    }
+
+    let new_id = api.add_device(AddDeviceRequest {
+        client_id: &controller.id,
+        name: name.trim(),
+        protocol: &protocol,
+        model: &model,
+    })?;
+    println!("Created device {new_id} on client {}.", controller.id);
+    for kv in &parameters {
+        api.set_device_parameter(&new_id, &kv.key, &kv.value)?;
+        println!(
+            "Set parameter '{key}' = '{value}'",
+            key = kv.key,
+            value = kv.value
+        );
+    }
+
+    if known.is_none_or(|known| known.learns)
+        && Confirm::new()
+            .with_prompt("Pair the receiver with learn mode now?")
+            .default(true)
+            .interact()?
+    {
+        learn_interactively(&api, &new_id, LEARN_WINDOW)?;
+    }
+    Ok(())
+}
+
+fn handle_device_clone(
+    device_id: &str,
+    name: &str,
+    overrides: Vec<KeyValue>,
+    force: bool,
+) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = &resolve_device(&api, device_id)?;
+    let settings = api.device_settings(device_id)?;
+
+    let mut parameters = settings.parameters;
+    if parameters.is_empty()
+        && let Some(known) = protocols::find(&settings.protocol, &settings.model)
+    {
+        // Older firmware leaves parameters out of device/info; ask for the ones the
+        // protocol is known to use.
+        for parameter in known.parameters {
+            if let Some(value) = api.get_device_parameter(device_id, parameter.name)? {
+                parameters.push((parameter.name.to_string(), value));
+            }
+        }
+    }
+    for kv in overrides {
+        match parameters.iter_mut().find(|(key, _)| *key == kv.key) {
+            Some((_, value)) => *value = kv.value,
+            None => parameters.push((kv.key, kv.value)),
+        }
+    }
+
+    check_definition(
+        None,
+        protocols::validate(&settings.protocol, &settings.model, &parameters),
+        force,
+    )?;
+
+    let new_id = api.add_device(AddDeviceRequest {
+        client_id: &settings.client_id,
+        name,
+        protocol: &settings.protocol,
+        model: &settings.model,
+    })?;
+    println!(
+        "Created device {new_id} on client {}, a copy of device {device_id}.",
+        settings.client_id
+    );
+    for (key, value) in &parameters {
+        api.set_device_parameter(&new_id, key, value)?;
+        println!("Set parameter '{key}' = '{value}'");
+    }
+    Ok(())
+}
+
+fn handle_device_remove(device_id: &str) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = &resolve_device(&api, device_id)?;
+    api.remove_device(device_id)?;
+    println!("Removed device {device_id}.");
+    Ok(())
+}
+
+fn handle_device_simple<F, M>(device_id: &str, action: F, message: M) -> Result<(), AppError>
+where
+    F: FnOnce(&TelldusApi, &str) -> Result<(), api::ApiError>,
+    M: FnOnce() -> String,
+{
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = resolve_device(&api, device_id)?;
+    action(&api, &device_id)?;
+    recent::record(&session.credentials, &device_id);
+    println!("{}", message());
+    Ok(())
+}
+
+/// Send a command, then read the state back and resend once if the device did not
+/// take it; 433 MHz receivers routinely miss a transmission without any error.
+fn handle_device_confirmed<F>(
+    device_id: &str,
+    action: F,
+    expected: DeviceState,
+    message: &str,
+) -> Result<(), AppError>
+where
+    F: Fn(&TelldusApi, &str) -> Result<(), api::ApiError>,
+{
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = resolve_device(&api, device_id)?;
+    let took_effect = |state: DeviceState| {
+        state.method == expected.method
+            && expected
+                .level
+                .is_none_or(|level| state.level == Some(level))
+    };
+
+    for attempt in 1..=2 {
+        action(&api, &device_id)?;
+        if !service::sleep(CONFIRM_DELAY) {
+            return Err(AppError::Interrupted);
+        }
+        let state = api.device_state(&device_id)?;
+        if took_effect(state) {
+            recent::record(&session.credentials, &device_id);
+            println!("{message}");
+            if attempt == 1 {
+                println!("Confirmed: device {device_id} reports the new state.");
+            } else {
+                println!("Confirmed after resending: device {device_id} reports the new state.");
+            }
+            return Ok(());
+        }
+        if attempt == 1 {
+            println!("Device {device_id} did not report the new state; resending once.");
+        }
+    }
+    Err(AppError::Unconfirmed(device_id))
+}
+
+/// Send the same command to every device in a room, one at a time, carrying on past
+/// failures and summing up at the end.
+fn handle_room_action<F>(
+    room: &str,
+    dry_run: bool,
+    verb: &str,
+    action: F,
+    done: &str,
+) -> Result<(), AppError>
+where
+    F: Fn(&TelldusApi, &str) -> Result<(), api::ApiError>,
+{
+    let rooms = load_rooms()?;
+    let Some(room) = rooms.find(room) else {
+        let names: Vec<&str> = rooms.rooms.iter().map(|room| room.name.as_str()).collect();
+        return Err(AppError::Usage(format!(
+            "No room named '{room}'; rooms.yaml has {}.",
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        )));
+    };
+    let session = authenticate()?;
+    let api = session.api()?;
+    let mut devices = api.list_devices()?;
+    devices.retain(|device| room.contains(device));
+    if devices.is_empty() {
+        return Err(AppError::Usage(format!(
+            "Room {} has no devices on this account.",
+            room.name
+        )));
+    }
+    sort_entries(&mut devices);
+
+    if dry_run {
+        let mut table = Table::new(&["ID", "NAME"]);
+        for device in &devices {
+            table.row([device.id.as_str(), device.name.as_str()]);
+        }
+        table.print();
+        println!(
+            "{} device(s) in {} would be {done}.",
+            devices.len(),
+            room.name
+        );
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for device in &devices {
+        check_interrupted()?;
+        match action(&api, &device.id) {
+            Ok(()) => println!("Device {} '{}': {done}.", device.id, device.name),
+            Err(err) => {
+                eprintln!(
+                    "Device {} '{}': failed to {verb}: {err}",
+                    device.id, device.name
+                );
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(AppError::RoomIncomplete(
+            failed,
+            devices.len(),
+            room.name.clone(),
+        ));
+    }
+    println!("{} device(s) in {} {done}.", devices.len(), room.name);
+    Ok(())
+}
+
+/// Turn a device on, wait locally, then turn it off; interrupting the wait leaves it on.
+fn handle_device_on_for(device_id: &str, duration: Duration) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = resolve_device(&api, device_id)?;
+    api.device_turn_on(&device_id)?;
+    recent::record(&session.credentials, &device_id);
+    println!(
+        "Turned device {device_id} on; turning it off in {}. Press Ctrl-C to keep it on.",
+        format_duration(duration)
+    );
+
+    let deadline = Instant::now() + duration;
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining.is_zero() {
+            break;
+        }
+        print!("\rTurning off in {:>9}", format_duration(remaining));
+        let _ = io::stdout().flush();
+        if !service::sleep(remaining.min(Duration::from_secs(1))) {
+            println!("\nLeft device {device_id} on.");
+            return Err(AppError::Interrupted);
+        }
+    }
+    println!();
+
+    api.device_turn_off(&device_id)?;
+    println!("Turned device {device_id} off.");
+    Ok(())
+}
+
+fn handle_device_blink(device_id: &str, times: u32, interval: Duration) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = resolve_device(&api, device_id)?;
+    let original = api.device_state(&device_id).ok();
+
+    // Each toggle also passes through the rate limiter, so the interval is a lower bound.
+    // Ctrl-C stops blinking but still restores the original state.
+    let mut interrupted = false;
+    for cycle in 1..=times {
+        print!("\rBlinking device {device_id}: {cycle}/{times}");
+        let _ = io::stdout().flush();
+        api.device_turn_on(&device_id)?;
+        interrupted = !service::sleep(interval);
+        api.device_turn_off(&device_id)?;
+        if interrupted || (cycle < times && !service::sleep(interval)) {
+            interrupted = true;
+            break;
+        }
+    }
+    println!();
+
+    match original {
+        Some(state) if state.method == WaitState::On.method() => {
+            api.device_turn_on(&device_id)?;
+            println!("Restored device {device_id} to on.");
+        }
+        Some(DeviceState {
+            method,
+            level: Some(level),
+        }) if method == WaitState::Dim.method() => {
+            api.device_dim(&device_id, level)?;
+            println!("Restored device {device_id} to level {level}.");
+        }
+        _ => println!("Left device {device_id} off."),
+    }
+    if interrupted {
+        return Err(AppError::Interrupted);
+    }
+    Ok(())
+}
+
+fn handle_device_wait_for(
+    device_id: &str,
+    state: WaitState,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = resolve_device(&api, device_id)?;
+    let label = format!("{state:?}").to_lowercase();
+
+    let started = Instant::now();
+    loop {
+        if api.device_state(&device_id)?.method == state.method() {
+            println!(
+                "Device {device_id} is {label} after {}s.",
+                started.elapsed().as_secs()
+            );
+            return Ok(());
+        }
+        if started.elapsed() + interval > timeout {
+            return Err(AppError::StateTimeout(device_id, label, timeout.as_secs()));
+        }
+        if !service::sleep(interval) {
+            return Err(AppError::Interrupted);
+        }
+    }
+}
+
+fn handle_device_learn_wizard(device_id: &str, window: u64) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    learn_interactively(&api, device_id, window)
+}
+
+/// Send learn signals until the user confirms the receiver paired, offering to adjust
+/// the house and unit codes between attempts.
+fn learn_interactively(api: &TelldusApi, device_id: &str, window: u64) -> Result<(), AppError> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        println!("Attempt {attempt}: put the receiver in pairing mode, then press Enter.");
+        Input::<String>::new()
+            .with_prompt("Ready")
+            .allow_empty(true)
+            .interact_text()?;
+
+        api.device_learn(device_id)?;
+        for remaining in (1..=window).rev() {
+            print!("\rSending learn signal to device {device_id}... {remaining:>3}s remaining");
+            let _ = io::stdout().flush();
+            if !service::sleep(Duration::from_secs(1)) {
+                println!();
+                return Err(AppError::Interrupted);
+            }
+        }
+        println!("\rLearn window closed for device {device_id}.{:24}", "");
+
+        if Confirm::new()
+            .with_prompt("Did the device react?")
+            .default(true)
+            .interact()?
+        {
+            println!("Device {device_id} paired after {attempt} attempt(s).");
+            return Ok(());
+        }
+
+        let choice = Select::new()
+            .with_prompt("How do you want to continue?")
+            .items(&["Retry", "Adjust house/unit parameters", "Abort"])
+            .default(0)
+            .interact()?;
+        match choice {
+            0 => {}
+            1 => {
+                for parameter in ["house", "unit"] {
+                    let current = api
+                        .get_device_parameter(device_id, parameter)?
+                        .unwrap_or_default();
+                    let value: String = Input::new()
+                        .with_prompt(format!("New {parameter} value"))
+                        .with_initial_text(current.clone())
+                        .allow_empty(true)
+                        .interact_text()?;
+                    let value = value.trim();
+                    if !value.is_empty() && value != current {
+                        api.set_device_parameter(device_id, parameter, value)?;
+                        println!("Set parameter '{parameter}' = '{value}'");
+                    }
+                }
+            }
+            _ => {
+                println!("Learn wizard aborted.");
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn handle_device_info(device_id: &str) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = &resolve_device(&api, device_id)?;
+    let info = api.device_info(device_id)?;
+    print_json(&info);
+    Ok(())
+}
+
+fn handle_device_history(
+    device_id: &str,
+    limit: Option<u32>,
+    range: Option<(i64, Option<i64>)>,
+) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = &resolve_device(&api, device_id)?;
+    if let Some((from, to)) = range {
+        let to = to.unwrap_or_else(unix_now);
+        return print_history_stream(api.device_history_iter(device_id, from, to), limit, "Event");
+    }
+    let entries = api.device_history(device_id, limit)?;
+    if output::is_json() {
+        return print_history_json(entries.into_iter().map(Ok));
+    }
+    if entries.is_empty() {
+        println!("No history entries found.");
+    } else {
+        for (idx, entry) in entries.iter().enumerate() {
+            println!("-- Event {} --", idx + 1);
+            print_json(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Print the latest events, then poll for newer ones until interrupted.
+///
+/// The newest timestamp seen is remembered, and each poll asks only for the history since
+/// then; events sharing that second are compared so none is printed twice.
+fn handle_device_follow(
+    device_id: &str,
+    limit: Option<u32>,
+    interval: Duration,
+    filter: Option<&filter::Filter>,
+) -> Result<(), AppError> {
+    response_cache::set_ttl(Duration::ZERO);
+    let session = authenticate()?;
+    let api = session.api()?;
+    let reference = device_id;
+    let device_id = &resolve_device(&api, reference)?;
+    // History entries do not carry the device's name, which a filter may compare.
+    let name = match filter {
+        Some(_) => api
+            .device_info(device_id)?
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .map_or_else(|| reference.to_string(), str::to_string),
+        None => reference.to_string(),
+    };
+    // Polls page from here rather than from the newest event, which may be days old.
+    let mut polled_to = unix_now();
+    let mut entries = api.device_history(device_id, Some(limit.unwrap_or(10)))?;
+    let mut newest = 0;
+    let mut seen = Vec::new();
+    let mut failures = changes::Changes::new(None);
+    status(&format!(
+        "Following the history of device {device_id} every {}; press Ctrl-C to stop.",
+        format_duration(interval)
+    ));
+    loop {
+        let timestamp = |entry: &serde_json::Value| {
+            output::HistoryEntry::from_value(entry)
+                .timestamp
+                .unwrap_or_default()
+        };
+        entries.sort_by_key(timestamp);
+        for entry in entries {
+            let ts = timestamp(&entry);
+            if ts < newest || seen.contains(&entry) {
+                continue;
+            }
+            if ts > newest {
+                newest = ts;
+                seen.clear();
+            }
+            let record = filter::with_context(
+                serde_json::to_value(output::HistoryEntry::from_value(&entry)).unwrap_or_default(),
+                "device",
+                device_id,
+                &name,
+            );
+            if filter.is_none_or(|filter| filter.matches(&record)) {
+                print_history_event(&record);
+            }
+            seen.push(entry);
+        }
+        if !service::sleep(interval) {
+            return Ok(());
+        }
+        let to = unix_now();
+        entries = match api
+            .device_history_iter(device_id, newest.max(polled_to), to)
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(entries) => {
+                polled_to = to;
+                failures.forget("history");
+                entries
+            }
+            Err(err) => {
+                if failures.update("history", err.to_string()) {
+                    eprintln!("warning: cannot poll history: {err}");
+                }
+                Vec::new()
+            }
+        };
+    }
+}
+
+/// One followed history event: a line of text, or the record in JSON output.
+fn print_history_event(record: &serde_json::Value) {
+    if output::is_json() {
+        output::print(record);
+        return;
+    }
+    let time = record["timestamp"].as_u64().map_or_else(
+        || "unknown time".to_string(),
+        |ts| schedule::local_time(ts).to_string(),
+    );
+    let mut line = format!("{time}  {}", record["state"].as_str().unwrap_or("unknown"));
+    if let Some(level) = record["level"].as_u64() {
+        line.push_str(&format!(" {level}"));
+    }
+    if let Some(origin) = record["origin"].as_str() {
+        line.push_str(&format!("  ({origin})"));
+    }
+    println!("{line}");
+}
+
+fn handle_device_set_parameter(
+    device_id: &str,
+    parameter: &str,
+    value: &str,
+    force: bool,
+) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = &resolve_device(&api, device_id)?;
+    if !force {
+        let settings = api.device_settings(device_id)?;
+        let findings =
+            protocols::validate_parameter(&settings.protocol, &settings.model, parameter, value);
+        check_definition(None, findings, force)?;
+    }
+    api.set_device_parameter(device_id, parameter, value)?;
+    println!("Set parameter '{parameter}' for device {device_id} to '{value}'.");
+    Ok(())
+}
+
+fn handle_device_get_parameter(device_id: &str, parameter: &str) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = &resolve_device(&api, device_id)?;
+    match api.get_device_parameter(device_id, parameter)? {
+        Some(value) => println!("Parameter '{parameter}' = '{value}'"),
+        None => println!("Parameter '{parameter}' not set for device {device_id}."),
+    }
+    Ok(())
+}
+
+struct SensorFilter {
+    pattern: Option<String>,
+    model: Option<String>,
+    protocol: Option<String>,
+    kind: Option<ReadingKind>,
+}
+
+impl SensorFilter {
+    fn matches(&self, sensor: &Sensor) -> bool {
+        let field = |pattern: &Option<String>, value: Option<&str>| {
+            pattern.as_deref().is_none_or(|pattern| {
+                value.is_some_and(|value| cache::matches_pattern(pattern, value))
+            })
+        };
+        field(&self.pattern, Some(&sensor.name))
+            && field(&self.model, sensor.model.as_deref())
+            && field(&self.protocol, sensor.protocol.as_deref())
+            && self.kind.is_none_or(|kind| sensor.has_kind(kind))
+    }
+}
+
+fn handle_sensors_list(include_ignored: bool, filter: &SensorFilter) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let mut sensors = api.sensors(include_ignored)?;
+    sensors.retain(|sensor| filter.matches(sensor));
+    sensors.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+    if output::is_json() {
+        let records: Vec<output::SensorRecord> =
+            sensors.iter().map(output::SensorRecord::from).collect();
+        output::print(&records);
+        return Ok(());
+    }
+    if sensors.is_empty() {
+        println!("No sensors found.");
+        return Ok(());
+    }
+    print_entries(sensors.iter().map(Sensor::to_entry).collect());
+    Ok(())
+}
+
+fn handle_sensors_push(pushgateway: &str, include_ignored: bool) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let sensors = api.sensors(include_ignored)?;
+    let readings: usize = sensors.iter().map(|sensor| sensor.readings.len()).sum();
+    metrics::push(
+        &session.client,
+        pushgateway,
+        metrics::prometheus_text(&sensors),
+    )?;
+    println!(
+        "Pushed {readings} reading(s) from {} sensor(s) to {pushgateway}.",
+        sensors.len()
+    );
+    Ok(())
+}
+
+fn handle_sensor_info(sensor_id: &str, scale: Option<Scale>) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let mut info = api.sensor_info(sensor_id, scale.map(Scale::number))?;
+    if let Some(kind) = scale.and_then(Scale::kind) {
+        api::retain_readings(&mut info, kind);
+    }
+    api::annotate_readings(&mut info);
+    print_json(&info);
+    Ok(())
+}
+
+fn handle_sensor_history(
+    sensor_id: &str,
+    scale: Scale,
+    limit: Option<u32>,
+    range: Option<(i64, Option<i64>)>,
+) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let kind = scale.kind();
+    if let Some((from, to)) = range {
+        let to = to.unwrap_or_else(unix_now);
+        let entries = api
+            .sensor_history_iter(sensor_id, scale.number(), from, to)
+            .filter_map(|entry| match entry {
+                Ok(entry) => describe_readings(entry, kind).map(Ok),
+                Err(err) => Some(Err(err)),
+            });
+        return print_history_stream(entries, limit, "Reading");
+    }
+    let entries: Vec<_> = api
+        .sensor_history(sensor_id, scale.number(), limit)?
+        .into_iter()
+        .filter_map(|entry| describe_readings(entry, kind))
+        .collect();
+    if output::is_json() {
+        return print_history_json(entries.into_iter().map(Ok));
+    }
+    if entries.is_empty() {
+        println!("No sensor history entries found.");
+    } else {
+        for (idx, entry) in entries.iter().enumerate() {
+            println!("-- Reading {} --", idx + 1);
+            print_json(entry);
+        }
+    }
+    Ok(())
+}
+
+fn handle_sensor_summary(sensor_id: &str, days: u32, scale: Option<Scale>) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let to = unix_now();
+    let time = schedule::local_time(to as u64);
+    let midnight = to - i64::from(time.hour * 3600 + time.minute * 60) - to % 60;
+    let from = midnight - i64::from(days - 1) * 86_400;
+    let scale = scale.unwrap_or(Scale::Number(0));
+    let entries = api
+        .sensor_history_iter(sensor_id, scale.number(), from, to)
+        .collect::<Result<Vec<_>, _>>()?;
+    let summary = history::daily_summary(&entries, scale.kind());
+    if output::is_json() {
+        output::print(&summary);
+        return Ok(());
+    }
+    if summary.is_empty() {
+        println!("No sensor readings since {}.", schedule::local_date(from));
+        return Ok(());
+    }
+    let mut table = Table::new(&["DATE", "READING", "MIN", "MAX", "MEAN"]);
+    for day in &summary {
+        let value = |value: f64| format!("{value:.1}{}", day.unit);
+        table.row([
+            day.date.clone(),
+            day.name.clone(),
+            value(day.min),
+            value(day.max),
+            value(day.mean),
+        ]);
+    }
+    table.print();
+    Ok(())
+}
+
+fn handle_sensor_compare(
+    sensor_ids: &[String],
+    scale: Scale,
+    since: Duration,
+    step: Duration,
+    delta: bool,
+) -> Result<(), AppError> {
+    if sensor_ids.len() < 2 {
+        return Err(AppError::Usage(
+            "Give at least two sensors to compare with --id.".into(),
+        ));
+    }
+    let step = step.as_secs().max(60) as i64;
+    let session = authenticate()?;
+    let api = session.api()?;
+    let names: HashMap<String, String> = api
+        .sensors(true)?
+        .into_iter()
+        .map(|sensor| (sensor.id, sensor.name))
+        .collect();
+    let to = unix_now();
+    let from = to.saturating_sub(since.as_secs() as i64);
+    let select = |reading: &api::SensorReading| match scale {
+        Scale::Kind(kind) => reading.kind == kind,
+        Scale::Number(number) => i64::from(reading.scale) == i64::from(number),
+    };
+
+    let mut series = Vec::new();
+    let mut headers = vec!["TIME".to_string()];
+    for (index, id) in sensor_ids.iter().enumerate() {
+        let entries = api
+            .sensor_history_iter(id, scale.number(), from, to)
+            .collect::<Result<Vec<_>, _>>()?;
+        let (means, unit) = history::bucket_means(&entries, step, select);
+        let name = names.get(id).map_or(id.as_str(), String::as_str);
+        let prefix = if delta && index > 0 { "Δ " } else { "" };
+        headers.push(
+            format!("{prefix}{name} {}", unit.unwrap_or_default())
+                .trim_end()
+                .to_string(),
+        );
+        series.push(means);
+    }
+
+    let mut slots: Vec<i64> = series
+        .iter()
+        .flat_map(|means| means.keys().copied())
+        .collect();
+    slots.sort_unstable();
+    slots.dedup();
+    let rows: Vec<history::ComparedSlot> = slots
+        .into_iter()
+        .map(|timestamp| {
+            let mut values: Vec<Option<f64>> = series
+                .iter()
+                .map(|means| means.get(&timestamp).copied())
+                .collect();
+            if delta {
+                let first = values[0];
+                for value in &mut values[1..] {
+                    *value = value.zip(first).map(|(value, first)| value - first);
+                }
+            }
+            history::ComparedSlot { timestamp, values }
+        })
+        .collect();
+
+    if output::is_json() {
+        output::print(&rows);
+        return Ok(());
+    }
+    if rows.is_empty() {
+        println!(
+            "No matching readings since {}.",
+            schedule::local_time(from as u64)
+        );
+        return Ok(());
+    }
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    let mut table = Table::new(&headers);
+    for row in &rows {
+        let mut cells = vec![schedule::local_time(row.timestamp as u64).to_string()];
+        cells.extend(
+            row.values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| match value {
+                    Some(value) if delta && index > 0 => format!("{value:+.1}"),
+                    Some(value) => format!("{value:.1}"),
+                    None => "-".into(),
+                }),
+        );
+        table.row(cells);
+    }
+    table.print();
+    Ok(())
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Print entries as pages arrive instead of collecting the whole range first.
+fn print_history_stream(
+    entries: impl Iterator<Item = Result<serde_json::Value, api::ApiError>>,
+    limit: Option<u32>,
+    label: &str,
+) -> Result<(), AppError> {
+    let limit = limit.map_or(usize::MAX, |limit| limit as usize);
+    if output::is_json() {
+        return print_history_json(entries.take(limit));
+    }
+    let mut count = 0;
+    for entry in entries.take(limit) {
+        count += 1;
+        println!("-- {label} {count} --");
+        print_json(&entry?);
+    }
+    if count == 0 {
+        println!("No history entries found.");
+    }
+    Ok(())
+}
+
+fn handle_sensor_ignore(sensor_id: &str, ignored: bool) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    api.sensor_set_ignored(SensorUpdateRequest {
+        id: sensor_id,
+        ignored,
+    })?;
+    if ignored {
+        println!("Sensor {sensor_id} is now ignored.");
+    } else {
+        println!("Sensor {sensor_id} is now active.");
+    }
+    Ok(())
+}
+
+fn handle_groups_list() -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let mut groups = api.list_groups()?;
+    if groups.is_empty() {
+        println!("No device groups found.");
+        return Ok(());
+    }
+    groups.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+    print_entries(groups);
+    Ok(())
+}
+
+fn handle_group_action<F, M>(group_id: &str, action: F, message: M) -> Result<(), AppError>
+where
+    F: FnOnce(&TelldusApi, &str) -> Result<(), api::ApiError>,
+    M: FnOnce() -> String,
+{
+    let session = authenticate()?;
+    let api = session.api()?;
+    let Some(members) = api.group_members(group_id)? else {
+        return Err(AppError::Usage(format!(
+            "Device {group_id} is not a group; use the devices subcommands instead."
+        )));
+    };
+    // Named before the command, which drops the cached device list.
+    let members = describe_members(&api, members)?;
+    action(&api, group_id)?;
+    println!(
+        "Group {group_id} {} with a single group command, reaching {members}.",
+        message()
+    );
+    Ok(())
+}
+
+/// Turn a device on or off; for a group, also report which members were commanded.
+fn handle_device_switch(device_id: &str, on: bool) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let device_id = resolve_device(&api, device_id)?;
+    let members = if cache::is_group(&api, &device_id)? {
+        api.group_members(&device_id)?
+            .map(|members| describe_members(&api, members))
+            .transpose()?
+    } else {
+        None
+    };
+    if on {
+        api.device_turn_on(&device_id)?;
+    } else {
+        api.device_turn_off(&device_id)?;
+    }
+    recent::record(&session.credentials, &device_id);
+    let state = if on { "on" } else { "off" };
+    match members {
+        Some(members) => println!("Turned group {device_id} {state}, reaching {members}."),
+        None => println!("Turned device {state}."),
+    }
+    Ok(())
+}
+
+/// Group members as `Lamp (1), Porch (2)`, or `no members` for an empty group.
+fn describe_members(api: &TelldusApi, members: Vec<String>) -> Result<String, AppError> {
+    let named: Vec<String> = cache::device_names(api, members)?
+        .into_iter()
+        .map(|(id, name)| match name {
+            Some(name) => format!("{name} ({id})"),
+            None => format!("device {id}"),
+        })
+        .collect();
+    Ok(match named.len() {
+        0 => "no members".into(),
+        1 => format!("1 member: {}", named[0]),
+        count => format!("{count} members: {}", named.join(", ")),
+    })
+}
+
+fn handle_events_list() -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let mut events = api.list_events()?;
+    if events.is_empty() {
+        println!("No events configured.");
+        return Ok(());
+    }
+    events.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+    print_entries(events);
+    Ok(())
+}
+
+fn handle_event_wizard() -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let devices = api.list_devices()?;
+
+    let description: String = Input::new()
+        .with_prompt("Event description")
+        .interact_text()?;
+
+    let trigger = match Select::new()
+        .with_prompt("Trigger")
+        .items(&["Device state change", "Sensor threshold", "Time of day"])
+        .default(0)
+        .interact()?
+    {
+        0 => {
+            let device = choose_entry("Device", &devices)?;
+            let method = choose_state("When the device is")?;
+            EventTrigger::Device {
+                device_id: device.id.clone(),
+                method,
+            }
+        }
+        1 => {
+            let sensors = api.list_sensors(false)?;
+            let sensor = choose_entry("Sensor", &sensors)?;
+            let value_type = SENSOR_VALUE_TYPES[Select::new()
+                .with_prompt("Reading")
+                .items(&SENSOR_VALUE_TYPES)
+                .default(0)
+                .interact()?];
+            let scale: i32 = Input::new()
+                .with_prompt("Scale")
+                .default(0)
+                .interact_text()?;
+            let edge = match Select::new()
+                .with_prompt("Fire when the reading")
+                .items(&["rises above", "falls below"])
+                .default(0)
+                .interact()?
+            {
+                0 => 1,
+                _ => -1,
+            };
+            let value: f64 = Input::new().with_prompt("Threshold").interact_text()?;
+            EventTrigger::Sensor {
+                sensor_id: sensor.id.clone(),
+                value_type: value_type.into(),
+                scale,
+                value,
+                edge,
+            }
+        }
+        _ => {
+            let (hour, minute) = prompt_clock("Time (HH:MM)")?;
+            EventTrigger::Time { hour, minute }
+        }
+    };
+
+    let mut conditions = Vec::new();
+    while Confirm::new()
+        .with_prompt("Add a condition?")
+        .default(false)
+        .interact()?
+    {
+        let condition = match Select::new()
+            .with_prompt("Condition")
+            .items(&["Device state", "Time window"])
+            .default(0)
+            .interact()?
+        {
+            0 => {
+                let device = choose_entry("Device", &devices)?;
+                let state = choose_state("Only when the device is")?;
+                EventCondition::Device {
+                    device_id: device.id.clone(),
+                    state,
+                }
+            }
+            _ => EventCondition::Time {
+                from: prompt_clock("From (HH:MM)")?,
+                to: prompt_clock("To (HH:MM)")?,
+            },
+        };
+        conditions.push(condition);
+    }
+
+    let mut actions = Vec::new();
+    loop {
+        let action = match Select::new()
+            .with_prompt("Action")
+            .items(&["Device command", "Push notification", "SMS"])
+            .default(0)
+            .interact()?
+        {
+            0 => {
+                let device = choose_entry("Device", &devices)?;
+                let (method, value) = match Select::new()
+                    .with_prompt("Command")
+                    .items(&["on", "off", "dim"])
+                    .default(0)
+                    .interact()?
+                {
+                    0 => (1, None),
+                    1 => (2, None),
+                    _ => {
+                        let level: u8 = Input::new()
+                            .with_prompt("Dim level (0-255)")
+                            .interact_text()?;
+                        (16, Some(level))
+                    }
+                };
+                EventAction::Device {
+                    device_id: device.id.clone(),
+                    method,
+                    value,
+                }
+            }
+            2 => {
+                let to: String = Input::new()
+                    .with_prompt("Recipient phone number")
+                    .interact_text()?;
+                let message: String = Input::new().with_prompt("Message").interact_text()?;
+                EventAction::Sms { to, message }
+            }
+            _ => {
+                let phones = api.list_phones()?;
+                let phone = choose_entry("Phone", &phones)?;
+                let message: String = Input::new().with_prompt("Message").interact_text()?;
+                EventAction::Push {
+                    phone_id: phone.id.clone(),
+                    message,
+                }
+            }
+        };
+        actions.push(action);
+        if !Confirm::new()
+            .with_prompt("Add another action?")
+            .default(false)
+            .interact()?
+        {
+            break;
+        }
+    }
+
+    println!(
+        "Event '{description}': 1 trigger, {} condition(s), {} action(s).",
+        conditions.len(),
+        actions.len()
+    );
+    if actions
+        .iter()
+        .any(|action| matches!(action, EventAction::Sms { .. }))
+    {
+        let profile = auth::fetch_profile(&session.client, &session.credentials)?;
+        match sms_credits(&profile) {
+            Some(credits) if credits <= 0.0 => println!(
+                "Warning: no SMS credits remain; SMS actions will not be delivered until you top up."
+            ),
+            Some(_) => {}
+            None => println!("Warning: Telldus Live did not report an SMS credit balance."),
+        }
+    }
+    if !Confirm::new()
+        .with_prompt("Create this event?")
+        .default(true)
+        .interact()?
+    {
+        println!("Event creation cancelled.");
+        return Ok(());
+    }
+
+    let event_id = api.create_event(&description, 30)?;
+    let configured = api.set_event_trigger(&event_id, &trigger).and_then(|()| {
+        for condition in &conditions {
+            api.set_event_condition(&event_id, condition)?;
+        }
+        for action in &actions {
+            api.set_event_action(&event_id, action)?;
+        }
+        Ok(())
+    });
+    if let Err(err) = configured {
+        // An event missing its trigger or some actions would fire wrongly or never.
+        match api.remove_event(&event_id) {
+            Ok(()) => eprintln!("Removed the incomplete event {event_id}."),
+            Err(remove_err) => eprintln!(
+                "Event {event_id} is incomplete and could not be removed ({remove_err}); \
+                 remove it in Telldus Live."
+            ),
+        }
+        return Err(err.into());
+    }
+    println!("Created event {event_id}.");
+    Ok(())
+}
+
+/// Sensor reading types accepted by Telldus Live sensor triggers.
+const SENSOR_VALUE_TYPES: [&str; 10] = [
+    "temp",
+    "humidity",
+    "rainrate",
+    "raintotal",
+    "winddirection",
+    "windaverage",
+    "windgust",
+    "uv",
+    "watt",
+    "lum",
+];
+
+fn choose_entry<'e>(prompt: &str, entries: &'e [Entry]) -> Result<&'e Entry, AppError> {
+    if entries.is_empty() {
+        return Err(AppError::Usage(format!(
+            "No candidates available for '{prompt}'."
+        )));
+    }
+    let labels: Vec<String> = entries
+        .iter()
+        .map(|entry| format!("{} ({})", entry.name, entry.id))
+        .collect();
+    let index = Select::new()
+        .with_prompt(prompt)
+        .items(&labels)
+        .default(0)
+        .interact()?;
+    Ok(&entries[index])
+}
+
+fn choose_state(prompt: &str) -> Result<u32, AppError> {
+    let index = Select::new()
+        .with_prompt(prompt)
+        .items(&["on", "off"])
+        .default(0)
+        .interact()?;
+    Ok(if index == 0 { 1 } else { 2 })
+}
+
+fn prompt_clock(prompt: &str) -> Result<(u8, u8), AppError> {
+    let input: String = Input::new()
+        .with_prompt(prompt)
+        .validate_with(|text: &String| match ScheduleTime::parse(text) {
+            Ok(ScheduleTime::Clock { .. }) => Ok(()),
+            Ok(_) => Err("use a clock time such as 06:30".to_string()),
+            Err(err) => Err(err),
+        })
+        .interact_text()?;
+    match ScheduleTime::parse(&input) {
+        Ok(ScheduleTime::Clock { hour, minute }) => Ok((hour, minute)),
+        _ => Err(AppError::Usage(format!("Invalid time '{input}'."))),
+    }
+}
+
+fn handle_scheduler_list() -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let mut jobs = api.list_jobs()?;
+    if jobs.is_empty() {
+        println!("No scheduler jobs found.");
+        return Ok(());
+    }
+    jobs.sort_by(|a, b| a.device_id.cmp(&b.device_id).then(a.id.cmp(&b.id)));
+
+    let mut table = Table::new(&["JOB", "DEVICE", "WHEN", "ACTION", "DAYS"]);
+    for job in jobs {
+        let days = if job.weekdays.is_empty() {
+            "-".to_string()
+        } else {
+            job.weekdays
+                .iter()
+                .map(u8::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let when = if job.active {
+            Cell::from(job.time.to_string())
+        } else {
+            Cell::styled(format!("{} (off)", job.time), Style::Off)
+        };
+        let action = job.describe_action();
+        table.row([
+            job.id.into(),
+            job.device_id.into(),
+            when,
+            action.into(),
+            days.into(),
+        ]);
+    }
+    table.print();
+    Ok(())
+}
+
+fn handle_scheduler_show(week: bool) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let jobs = api.list_jobs()?;
+    if jobs.is_empty() {
+        println!("No scheduler jobs found.");
+        return Ok(());
+    }
+
+    let days: Vec<u8> = if week {
+        (1..=7).collect()
+    } else {
+        vec![schedule::today()]
+    };
+
+    println!();
+    println!("{:<5}00    06    12    18", "");
+    let mut agenda = Vec::new();
+    for day in days {
+        let mut slots = [0u32; 24];
+        let mut items: Vec<(ScheduleTime, String)> = Vec::new();
+        for job in jobs
+            .iter()
+            .filter(|job| job.active && (job.weekdays.is_empty() || job.weekdays.contains(&day)))
+        {
+            if let ScheduleTime::Clock { hour, .. } = job.time {
+                slots[usize::from(hour)] += 1;
+            }
+            items.push((
+                job.time,
+                format!("{} {} #{}", job.time, job.describe_action(), job.device_id),
+            ));
+        }
+
+        let row: String = slots
+            .iter()
+            .map(|count| match count {
+                0 => '.',
+                1..=9 => char::from_digit(*count, 10).unwrap_or('+'),
+                _ => '+',
+            })
+            .collect();
+        let sun_jobs = items
+            .iter()
+            .filter(|(time, _)| !matches!(time, ScheduleTime::Clock { .. }))
+            .count();
+        let label = WEEKDAY_NAMES[usize::from(day - 1)];
+        if sun_jobs > 0 {
+            println!("{label:<5}{row}  +{sun_jobs} sun-relative");
+        } else {
+            println!("{label:<5}{row}");
+        }
+
+        items.sort_by_key(|(time, _)| (time.kind() != "time", time.hour(), time.minute()));
+        if !items.is_empty() {
+            let descriptions: Vec<String> = items.into_iter().map(|(_, text)| text).collect();
+            agenda.push(format!("{label}: {}", descriptions.join(", ")));
+        }
+    }
+
+    println!();
+    println!("Digits count active jobs starting in each hour; '+' marks ten or more.");
+    for line in agenda {
+        println!("{line}");
+    }
+    let inactive = jobs.iter().filter(|job| !job.active).count();
+    if inactive > 0 {
+        println!("{inactive} inactive job(s) not shown.");
+    }
+    Ok(())
+}
+
+fn handle_scheduler_export(format: ExportFormat, output: Option<&Path>) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let jobs = api.list_jobs()?;
+    let device_names: HashMap<String, String> = api
+        .list_devices()?
+        .into_iter()
+        .map(|entry| (entry.id, entry.name))
+        .collect();
+
+    let document = match format {
+        ExportFormat::Ics => schedule::jobs_to_ics(&jobs, &device_names),
+    };
+    match output {
+        Some(path) => {
+            fs::write(path, document)
+                .map_err(|err| AppError::WriteFailed(path.display().to_string(), err))?;
+            println!(
+                "Exported {} scheduler job(s) to {}.",
+                jobs.iter().filter(|job| job.active).count(),
+                path.display()
+            );
+        }
+        None => print!("{document}"),
+    }
+    Ok(())
+}
+
+fn handle_scheduler_set(
+    device_id: &str,
+    action: ScheduleAction,
+    level: Option<u8>,
+    at: ScheduleTime,
+    days: &Weekdays,
+    except: &[ExceptDate],
+    yes: bool,
+) -> Result<(), AppError> {
+    let method_value = match (action, level) {
+        (ScheduleAction::Dim, Some(level)) => Some(level),
+        (ScheduleAction::Dim, None) => {
+            return Err(AppError::Usage("Dim jobs require --level (0-255).".into()));
+        }
+        (_, Some(_)) => {
+            return Err(AppError::Usage(
+                "--level is only valid with --action dim.".into(),
+            ));
+        }
+        (_, None) => None,
+    };
+
+    if !except.is_empty() {
+        return Err(AppError::Usage(local_job_hint(
+            device_id,
+            action,
+            method_value,
+            at,
+            days,
+            except,
+        )));
+    }
+
+    // Echo how free-form times and days were read before anything is submitted.
+    let action_text = match method_value {
+        Some(level) => format!("dim device {device_id} to {level}"),
+        None => format!("turn {} device {device_id}", action.name()),
+    };
+    println!("Will {action_text} {} {}.", at.describe(), days.describe());
+    if !yes
+        && io::stdin().is_terminal()
+        && !Confirm::new()
+            .with_prompt("Create this scheduler job?")
+            .default(true)
+            .interact()?
+    {
+        println!("Scheduler job not created.");
+        return Ok(());
+    }
+
+    let session = authenticate()?;
+    let api = session.api()?;
+    let job_id = api.set_job(SchedulerJobRequest {
+        device_id,
+        method: action.method(),
+        method_value,
+        time: at,
+        weekdays: days.numbers(),
+    })?;
+    println!("Created scheduler job {job_id} for device {device_id} at {at}.");
+    Ok(())
+}
+
+/// Telldus Live jobs cannot skip dates, so offer the equivalent `schedules.yaml` job.
+fn local_job_hint(
+    device_id: &str,
+    action: ScheduleAction,
+    level: Option<u8>,
+    at: ScheduleTime,
+    days: &Weekdays,
+    except: &[ExceptDate],
+) -> String {
+    let mut job = format!(
+        "  - at: \"{at}\"\n    device: {device_id}\n    action: {}\n",
+        action.name()
+    );
+    if let Some(level) = level {
+        job.push_str(&format!("    level: {level}\n"));
+    }
+    if days.numbers().len() < 7 {
+        job.push_str(&format!("    days: {}\n", days.to_argument()));
+    }
+    let dates: Vec<String> = except.iter().map(|date| format!("\"{date}\"")).collect();
+    job.push_str(&format!("    except: [{}]", dates.join(", ")));
+    format!(
+        "Telldus Live scheduler jobs cannot skip dates. Add this job under `jobs:` in \
+         schedules.yaml and run it with `telltales daemon run` instead:\n{job}"
+    )
+}
+
+fn handle_scheduler_remove(job_id: &str) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    api.remove_job(job_id)?;
+    println!("Removed scheduler job {job_id}.");
+    Ok(())
+}
+
+fn handle_battery(all: bool, threshold: u8) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let mut statuses: Vec<_> = api
+        .list_batteries()?
+        .into_iter()
+        .filter(|status| {
+            all || status.level.is_low(threshold) || status.level == BatteryLevel::Unknown
+        })
+        .collect();
+    if statuses.is_empty() {
+        println!("All reported batteries are fine.");
+        return Ok(());
+    }
+    statuses.sort_by(|a, b| {
+        a.category
+            .as_str()
+            .cmp(b.category.as_str())
+            .then(a.name.cmp(&b.name))
+    });
+
+    let mut table = Table::new(&["TYPE", "ID", "NAME", "BATTERY"]);
+    for status in statuses {
+        let style = if status.level == BatteryLevel::Unknown {
+            Style::Warning
+        } else if status.level.is_low(threshold) {
+            Style::Problem
+        } else {
+            Style::On
+        };
+        table.row([
+            status.category.as_str().into(),
+            status.id.into(),
+            status.name.into(),
+            Cell::styled(status.level.to_string(), style),
+        ]);
+    }
+    table.print();
+    Ok(())
+}
+
+fn handle_stats() -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let clients = api.raw_list("/json/clients/list", &[], &["client", "clients"])?;
+    // Every method bit, so each device reports which commands it supports.
+    let devices = api.raw_list(
+        "/json/devices/list",
+        &[("supportedMethods", "1023"), ("extras", "battery")],
+        &["device", "devices"],
+    )?;
+    let sensors = api.raw_list(
+        "/json/sensors/list",
+        &[("includeIgnored", "1"), ("includeValues", "0")],
+        &["sensor", "sensors"],
+    )?;
+    let jobs = api.list_jobs()?;
+    let events = api.list_events()?.len();
+    let stats = stats::collect(
+        &clients,
+        &devices,
+        &sensors,
+        &jobs,
+        events,
+        unix_now().max(0) as u64,
+    );
+
+    if output::is_json() {
+        output::print(&stats);
+        return Ok(());
+    }
+    let (controllers, devices, sensors) = (&stats.controllers, &stats.devices, &stats.sensors);
+    println!(
+        "{:<12} {} ({} online, {} offline)",
+        "Controllers", controllers.total, controllers.online, controllers.offline
+    );
+    println!(
+        "{:<12} {} ({} group(s), {} dimmable, {} battery-powered)",
+        "Devices", devices.total, devices.groups, devices.dimmable, devices.battery
+    );
+    println!(
+        "{:<12} {} ({} ignored, {} battery-powered)",
+        "Sensors", sensors.total, sensors.ignored, sensors.battery
+    );
+    let seen = &stats.last_seen;
+    println!(
+        "{:<12} {} under 1h, {} 1h-1d, {} 1d-7d, {} older, {} never",
+        "Last seen", seen.hour, seen.day, seen.week, seen.older, seen.never
+    );
+    println!(
+        "{:<12} {} ({} active)",
+        "Schedules", stats.schedules.total, stats.schedules.active
+    );
+    println!("{:<12} {}", "Events", stats.events);
+    Ok(())
+}
+
+fn handle_snapshot_save(name: &str, deep: bool) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let clients = api.raw_list("/json/clients/list", &[], &["client", "clients"])?;
+    let mut devices = api.raw_list("/json/devices/list", &[], &["device", "devices"])?;
+    let sensors = api.raw_list(
+        "/json/sensors/list",
+        &[("includeIgnored", "1"), ("includeValues", "0")],
+        &["sensor", "sensors"],
+    )?;
+
+    if deep {
+        for device in devices.iter_mut() {
+            let Some(id) = device.get("id").map(|id| match id {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            }) else {
+                continue;
+            };
+            *device = api.device_info(&id)?;
+        }
+    }
+
+    let taken_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let document = snapshot::build(
+        taken_at,
+        vec![
+            ("clients", clients),
+            ("devices", devices),
+            ("sensors", sensors),
+        ],
+    );
+    let path = snapshot::save(name, &document)?;
+    println!("Saved snapshot '{name}' to {}.", path.display());
+    Ok(())
+}
+
+fn handle_snapshot_diff(before: &str, after: &str) -> Result<(), AppError> {
+    let old = snapshot::load(before)?;
+    let new = snapshot::load(after)?;
+    let changes = snapshot::diff(&old, &new);
+    if changes.is_empty() {
+        println!("No differences between '{before}' and '{after}'.");
+        return Ok(());
+    }
+
+    for change in changes {
+        match change {
+            snapshot::Change::Added { section, id, name } => {
+                println!("+ {section} {id} ({name}) added")
+            }
+            snapshot::Change::Removed { section, id, name } => {
+                println!("- {section} {id} ({name}) removed")
+            }
+            snapshot::Change::Modified {
+                section,
+                id,
+                name,
+                field,
+                before,
+                after,
+            } => println!("~ {section} {id} ({name}) {field}: '{before}' -> '{after}'"),
+        }
+    }
+    Ok(())
+}
+
+fn handle_snapshot_list() -> Result<(), AppError> {
+    let names = snapshot::list()?;
+    if names.is_empty() {
+        println!("No snapshots saved yet.");
+    }
+    for name in names {
+        println!("{name}");
+    }
+    Ok(())
+}
+
+fn load_daemon_jobs(file: Option<PathBuf>) -> Result<(PathBuf, daemon::Schedules), AppError> {
+    let path = match file {
+        Some(path) => path,
+        None => daemon::default_path()?,
+    };
+    let schedules = daemon::load(&path)?;
+    Ok((path, schedules))
+}
+
+fn handle_daemon_check(file: Option<PathBuf>) -> Result<(), AppError> {
+    let (path, schedules) = load_daemon_jobs(file)?;
+    if let Some(sink) = &schedules.metrics {
+        println!(
+            "Sends sensor readings to {} every {}.",
+            sink.describe(),
+            format_duration(sink.interval)
+        );
+    }
+    if let Some(vacation) = &schedules.vacation {
+        println!("Vacation mode switches lights at random times within these windows:");
+        let mut table = Table::new(&["DEVICE", "ON", "OFF"]);
+        for light in &vacation.lights {
+            table.row([
+                light.device.clone(),
+                light.on.to_string(),
+                light.off.to_string(),
+            ]);
+        }
+        table.print();
+    }
+    let jobs = &schedules.jobs;
+    if jobs.is_empty() {
+        println!("No jobs defined in {}.", path.display());
+    } else {
+        let mut table = Table::new(&["NAME", "WHEN", "DEVICE", "ACTION"]);
+        for job in jobs {
+            let mut action = job.describe_action();
+            if let Some(condition) = &job.only_if {
+                let state = format!("{:?}", condition.state).to_lowercase();
+                action.push_str(&format!(" if {} is {state}", condition.device));
+            }
+            table.row([
+                job.name.as_str(),
+                job.describe_when().as_str(),
+                job.device.as_str(),
+                action.as_str(),
+            ]);
+        }
+        table.print();
+    }
+    if schedules.needs_location() {
+        match schedules.location {
+            Some(location) => println!("{}", describe_sun(location)),
+            None => println!(
+                "Sunrise and sunset will be computed for the controller's location; \
+                 set location in {} to choose another.",
+                path.display()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Today's sunrise and sunset at `location` in local time.
+fn describe_sun(location: sun::Location) -> String {
+    let times = sun::sun_times(location, unix_now() as u64);
+    let format = |time: Option<u64>| {
+        time.map_or_else(
+            || "none".to_string(),
+            |time| {
+                let time = schedule::local_time(time);
+                format!("{:02}:{:02}", time.hour, time.minute)
+            },
+        )
+    };
+    format!(
+        "Today at {:.4}, {:.4}: sunrise {}, sunset {}.",
+        location.latitude,
+        location.longitude,
+        format(times.sunrise),
+        format(times.sunset)
+    )
+}
+
+fn handle_daemon_run(
+    file: Option<PathBuf>,
+    pid_file: Option<PathBuf>,
+    filter: Option<&filter::Filter>,
+) -> Result<(), AppError> {
+    response_cache::set_ttl(Duration::ZERO);
+    let (path, mut schedules) = load_daemon_jobs(file)?;
+    if schedules.jobs.is_empty() && schedules.metrics.is_none() && schedules.vacation.is_none() {
+        return Err(AppError::Usage(format!(
+            "No jobs, metrics or vacation lights defined in {}.",
+            path.display()
+        )));
+    }
+    let session = authenticate()?;
+    let api = session.api()?;
+    if schedules.needs_location() && schedules.location.is_none() {
+        schedules.location = Some(api.controller_location()?.ok_or_else(|| {
+            AppError::Usage(format!(
+                "Jobs or vacation windows follow sunrise or sunset, but no controller reports its location; \
+                 set location in {}.",
+                path.display()
+            ))
+        })?);
+    }
+    let _pid_file = pid_file
+        .map(|path| {
+            service::PidFile::create(&path)
+                .map_err(|err| AppError::WriteFailed(path.display().to_string(), err))
+        })
+        .transpose()?;
+    status(&format!(
+        "Running {} job(s) from {}; press Ctrl-C to stop.",
+        schedules.jobs.len(),
+        path.display()
+    ));
+    if let Some(sink) = &schedules.metrics {
+        status(&format!(
+            "Sending sensor readings to {} every {}.",
+            sink.describe(),
+            format_duration(sink.interval)
+        ));
+    }
+    if let Some(vacation) = &schedules.vacation {
+        status(&format!(
+            "Vacation mode: switching {} light(s) at random times.",
+            vacation.lights.len()
+        ));
+    }
+    service::sd_notify("READY=1");
+    daemon::run(&api, &schedules, filter);
+    service::sd_notify("STOPPING=1");
+    println!("Stopped.");
+    Ok(())
+}
+
+fn handle_daemon_install_service(
+    file: Option<PathBuf>,
+    output: Option<PathBuf>,
+) -> Result<(), AppError> {
+    let (path, _) = load_daemon_jobs(file)?;
+    let path = path.canonicalize().unwrap_or(path);
+    let executable = env::current_exe()
+        .map_err(|err| AppError::Usage(format!("cannot locate the telltales binary: {err}")))?;
+    let unit = service::systemd_unit(&executable, &path);
+    match output {
+        Some(output) => {
+            fs::write(&output, unit)
+                .map_err(|err| AppError::WriteFailed(output.display().to_string(), err))?;
+            println!("Wrote {}.", output.display());
+            println!(
+                "Enable it with: systemctl --user daemon-reload && systemctl --user enable --now {}",
+                output
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .unwrap_or_default()
+            );
+        }
+        None => print!("{unit}"),
     }
+    Ok(())
+}
+
+fn load_alerts(file: Option<PathBuf>) -> Result<(PathBuf, alerts::Config), AppError> {
+    let path = match file {
+        Some(path) => path,
+        None => alerts::default_path()?,
+    };
+    let config = alerts::load(&path)?;
+    Ok((path, config))
+}
 
+fn handle_alerts_check(file: Option<PathBuf>) -> Result<(), AppError> {
+    let (path, config) = load_alerts(file)?;
+    if config.rules.is_empty() && config.controllers.is_none() {
+        println!("No rules defined in {}.", path.display());
+        return Ok(());
+    }
+    println!(
+        "Checking every {} ({} rule(s){}).",
+        format_duration(config.interval),
+        config.rules.len(),
+        if config.controllers.is_some() {
+            " and the controllers"
+        } else {
+            ""
+        }
+    );
+    if !config.rules.is_empty() {
+        let mut table = Table::new(&["NAME", "SENSOR", "CONDITION", "ACTIONS"]);
+        for rule in &config.rules {
+            let actions: Vec<String> = rule.actions.iter().map(ToString::to_string).collect();
+            table.row([
+                rule.name.clone(),
+                rule.sensor.clone(),
+                rule.describe(),
+                actions.join(", "),
+            ]);
+        }
+        table.print();
+    }
+    if let Some(watch) = &config.controllers {
+        let controllers = if watch.only.is_empty() {
+            "every controller".to_string()
+        } else {
+            watch.only.join(", ")
+        };
+        let actions: Vec<String> = watch.actions.iter().map(ToString::to_string).collect();
+        println!();
+        println!("{:<14} {}", "Controllers:", controllers);
+        println!("{:<14} {}", "Alert when:", watch.describe());
+        println!("{:<14} {}", "Actions:", actions.join(", "));
+    }
     Ok(())
 }
 
-fn handle_device_remove(device_id: &str) -> Result<(), AppError> {
+fn handle_alerts_run(
+    file: Option<PathBuf>,
+    filter: Option<&filter::Filter>,
+) -> Result<(), AppError> {
+    response_cache::set_ttl(Duration::ZERO);
+    let (path, mut config) = load_alerts(file)?;
+    if config.rules.is_empty() && config.controllers.is_none() {
+        return Err(AppError::Usage(format!(
+            "No rules defined in {}.",
+            path.display()
+        )));
+    }
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    api.remove_device(device_id)?;
-    println!("Removed device {device_id}.");
+    let api = session.api()?;
+    if config.needs_location() && config.location.is_none() {
+        config.location = Some(api.controller_location()?.ok_or_else(|| {
+            AppError::Usage(format!(
+                "Rules follow sunrise or sunset, but no controller reports its location; \
+                 set location in {}.",
+                path.display()
+            ))
+        })?);
+    }
+    status(&format!(
+        "Watching {} rule(s){} from {}; press Ctrl-C to stop.",
+        config.rules.len(),
+        if config.controllers.is_some() {
+            " and the controllers"
+        } else {
+            ""
+        },
+        path.display()
+    ));
+    alerts::run(&api, &session.client, &config, filter);
     Ok(())
 }
 
-fn handle_device_simple<F, M>(device_id: &str, action: F, message: M) -> Result<(), AppError>
-where
-    F: FnOnce(&TelldusApi, &str) -> Result<(), api::ApiError>,
-    M: FnOnce() -> String,
-{
+/// Print a long-running command's status line, on stderr when stdout carries NDJSON
+/// events so the stream stays parseable.
+fn status(message: &str) {
+    if output::is_ndjson() {
+        eprintln!("{message}");
+    } else {
+        println!("{message}");
+    }
+}
+
+fn handle_serve(listen: SocketAddr, token: Option<String>) -> Result<(), AppError> {
+    response_cache::set_ttl(Duration::ZERO);
+    let token = token
+        .or_else(|| env::var(SERVE_TOKEN_VAR).ok())
+        .filter(|token| !token.trim().is_empty())
+        .ok_or_else(|| {
+            AppError::Usage(format!(
+                "serve needs a bearer token; set {SERVE_TOKEN_VAR} or pass --token."
+            ))
+        })?;
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    action(&api, device_id)?;
-    println!("{}", message());
+    let api = session.api()?;
+    println!("Serving the REST API on http://{listen}; press Ctrl-C to stop.");
+    serve::run(&api, listen, &token)?;
+    println!("Stopped.");
     Ok(())
 }
 
-fn handle_device_info(device_id: &str) -> Result<(), AppError> {
+fn handle_doctor() -> Result<(), AppError> {
+    let checks = doctor::run();
+    for check in &checks {
+        println!("[{}] {:<22} {}", check.status, check.label, check.detail);
+    }
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == doctor::Status::Fail)
+        .count();
+    if failed > 0 {
+        return Err(AppError::ChecksFailed(failed));
+    }
+    println!("All checks passed.");
+    Ok(())
+}
+
+fn handle_user_profile() -> Result<(), AppError> {
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    let info = api.device_info(device_id)?;
-    print_json(&info);
+    let profile = auth::fetch_profile(&session.client, &session.credentials)?;
+    let user = profile.get("user").unwrap_or(&profile);
+    let field = |keys: &[&str]| {
+        keys.iter()
+            .filter_map(|key| user.get(*key))
+            .find_map(|value| match value {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(text) if text.trim().is_empty() => None,
+                serde_json::Value::String(text) => Some(text.trim().to_string()),
+                other => Some(other.to_string()),
+            })
+            .unwrap_or_else(|| "-".into())
+    };
+
+    println!();
+    println!(
+        "{:<10} {}",
+        "Name",
+        auth::account_name(&profile).unwrap_or_else(|| "-".into())
+    );
+    println!("{:<10} {}", "Email", field(&["email"]));
+    println!(
+        "{:<10} {}",
+        "Locale",
+        field(&["locale", "language", "lang"])
+    );
+    println!("{:<10} {}", "Country", field(&["country"]));
+    println!("{:<10} {}", "Credits", field(&["credits", "smsCredits"]));
+    println!("{:<10} {}", "Pro", field(&["pro", "isPro"]));
     Ok(())
 }
 
-fn handle_device_history(device_id: &str, limit: Option<u32>) -> Result<(), AppError> {
+fn handle_sms_credits() -> Result<(), AppError> {
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    let entries = api.device_history(device_id, limit)?;
-    if entries.is_empty() {
-        println!("No history entries found.");
-    } else {
-        for (idx, entry) in entries.iter().enumerate() {
-            println!("-- Event {} --", idx + 1);
-            print_json(entry);
+    let profile = auth::fetch_profile(&session.client, &session.credentials)?;
+    match sms_credits(&profile) {
+        Some(credits) if credits <= 0.0 => {
+            println!("SMS credits exhausted; SMS event actions will not be delivered.")
         }
+        Some(credits) => println!("SMS credits remaining: {credits}"),
+        None => println!("Telldus Live did not report an SMS credit balance."),
     }
     Ok(())
 }
 
-fn handle_device_set_parameter(
-    device_id: &str,
-    parameter: &str,
-    value: &str,
-) -> Result<(), AppError> {
+/// Extract the SMS credit balance from a `user/profile` payload.
+fn sms_credits(profile: &serde_json::Value) -> Option<f64> {
+    let user = profile.get("user").unwrap_or(profile);
+    ["credits", "smsCredits"]
+        .iter()
+        .filter_map(|key| user.get(*key))
+        .find_map(|value| match value {
+            serde_json::Value::Number(number) => number.as_f64(),
+            serde_json::Value::String(text) => text.trim().parse().ok(),
+            _ => None,
+        })
+}
+
+fn handle_phones_list() -> Result<(), AppError> {
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    api.set_device_parameter(device_id, parameter, value)?;
-    println!("Set parameter '{parameter}' for device {device_id} to '{value}'.");
+    let api = session.api()?;
+    let phones = api.list_phones()?;
+    if phones.is_empty() {
+        println!(
+            "No phones registered; sign in with the Telldus Live app to receive push notifications."
+        );
+        return Ok(());
+    }
+    print_entries(phones);
     Ok(())
 }
 
-fn handle_device_get_parameter(device_id: &str, parameter: &str) -> Result<(), AppError> {
+fn handle_push_test(phone_id: &str) -> Result<(), AppError> {
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    match api.get_device_parameter(device_id, parameter)? {
-        Some(value) => println!("Parameter '{parameter}' = '{value}'"),
-        None => println!("Parameter '{parameter}' not set for device {device_id}."),
+    let api = session.api()?;
+    api.send_push_test(phone_id)?;
+    println!("Sent test push notification to phone {phone_id}.");
+    Ok(())
+}
+
+fn handle_notify_push(message: &str, phone_ids: Vec<String>) -> Result<(), AppError> {
+    if message.trim().is_empty() {
+        return Err(AppError::Usage("Push message must not be empty.".into()));
+    }
+
+    let session = authenticate()?;
+    let api = session.api()?;
+    let targets = if phone_ids.is_empty() {
+        api.list_phones()?
+            .into_iter()
+            .map(|phone| phone.id)
+            .collect()
+    } else {
+        phone_ids
+    };
+    if targets.is_empty() {
+        return Err(AppError::Usage(
+            "No phones registered; sign in with the Telldus Live app first.".into(),
+        ));
+    }
+
+    for phone_id in &targets {
+        check_interrupted()?;
+        api.send_push(phone_id, message)?;
+        println!("Sent push notification to phone {phone_id}.");
     }
     Ok(())
 }
 
-fn handle_sensor_info(sensor_id: &str, scale: Option<i32>) -> Result<(), AppError> {
+fn handle_zwave_nodes(client_id: &str) -> Result<(), AppError> {
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    let info = api.sensor_info(sensor_id, scale)?;
-    print_json(&info);
+    let api = session.api()?;
+    let mut nodes = api.list_zwave_nodes(client_id)?;
+    if nodes.is_empty() {
+        println!("No Z-Wave nodes found on client {client_id}.");
+        return Ok(());
+    }
+    nodes.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+    print_entries(nodes);
     Ok(())
 }
 
-fn handle_sensor_history(sensor_id: &str, scale: i32, limit: Option<u32>) -> Result<(), AppError> {
+fn handle_zwave_get_config(device_id: &str, parameter: u8) -> Result<(), AppError> {
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    let entries = api.sensor_history(sensor_id, scale, limit)?;
-    if entries.is_empty() {
-        println!("No sensor history entries found.");
-    } else {
-        for (idx, entry) in entries.iter().enumerate() {
-            println!("-- Reading {} --", idx + 1);
-            print_json(entry);
+    let api = session.api()?;
+    match api.get_zwave_config(device_id, parameter)? {
+        Some(value) => println!("Configuration parameter {parameter} = {value}"),
+        None => {
+            println!("Configuration parameter {parameter} not reported for device {device_id}.")
         }
     }
     Ok(())
 }
 
-fn handle_sensor_ignore(sensor_id: &str, ignored: bool) -> Result<(), AppError> {
+fn handle_zwave_set_config(
+    device_id: &str,
+    parameter: u8,
+    size: u8,
+    value: i64,
+) -> Result<(), AppError> {
+    let bits = u32::from(size) * 8;
+    let min = -(1i64 << (bits - 1));
+    let max = (1i64 << bits) - 1;
+    if value < min || value > max {
+        return Err(AppError::Usage(format!(
+            "Value {value} does not fit in a {size}-byte parameter ({min}..={max})."
+        )));
+    }
+
     let session = authenticate()?;
-    let api = TelldusApi::new(&session.client, &session.credentials);
-    api.sensor_set_ignored(SensorUpdateRequest {
-        id: sensor_id,
-        ignored,
+    let api = session.api()?;
+    api.set_zwave_config(ZwaveConfigRequest {
+        id: device_id,
+        parameter,
+        size,
+        value,
     })?;
-    if ignored {
-        println!("Sensor {sensor_id} is now ignored.");
-    } else {
-        println!("Sensor {sensor_id} is now active.");
-    }
+    println!(
+        "Set configuration parameter {parameter} ({size} bytes) for device {device_id} to {value}."
+    );
     Ok(())
 }
 
+fn handle_complete(words: &[String]) {
+    let mut command = Cli::command();
+    command.build();
+    let names = |kind| completion_names(kind).unwrap_or_default();
+    for candidate in completions::candidates(&command, words, names) {
+        println!("{candidate}");
+    }
+}
+
+/// Device or sensor names for completing `--id`, from the cached lists when recent, and
+/// without validating the token or starting the OAuth flow.
+fn completion_names(kind: Category) -> Result<Vec<String>, AppError> {
+    let credentials = stored_account()?;
+    let client = shared_client()?;
+    let api = TelldusApi::builder()
+        .client(&client)
+        .credentials(&credentials)
+        .build()?;
+    Ok(cache::names(&api, kind)?)
+}
+
+/// The account in the credentials file, for local state that needs no request to read.
+fn stored_account() -> Result<TelldusCredentials, AppError> {
+    Ok(config::load_credentials()?.unwrap_or_default())
+}
+
+/// Accept either a numeric device id or a device name known to the local name cache.
+fn resolve_device(api: &TelldusApi, reference: &str) -> Result<String, AppError> {
+    cache::resolve(api, Category::Device, reference)?
+        .ok_or_else(|| AppError::Usage(format!("No device named '{reference}' found.")))
+}
+
 struct Session {
     client: reqwest::blocking::Client,
     credentials: TelldusCredentials,
+    location: PathBuf,
+}
+
+impl Session {
+    fn api(&self) -> Result<TelldusApi<'_>, AppError> {
+        let mut builder = TelldusApi::builder()
+            .client(&self.client)
+            .credentials(&self.credentials)
+            .retry(RETRY_POLICY.get().copied().unwrap_or_default());
+        if let Some(timeout) = REQUEST_TIMEOUT.get() {
+            builder = builder.timeout(*timeout);
+        }
+        if io::stdin().is_terminal() && !fixtures::is_replaying() {
+            builder = builder.reauthorize(|rejected| {
+                reauthorize(&self.client, &self.location, rejected).map_err(|err| {
+                    if err.is_interrupted() {
+                        api::ApiError::Interrupted
+                    } else {
+                        api::ApiError::Reauthorize(err.to_string())
+                    }
+                })
+            });
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Run the OAuth flow again after Telldus Live rejected the stored token mid-command,
+/// so the rejected request can be resent without a separate `auth validate`.
+fn reauthorize(
+    client: &reqwest::blocking::Client,
+    location: &Path,
+    rejected: &TelldusCredentials,
+) -> Result<TelldusCredentials, AppError> {
+    // Another run, or an earlier client in this one, may have replaced the token already.
+    if let Some(stored) = config::load_credentials_from(location)?
+        && !stored.token.trim().is_empty()
+        && stored.token != rejected.token
+    {
+        return Ok(stored);
+    }
+    eprintln!(
+        "Telldus Live rejected the access token; re-authorizing, then resending the request."
+    );
+    let (credentials, name) = config::refresh_credentials_at(location, |credentials| {
+        auth::reauthorize(client, credentials).map_err(AppError::from)
+    })?;
+    if let Some(name) = name {
+        eprintln!("Authenticated as {name}.");
+    }
+    eprintln!("Stored refreshed OAuth access token.");
+    Ok(credentials)
 }
 
 fn authenticate() -> Result<Session, AppError> {
-    let mut credentials = ensure_credentials()?;
+    // Recorded responses need no account, so offline demos work without credentials.
+    if !fixtures::is_replaying() {
+        ensure_credentials()?;
+    }
     let location = credentials_path()?;
-    println!("Using credentials file at {}", location.to_string_lossy());
+    eprintln!("Using credentials file at {}", location.to_string_lossy());
+    start_session(&location)
+}
 
-    let client = build_http_client()?;
-    let outcome = auth::validate_with_client(&client, &mut credentials)?;
+/// Validate the credentials at `location`, storing refreshed tokens back to the file.
+fn start_session(location: &Path) -> Result<Session, AppError> {
+    let client = shared_client()?;
+    if fixtures::is_replaying() {
+        return Ok(Session {
+            client,
+            credentials: config::load_credentials_from(location)?.unwrap_or_default(),
+            location: location.to_path_buf(),
+        });
+    }
+    let (credentials, outcome) = config::refresh_credentials_at(location, |credentials| {
+        auth::validate_with_client(&client, credentials).map_err(AppError::from)
+    })?;
     if outcome.tokens_refreshed {
-        save_credentials(&credentials)?;
-        println!("Stored refreshed OAuth access token.");
+        eprintln!("Stored refreshed OAuth access token.");
     }
     if let Some(name) = outcome.account_name {
-        println!("Authenticated as {name}.");
+        eprintln!("Authenticated as {name}.");
     }
 
     Ok(Session {
         client,
         credentials,
+        location: location.to_path_buf(),
+    })
+}
+
+fn print_entries(entries: Vec<Entry>) {
+    let mut table = Table::new(&["TYPE", "ID", "NAME", "DETAILS"]);
+    for entry in entries {
+        table.row(entry_cells(entry));
+    }
+    table.print();
+}
+
+fn entry_cells(entry: Entry) -> [Cell; 4] {
+    let details = entry.details.unwrap_or_else(|| "-".into());
+    let style = match entry.state {
+        Some(method) => Style::for_state(api::method_name(method)),
+        None if details.split(", ").any(|part| part == "offline") => Style::Problem,
+        None if details.split(", ").any(|part| part == "ignored") => Style::Off,
+        None => Style::Plain,
+    };
+    [
+        entry.category.as_str().into(),
+        entry.id.into(),
+        Cell::styled(entry.name, style),
+        details.into(),
+    ]
+}
+
+fn fetch_entries(api: &TelldusApi, kind: DeviceKind) -> Result<Vec<Entry>, AppError> {
+    Ok(match kind {
+        DeviceKind::All => {
+            let mut combined = Vec::new();
+            combined.extend(api.list_controllers()?);
+            combined.extend(api.list_devices()?);
+            combined.extend(api.list_sensors(false)?);
+            combined
+        }
+        DeviceKind::Controllers => api.list_controllers()?,
+        DeviceKind::Devices => api.list_devices()?,
+        DeviceKind::Sensors => api.list_sensors(false)?,
     })
 }
 
+fn sort_entries(entries: &mut [Entry]) {
+    entries.sort_by(|a, b| {
+        a.category
+            .as_str()
+            .cmp(b.category.as_str())
+            .then(a.name.cmp(&b.name))
+            .then(a.id.cmp(&b.id))
+    });
+}
+
+/// List the default account and every profile, in that order, tagging each row.
+///
+/// A profile that cannot be read or authenticated is reported and skipped.
+fn handle_devices_list_all_profiles(
+    kind: DeviceKind,
+    pattern: Option<&str>,
+) -> Result<(), AppError> {
+    let mut accounts = vec![("default".to_string(), authenticate()?)];
+    for (name, path) in config::profiles()? {
+        match config::load_credentials_from(&path) {
+            Ok(Some(credentials)) if credentials.is_complete() => {}
+            Ok(_) => {
+                eprintln!(
+                    "warning: profile {name}: {} has no API keys; skipping",
+                    path.display()
+                );
+                continue;
+            }
+            Err(err) => {
+                eprintln!("warning: profile {name}: {err}; skipping");
+                continue;
+            }
+        }
+        match start_session(&path) {
+            Ok(session) => accounts.push((name, session)),
+            Err(err) => eprintln!("warning: profile {name}: {err}; skipping"),
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (profile, session) in &accounts {
+        let mut entries = fetch_entries(&session.api()?, kind)?;
+        if let Some(pattern) = pattern {
+            entries.retain(|entry| cache::matches_pattern(pattern, &entry.name));
+        }
+        sort_entries(&mut entries);
+        rows.extend(entries.into_iter().map(|entry| (profile.clone(), entry)));
+    }
+
+    if output::is_json() {
+        let resources: Vec<output::Resource> = rows
+            .iter()
+            .map(|(profile, entry)| output::Resource {
+                profile: Some(profile.clone()),
+                ..output::Resource::from(entry)
+            })
+            .collect();
+        output::print(&resources);
+        return Ok(());
+    }
+    if rows.is_empty() {
+        println!("No resources returned for the selected filter.");
+        return Ok(());
+    }
+    let mut table = Table::new(&["PROFILE", "TYPE", "ID", "NAME", "DETAILS"]);
+    for (profile, entry) in rows {
+        let mut cells = vec![Cell::from(profile)];
+        cells.extend(entry_cells(entry));
+        table.row(cells);
+    }
+    table.print();
+    Ok(())
+}
+
+fn print_resources(entries: &[Entry]) -> Result<(), AppError> {
+    let resources: Vec<output::Resource> = entries.iter().map(output::Resource::from).collect();
+    output::print(&resources);
+    Ok(())
+}
+
+fn print_history_json(
+    entries: impl Iterator<Item = Result<serde_json::Value, api::ApiError>>,
+) -> Result<(), AppError> {
+    let entries = entries
+        .map(|entry| entry.map(|entry| output::HistoryEntry::from_value(&entry)))
+        .collect::<Result<Vec<_>, _>>()?;
+    output::print(&entries);
+    Ok(())
+}
+
+fn handle_energy(since: Duration, sensor_ids: &[String]) -> Result<(), AppError> {
+    let session = authenticate()?;
+    let api = session.api()?;
+    let mut sensors = api.sensors(false)?;
+    sensors.retain(|sensor| {
+        sensor.has_kind(ReadingKind::Power)
+            && (sensor_ids.is_empty() || sensor_ids.contains(&sensor.id))
+    });
+    sensors.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+    if sensors.is_empty() {
+        println!("No power sensors found.");
+        return Ok(());
+    }
+
+    let to = unix_now();
+    let from = to.saturating_sub(since.as_secs() as i64);
+    let mut days = Vec::new();
+    for sensor in &sensors {
+        let has_meter = sensor.readings.iter().any(|reading| {
+            reading.kind == ReadingKind::Power && reading.scale == energy::SCALE_KWH
+        });
+        let scale = if has_meter {
+            energy::SCALE_KWH
+        } else {
+            energy::SCALE_WATT
+        };
+        let entries = api
+            .sensor_history_iter(&sensor.id, scale as i32, from, to)
+            .collect::<Result<Vec<_>, _>>()?;
+        days.extend(
+            energy::daily_usage(&entries)
+                .into_iter()
+                .map(|(date, kwh)| energy::DailyEnergy {
+                    id: sensor.id.clone(),
+                    sensor: sensor.name.clone(),
+                    date,
+                    kwh,
+                }),
+        );
+    }
+    if output::is_json() {
+        output::print(&days);
+        return Ok(());
+    }
+    if days.is_empty() {
+        println!("No energy readings since {}.", schedule::local_date(from));
+        return Ok(());
+    }
+
+    days.sort_by(|a, b| a.date.cmp(&b.date).then(a.sensor.cmp(&b.sensor)));
+    let mut table = Table::new(&["DATE", "SENSOR", "KWH"]);
+    for day in &days {
+        table.row([
+            day.date.clone(),
+            day.sensor.clone(),
+            format!("{:.2}", day.kwh),
+        ]);
+    }
+    for sensor in &sensors {
+        let total: f64 = days
+            .iter()
+            .filter(|day| day.id == sensor.id)
+            .map(|day| day.kwh)
+            .sum();
+        table.row(["total".into(), sensor.name.clone(), format!("{total:.2}")]);
+    }
+    let total: f64 = days.iter().map(|day| day.kwh).sum();
+    table.row(["total".into(), "all sensors".into(), format!("{total:.2}")]);
+    table.print();
+    Ok(())
+}
+
+fn handle_energy_watch(
+    interval: Duration,
+    spike: f64,
+    heartbeat: Option<Duration>,
+    sensor_ids: &[String],
+    filter: Option<&filter::Filter>,
+) -> Result<(), AppError> {
+    response_cache::set_ttl(Duration::ZERO);
+    let session = authenticate()?;
+    let api = session.api()?;
+    let redraw = !output::is_json() && io::stdout().is_terminal();
+    let mut monitor = energy::Monitor::new(spike);
+    let mut changes = changes::Changes::new(heartbeat);
+    let mut failures = changes::Changes::new(None);
+    loop {
+        let mut sensors = match api.sensors(false) {
+            Ok(sensors) => {
+                failures.forget("sensors");
+                sensors
+            }
+            // Once watching, a failed poll only delays the next update.
+            Err(err) if !monitor.loads().is_empty() => {
+                if failures.update("sensors", err.to_string()) {
+                    eprintln!("warning: cannot poll sensors: {err}");
+                }
+                if !service::sleep(interval) {
+                    return Ok(());
+                }
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        sensors.retain(|sensor| sensor_ids.is_empty() || sensor_ids.contains(&sensor.id));
+        sensors.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+        monitor.update(&sensors, unix_now() as u64);
+        let loads = monitor.loads();
+        if loads.is_empty() {
+            status("No sensors report their current load in W.");
+            return Ok(());
+        }
+        let loads: Vec<&energy::Load> = loads
+            .iter()
+            .filter(|load| filter.is_none_or(|filter| filter.matches(&load.record())))
+            .collect();
+        // The terminal view is redrawn in place; piped output only repeats sensors whose
+        // load changed, so logs are not flooded with identical readings.
+        let changed: Vec<&energy::Load> = loads
+            .iter()
+            .copied()
+            .filter(|load| changes.update(&load.id, (format!("{:.1}", load.watts), load.spike)))
+            .collect();
+        let shown = if redraw { loads.clone() } else { changed };
+        if !shown.is_empty() {
+            if output::is_json() {
+                let records: Vec<_> = shown.iter().map(|load| load.record()).collect();
+                output::print(&records);
+            } else {
+                if redraw {
+                    // Clear the screen and home the cursor so the table updates in place.
+                    print!("\x1b[2J\x1b[H");
+                }
+                println!(
+                    "Power at {}; polling every {}, press Ctrl-C to stop.",
+                    schedule::local_time(unix_now() as u64),
+                    format_duration(interval)
+                );
+                let mut table = Table::new(&["SENSOR", "W", "AVG W", "KWH"]);
+                for load in &shown {
+                    let style = if load.spike {
+                        Style::Warning
+                    } else {
+                        Style::Plain
+                    };
+                    table.row([
+                        Cell::from(load.sensor.as_str()),
+                        Cell::styled(format!("{:.1}", load.watts), style),
+                        Cell::from(format!("{:.1}", load.average_watts)),
+                        Cell::from(format!("{:.3}", load.kwh)),
+                    ]);
+                }
+                let watts: f64 = loads.iter().map(|load| load.watts).sum();
+                let kwh: f64 = loads.iter().map(|load| load.kwh).sum();
+                table.row([
+                    Cell::from("total"),
+                    Cell::from(format!("{watts:.1}")),
+                    Cell::from(""),
+                    Cell::from(format!("{kwh:.3}")),
+                ]);
+                table.print();
+            }
+        }
+        if !service::sleep(interval) {
+            return Ok(());
+        }
+    }
+}
+
+fn handle_schema(model: SchemaModel) {
+    let schema = match model {
+        SchemaModel::Devices => output::array_schema::<output::Resource>("telltales devices list"),
+        SchemaModel::Sensors => {
+            output::array_schema::<output::SensorRecord>("telltales sensors list")
+        }
+        SchemaModel::History => output::array_schema::<output::HistoryEntry>("telltales history"),
+    };
+    print_json(&schema);
+}
+
 fn print_json(value: &serde_json::Value) {
-    match to_string_pretty(value) {
-        Ok(text) => println!("{text}"),
-        Err(_) => println!("{value}"),
+    output::print(value);
+}
+
+/// A `--scale` given either as the raw Telldus number or as the kind of reading wanted.
+#[derive(Debug, Clone, Copy)]
+enum Scale {
+    Number(i32),
+    Kind(ReadingKind),
+}
+
+impl Scale {
+    /// The number sent to Telldus; a kind asks for its base unit (°C, %, kWh, ...).
+    fn number(self) -> i32 {
+        match self {
+            Scale::Number(number) => number,
+            Scale::Kind(_) => 0,
+        }
+    }
+
+    fn kind(self) -> Option<ReadingKind> {
+        match self {
+            Scale::Number(_) => None,
+            Scale::Kind(kind) => Some(kind),
+        }
+    }
+}
+
+fn parse_scale(arg: &str) -> Result<Scale, String> {
+    if let Ok(number) = arg.trim().parse() {
+        return Ok(Scale::Number(number));
+    }
+    parse_reading_kind(arg)
+        .map(Scale::Kind)
+        .map_err(|err| format!("expected a number or {err}"))
+}
+
+fn parse_reading_kind(arg: &str) -> Result<ReadingKind, String> {
+    ReadingKind::parse(arg).ok_or_else(|| {
+        let names: Vec<&str> = ReadingKind::ALL.iter().map(|kind| kind.as_str()).collect();
+        format!("one of: {}", names.join(", "))
+    })
+}
+
+fn parse_callback_url(arg: &str) -> Result<Url, String> {
+    let url = Url::parse(arg.trim()).map_err(|err| err.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err("expected an http:// or https:// URL with a host".into());
+    }
+    Ok(url)
+}
+
+/// Name the readings in a raw sensor payload and keep only `kind`, if given.
+fn describe_readings(
+    mut value: serde_json::Value,
+    kind: Option<ReadingKind>,
+) -> Option<serde_json::Value> {
+    if let Some(kind) = kind
+        && !api::retain_readings(&mut value, kind)
+    {
+        return None;
     }
+    api::annotate_readings(&mut value);
+    Some(value)
+}
+
+fn parse_filter(arg: &str) -> Result<filter::Filter, String> {
+    filter::Filter::parse(arg).map_err(|err| err.to_string())
+}
+
+fn parse_query(arg: &str) -> Result<query::Query, String> {
+    query::Query::parse(arg).map_err(|err| err.to_string())
+}
+
+fn parse_user_agent(arg: &str) -> Result<String, String> {
+    let agent = arg.trim();
+    if agent.is_empty() {
+        return Err("user agent must not be empty".into());
+    }
+    reqwest::header::HeaderValue::from_str(agent)
+        .map_err(|_| "user agent must be printable ASCII".to_string())?;
+    Ok(agent.to_string())
+}
+
+fn parse_timeout(arg: &str) -> Result<Duration, String> {
+    match parse_duration(arg)? {
+        timeout if timeout.is_zero() => Err("timeout must be longer than zero".into()),
+        timeout => Ok(timeout),
+    }
+}
+
+fn parse_preset(arg: &str) -> Result<&'static protocols::Preset, String> {
+    protocols::find_preset(arg).ok_or_else(|| {
+        let names: Vec<_> = protocols::PRESETS
+            .iter()
+            .map(|preset| preset.name)
+            .collect();
+        format!("unknown preset; choose one of {}", names.join(", "))
+    })
 }
 
 fn parse_key_value(arg: &str) -> Result<KeyValue, String> {
@@ -610,3 +5483,12 @@ fn parse_key_value(arg: &str) -> Result<KeyValue, String> {
         value: value.to_string(),
     })
 }
+
+fn parse_zwave_size(arg: &str) -> Result<u8, String> {
+    match arg.trim() {
+        "1" => Ok(1),
+        "2" => Ok(2),
+        "4" => Ok(4),
+        _ => Err("size must be 1, 2 or 4 bytes".to_string()),
+    }
+}
@@ -0,0 +1,219 @@
+use crate::api::Sensor;
+use crate::http_client;
+use crate::otlp;
+use crate::schedule::parse_duration;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::time::Duration;
+use thiserror::Error;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Keep StatsD datagrams under a typical Ethernet MTU so they are not fragmented.
+const MAX_DATAGRAM: usize = 1432;
+
+#[derive(Debug, Error)]
+pub enum MetricsError {
+    #[error("pushing metrics to {0} failed: {1}")]
+    Push(String, #[source] reqwest::Error),
+    #[error("cannot reach metrics collector {0}: {1}")]
+    Connect(String, #[source] io::Error),
+    #[error("sending metrics to {0} failed: {1}")]
+    Send(String, #[source] io::Error),
+}
+
+/// The `metrics:` section of schedules.yaml.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkConfig {
+    /// Graphite plaintext listener as `host:port`, usually port 2003.
+    graphite: Option<String>,
+    /// StatsD UDP listener as `host:port`, usually port 8125.
+    statsd: Option<String>,
+    /// OTLP/HTTP collector base URL, usually `http://host:4318`.
+    otlp: Option<String>,
+    /// Time between readings, such as `60s` or `5m`.
+    interval: Option<String>,
+    /// First component of every metric path.
+    prefix: Option<String>,
+}
+
+/// Where and how often the daemon sends sensor readings.
+#[derive(Debug, Clone)]
+pub struct Sink {
+    collector: Collector,
+    pub interval: Duration,
+    prefix: String,
+}
+
+#[derive(Debug, Clone)]
+enum Collector {
+    Graphite(String),
+    Statsd(String),
+    Otlp(Client, String),
+}
+
+impl Sink {
+    pub fn from_config(config: SinkConfig) -> Result<Self, String> {
+        let collector = match (config.graphite, config.statsd, config.otlp) {
+            (Some(address), None, None) => Collector::Graphite(address),
+            (None, Some(address), None) => Collector::Statsd(address),
+            (None, None, Some(endpoint)) => {
+                let client = http_client::builder()
+                    .build()
+                    .map_err(|err| err.to_string())?;
+                Collector::Otlp(client, endpoint)
+            }
+            _ => return Err("set exactly one of graphite, statsd or otlp".into()),
+        };
+        let interval = match config.interval {
+            Some(text) => parse_duration(&text)?,
+            None => Duration::from_secs(60),
+        };
+        if interval.is_zero() {
+            return Err("interval must be at least 1s".into());
+        }
+        Ok(Self {
+            collector,
+            interval,
+            prefix: config.prefix.unwrap_or_else(|| "telltales".into()),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.collector {
+            Collector::Graphite(address) => format!("Graphite at {address}"),
+            Collector::Statsd(address) => format!("StatsD at {address}"),
+            Collector::Otlp(_, endpoint) => format!("OTLP collector at {endpoint}"),
+        }
+    }
+
+    /// Send every reading as `<prefix>.<sensor>_<id>.<reading>`, stamped with `timestamp`.
+    ///
+    /// The sensor id keeps sensors that share a name apart.
+    pub fn send(&self, sensors: &[Sensor], timestamp: u64) -> Result<(), MetricsError> {
+        if let Collector::Otlp(client, endpoint) = &self.collector {
+            return otlp::export_readings(client, endpoint, sensors, timestamp)
+                .map_err(|err| MetricsError::Push(endpoint.clone(), err));
+        }
+        let metrics = sensors.iter().flat_map(|sensor| {
+            sensor.readings.iter().map(move |reading| {
+                let path = format!(
+                    "{}.{}_{}.{}",
+                    self.prefix,
+                    path_component(&sensor.name),
+                    path_component(&sensor.id),
+                    path_component(&reading.name)
+                );
+                (path, reading.value)
+            })
+        });
+        match &self.collector {
+            Collector::Graphite(address) => {
+                let mut body = String::new();
+                for (path, value) in metrics {
+                    let _ = writeln!(body, "{path} {value} {timestamp}");
+                }
+                let mut stream = TcpStream::connect(address)
+                    .map_err(|err| MetricsError::Connect(address.clone(), err))?;
+                stream
+                    .set_write_timeout(Some(SEND_TIMEOUT))
+                    .and_then(|()| stream.write_all(body.as_bytes()))
+                    .map_err(|err| MetricsError::Send(address.clone(), err))
+            }
+            Collector::Statsd(address) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")
+                    .and_then(|socket| socket.connect(address).map(|()| socket))
+                    .map_err(|err| MetricsError::Connect(address.clone(), err))?;
+                let send = |datagram: &str| {
+                    socket
+                        .send(datagram.as_bytes())
+                        .map(drop)
+                        .map_err(|err| MetricsError::Send(address.clone(), err))
+                };
+                let mut datagram = String::new();
+                for (path, value) in metrics {
+                    // A signed gauge value adjusts the gauge instead of setting it, so a
+                    // negative reading first resets the gauge to zero.
+                    let line = if value < 0.0 {
+                        format!("{path}:0|g\n{path}:{value}|g")
+                    } else {
+                        format!("{path}:{value}|g")
+                    };
+                    if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM {
+                        send(&datagram)?;
+                        datagram.clear();
+                    }
+                    if !datagram.is_empty() {
+                        datagram.push('\n');
+                    }
+                    datagram.push_str(&line);
+                }
+                if !datagram.is_empty() {
+                    send(&datagram)?;
+                }
+                Ok(())
+            }
+            Collector::Otlp(..) => unreachable!("OTLP readings are exported above"),
+        }
+    }
+}
+
+/// Current sensor readings in the Prometheus text exposition format.
+pub fn prometheus_text(sensors: &[Sensor]) -> String {
+    let mut text = String::from(
+        "# HELP telltales_sensor_reading Latest value reported by a Telldus sensor.\n\
+         # TYPE telltales_sensor_reading gauge\n",
+    );
+    for sensor in sensors {
+        for reading in &sensor.readings {
+            let _ = writeln!(
+                text,
+                "telltales_sensor_reading{{sensor_id=\"{}\",sensor=\"{}\",reading=\"{}\",kind=\"{}\",unit=\"{}\"}} {}",
+                label(&sensor.id),
+                label(&sensor.name),
+                label(&reading.name),
+                reading.kind.as_str(),
+                label(reading.unit()),
+                reading.value
+            );
+        }
+    }
+    text
+}
+
+/// Replace the metrics of the Pushgateway group at `url`, e.g. `http://host:9091/metrics/job/telldus`.
+pub fn push(client: &Client, url: &str, body: String) -> Result<(), MetricsError> {
+    client
+        .put(url)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(body)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| MetricsError::Push(url.to_string(), err))?;
+    Ok(())
+}
+
+/// Lowercase `name` and replace anything but letters and digits, since `.` separates path levels.
+fn path_component(name: &str) -> String {
+    let component: String = name
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { '_' })
+        .collect();
+    if component.is_empty() {
+        "unnamed".into()
+    } else {
+        component
+    }
+}
+
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
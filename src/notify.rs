@@ -0,0 +1,349 @@
+use crate::schedule::{rfc5322_date, unix_now};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use native_tls::{TlsConnector, TlsStream};
+use reqwest::blocking::Client;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use thiserror::Error;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum NotifyError {
+    #[error("cannot run {0}: {1}")]
+    Spawn(&'static str, #[source] io::Error),
+    #[error("{0} exited with {1}")]
+    Failed(&'static str, std::process::ExitStatus),
+    #[error("cannot reach mail server {0}: {1}")]
+    Connect(String, #[source] io::Error),
+    #[error("TLS with mail server failed: {0}")]
+    Tls(String),
+    #[error("mail server connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("webhook request failed: {0}")]
+    Webhook(#[from] reqwest::Error),
+    #[error("mail server rejected {0}: {1}")]
+    Smtp(String, String),
+    #[error("{0}")]
+    InsecureAuth(String),
+    #[cfg(not(any(unix, windows)))]
+    #[error("desktop notifications are not supported on this platform")]
+    Unsupported,
+}
+
+/// Raise a native desktop notification using the platform's own notifier:
+/// `notify-send` on Linux and BSD, `osascript` on macOS, and PowerShell on Windows.
+pub fn desktop(title: &str, body: &str) -> Result<(), NotifyError> {
+    let (program, mut command) = desktop_command(title, body)?;
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status()
+        .map_err(|err| NotifyError::Spawn(program, err))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(NotifyError::Failed(program, status))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn desktop_command(title: &str, body: &str) -> Result<(&'static str, Command), NotifyError> {
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {} with title {}",
+        applescript_string(body),
+        applescript_string(title)
+    ));
+    Ok(("osascript", command))
+}
+
+#[cfg(target_os = "macos")]
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(windows)]
+fn desktop_command(title: &str, body: &str) -> Result<(&'static str, Command), NotifyError> {
+    // A tray balloon needs nothing beyond the stock Windows Forms assembly.
+    const SCRIPT: &str = "Add-Type -AssemblyName System.Windows.Forms; \
+        $icon = New-Object System.Windows.Forms.NotifyIcon; \
+        $icon.Icon = [System.Drawing.SystemIcons]::Information; \
+        $icon.Visible = $true; \
+        $icon.ShowBalloonTip(10000, $env:TELLTALES_TITLE, $env:TELLTALES_BODY, 'Info'); \
+        Start-Sleep -Seconds 5; $icon.Dispose()";
+    let mut command = Command::new("powershell");
+    command
+        .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+        .env("TELLTALES_TITLE", title)
+        .env("TELLTALES_BODY", body);
+    Ok(("powershell", command))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn desktop_command(title: &str, body: &str) -> Result<(&'static str, Command), NotifyError> {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name", "telltales", "--", title, body]);
+    Ok(("notify-send", command))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn desktop_command(_title: &str, _body: &str) -> Result<(&'static str, Command), NotifyError> {
+    Err(NotifyError::Unsupported)
+}
+
+/// POST a JSON body to an arbitrary URL, such as a Slack, Discord, or Matrix webhook.
+pub fn webhook(
+    client: &Client,
+    url: &str,
+    headers: &[(String, String)],
+    body: &Value,
+) -> Result<(), NotifyError> {
+    let mut request = client.post(url).json(body);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request.send()?.error_for_status()?;
+    Ok(())
+}
+
+/// Outgoing mail server settings.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SmtpConfig {
+    pub server: String,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    /// Send the password over an unencrypted connection (`security: none`) anyway.
+    #[serde(default)]
+    pub insecure_auth: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// TLS from the first byte, usually on port 465.
+    Tls,
+    /// Plain connection upgraded with STARTTLS, usually on port 587.
+    #[default]
+    Starttls,
+    /// No encryption; only sensible for a relay on the local network.
+    None,
+}
+
+impl SmtpConfig {
+    /// Refuse to log in without encryption, which would send the password in the clear,
+    /// unless `insecure_auth` asks for it.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.username.is_some() && self.security == SmtpSecurity::None && !self.insecure_auth {
+            return Err(
+                "a username with `security: none` would send the password unencrypted; \
+                 use starttls or tls, or set `insecure_auth: true`"
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.security {
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::Starttls => 587,
+            SmtpSecurity::None => 25,
+        })
+    }
+}
+
+/// Deliver a plain-text message through the configured SMTP server.
+pub fn email(
+    config: &SmtpConfig,
+    to: &[String],
+    subject: &str,
+    body: &str,
+) -> Result<(), NotifyError> {
+    config.validate().map_err(NotifyError::InsecureAuth)?;
+    let address = format!("{}:{}", config.server, config.port());
+    let tcp = TcpStream::connect(&address).map_err(|err| NotifyError::Connect(address, err))?;
+    tcp.set_read_timeout(Some(SMTP_TIMEOUT))?;
+    tcp.set_write_timeout(Some(SMTP_TIMEOUT))?;
+
+    let mut smtp = match config.security {
+        SmtpSecurity::Tls => SmtpConnection::Tls(start_tls(&config.server, tcp)?),
+        SmtpSecurity::Starttls | SmtpSecurity::None => SmtpConnection::Plain(tcp),
+    };
+    smtp.expect("greeting", 2)?;
+    smtp.command("EHLO telltales", 2)?;
+    if config.security == SmtpSecurity::Starttls {
+        smtp.command("STARTTLS", 2)?;
+        let SmtpConnection::Plain(tcp) = smtp else {
+            unreachable!("STARTTLS is only sent on plain connections");
+        };
+        smtp = SmtpConnection::Tls(start_tls(&config.server, tcp)?);
+        smtp.command("EHLO telltales", 2)?;
+    }
+    if let Some(username) = &config.username {
+        let password = config.password.as_deref().unwrap_or_default();
+        let token = BASE64.encode(format!("\0{username}\0{password}"));
+        smtp.send(&format!("AUTH PLAIN {token}"))?;
+        smtp.expect("AUTH", 2)?;
+    }
+    smtp.command(&format!("MAIL FROM:<{}>", config.from), 2)?;
+    for recipient in to {
+        smtp.command(&format!("RCPT TO:<{recipient}>"), 2)?;
+    }
+    smtp.command("DATA", 3)?;
+    let now = unix_now();
+    smtp.send(&message(
+        &config.from,
+        to,
+        subject,
+        body,
+        now,
+        &message_id(&config.from, now),
+    ))?;
+    smtp.command(".", 2)?;
+    // The message is accepted at this point, so a failed goodbye does not matter.
+    let _ = smtp.command("QUIT", 2);
+    Ok(())
+}
+
+fn start_tls(server: &str, tcp: TcpStream) -> Result<TlsStream<TcpStream>, NotifyError> {
+    let connector = TlsConnector::new().map_err(|err| NotifyError::Tls(err.to_string()))?;
+    connector
+        .connect(server, tcp)
+        .map_err(|err| NotifyError::Tls(err.to_string()))
+}
+
+/// A unique `Message-ID` in the sender's domain.
+fn message_id(from: &str, now: u64) -> String {
+    let mut random = [0u8; 8];
+    let _ = SystemRandom::new().fill(&mut random);
+    let domain = from
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.trim_end_matches('>'))
+        .filter(|domain| !domain.is_empty())
+        .unwrap_or("telltales.invalid");
+    format!("<{now}.{:016x}@{domain}>", u64::from_be_bytes(random))
+}
+
+/// Headers and body with CRLF line endings and leading dots escaped for DATA.
+///
+/// Header values have line breaks and other control characters replaced, since the
+/// subject can carry controller and sensor names from Telldus Live.
+fn message(
+    from: &str,
+    to: &[String],
+    subject: &str,
+    body: &str,
+    now: u64,
+    message_id: &str,
+) -> String {
+    let subject = header_value(subject);
+    let subject = if subject.is_ascii() {
+        subject
+    } else {
+        format!("=?utf-8?B?{}?=", BASE64.encode(subject))
+    };
+    let mut text = format!(
+        "Date: {}\r\nMessage-ID: {}\r\nFrom: {}\r\nTo: {}\r\nSubject: {subject}\r\n\
+         MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: 8bit\r\n\r\n",
+        rfc5322_date(now),
+        header_value(message_id),
+        header_value(from),
+        header_value(&to.join(", "))
+    );
+    for line in body.lines() {
+        if line.starts_with('.') {
+            text.push('.');
+        }
+        text.push_str(line);
+        text.push_str("\r\n");
+    }
+    text.truncate(text.trim_end_matches("\r\n").len());
+    text
+}
+
+fn header_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|ch| if ch.is_control() { ' ' } else { ch })
+        .collect()
+}
+
+enum SmtpConnection {
+    Plain(TcpStream),
+    Tls(TlsStream<TcpStream>),
+}
+
+impl SmtpConnection {
+    fn stream(&mut self) -> &mut dyn ReadWrite {
+        match self {
+            SmtpConnection::Plain(stream) => stream,
+            SmtpConnection::Tls(stream) => stream,
+        }
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), NotifyError> {
+        let stream = self.stream();
+        stream.write_all(line.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    fn command(&mut self, line: &str, class: u8) -> Result<(), NotifyError> {
+        self.send(line)?;
+        let verb = line.split([' ', ':']).next().unwrap_or(line);
+        self.expect(verb, class)
+    }
+
+    /// Read one possibly multi-line reply and check its first status digit.
+    fn expect(&mut self, what: &str, class: u8) -> Result<(), NotifyError> {
+        let mut reply = String::new();
+        loop {
+            let line = self.read_line()?;
+            let last = line.as_bytes().get(3) != Some(&b'-');
+            reply.push_str(&line);
+            if last {
+                break;
+            }
+            reply.push(' ');
+        }
+        if reply.as_bytes().first() == Some(&(b'0' + class)) {
+            Ok(())
+        } else {
+            Err(NotifyError::Smtp(what.to_string(), reply))
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String, NotifyError> {
+        // Byte-wise reads keep nothing buffered when the stream is upgraded to TLS.
+        let stream = self.stream();
+        let mut line = Vec::new();
+        let mut byte = [0u8];
+        while line.last() != Some(&b'\n') {
+            if stream.read(&mut byte)? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            line.push(byte[0]);
+        }
+        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+    }
+}
+
+trait ReadWrite: Read + Write {}
+
+impl<T: Read + Write> ReadWrite for T {}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,97 @@
+use super::*;
+
+const NOW: u64 = 1_792_078_192;
+
+fn compose(subject: &str, body: &str) -> String {
+    message(
+        "alerts@example.com",
+        &["me@example.com".to_string(), "you@example.com".to_string()],
+        subject,
+        body,
+        NOW,
+        "<1.2@example.com>",
+    )
+}
+
+fn headers(message: &str) -> Vec<&str> {
+    message
+        .split("\r\n\r\n")
+        .next()
+        .unwrap()
+        .split("\r\n")
+        .collect()
+}
+
+#[test]
+fn messages_carry_date_and_message_id() {
+    let text = compose("Attic too hot", "28.5 °C");
+    let headers = headers(&text);
+
+    assert_eq!(headers[0], "Date: Thu, 15 Oct 2026 15:29:52 +0000");
+    assert_eq!(headers[1], "Message-ID: <1.2@example.com>");
+    assert!(headers.contains(&"To: me@example.com, you@example.com"));
+    assert!(headers.contains(&"Subject: Attic too hot"));
+    assert!(text.ends_with("\r\n\r\n28.5 °C"));
+}
+
+#[test]
+fn leading_dots_are_stuffed_and_lines_end_in_crlf() {
+    let text = compose("Report", ".hidden\nplain\n..two\n.");
+    let body = text.split_once("\r\n\r\n").unwrap().1;
+
+    assert_eq!(body, "..hidden\r\nplain\r\n...two\r\n..");
+}
+
+#[test]
+fn non_ascii_subjects_are_encoded() {
+    let text = compose("Växthus för kallt", "");
+
+    let expected = format!(
+        "Subject: =?utf-8?B?{}?=",
+        BASE64.encode("Växthus för kallt")
+    );
+    assert!(headers(&text).contains(&expected.as_str()));
+}
+
+#[test]
+fn line_breaks_cannot_inject_headers() {
+    let text = compose("Hall offline\r\nBcc: victim@example.com\n\nforged", "body");
+    let headers = headers(&text);
+
+    assert!(!headers.iter().any(|header| header.starts_with("Bcc:")));
+    assert!(
+        headers.contains(&"Subject: Hall offline  Bcc: victim@example.com  forged"),
+        "{headers:?}"
+    );
+    assert_eq!(text.split_once("\r\n\r\n").unwrap().1, "body");
+}
+
+#[test]
+fn message_ids_use_the_sender_domain() {
+    let id = message_id("Alerts <alerts@example.com>", NOW);
+
+    assert!(id.starts_with(&format!("<{NOW}.")));
+    assert!(id.ends_with("@example.com>"));
+    assert_ne!(id, message_id("alerts@example.com", NOW));
+}
+
+#[test]
+fn passwords_are_not_sent_unencrypted_without_opting_in() {
+    let mut config = SmtpConfig {
+        server: "relay.lan".into(),
+        port: None,
+        security: SmtpSecurity::None,
+        username: Some("alerts".into()),
+        password: Some("secret".into()),
+        from: "alerts@example.com".into(),
+        insecure_auth: false,
+    };
+    assert!(config.validate().is_err());
+
+    config.insecure_auth = true;
+    assert!(config.validate().is_ok());
+
+    config.insecure_auth = false;
+    config.username = None;
+    assert!(config.validate().is_ok());
+}
@@ -0,0 +1,174 @@
+use crate::api::Sensor;
+use crate::http_client;
+use reqwest::blocking::Client;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Buffered spans are exported once this many have piled up...
+const FLUSH_SPANS: usize = 64;
+/// ...or once the oldest has waited this long, so long-running commands export steadily.
+const FLUSH_AFTER: Duration = Duration::from_secs(10);
+/// OTLP span kind for an outgoing request.
+const SPAN_KIND_CLIENT: u8 = 3;
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+static SPANS: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+static OLDEST_SPAN: Mutex<Option<Instant>> = Mutex::new(None);
+
+struct Exporter {
+    client: Client,
+    endpoint: String,
+    trace_id: String,
+}
+
+/// Export a trace of every API call to the OTLP/HTTP collector at `endpoint`, e.g. `http://localhost:4318`.
+pub fn enable(endpoint: &str) -> Result<(), reqwest::Error> {
+    let client = http_client::builder().timeout(EXPORT_TIMEOUT).build()?;
+    let _ = EXPORTER.set(Exporter {
+        client,
+        endpoint: endpoint.trim_end_matches('/').to_string(),
+        // One trace per invocation, with a span per API call.
+        trace_id: format!("{:016x}{:016x}", random_u64(), random_u64()),
+    });
+    Ok(())
+}
+
+/// Record one Telldus Live request as a client span.
+pub fn record_call(method: &str, path: &str, started: SystemTime, elapsed: Duration, ok: bool) {
+    if EXPORTER.get().is_none() {
+        return;
+    }
+    let start = unix_nanos(started);
+    let span = json!({
+        "traceId": EXPORTER.get().map(|exporter| exporter.trace_id.as_str()),
+        "spanId": format!("{:016x}", random_u64()),
+        "name": format!("{method} {path}"),
+        "kind": SPAN_KIND_CLIENT,
+        "startTimeUnixNano": start.to_string(),
+        "endTimeUnixNano": (start + elapsed.as_nanos()).to_string(),
+        "attributes": [
+            attribute("http.request.method", method),
+            attribute("url.path", path),
+        ],
+        // 1 is OK and 2 is ERROR in the OTLP status enum.
+        "status": { "code": if ok { 1 } else { 2 } },
+    });
+    let pending = match SPANS.lock() {
+        Ok(mut spans) => {
+            spans.push(span);
+            spans.len()
+        }
+        Err(_) => return,
+    };
+    let oldest = OLDEST_SPAN
+        .lock()
+        .map(|mut oldest| *oldest.get_or_insert_with(Instant::now))
+        .unwrap_or_else(|_| Instant::now());
+    if pending >= FLUSH_SPANS || oldest.elapsed() >= FLUSH_AFTER {
+        flush();
+    }
+}
+
+/// Export any buffered spans; failures are reported but never fail the command.
+pub fn flush() {
+    let Some(exporter) = EXPORTER.get() else {
+        return;
+    };
+    let spans = match SPANS.lock() {
+        Ok(mut spans) => std::mem::take(&mut *spans),
+        Err(_) => return,
+    };
+    if let Ok(mut oldest) = OLDEST_SPAN.lock() {
+        *oldest = None;
+    }
+    if spans.is_empty() {
+        return;
+    }
+    let body = json!({
+        "resourceSpans": [{
+            "resource": resource(),
+            "scopeSpans": [{ "scope": { "name": "telltales" }, "spans": spans }]
+        }]
+    });
+    if let Err(err) = post(&exporter.client, &exporter.endpoint, "traces", &body) {
+        eprintln!("warning: cannot export traces: {err}");
+    }
+}
+
+/// Send current readings as gauges, one metric per reading kind and unit.
+pub fn export_readings(
+    client: &Client,
+    endpoint: &str,
+    sensors: &[Sensor],
+    timestamp: u64,
+) -> Result<(), reqwest::Error> {
+    let time = (u128::from(timestamp) * 1_000_000_000).to_string();
+    let mut metrics: BTreeMap<(&str, &str), Vec<Value>> = BTreeMap::new();
+    for sensor in sensors {
+        for reading in &sensor.readings {
+            metrics
+                .entry((reading.kind.as_str(), reading.unit()))
+                .or_default()
+                .push(json!({
+                    "asDouble": reading.value,
+                    "timeUnixNano": time,
+                    "attributes": [
+                        attribute("sensor.id", &sensor.id),
+                        attribute("sensor.name", &sensor.name),
+                        attribute("reading", &reading.name),
+                    ],
+                }));
+        }
+    }
+    let metrics: Vec<Value> = metrics
+        .into_iter()
+        .map(|((kind, unit), points)| {
+            json!({
+                "name": format!("telltales.sensor.{}", kind.replace('-', "_")),
+                "unit": unit,
+                "gauge": { "dataPoints": points },
+            })
+        })
+        .collect();
+    let body = json!({
+        "resourceMetrics": [{
+            "resource": resource(),
+            "scopeMetrics": [{ "scope": { "name": "telltales" }, "metrics": metrics }]
+        }]
+    });
+    post(client, endpoint.trim_end_matches('/'), "metrics", &body)
+}
+
+fn post(client: &Client, endpoint: &str, signal: &str, body: &Value) -> Result<(), reqwest::Error> {
+    client
+        .post(format!("{endpoint}/v1/{signal}"))
+        .json(body)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+fn resource() -> Value {
+    json!({ "attributes": [attribute("service.name", "telltales")] })
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default()
+}
+
+/// Random bits for trace and span ids, which OTLP requires to be unique.
+fn random_u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    let _ = SystemRandom::new().fill(&mut bytes);
+    u64::from_be_bytes(bytes)
+}
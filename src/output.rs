@@ -0,0 +1,596 @@
+use crate::api::{Entry, Sensor, SensorReading, method_name, sensor_readings};
+use crate::query::Query;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+static QUERY: OnceLock<Query> = OnceLock::new();
+static TEMPLATE: OnceLock<String> = OnceLock::new();
+static COLOR: OnceLock<bool> = OnceLock::new();
+static WIDE: OnceLock<bool> = OnceLock::new();
+
+/// Widest a table column other than the last may grow before its cells are truncated.
+const MAX_COLUMN_WIDTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable tables
+    #[default]
+    Table,
+    /// Machine-readable JSON matching `telltales schema`
+    Json,
+    /// One compact JSON object per line; watch and daemon modes print one per event
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and `NO_COLOR` is unset
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How a table cell is colored when color is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    /// Something switched on or healthy (green).
+    On,
+    /// Something switched off or inactive (grey).
+    Off,
+    /// Needs attention soon (yellow).
+    Warning,
+    /// Offline, failed, or critical (red).
+    Problem,
+}
+
+impl Style {
+    fn code(self) -> Option<&'static str> {
+        match self {
+            Style::Plain => None,
+            Style::On => Some("32"),
+            Style::Off => Some("90"),
+            Style::Warning => Some("33"),
+            Style::Problem => Some("31"),
+        }
+    }
+
+    /// Style for a device state name as returned by [`method_name`].
+    pub fn for_state(state: &str) -> Self {
+        match state {
+            "on" | "dim" | "up" => Style::On,
+            "off" | "down" => Style::Off,
+            _ => Style::Plain,
+        }
+    }
+}
+
+pub fn set_color(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            io::stdout().is_terminal()
+                && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        }
+    };
+    let _ = COLOR.set(enabled);
+}
+
+/// Show every table cell in full instead of fitting tables to the terminal.
+pub fn set_wide(wide: bool) {
+    let _ = WIDE.set(wide);
+}
+
+fn is_wide() -> bool {
+    WIDE.get().copied().unwrap_or_default()
+}
+
+/// Columns available for a table line, or `None` when output is not a terminal.
+fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        // SAFETY: TIOCGWINSZ only writes the winsize struct passed to it.
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_col > 0
+        {
+            return Some(size.ws_col.into());
+        }
+    }
+    env::var("COLUMNS").ok()?.parse().ok()
+}
+
+/// Wrap `text` in the ANSI color for `style`, or return it unchanged when color is off.
+pub fn paint(text: &str, style: Style) -> String {
+    match style.code() {
+        Some(code) if COLOR.get().copied().unwrap_or_default() => {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        }
+        _ => text.to_string(),
+    }
+}
+
+/// A column-aligned table printed by every listing command.
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<Cell>>,
+}
+
+pub struct Cell {
+    text: String,
+    style: Style,
+}
+
+impl Cell {
+    pub fn styled(text: impl Into<String>, style: Style) -> Self {
+        Self {
+            text: text.into(),
+            style,
+        }
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::styled(text, Style::Plain)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::styled(text, Style::Plain)
+    }
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row<C: Into<Cell>>(&mut self, cells: impl IntoIterator<Item = C>) {
+        self.rows.push(cells.into_iter().map(Into::into).collect());
+    }
+
+    /// Print the table after a blank line, padding every column but the last.
+    ///
+    /// Widths are measured in terminal columns, so accented names, CJK, and emoji line up.
+    /// On a terminal, unless `--wide` is set, long cells are cut so each line fits it.
+    pub fn print(&self) {
+        let mut widths: Vec<usize> = self.headers.iter().map(|header| header.width()).collect();
+        for row in &self.rows {
+            for (index, cell) in row.iter().enumerate() {
+                if let Some(column) = widths.get_mut(index) {
+                    *column = (*column).max(cell.text.width());
+                }
+            }
+        }
+        let last = widths.len().saturating_sub(1);
+        let mut fit_last = false;
+        // Piped output keeps every cell whole for the scripts reading it.
+        if let Some(terminal) = terminal_width().filter(|_| !is_wide()) {
+            for column in &mut widths[..last] {
+                *column = (*column).min(MAX_COLUMN_WIDTH);
+            }
+            // The last column gets whatever the padded columns leave of the terminal.
+            let used: usize = widths[..last].iter().map(|width| width + 1).sum();
+            widths[last] = terminal.saturating_sub(used).max(1);
+            fit_last = true;
+        }
+        println!();
+        let headers = self
+            .headers
+            .iter()
+            .map(|header| Cell::from(header.as_str()));
+        println!(
+            "{}",
+            self.line(&headers.collect::<Vec<_>>(), &widths, fit_last)
+        );
+        for row in &self.rows {
+            println!("{}", self.line(row, &widths, fit_last));
+        }
+    }
+
+    fn line(&self, cells: &[Cell], widths: &[usize], fit_last: bool) -> String {
+        let last = cells.len().saturating_sub(1);
+        let mut line = String::new();
+        for (index, cell) in cells.iter().enumerate() {
+            if index > 0 {
+                line.push(' ');
+            }
+            if index < last {
+                let column = widths.get(index).copied().unwrap_or_default();
+                let text = truncate(&cell.text, column);
+                line.push_str(&paint(&text, cell.style));
+                line.push_str(&" ".repeat(column.saturating_sub(text.width())));
+            } else if fit_last {
+                let column = widths.get(index).copied().unwrap_or_default();
+                line.push_str(&paint(&truncate(&cell.text, column), cell.style));
+            } else {
+                line.push_str(&paint(&cell.text, cell.style));
+            }
+        }
+        line
+    }
+}
+
+/// Shorten `text` to at most `max` terminal columns, marking the cut with an ellipsis.
+fn truncate(text: &str, max: usize) -> String {
+    if text.width() <= max {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for ch in text.chars() {
+        let width = ch.width().unwrap_or_default();
+        if used + width + 1 > max {
+            break;
+        }
+        truncated.push(ch);
+        used += width;
+    }
+    truncated.push('…');
+    truncated
+}
+
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Filter structured output through `query`; this implies JSON output.
+pub fn set_query(query: Query) {
+    let _ = QUERY.set(query);
+}
+
+/// Print each output record through a `{field}` template; this implies structured output.
+///
+/// `\t`, `\n`, and `\\` escapes are expanded so shell-quoted templates work as expected.
+pub fn set_template(template: &str) {
+    let template = template
+        .replace("\\\\", "\u{0}")
+        .replace("\\t", "\t")
+        .replace("\\n", "\n")
+        .replace('\u{0}', "\\");
+    let _ = TEMPLATE.set(template);
+}
+
+pub fn is_json() -> bool {
+    QUERY.get().is_some()
+        || TEMPLATE.get().is_some()
+        || FORMAT.get().copied().unwrap_or_default() != OutputFormat::Table
+}
+
+/// Whether records go out one per line, so long-running commands can be piped into `jq`
+/// or a log shipper as they happen.
+pub fn is_ndjson() -> bool {
+    FORMAT.get().copied().unwrap_or_default() == OutputFormat::Ndjson
+}
+
+/// Print typed output as pretty JSON, one compact line per record with `--output ndjson`,
+/// or the results of `--query` with strings unquoted.
+pub fn print<T: Serialize + ?Sized>(value: &T) {
+    let value = match serde_json::to_value(value) {
+        Ok(value) => value,
+        Err(err) => return eprintln!("error: cannot serialize output: {err}"),
+    };
+    let results = match QUERY.get() {
+        Some(query) => query.apply(value),
+        None => vec![value],
+    };
+    for result in results {
+        match (TEMPLATE.get(), result) {
+            (Some(template), Value::Array(items)) => {
+                for item in &items {
+                    println!("{}", render(template, item));
+                }
+            }
+            (Some(template), item) => println!("{}", render(template, &item)),
+            (None, Value::String(text)) if QUERY.get().is_some() => println!("{text}"),
+            (None, other) => print_value(&other),
+        }
+    }
+}
+
+/// Replace `{field}` placeholders (dotted paths such as `{readings.0.value}` reach nested
+/// values) with the record's values; `{{` and `}}` produce literal braces.
+fn render(template: &str, record: &Value) -> String {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        text.push_str(&rest[..start]);
+        let brace = &rest[start..start + 1];
+        rest = &rest[start + 1..];
+        if let Some(after) = rest.strip_prefix(brace) {
+            text.push_str(brace);
+            rest = after;
+            continue;
+        }
+        match rest.find('}') {
+            Some(end) if brace == "{" => {
+                text.push_str(&plain(lookup(record, &rest[..end])));
+                rest = &rest[end + 1..];
+            }
+            _ => text.push_str(brace),
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+fn lookup<'a>(record: &'a Value, path: &str) -> &'a Value {
+    path.split('.').fold(record, |value, key| match value {
+        Value::Array(items) => key
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| items.get(index))
+            .unwrap_or(&Value::Null),
+        _ => value.get(key.trim()).unwrap_or(&Value::Null),
+    })
+}
+
+fn plain(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn print_value(value: &Value) {
+    if is_ndjson() {
+        match value {
+            Value::Array(items) => items.iter().for_each(|item| println!("{item}")),
+            other => println!("{other}"),
+        }
+        return;
+    }
+    match serde_json::to_string_pretty(value) {
+        Ok(text) => println!("{text}"),
+        Err(_) => println!("{value}"),
+    }
+}
+
+/// JSON Schema (draft 2020-12) describing how a type is serialized.
+pub trait JsonSchema {
+    fn json_schema() -> Value;
+}
+
+/// A controller, device, group, or sensor as listed by `devices list` and `sensors list`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Resource {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: String,
+    pub name: String,
+    pub details: Option<String>,
+    pub state: Option<&'static str>,
+    /// Account the resource belongs to, only set by `devices list --all-profiles`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Room from rooms.yaml, only set by `devices list --by-room`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+}
+
+impl From<&Entry> for Resource {
+    fn from(entry: &Entry) -> Self {
+        Self {
+            kind: entry.category.as_str(),
+            id: entry.id.clone(),
+            name: entry.name.clone(),
+            details: entry.details.clone(),
+            state: entry.state.map(method_name),
+            profile: None,
+            room: None,
+        }
+    }
+}
+
+impl JsonSchema for Resource {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["type", "id", "name", "details", "state"],
+            "properties": {
+                "type": {
+                    "enum": ["controller", "device", "sensor", "event", "phone"],
+                    "description": "Kind of resource"
+                },
+                "id": { "type": "string" },
+                "name": { "type": "string" },
+                "details": {
+                    "type": ["string", "null"],
+                    "description": "Free-form summary such as protocol, model, or battery"
+                },
+                "state": {
+                    "enum": ["on", "off", "bell", "dim", "up", "down", "stop", "unknown", null],
+                    "description": "Last command sent to a device; null for other resources"
+                },
+                "profile": {
+                    "type": "string",
+                    "description": "Account the resource belongs to; only present with --all-profiles"
+                },
+                "room": {
+                    "type": "string",
+                    "description": "Room from rooms.yaml; only present with --by-room"
+                }
+            },
+            "additionalProperties": false
+        })
+    }
+}
+
+/// One device event or sensor reading from `devices history` or `sensors history`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    /// Unix timestamp in seconds.
+    pub timestamp: Option<i64>,
+    pub state: Option<&'static str>,
+    pub level: Option<u8>,
+    pub origin: Option<String>,
+    pub readings: Vec<Reading>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Reading {
+    pub name: String,
+    pub kind: &'static str,
+    pub value: f64,
+    pub unit: &'static str,
+    pub scale: u32,
+}
+
+impl From<&SensorReading> for Reading {
+    fn from(reading: &SensorReading) -> Self {
+        Self {
+            name: reading.name.clone(),
+            kind: reading.kind.as_str(),
+            value: reading.value,
+            unit: reading.unit(),
+            scale: reading.scale,
+        }
+    }
+}
+
+impl HistoryEntry {
+    pub fn from_value(value: &Value) -> Self {
+        let readings = sensor_readings(value).iter().map(Reading::from).collect();
+        Self {
+            timestamp: text(value, "ts").and_then(|ts| ts.parse().ok()),
+            state: text(value, "state")
+                .and_then(|state| state.parse().ok())
+                .map(method_name),
+            level: text(value, "stateValue").and_then(|level| level.parse().ok()),
+            origin: text(value, "origin").filter(|origin| !origin.is_empty()),
+            readings,
+        }
+    }
+}
+
+impl JsonSchema for HistoryEntry {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["timestamp", "state", "level", "origin", "readings"],
+            "properties": {
+                "timestamp": {
+                    "type": ["integer", "null"],
+                    "description": "Unix timestamp in seconds"
+                },
+                "state": {
+                    "enum": ["on", "off", "bell", "dim", "up", "down", "stop", "unknown", null],
+                    "description": "Device command recorded by the event; null for sensor readings"
+                },
+                "level": { "type": ["integer", "null"], "minimum": 0, "maximum": 255 },
+                "origin": { "type": ["string", "null"] },
+                "readings": {
+                    "type": "array",
+                    "description": "Sensor values; empty for device events",
+                    "items": Reading::json_schema()
+                }
+            },
+            "additionalProperties": false
+        })
+    }
+}
+
+impl JsonSchema for Reading {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["name", "kind", "value", "unit", "scale"],
+            "properties": {
+                "name": { "type": "string", "description": "Telldus value name such as temp or humidity" },
+                "kind": {
+                    "enum": [
+                        "temperature", "humidity", "rain-rate", "rain-total", "wind-direction",
+                        "wind-average", "wind-gust", "uv", "power", "luminance", "pressure",
+                        "dew-point", "other"
+                    ],
+                    "description": "What the value measures"
+                },
+                "value": { "type": "number" },
+                "unit": { "type": "string", "description": "Unit such as °C, % or W; empty if unitless" },
+                "scale": { "type": "integer", "description": "Telldus scale the value is reported in" }
+            },
+            "additionalProperties": false
+        })
+    }
+}
+
+/// A sensor with its latest readings, as printed by `sensors list`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SensorRecord {
+    pub id: String,
+    pub name: String,
+    pub model: Option<String>,
+    pub protocol: Option<String>,
+    pub ignored: bool,
+    pub readings: Vec<Reading>,
+}
+
+impl From<&Sensor> for SensorRecord {
+    fn from(sensor: &Sensor) -> Self {
+        Self {
+            id: sensor.id.clone(),
+            name: sensor.name.clone(),
+            model: sensor.model.clone(),
+            protocol: sensor.protocol.clone(),
+            ignored: sensor.ignored,
+            readings: sensor.readings.iter().map(Reading::from).collect(),
+        }
+    }
+}
+
+impl JsonSchema for SensorRecord {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["id", "name", "model", "protocol", "ignored", "readings"],
+            "properties": {
+                "id": { "type": "string" },
+                "name": { "type": "string" },
+                "model": { "type": ["string", "null"] },
+                "protocol": { "type": ["string", "null"] },
+                "ignored": { "type": "boolean" },
+                "readings": {
+                    "type": "array",
+                    "description": "Latest value of each quantity the sensor reports",
+                    "items": Reading::json_schema()
+                }
+            },
+            "additionalProperties": false
+        })
+    }
+}
+
+/// Schema for a JSON array of `T`, as printed by the list commands.
+pub fn array_schema<T: JsonSchema>(title: &str) -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": title,
+        "type": "array",
+        "items": T::json_schema()
+    })
+}
+
+fn text(value: &Value, key: &str) -> Option<String> {
+    match value.get(key)? {
+        Value::String(text) => Some(text.trim().to_string()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,490 @@
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Shape of a device parameter value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    /// Whole number in an inclusive range.
+    Number(u32, u32),
+    /// One letter in an inclusive range, such as the `A`-`P` house codes of code switches.
+    Letter(char, char),
+    /// Fixed-length string of `0` and `1`, as set with DIP switches.
+    Bits(usize),
+}
+
+impl ValueKind {
+    pub fn describe(self) -> String {
+        match self {
+            ValueKind::Number(min, max) => format!("{min}-{max}"),
+            ValueKind::Letter(first, last) => format!("{first}-{last}"),
+            ValueKind::Bits(len) => format!("{len} digits of 0/1"),
+        }
+    }
+
+    /// Check `value` and return it in the form Telldus expects, e.g. house letters in
+    /// upper case.
+    pub fn check(self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+        match self {
+            ValueKind::Number(min, max) => match value.parse::<u32>() {
+                Ok(number) if (min..=max).contains(&number) => Ok(number.to_string()),
+                _ => Err(format!("expected a number {min}-{max}")),
+            },
+            ValueKind::Letter(first, last) => {
+                let mut chars = value.chars();
+                match (chars.next().map(|ch| ch.to_ascii_uppercase()), chars.next()) {
+                    (Some(letter), None) if (first..=last).contains(&letter) => {
+                        Ok(letter.to_string())
+                    }
+                    _ => Err(format!("expected a letter {first}-{last}")),
+                }
+            }
+            ValueKind::Bits(len) => {
+                if value.len() == len && value.chars().all(|ch| ch == '0' || ch == '1') {
+                    Ok(value.to_string())
+                } else {
+                    Err(format!("expected {len} digits of 0 or 1"))
+                }
+            }
+        }
+    }
+
+    /// The first value in the range, e.g. house `A` or unit `1`.
+    pub fn lowest(self) -> String {
+        match self {
+            ValueKind::Number(min, _) => min.to_string(),
+            ValueKind::Letter(first, _) => first.to_string(),
+            ValueKind::Bits(len) => "0".repeat(len),
+        }
+    }
+
+    /// A value picked at random from the range.
+    pub fn random(self) -> String {
+        let pick = |count: u32| {
+            let mut bytes = [0u8; 4];
+            // The system generator only fails when the OS has no entropy source at all.
+            SystemRandom::new()
+                .fill(&mut bytes)
+                .expect("system random number generator");
+            u32::from_le_bytes(bytes) % count
+        };
+        match self {
+            ValueKind::Number(min, max) => (min + pick(max - min + 1)).to_string(),
+            ValueKind::Letter(first, last) => {
+                char::from_u32(first as u32 + pick(last as u32 - first as u32 + 1))
+                    .unwrap_or(first)
+                    .to_string()
+            }
+            ValueKind::Bits(len) => (0..len)
+                .map(|_| if pick(2) == 1 { '1' } else { '0' })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub kind: ValueKind,
+}
+
+/// A protocol/model combination Telldus Live supports.
+#[derive(Debug)]
+pub struct Model {
+    pub protocol: &'static str,
+    pub model: &'static str,
+    pub description: &'static str,
+    pub parameters: &'static [Parameter],
+    /// The receiver pairs with whatever code it hears in learn mode, instead of
+    /// having its code set on the device itself.
+    pub learns: bool,
+}
+
+const HOUSE_LETTER: Parameter = Parameter {
+    name: "house",
+    kind: ValueKind::Letter('A', 'P'),
+};
+const UNIT_16: Parameter = Parameter {
+    name: "unit",
+    kind: ValueKind::Number(1, 16),
+};
+const UNIT_4: Parameter = Parameter {
+    name: "unit",
+    kind: ValueKind::Number(1, 4),
+};
+const ARCTECH_HOUSE: Parameter = Parameter {
+    name: "house",
+    kind: ValueKind::Number(1, 67_108_863),
+};
+const CODE_BITS: Parameter = Parameter {
+    name: "code",
+    kind: ValueKind::Bits(10),
+};
+
+/// The common 433 MHz receivers, so the CLI can prompt for and check their parameters.
+///
+/// Telldus Live accepts any protocol and model string; anything else is passed through
+/// unchecked.
+pub const MODELS: &[Model] = &[
+    Model {
+        protocol: "arctech",
+        model: "selflearning-switch",
+        description: "Nexa, Proove, KlikAanKlikUit self-learning on/off",
+        parameters: &[ARCTECH_HOUSE, UNIT_16],
+        learns: true,
+    },
+    Model {
+        protocol: "arctech",
+        model: "selflearning-dimmer",
+        description: "Nexa, Proove, KlikAanKlikUit self-learning dimmer",
+        parameters: &[ARCTECH_HOUSE, UNIT_16],
+        learns: true,
+    },
+    Model {
+        protocol: "arctech",
+        model: "codeswitch",
+        description: "Nexa, Proove code wheel switch",
+        parameters: &[HOUSE_LETTER, UNIT_16],
+        learns: false,
+    },
+    Model {
+        protocol: "arctech",
+        model: "bell",
+        description: "Nexa, Proove doorbell",
+        parameters: &[HOUSE_LETTER],
+        learns: false,
+    },
+    Model {
+        protocol: "everflourish",
+        model: "selflearning",
+        description: "Everflourish, GAO self-learning",
+        parameters: &[
+            Parameter {
+                name: "house",
+                kind: ValueKind::Number(0, 16_383),
+            },
+            UNIT_4,
+        ],
+        learns: true,
+    },
+    Model {
+        protocol: "hasta",
+        model: "selflearningv2",
+        description: "Hasta motorised blinds",
+        parameters: &[
+            Parameter {
+                name: "house",
+                kind: ValueKind::Number(1, 65_536),
+            },
+            Parameter {
+                name: "unit",
+                kind: ValueKind::Number(1, 15),
+            },
+        ],
+        learns: true,
+    },
+    Model {
+        protocol: "risingsun",
+        model: "selflearning",
+        description: "Rising Sun, Kjell & Company self-learning",
+        parameters: &[
+            Parameter {
+                name: "house",
+                kind: ValueKind::Number(1, 33_554_432),
+            },
+            UNIT_16,
+        ],
+        learns: true,
+    },
+    Model {
+        protocol: "risingsun",
+        model: "codeswitch",
+        description: "Rising Sun code switch",
+        parameters: &[
+            Parameter {
+                name: "house",
+                kind: ValueKind::Number(1, 4),
+            },
+            UNIT_4,
+        ],
+        learns: false,
+    },
+    Model {
+        protocol: "sartano",
+        model: "codeswitch",
+        description: "Sartano, Kjell & Company DIP switch",
+        parameters: &[CODE_BITS],
+        learns: false,
+    },
+    Model {
+        protocol: "fuhaote",
+        model: "codeswitch",
+        description: "Fuhaote DIP switch",
+        parameters: &[CODE_BITS],
+        learns: false,
+    },
+    Model {
+        protocol: "silvanchip",
+        model: "ecosavers",
+        description: "Ecosavers self-learning",
+        parameters: &[
+            Parameter {
+                name: "house",
+                kind: ValueKind::Number(1, 1_048_575),
+            },
+            UNIT_4,
+        ],
+        learns: true,
+    },
+    Model {
+        protocol: "upm",
+        model: "selflearning",
+        description: "UPM self-learning",
+        parameters: &[
+            Parameter {
+                name: "house",
+                kind: ValueKind::Number(0, 4095),
+            },
+            UNIT_4,
+        ],
+        learns: true,
+    },
+    Model {
+        protocol: "waveman",
+        model: "codeswitch",
+        description: "Waveman code switch",
+        parameters: &[HOUSE_LETTER, UNIT_16],
+        learns: false,
+    },
+    Model {
+        protocol: "x10",
+        model: "codeswitch",
+        description: "X10 code switch",
+        parameters: &[HOUSE_LETTER, UNIT_16],
+        learns: false,
+    },
+    Model {
+        protocol: "yidong",
+        model: "memory",
+        description: "Yidong memory switch",
+        parameters: &[UNIT_4],
+        learns: true,
+    },
+];
+
+impl Model {
+    /// Parameters for a new device: a random house code for self-learning receivers, so
+    /// it is unlikely to clash with a neighbour's remote, and the lowest value of
+    /// everything else. Code switches need their values changed to match the receiver.
+    pub fn default_parameters(&self) -> Vec<(String, String)> {
+        self.parameters
+            .iter()
+            .map(|parameter| {
+                let value = if self.learns && parameter.name == "house" {
+                    parameter.kind.random()
+                } else {
+                    parameter.kind.lowest()
+                };
+                (parameter.name.to_string(), value)
+            })
+            .collect()
+    }
+
+    /// Parameters for a new device whose house code is not in `taken`, a list of
+    /// `(house, unit)` pairs already in use with this protocol. A random unused house is
+    /// preferred; when the range is exhausted the next free unit of a used house is taken.
+    /// `None` when the model has no house code or every combination is in use.
+    pub fn unused_parameters(&self, taken: &[(String, String)]) -> Option<Vec<(String, String)>> {
+        let house = self
+            .parameters
+            .iter()
+            .find(|parameter| parameter.name == "house")?;
+        let unit = self
+            .parameters
+            .iter()
+            .find(|parameter| parameter.name == "unit");
+        let is_taken = |house: &str, unit: Option<&str>| {
+            taken.iter().any(|(used_house, used_unit)| {
+                used_house.eq_ignore_ascii_case(house) && unit.is_none_or(|unit| used_unit == unit)
+            })
+        };
+
+        let mut chosen = (0..64)
+            .map(|_| house.kind.random())
+            .find(|candidate| !is_taken(candidate, None))
+            .map(|house| (house, unit.map(|unit| unit.kind.lowest())));
+        if chosen.is_none()
+            && let Some(ValueKind::Number(min, max)) = unit.map(|unit| unit.kind)
+        {
+            let mut houses: Vec<&str> = taken.iter().map(|(house, _)| house.as_str()).collect();
+            houses.sort_unstable();
+            houses.dedup();
+            chosen = houses.into_iter().find_map(|house| {
+                let unit = (min..=max)
+                    .map(|unit| unit.to_string())
+                    .find(|unit| !is_taken(house, Some(unit)))?;
+                Some((house.to_string(), Some(unit)))
+            });
+        }
+        let (house, unit) = chosen?;
+
+        Some(
+            self.parameters
+                .iter()
+                .map(|parameter| {
+                    let value = match parameter.name {
+                        "house" => house.clone(),
+                        "unit" => unit.clone().unwrap_or_else(|| parameter.kind.lowest()),
+                        _ => parameter.kind.lowest(),
+                    };
+                    (parameter.name.to_string(), value)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A named shortcut for a common receiver, for `devices add --preset`.
+#[derive(Debug)]
+pub struct Preset {
+    pub name: &'static str,
+    pub protocol: &'static str,
+    /// Model as sent to Telldus Live, with a `:vendor` suffix where Telldus uses one.
+    pub model: &'static str,
+}
+
+impl Preset {
+    pub fn known(&self) -> &'static Model {
+        find(self.protocol, self.model).expect("presets refer to catalog models")
+    }
+}
+
+const fn preset(name: &'static str, protocol: &'static str, model: &'static str) -> Preset {
+    Preset {
+        name,
+        protocol,
+        model,
+    }
+}
+
+pub const PRESETS: &[Preset] = &[
+    preset("nexa-selflearning", "arctech", "selflearning-switch:nexa"),
+    preset("nexa-dimmer", "arctech", "selflearning-dimmer:nexa"),
+    preset("nexa-codeswitch", "arctech", "codeswitch:nexa"),
+    preset("nexa-bell", "arctech", "bell:nexa"),
+    preset(
+        "proove-selflearning",
+        "arctech",
+        "selflearning-switch:proove",
+    ),
+    preset("proove-dimmer", "arctech", "selflearning-dimmer:proove"),
+    preset("proove-codeswitch", "arctech", "codeswitch:proove"),
+    preset("everflourish", "everflourish", "selflearning"),
+    preset("hasta-blinds", "hasta", "selflearningv2"),
+    preset("risingsun-selflearning", "risingsun", "selflearning"),
+    preset("risingsun-codeswitch", "risingsun", "codeswitch"),
+    preset("sartano", "sartano", "codeswitch"),
+    preset("fuhaote", "fuhaote", "codeswitch"),
+    preset("ecosavers", "silvanchip", "ecosavers"),
+    preset("upm", "upm", "selflearning"),
+    preset("waveman", "waveman", "codeswitch"),
+    preset("x10", "x10", "codeswitch"),
+    preset("yidong", "yidong", "memory"),
+];
+
+/// Look up a preset by name, ignoring case.
+pub fn find_preset(name: &str) -> Option<&'static Preset> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name.trim()))
+}
+
+/// What is wrong with a device definition. Telldus Live accepts any protocol, model
+/// and parameters, but a receiver never hears a device that is missing its codes.
+#[derive(Debug, Default)]
+pub struct Findings {
+    /// Missing or out-of-range parameters of a catalog model.
+    pub errors: Vec<String>,
+    /// Definitions the catalog cannot check, and parameters the model does not use.
+    pub warnings: Vec<String>,
+}
+
+/// Check a new device's protocol, model and complete set of parameters.
+pub fn validate(protocol: &str, model: &str, parameters: &[(String, String)]) -> Findings {
+    let mut findings = Findings::default();
+    let Some(known) = lookup(protocol, model, &mut findings) else {
+        return findings;
+    };
+    for parameter in known.parameters {
+        if !parameters.iter().any(|(name, _)| name == parameter.name) {
+            findings.errors.push(format!(
+                "{} {} needs parameter {} ({})",
+                known.protocol,
+                known.model,
+                parameter.name,
+                parameter.kind.describe()
+            ));
+        }
+    }
+    for (name, value) in parameters {
+        check_parameter(known, name, value, &mut findings);
+    }
+    findings
+}
+
+/// Check one parameter about to be set on an existing device.
+pub fn validate_parameter(protocol: &str, model: &str, name: &str, value: &str) -> Findings {
+    let mut findings = Findings::default();
+    if let Some(known) = lookup(protocol, model, &mut findings) {
+        check_parameter(known, name, value, &mut findings);
+    }
+    findings
+}
+
+fn lookup(protocol: &str, model: &str, findings: &mut Findings) -> Option<&'static Model> {
+    let known = find(protocol, model);
+    if known.is_none() {
+        let models: Vec<_> = MODELS
+            .iter()
+            .filter(|known| known.protocol.eq_ignore_ascii_case(protocol.trim()))
+            .map(|known| known.model)
+            .collect();
+        findings.warnings.push(if models.is_empty() {
+            format!("protocol {protocol} is not in the catalog; its parameters are not checked")
+        } else {
+            format!(
+                "{protocol} has no model {model} in the catalog (known: {}); its parameters are not checked",
+                models.join(", ")
+            )
+        });
+    }
+    known
+}
+
+fn check_parameter(known: &Model, name: &str, value: &str, findings: &mut Findings) {
+    match known
+        .parameters
+        .iter()
+        .find(|parameter| parameter.name == name)
+    {
+        Some(parameter) => {
+            if let Err(message) = parameter.kind.check(value) {
+                findings
+                    .errors
+                    .push(format!("parameter {name} is '{value}' but {message}"));
+            }
+        }
+        None => findings.warnings.push(format!(
+            "{} {} does not use parameter {name}",
+            known.protocol, known.model
+        )),
+    }
+}
+
+/// Look up a combination, ignoring case and a `:vendor` suffix on the model.
+pub fn find(protocol: &str, model: &str) -> Option<&'static Model> {
+    let model = model.split(':').next().unwrap_or_default().trim();
+    MODELS.iter().find(|known| {
+        known.protocol.eq_ignore_ascii_case(protocol.trim())
+            && known.model.eq_ignore_ascii_case(model)
+    })
+}
@@ -0,0 +1,177 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ProvisionError {
+    #[error("cannot read {0}: {1}")]
+    Read(String, #[source] io::Error),
+    #[error("cannot parse {0}: {1}")]
+    Yaml(String, #[source] serde_yaml::Error),
+    #[error("{0} line {1}: {2}")]
+    Csv(String, usize, String),
+    #[error("{0} lists no devices")]
+    Empty(String),
+}
+
+/// One device to create.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewDevice {
+    /// Controller to create the device on; `--client-id` supplies a default.
+    #[serde(default, alias = "client", deserialize_with = "scalar_option")]
+    pub client_id: Option<String>,
+    pub name: String,
+    pub protocol: String,
+    pub model: String,
+    #[serde(default, deserialize_with = "scalar_map")]
+    pub parameters: Vec<(String, String)>,
+}
+
+/// Read the devices in `path`, as CSV when it ends in `.csv` and YAML otherwise.
+///
+/// CSV files start with a header row naming the `name`, `protocol` and `model` columns,
+/// optionally `client_id`; every other column is a device parameter, and empty cells are
+/// left unset. YAML files hold a list of devices with a `parameters` map each.
+pub fn load(path: &Path) -> Result<Vec<NewDevice>, ProvisionError> {
+    let display = path.display().to_string();
+    let text =
+        fs::read_to_string(path).map_err(|err| ProvisionError::Read(display.clone(), err))?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    let devices = if is_csv {
+        parse_csv(&text)
+            .map_err(|(line, message)| ProvisionError::Csv(display.clone(), line, message))?
+    } else {
+        serde_yaml::from_str(&text).map_err(|err| ProvisionError::Yaml(display.clone(), err))?
+    };
+    if devices.is_empty() {
+        return Err(ProvisionError::Empty(display));
+    }
+    Ok(devices)
+}
+
+/// Parse CSV devices, reporting errors with their 1-based line number.
+fn parse_csv(text: &str) -> Result<Vec<NewDevice>, (usize, String)> {
+    let mut rows = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim_end_matches('\r')))
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+    let Some((header_line, header)) = rows.next() else {
+        return Ok(Vec::new());
+    };
+    let header: Vec<String> = split_csv_line(header)
+        .map_err(|message| (header_line, message))?
+        .into_iter()
+        .map(|column| column.trim().to_lowercase())
+        .collect();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let (Some(name), Some(protocol), Some(model)) =
+        (column("name"), column("protocol"), column("model"))
+    else {
+        return Err((
+            header_line,
+            "header must name the name, protocol and model columns".into(),
+        ));
+    };
+    let client = column("client_id").or_else(|| column("client"));
+
+    let mut devices = Vec::new();
+    for (line, row) in rows {
+        let cells = split_csv_line(row).map_err(|message| (line, message))?;
+        if cells.len() > header.len() {
+            return Err((
+                line,
+                format!("{} cells but {} columns", cells.len(), header.len()),
+            ));
+        }
+        let cell = |index: usize| {
+            cells
+                .get(index)
+                .map(|cell| cell.trim().to_string())
+                .filter(|cell| !cell.is_empty())
+        };
+        let required =
+            |index: usize| cell(index).ok_or_else(|| (line, format!("{} is empty", header[index])));
+        devices.push(NewDevice {
+            client_id: client.and_then(cell),
+            name: required(name)?,
+            protocol: required(protocol)?,
+            model: required(model)?,
+            parameters: header
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| {
+                    ![Some(name), Some(protocol), Some(model), client].contains(&Some(*index))
+                })
+                .filter_map(|(index, key)| Some((key.clone(), cell(index)?)))
+                .collect(),
+        });
+    }
+    Ok(devices)
+}
+
+/// Split one CSV record, honouring double-quoted cells with `""` escapes.
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if cell.trim().is_empty() => {
+                cell.clear();
+                quoted = true;
+            }
+            (',', false) => cells.push(std::mem::take(&mut cell)),
+            _ => cell.push(ch),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted cell".into());
+    }
+    cells.push(cell);
+    Ok(cells)
+}
+
+/// Accept numbers as well as strings, since YAML reads `client_id: 367974` as a number.
+fn scalar_option<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<serde_yaml::Value>::deserialize(deserializer)?;
+    value
+        .map(|value| scalar(&value))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+fn scalar_map<'de, D>(deserializer: D) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    BTreeMap::<String, serde_yaml::Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| Ok((key, scalar(&value)?)))
+        .collect::<Result<_, String>>()
+        .map_err(serde::de::Error::custom)
+}
+
+fn scalar(value: &serde_yaml::Value) -> Result<String, String> {
+    match value {
+        serde_yaml::Value::String(text) => Ok(text.clone()),
+        serde_yaml::Value::Number(number) => Ok(number.to_string()),
+        serde_yaml::Value::Bool(flag) => Ok(flag.to_string()),
+        _ => Err("expected a string or number".into()),
+    }
+}
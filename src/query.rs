@@ -0,0 +1,203 @@
+use serde_json::Value;
+use std::cmp::Ordering;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum QueryError {
+    #[error("invalid query at '{0}': {1}")]
+    Syntax(String, &'static str),
+}
+
+/// A small jq subset: `.field`, `.[n]`, `.[]`, and `select(PATH OP LITERAL)` stages joined by `|`.
+#[derive(Debug, Clone)]
+pub struct Query {
+    stages: Vec<Stage>,
+}
+
+#[derive(Debug, Clone)]
+enum Stage {
+    Path(Vec<Step>),
+    Select(Vec<Step>, Comparison, Value),
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Field(String),
+    Index(i64),
+    Iterate,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, QueryError> {
+        let stages = split_stages(text)
+            .into_iter()
+            .map(|stage| parse_stage(stage.trim()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { stages })
+    }
+
+    /// Every value the query yields for `input`, in order.
+    pub fn apply(&self, input: Value) -> Vec<Value> {
+        self.stages.iter().fold(vec![input], |values, stage| {
+            values
+                .into_iter()
+                .flat_map(|value| match stage {
+                    Stage::Path(steps) => walk(value, steps),
+                    Stage::Select(steps, comparison, literal) => {
+                        let found = walk(value.clone(), steps).into_iter().next();
+                        let keep = found.is_some_and(|found| compare(&found, *comparison, literal));
+                        if keep { vec![value] } else { Vec::new() }
+                    }
+                })
+                .collect()
+        })
+    }
+}
+
+/// Split on `|` outside string literals.
+fn split_stages(text: &str) -> Vec<&str> {
+    let mut stages = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, ch) in text.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '|' if !in_string => {
+                stages.push(&text[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    stages.push(&text[start..]);
+    stages
+}
+
+fn parse_stage(text: &str) -> Result<Stage, QueryError> {
+    let syntax = |message| QueryError::Syntax(text.to_string(), message);
+    if let Some(inner) = text
+        .strip_prefix("select(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let (path, rest) = parse_path(inner.trim_start()).map_err(syntax)?;
+        let rest = rest.trim_start();
+        let (comparison, literal) = [
+            ("==", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ]
+        .into_iter()
+        .find_map(|(symbol, comparison)| {
+            rest.strip_prefix(symbol)
+                .map(|literal| (comparison, literal))
+        })
+        .ok_or_else(|| syntax("expected ==, !=, <, <=, > or >= in select"))?;
+        let literal = serde_json::from_str(literal.trim())
+            .map_err(|_| syntax("select needs a JSON literal such as \"on\", 20 or null"))?;
+        return Ok(Stage::Select(path, comparison, literal));
+    }
+    match parse_path(text).map_err(syntax)? {
+        (path, "") => Ok(Stage::Path(path)),
+        _ => Err(syntax("unexpected text after path")),
+    }
+}
+
+/// Parse a path such as `.`, `.name`, `.[]`, or `.items[0][1].id`, returning the unparsed rest.
+fn parse_path(text: &str) -> Result<(Vec<Step>, &str), &'static str> {
+    let mut rest = text.strip_prefix('.').ok_or("paths start with '.'")?;
+    let mut steps = Vec::new();
+    let mut first = true;
+    loop {
+        let name_len = rest
+            .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+            .unwrap_or(rest.len());
+        if name_len > 0 {
+            steps.push(Step::Field(rest[..name_len].to_string()));
+            rest = &rest[name_len..];
+        }
+        let mut brackets = false;
+        while let Some(after) = rest.strip_prefix('[') {
+            let (index, after) = after.split_once(']').ok_or("unclosed '['")?;
+            steps.push(match index.trim() {
+                "" => Step::Iterate,
+                index => Step::Index(index.parse().map_err(|_| "bad index")?),
+            });
+            rest = after;
+            brackets = true;
+        }
+        // Only the identity path `.` may have nothing after its dot.
+        if name_len == 0 && !brackets && (!first || rest.starts_with('.')) {
+            return Err("empty path segment");
+        }
+        first = false;
+        match rest.strip_prefix('.') {
+            Some(after) => rest = after,
+            None => return Ok((steps, rest)),
+        }
+    }
+}
+
+fn walk(value: Value, steps: &[Step]) -> Vec<Value> {
+    let Some((step, rest)) = steps.split_first() else {
+        return vec![value];
+    };
+    let next = match (step, value) {
+        (Step::Field(name), Value::Object(mut map)) => {
+            vec![map.remove(name).unwrap_or(Value::Null)]
+        }
+        (Step::Index(index), Value::Array(mut items)) => {
+            let len = items.len() as i64;
+            let index = if *index < 0 { len + index } else { *index };
+            if (0..len).contains(&index) {
+                vec![items.swap_remove(index as usize)]
+            } else {
+                vec![Value::Null]
+            }
+        }
+        (Step::Iterate, Value::Array(items)) => items,
+        (Step::Iterate, Value::Object(map)) => map.into_iter().map(|(_, item)| item).collect(),
+        (Step::Iterate, _) => Vec::new(),
+        _ => vec![Value::Null],
+    };
+    next.into_iter()
+        .flat_map(|value| walk(value, rest))
+        .collect()
+}
+
+fn compare(left: &Value, comparison: Comparison, right: &Value) -> bool {
+    let ordering = match (left, right) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ if left == right => Some(Ordering::Equal),
+        _ => None,
+    };
+    match comparison {
+        Comparison::Equal => ordering == Some(Ordering::Equal),
+        Comparison::NotEqual => ordering != Some(Ordering::Equal),
+        Comparison::Less => ordering == Some(Ordering::Less),
+        Comparison::LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        Comparison::Greater => ordering == Some(Ordering::Greater),
+        Comparison::GreaterOrEqual => {
+            matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
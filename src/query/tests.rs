@@ -0,0 +1,110 @@
+use super::*;
+use serde_json::json;
+
+fn run(query: &str, input: Value) -> Vec<Value> {
+    Query::parse(query)
+        .unwrap_or_else(|err| panic!("{query}: {err}"))
+        .apply(input)
+}
+
+fn syntax_error(query: &str) -> &'static str {
+    match Query::parse(query) {
+        Ok(_) => panic!("{query} should not parse"),
+        Err(QueryError::Syntax(_, message)) => message,
+    }
+}
+
+#[test]
+fn identity_yields_the_input() {
+    assert_eq!(run(".", json!({"a": 1})), [json!({"a": 1})]);
+}
+
+#[test]
+fn fields_and_indices_walk_into_the_input() {
+    let input = json!({"items": [{"id": "1"}, {"id": "2"}]});
+
+    assert_eq!(run(".items[1].id", input.clone()), [json!("2")]);
+    assert_eq!(run(".items[-1].id", input.clone()), [json!("2")]);
+    assert_eq!(run(".items[5]", input.clone()), [Value::Null]);
+    assert_eq!(run(".missing.id", input), [Value::Null]);
+}
+
+#[test]
+fn chained_indices_walk_nested_arrays() {
+    let input = json!({"a": [[1, 2], [3, 4]]});
+
+    assert_eq!(run(".a[1][0]", input.clone()), [json!(3)]);
+    assert_eq!(run(".a[][1]", input.clone()), [json!(2), json!(4)]);
+    assert_eq!(run(".[0][1]", json!([[1, 2]])), [json!(2)]);
+    assert_eq!(run(".a.[0][1]", input), [json!(2)]);
+}
+
+#[test]
+fn iteration_yields_every_item_or_value() {
+    assert_eq!(
+        run(".[] | .name", json!([{"name": "a"}, {"name": "b"}])),
+        [json!("a"), json!("b")]
+    );
+    assert_eq!(run(".[]", json!({"x": 1, "y": 2})), [json!(1), json!(2)]);
+    assert!(run(".[]", json!(3)).is_empty());
+}
+
+#[test]
+fn select_keeps_values_whose_path_compares_true() {
+    let input = json!([
+        {"name": "Kitchen", "temp": 21.5},
+        {"name": "Attic", "temp": 28},
+        {"name": "Porch", "temp": null}
+    ]);
+
+    assert_eq!(
+        run(".[] | select(.temp > 25) | .name", input.clone()),
+        [json!("Attic")]
+    );
+    assert_eq!(
+        run(".[] | select(.temp <= 21.5) | .name", input.clone()),
+        [json!("Kitchen")]
+    );
+    assert_eq!(
+        run(".[] | select(.temp == null) | .name", input.clone()),
+        [json!("Porch")]
+    );
+    assert_eq!(
+        run(r#".[] | select(.name != "Attic") | .name"#, input),
+        [json!("Kitchen"), json!("Porch")]
+    );
+}
+
+#[test]
+fn pipes_inside_string_literals_do_not_split_stages() {
+    assert_eq!(
+        run(
+            r#".[] | select(.name == "a|b")"#,
+            json!([{"name": "a|b"}, {"name": "a"}])
+        ),
+        [json!({"name": "a|b"})]
+    );
+}
+
+#[test]
+fn empty_path_segments_are_rejected() {
+    for query in ["..name", ".a..b", ".a.", ".a[0]."] {
+        assert_eq!(syntax_error(query), "empty path segment", "{query}");
+    }
+}
+
+#[test]
+fn malformed_queries_are_rejected() {
+    assert_eq!(syntax_error("name"), "paths start with '.'");
+    assert_eq!(syntax_error(".a[0"), "unclosed '['");
+    assert_eq!(syntax_error(".a[x]"), "bad index");
+    assert_eq!(syntax_error(".a b"), "unexpected text after path");
+    assert_eq!(
+        syntax_error("select(.a ~ 1)"),
+        "expected ==, !=, <, <=, > or >= in select"
+    );
+    assert_eq!(
+        syntax_error("select(.a == on)"),
+        "select needs a JSON literal such as \"on\", 20 or null"
+    );
+}
@@ -0,0 +1,57 @@
+use crate::config::{self, TelldusCredentials};
+use crate::schedule::unix_now;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+const RECENT_FILE: &str = "recent.yaml";
+const MAX_RECENT: usize = 10;
+
+/// The devices most recently switched from the command line, newest first.
+///
+/// Only single-device commands are remembered; the daemon, alerts and the REST API
+/// leave the list alone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Recent {
+    pub devices: Vec<RecentDevice>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentDevice {
+    pub id: String,
+    /// Unix timestamp of the last command sent to the device.
+    pub commanded_at: u64,
+}
+
+/// The account's remembered devices; none when the file is missing or unreadable.
+pub fn load(credentials: &TelldusCredentials) -> Recent {
+    config::account_file(credentials, RECENT_FILE)
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| serde_yaml::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Move `id` to the front of the list.
+///
+/// Failures are ignored: the list is a convenience and the command has already been sent.
+pub fn record(credentials: &TelldusCredentials, id: &str) {
+    let mut recent = load(credentials);
+    recent.devices.retain(|device| device.id != id);
+    recent.devices.insert(
+        0,
+        RecentDevice {
+            id: id.to_string(),
+            commanded_at: unix_now(),
+        },
+    );
+    recent.devices.truncate(MAX_RECENT);
+    if let Ok(path) = config::account_file(credentials, RECENT_FILE)
+        && path
+            .parent()
+            .is_none_or(|dir| fs::create_dir_all(dir).is_ok())
+        && let Ok(yaml) = serde_yaml::to_string(&recent)
+    {
+        let _ = fs::write(path, yaml);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::config::{self, TelldusCredentials};
+use crate::fixtures;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const CACHE_DIR: &str = "responses";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TTL_SECS: AtomicU64 = AtomicU64::new(0);
+static REFRESH: AtomicBool = AtomicBool::new(false);
+static HITS: AtomicU64 = AtomicU64::new(0);
+
+/// A stored response body and the validators to revalidate it with, kept in the
+/// account's `responses` directory so profiles never see each other's devices.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// Unix timestamp of when Telldus Live last sent or confirmed the body.
+    #[serde(default)]
+    pub stored_at: u64,
+    pub body: String,
+}
+
+impl Entry {
+    pub fn new(body: String, etag: Option<String>, last_modified: Option<String>) -> Self {
+        Self {
+            etag,
+            last_modified,
+            stored_at: now(),
+            body,
+        }
+    }
+}
+
+/// Start caching; library users and tests leave the config dir alone.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Serve list responses younger than `ttl` without asking Telldus Live; zero turns this off.
+///
+/// Long-running commands set it back to zero so every poll sees current states.
+pub fn set_ttl(ttl: Duration) {
+    TTL_SECS.store(ttl.as_secs(), Ordering::Relaxed);
+}
+
+/// Ask Telldus Live even when a copy is within the TTL (`--refresh`).
+pub fn set_refresh(refresh: bool) {
+    REFRESH.store(refresh, Ordering::Relaxed);
+}
+
+/// Run `f` serving list responses younger than `ttl` from their copies, or younger than
+/// the TTL already set if that is longer.
+pub fn with_ttl<T>(ttl: Duration, f: impl FnOnce() -> T) -> T {
+    let previous = TTL_SECS.fetch_max(ttl.as_secs(), Ordering::Relaxed);
+    let result = f();
+    TTL_SECS.store(previous, Ordering::Relaxed);
+    result
+}
+
+/// Run `f` asking Telldus Live even for responses a copy could answer.
+pub fn refreshing<T>(f: impl FnOnce() -> T) -> T {
+    let previous = REFRESH.swap(true, Ordering::Relaxed);
+    let result = f();
+    REFRESH.store(previous, Ordering::Relaxed);
+    result
+}
+
+/// Count a response served from its copy without asking Telldus Live.
+pub fn record_hit() {
+    HITS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Responses served from copies so far.
+pub fn hits() -> u64 {
+    HITS.load(Ordering::Relaxed)
+}
+
+/// Whether responses to `path` are kept: the list and info reads that watch modes repeat.
+pub fn is_cacheable(path: &str) -> bool {
+    is_enabled() && matches!(path.rsplit('/').next(), Some("list" | "info"))
+}
+
+/// Whether `entry` can answer a request for `path` without asking Telldus Live.
+pub fn is_fresh(path: &str, entry: &Entry) -> bool {
+    let ttl = TTL_SECS.load(Ordering::Relaxed);
+    ttl > 0
+        && is_list(path)
+        && !REFRESH.load(Ordering::Relaxed)
+        && now().saturating_sub(entry.stored_at) < ttl
+}
+
+/// Whether `entry` is worth storing: it can be revalidated, or it is a list that may be
+/// served from the copy.
+///
+/// Name resolution, `--match` and completions read list copies for up to an hour (see
+/// [`crate::cache`]); with `--cache-ttl` set every list request is served straight from
+/// a copy younger than the TTL.
+pub fn is_worth_keeping(path: &str, entry: &Entry) -> bool {
+    entry.etag.is_some() || entry.last_modified.is_some() || is_list(path)
+}
+
+pub fn load(
+    credentials: &TelldusCredentials,
+    path: &str,
+    params: &[(&str, &str)],
+) -> Option<Entry> {
+    let file = entry_path(credentials, path, params)?;
+    serde_json::from_str(&fs::read_to_string(file).ok()?).ok()
+}
+
+/// Keep `entry` for the next identical request.
+///
+/// Failures are ignored: the cache only saves requests.
+pub fn store(credentials: &TelldusCredentials, path: &str, params: &[(&str, &str)], entry: &Entry) {
+    let Some(file) = entry_path(credentials, path, params) else {
+        return;
+    };
+    let Ok(json) = serde_json::to_string(entry) else {
+        return;
+    };
+    let mut dirs = fs::DirBuilder::new();
+    dirs.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut dirs, 0o700);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let _ = file
+        .parent()
+        .map_or(Ok(()), |dir| dirs.create(dir))
+        .and_then(|()| options.open(&file))
+        .and_then(|mut out| out.write_all(json.as_bytes()));
+}
+
+/// Drop every copy kept for the account, after a request that may have changed it.
+pub fn clear(credentials: &TelldusCredentials) {
+    if is_enabled()
+        && let Some(dir) = account_dir(credentials)
+    {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+fn entry_path(
+    credentials: &TelldusCredentials,
+    path: &str,
+    params: &[(&str, &str)],
+) -> Option<PathBuf> {
+    Some(fixtures::fixture_path(
+        &account_dir(credentials)?,
+        "GET",
+        path,
+        params,
+    ))
+}
+
+fn account_dir(credentials: &TelldusCredentials) -> Option<PathBuf> {
+    Some(config::account_dir(credentials).ok()?.join(CACHE_DIR))
+}
+
+fn is_list(path: &str) -> bool {
+    path.ends_with("/list")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
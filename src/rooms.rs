@@ -0,0 +1,109 @@
+use crate::api::Entry;
+use crate::config::{ConfigError, config_dir};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const ROOMS_FILE: &str = "rooms.yaml";
+
+#[derive(Debug, Error)]
+pub enum RoomsError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("failed to read rooms {0}: {1}")]
+    ReadFailed(String, #[source] io::Error),
+    #[error("failed to parse rooms {0}: {1}")]
+    ParseFailed(String, #[source] serde_yaml::Error),
+    #[error("room '{0}': {1}")]
+    Invalid(String, String),
+}
+
+/// `rooms.yaml`, since Telldus Live has no notion of where a device is:
+///
+/// ```yaml
+/// rooms:
+///   Bedroom:
+///     - Bedside lamp
+///     - 6942590
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RoomsFile {
+    rooms: BTreeMap<String, Vec<String>>,
+}
+
+/// Every room and the devices and sensors in it, sorted by room name.
+#[derive(Debug, Clone, Default)]
+pub struct Rooms {
+    pub rooms: Vec<Room>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Room {
+    pub name: String,
+    /// Device or sensor ids and names as written in the file.
+    pub members: Vec<String>,
+}
+
+impl Room {
+    /// Whether `entry` is in the room, by id or by name ignoring case.
+    pub fn contains(&self, entry: &Entry) -> bool {
+        self.members
+            .iter()
+            .any(|member| *member == entry.id || member.eq_ignore_ascii_case(&entry.name))
+    }
+}
+
+impl Rooms {
+    /// The room `entry` is in, if any.
+    pub fn room_of(&self, entry: &Entry) -> Option<&Room> {
+        self.rooms.iter().find(|room| room.contains(entry))
+    }
+
+    /// The room called `name`, ignoring case.
+    pub fn find(&self, name: &str) -> Option<&Room> {
+        self.rooms
+            .iter()
+            .find(|room| room.name.eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+pub fn default_path() -> Result<PathBuf, RoomsError> {
+    Ok(config_dir()?.join(ROOMS_FILE))
+}
+
+/// Load the rooms, refusing a device listed in two rooms.
+pub fn load(path: &Path) -> Result<Rooms, RoomsError> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| RoomsError::ReadFailed(path.display().to_string(), err))?;
+    let file: RoomsFile = serde_yaml::from_str(&text)
+        .map_err(|err| RoomsError::ParseFailed(path.display().to_string(), err))?;
+    let mut seen: BTreeMap<String, String> = BTreeMap::new();
+    let mut rooms = Vec::new();
+    for (name, members) in file.rooms {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(RoomsError::Invalid(
+                name,
+                "room names must not be empty".into(),
+            ));
+        }
+        for member in &members {
+            if let Some(other) = seen.insert(member.trim().to_lowercase(), name.clone()) {
+                return Err(RoomsError::Invalid(
+                    name,
+                    format!("'{member}' is already in room '{other}'"),
+                ));
+            }
+        }
+        let members = members
+            .into_iter()
+            .map(|member| member.trim().to_string())
+            .collect();
+        rooms.push(Room { name, members });
+    }
+    Ok(Rooms { rooms })
+}
@@ -0,0 +1,729 @@
+use crate::api::SchedulerJob;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAX_OFFSET_MINUTES: i32 = 23 * 60 + 59;
+
+/// Weekday labels indexed by Telldus weekday number minus one (1 = Monday).
+pub const WEEKDAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleTime {
+    Clock { hour: u8, minute: u8 },
+    Sunrise { offset: i32 },
+    Sunset { offset: i32 },
+}
+
+impl ScheduleTime {
+    /// Parse `22:30`, `10:30pm`, `noon`, `sunset-30m` or `30 minutes before sunset`.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let lowered = input.trim().to_ascii_lowercase();
+        let trimmed = lowered.strip_prefix("at ").unwrap_or(&lowered).trim();
+        for (word, sign) in [(" before ", -1), (" after ", 1)] {
+            if let Some((amount, event)) = trimmed.split_once(word) {
+                let offset = parse_amount(amount)
+                    .filter(|minutes| *minutes <= MAX_OFFSET_MINUTES)
+                    .ok_or_else(|| {
+                        format!("unrecognized offset '{amount}'; use e.g. 30 minutes or 1h30m")
+                    })?;
+                return match event.trim() {
+                    "sunrise" => Ok(ScheduleTime::Sunrise {
+                        offset: sign * offset,
+                    }),
+                    "sunset" => Ok(ScheduleTime::Sunset {
+                        offset: sign * offset,
+                    }),
+                    other => Err(format!(
+                        "times can be {} sunrise or sunset, not '{other}'",
+                        word.trim()
+                    )),
+                };
+            }
+        }
+        match trimmed {
+            "noon" | "midday" => {
+                return Ok(ScheduleTime::Clock {
+                    hour: 12,
+                    minute: 0,
+                });
+            }
+            "midnight" => return Ok(ScheduleTime::Clock { hour: 0, minute: 0 }),
+            _ => {}
+        }
+        if let Some(time) = parse_twelve_hour(trimmed) {
+            return time.ok_or_else(|| format!("invalid 12-hour time '{input}'"));
+        }
+        for (prefix, sunrise) in [("sunrise", true), ("sunset", false)] {
+            if let Some(rest) = trimmed.strip_prefix(prefix) {
+                let offset = parse_offset(rest.trim())?;
+                return Ok(if sunrise {
+                    ScheduleTime::Sunrise { offset }
+                } else {
+                    ScheduleTime::Sunset { offset }
+                });
+            }
+        }
+
+        let (hour, minute) = trimmed.split_once(':').ok_or_else(|| {
+            format!("unrecognized time '{input}'; use HH:MM, 10pm, sunrise or sunset")
+        })?;
+        let hour: u8 = hour
+            .trim()
+            .parse()
+            .ok()
+            .filter(|h| *h < 24)
+            .ok_or_else(|| format!("invalid hour in '{input}'"))?;
+        let minute: u8 = minute
+            .trim()
+            .parse()
+            .ok()
+            .filter(|m| *m < 60)
+            .ok_or_else(|| format!("invalid minute in '{input}'"))?;
+        Ok(ScheduleTime::Clock { hour, minute })
+    }
+
+    /// Scheduler job `type` understood by Telldus Live.
+    pub fn kind(self) -> &'static str {
+        match self {
+            ScheduleTime::Clock { .. } => "time",
+            ScheduleTime::Sunrise { .. } => "sunrise",
+            ScheduleTime::Sunset { .. } => "sunset",
+        }
+    }
+
+    pub fn hour(self) -> u8 {
+        match self {
+            ScheduleTime::Clock { hour, .. } => hour,
+            _ => 0,
+        }
+    }
+
+    pub fn minute(self) -> u8 {
+        match self {
+            ScheduleTime::Clock { minute, .. } => minute,
+            _ => 0,
+        }
+    }
+
+    pub fn offset(self) -> i32 {
+        match self {
+            ScheduleTime::Clock { .. } => 0,
+            ScheduleTime::Sunrise { offset } | ScheduleTime::Sunset { offset } => offset,
+        }
+    }
+
+    /// The time in words, e.g. `at 22:30` or `30m before sunset`, for echoing what was parsed.
+    pub fn describe(self) -> String {
+        let (event, offset) = match self {
+            ScheduleTime::Clock { .. } => return format!("at {self}"),
+            ScheduleTime::Sunrise { offset } => ("sunrise", offset),
+            ScheduleTime::Sunset { offset } => ("sunset", offset),
+        };
+        match offset {
+            0 => format!("at {event}"),
+            _ if offset < 0 => format!("{} before {event}", &format_offset(offset)[1..]),
+            _ => format!("{} after {event}", &format_offset(offset)[1..]),
+        }
+    }
+
+    pub fn from_parts(kind: &str, hour: u8, minute: u8, offset: i32) -> Self {
+        match kind {
+            "sunrise" => ScheduleTime::Sunrise { offset },
+            "sunset" => ScheduleTime::Sunset { offset },
+            _ => ScheduleTime::Clock { hour, minute },
+        }
+    }
+}
+
+impl fmt::Display for ScheduleTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ScheduleTime::Clock { hour, minute } => write!(f, "{hour:02}:{minute:02}"),
+            ScheduleTime::Sunrise { offset } => write!(f, "sunrise{}", format_offset(offset)),
+            ScheduleTime::Sunset { offset } => write!(f, "sunset{}", format_offset(offset)),
+        }
+    }
+}
+
+/// A time ending in am or pm (`10pm`, `7:15 a.m.`), as `Some(None)` when it is out of
+/// range, or `None` when `input` is not a 12-hour time at all.
+fn parse_twelve_hour(input: &str) -> Option<Option<ScheduleTime>> {
+    let compact = input.replace(['.', ' '], "");
+    let (clock, afternoon) = match compact.strip_suffix("am") {
+        Some(clock) => (clock, false),
+        None => (compact.strip_suffix("pm")?, true),
+    };
+    let (hour, minute) = clock.split_once(':').unwrap_or((clock, "0"));
+    let time = hour
+        .parse::<u8>()
+        .ok()
+        .filter(|hour| (1..=12).contains(hour))
+        .zip(minute.parse::<u8>().ok().filter(|minute| *minute < 60))
+        .map(|(hour, minute)| ScheduleTime::Clock {
+            hour: hour % 12 + if afternoon { 12 } else { 0 },
+            minute,
+        });
+    Some(time)
+}
+
+/// Minutes in an amount of time such as `30 minutes`, `an hour`, `1 hour 15 min` or `1h30m`.
+fn parse_amount(input: &str) -> Option<i32> {
+    let input = input
+        .trim()
+        .replace("half an hour", "30 minutes")
+        .replace("an hour", "1 hour");
+    let mut words = input.split_whitespace().peekable();
+    let mut total = 0;
+    let mut any = false;
+    while let Some(word) = words.next() {
+        let minutes = if word.chars().all(|ch| ch.is_ascii_digit()) {
+            let amount: i32 = word.parse().ok()?;
+            let unit = words.next()?;
+            match unit.trim_end_matches('s') {
+                "m" | "min" | "minute" => amount,
+                "h" | "hr" | "hour" => amount.checked_mul(60)?,
+                _ => return None,
+            }
+        } else {
+            parse_offset(&format!("+{word}")).ok()?
+        };
+        total += minutes;
+        any = true;
+        words.next_if_eq(&"and");
+    }
+    any.then_some(total)
+}
+
+fn parse_offset(input: &str) -> Result<i32, String> {
+    if input.is_empty() {
+        return Ok(0);
+    }
+    let (sign, body) = if let Some(rest) = input.strip_prefix('+') {
+        (1, rest)
+    } else if let Some(rest) = input.strip_prefix('-') {
+        (-1, rest)
+    } else {
+        return Err(format!("offset '{input}' must start with + or -"));
+    };
+
+    let mut total = 0i32;
+    let mut digits = String::new();
+    for ch in body.chars() {
+        if ch.is_ascii_digit() {
+            digits.push(ch);
+            continue;
+        }
+        let amount: i32 = digits
+            .parse()
+            .map_err(|_| format!("invalid offset '{input}'"))?;
+        digits.clear();
+        let minutes = match ch {
+            'h' => amount.saturating_mul(60),
+            'm' => amount,
+            _ => {
+                return Err(format!(
+                    "unknown offset unit '{ch}' in '{input}'; use h or m"
+                ));
+            }
+        };
+        total = total.saturating_add(minutes);
+    }
+    if !digits.is_empty() {
+        let minutes = digits
+            .parse::<i32>()
+            .map_err(|_| format!("invalid offset '{input}'"))?;
+        total = total.saturating_add(minutes);
+    }
+
+    if total > MAX_OFFSET_MINUTES {
+        return Err(format!("offset '{input}' exceeds 23h59m"));
+    }
+    Ok(sign * total)
+}
+
+/// The days of the week a scheduler job runs on, as Telldus weekday numbers (1 = Monday).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Weekdays(Vec<u8>);
+
+impl Default for Weekdays {
+    fn default() -> Self {
+        Self((1..=7).collect())
+    }
+}
+
+impl Weekdays {
+    /// Parse `daily`, `weekdays`, `weekends`, or days and ranges such as `mon,wed,fri`,
+    /// `mon-fri` or `tuesday and thursday`.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let lowered = input.trim().to_ascii_lowercase();
+        match lowered.as_str() {
+            "daily" | "every day" | "everyday" | "all" => return Ok(Self::default()),
+            "weekdays" | "workdays" => return Ok(Self((1..=5).collect())),
+            "weekends" | "weekend" => return Ok(Self(vec![6, 7])),
+            _ => {}
+        }
+        let mut days = Vec::new();
+        let list = lowered.replace(" and ", ",").replace(" to ", "-");
+        for item in list.split([',', ' ']).filter(|item| !item.is_empty()) {
+            let (first, last) = match item.split_once('-') {
+                Some((first, last)) => (weekday_number(first)?, weekday_number(last)?),
+                None => (weekday_number(item)?, weekday_number(item)?),
+            };
+            // A range may wrap past Sunday, as in fri-mon.
+            let mut day = first;
+            loop {
+                days.push(day);
+                if day == last {
+                    break;
+                }
+                day = day % 7 + 1;
+            }
+        }
+        if days.is_empty() {
+            return Err("no days given; use e.g. weekdays or mon,wed,fri".into());
+        }
+        days.sort_unstable();
+        days.dedup();
+        Ok(Self(days))
+    }
+
+    pub fn numbers(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The days as `--days` and `days:` accept them, e.g. `daily` or `mon,wed,fri`.
+    pub fn to_argument(&self) -> String {
+        if self.0.len() == 7 {
+            return "daily".into();
+        }
+        let names: Vec<String> = self
+            .0
+            .iter()
+            .map(|&day| WEEKDAY_NAMES[usize::from(day - 1)].to_lowercase())
+            .collect();
+        names.join(",")
+    }
+
+    /// Whether the job runs on `time`'s day of the week.
+    pub fn includes(&self, time: &LocalTime) -> bool {
+        self.0.contains(&time.telldus_weekday())
+    }
+
+    /// The days in words, e.g. `every day`, `on Mon-Fri` or `on Mon, Wed, Fri`, for echoing
+    /// what was parsed. Runs of three or more days are shown as ranges.
+    pub fn describe(&self) -> String {
+        if self.0.len() == 7 {
+            return "every day".into();
+        }
+        let mut runs: Vec<(u8, u8)> = Vec::new();
+        for &day in &self.0 {
+            match runs.last_mut() {
+                Some((_, last)) if *last + 1 == day => *last = day,
+                _ => runs.push((day, day)),
+            }
+        }
+        let name = |day: u8| WEEKDAY_NAMES[usize::from(day - 1)];
+        let parts: Vec<String> = runs
+            .into_iter()
+            .flat_map(|(first, last)| match last - first {
+                0 => vec![name(first).to_string()],
+                1 => vec![name(first).to_string(), name(last).to_string()],
+                _ => vec![format!("{}-{}", name(first), name(last))],
+            })
+            .collect();
+        format!("on {}", parts.join(", "))
+    }
+}
+
+/// A date a job skips: one day such as `2024-12-24`, or `12-24` for every year.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExceptDate {
+    year: Option<i64>,
+    month: u32,
+    day: u32,
+}
+
+impl ExceptDate {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let usage = || format!("invalid date '{input}'; use YYYY-MM-DD, or MM-DD for every year");
+        let parts: Vec<&str> = input.trim().split('-').collect();
+        let (year, month, day) = match parts[..] {
+            [year, month, day] if year.len() == 4 => {
+                (Some(year.parse().map_err(|_| usage())?), month, day)
+            }
+            [month, day] => (None, month, day),
+            _ => return Err(usage()),
+        };
+        let month: u32 = month.parse().map_err(|_| usage())?;
+        let day: u32 = day.parse().map_err(|_| usage())?;
+        // February 29th is allowed for yearly dates; it simply skips the leap years.
+        let days_in_month = match month {
+            2 if year.is_none_or(|year| year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)) => {
+                29
+            }
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            1..=12 => 31,
+            _ => return Err(usage()),
+        };
+        if !(1..=days_in_month).contains(&day) {
+            return Err(usage());
+        }
+        Ok(Self { year, month, day })
+    }
+
+    pub fn matches(&self, time: &LocalTime) -> bool {
+        self.year.is_none_or(|year| year == time.year)
+            && self.month == time.month
+            && self.day == time.day
+    }
+}
+
+impl fmt::Display for ExceptDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(year) = self.year {
+            write!(f, "{year:04}-")?;
+        }
+        write!(f, "{:02}-{:02}", self.month, self.day)
+    }
+}
+
+/// Telldus weekday number of a day name or its start: `mo`, `mon`, `tues` and `Thursday`
+/// all work.
+fn weekday_number(name: &str) -> Result<u8, String> {
+    const FULL_NAMES: [&str; 7] = [
+        "monday",
+        "tuesday",
+        "wednesday",
+        "thursday",
+        "friday",
+        "saturday",
+        "sunday",
+    ];
+    let name = name.trim();
+    FULL_NAMES
+        .iter()
+        .position(|day| name.len() >= 2 && day.starts_with(name))
+        .map(|index| index as u8 + 1)
+        .ok_or_else(|| format!("unknown day '{name}'; use e.g. mon, tuesday or weekends"))
+}
+
+fn format_offset(offset: i32) -> String {
+    if offset == 0 {
+        return String::new();
+    }
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.abs();
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{sign}{m}m"),
+        (h, 0) => format!("{sign}{h}h"),
+        (h, m) => format!("{sign}{h}h{m}m"),
+    }
+}
+
+/// Render a duration as e.g. `1h05m`, `14m30s` or `45s`, rounding down to seconds.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h{minutes:02}m")
+    } else if minutes > 0 {
+        format!("{minutes}m{seconds:02}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// Render an age such as `3 days ago` or `2h05m ago`.
+pub fn describe_age(seconds: u64) -> String {
+    match seconds / 86_400 {
+        0 => format!("{} ago", format_duration(Duration::from_secs(seconds))),
+        1 => "1 day ago".into(),
+        days => format!("{days} days ago"),
+    }
+}
+
+/// Parse durations such as `90`, `45s`, `15m`, `1h30m` or `7d`; bare numbers are seconds.
+pub fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let input = arg.trim();
+    if input.is_empty() {
+        return Err("duration must not be empty".to_string());
+    }
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for ch in input.chars() {
+        if ch.is_ascii_digit() {
+            digits.push(ch);
+            continue;
+        }
+        let amount: u64 = digits
+            .parse()
+            .map_err(|_| format!("invalid duration '{input}'"))?;
+        digits.clear();
+        let unit = match ch {
+            'd' => 86_400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => {
+                return Err(format!(
+                    "unknown duration unit '{ch}' in '{input}'; use d, h, m or s"
+                ));
+            }
+        };
+        total = total.saturating_add(amount.saturating_mul(unit));
+    }
+    if !digits.is_empty() {
+        let seconds: u64 = digits
+            .parse()
+            .map_err(|_| format!("invalid duration '{input}'"))?;
+        total = total.saturating_add(seconds);
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Wall-clock time in the system time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    /// Day of the week as in cron: 0 = Sunday through 6 = Saturday.
+    pub weekday: u32,
+}
+
+impl LocalTime {
+    /// Day of the week as Telldus numbers it: 1 = Monday through 7 = Sunday.
+    pub fn telldus_weekday(&self) -> u8 {
+        ((self.weekday + 6) % 7 + 1) as u8
+    }
+}
+
+impl fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute
+        )
+    }
+}
+
+/// Convert a Unix timestamp to local time using the C library's time zone rules.
+#[cfg(unix)]
+pub fn local_time(timestamp: u64) -> LocalTime {
+    let time = timestamp as libc::time_t;
+    // SAFETY: `tm` is plain old data, and localtime_r only writes into the buffer we own.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return utc_time(timestamp);
+    }
+    LocalTime {
+        year: i64::from(tm.tm_year) + 1900,
+        month: (tm.tm_mon + 1) as u32,
+        day: tm.tm_mday as u32,
+        hour: tm.tm_hour as u32,
+        minute: tm.tm_min as u32,
+        weekday: tm.tm_wday as u32,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn local_time(timestamp: u64) -> LocalTime {
+    utc_time(timestamp)
+}
+
+/// Local calendar date of a Unix timestamp as `YYYY-MM-DD`.
+pub fn local_date(timestamp: i64) -> String {
+    let time = local_time(timestamp.max(0) as u64);
+    format!("{:04}-{:02}-{:02}", time.year, time.month, time.day)
+}
+
+/// Convert a Unix timestamp to UTC.
+pub fn utc_time(timestamp: u64) -> LocalTime {
+    let days = timestamp / 86_400;
+    let seconds = timestamp % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    LocalTime {
+        year,
+        month,
+        day,
+        hour: (seconds / 3600) as u32,
+        minute: (seconds % 3600 / 60) as u32,
+        weekday: u32::from(weekday_of(days) % 7),
+    }
+}
+
+/// Current weekday as a Telldus weekday number (1 = Monday), based on UTC.
+pub fn today() -> u8 {
+    weekday_of(unix_now() / 86_400)
+}
+
+/// Render scheduler jobs as an iCalendar document with weekly recurring events.
+///
+/// Start times are given in UTC, as of each job's next run in the system time zone, so
+/// calendars in any zone show the right instant; after a daylight-saving change they are
+/// an hour off until the next export. Sunrise/sunset jobs cannot be expressed in
+/// iCalendar, so they are anchored at the next run time Telldus reports and will drift
+/// with the seasons.
+pub fn jobs_to_ics(jobs: &[SchedulerJob], device_names: &HashMap<String, String>) -> String {
+    let now = unix_now();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//telltales//scheduler export//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    for job in jobs.iter().filter(|job| job.active) {
+        let weekdays: Vec<u8> = if job.weekdays.is_empty() {
+            (1..=7).collect()
+        } else {
+            job.weekdays.clone()
+        };
+        let start = match job.time {
+            ScheduleTime::Clock { hour, minute } => {
+                match next_local_clock(now, hour, minute, &weekdays) {
+                    Some(start) => start,
+                    None => continue,
+                }
+            }
+            _ => match job.next_run {
+                Some(timestamp) if timestamp > 0 => timestamp as u64,
+                _ => continue,
+            },
+        };
+        // Weekdays are local; a start just before or after local midnight falls on
+        // the neighbouring UTC day.
+        let shift =
+            i16::from(weekday_of(start / 86_400)) - i16::from(local_time(start).telldus_weekday());
+        let byday: Vec<&str> = weekdays
+            .iter()
+            .filter_map(|day| {
+                let utc_day = (i16::from(*day) - 1 + shift).rem_euclid(7);
+                ICS_WEEKDAYS.get(utc_day as usize)
+            })
+            .copied()
+            .collect();
+        let device = device_names
+            .get(&job.device_id)
+            .cloned()
+            .unwrap_or_else(|| format!("Device {}", job.device_id));
+
+        lines.push("BEGIN:VEVENT".into());
+        lines.push(format!("UID:telltales-job-{}@telltales", job.id));
+        lines.push(format!("DTSTAMP:{}", format_timestamp(now)));
+        lines.push(format!("DTSTART:{}", format_timestamp(start)));
+        lines.push("DURATION:PT1M".into());
+        lines.push(format!("RRULE:FREQ=WEEKLY;BYDAY={}", byday.join(",")));
+        lines.push(format!(
+            "SUMMARY:{}",
+            escape_text(&format!("{device} {}", job.describe_action()))
+        ));
+        lines.push(format!(
+            "DESCRIPTION:{}",
+            escape_text(&format!(
+                "Telldus scheduler job {} fires at {}.",
+                job.id, job.time
+            ))
+        ));
+        lines.push("END:VEVENT".into());
+    }
+
+    lines.push("END:VCALENDAR".into());
+    let mut output = lines.join("\r\n");
+    output.push_str("\r\n");
+    output
+}
+
+/// The Unix timestamp of `hour:minute` local time on the first of the coming seven days,
+/// starting today, whose Telldus weekday is in `weekdays`.
+fn next_local_clock(now: u64, hour: u8, minute: u8, weekdays: &[u8]) -> Option<u64> {
+    let wanted = i64::from(hour) * 3600 + i64::from(minute) * 60;
+    (0..7).map(|ahead| now + ahead * 86_400).find_map(|day| {
+        let local = local_time(day);
+        if !weekdays.contains(&local.telldus_weekday()) {
+            return None;
+        }
+        let since_midnight = i64::from(local.hour * 3600 + local.minute * 60) + (day % 60) as i64;
+        let mut start = day as i64 - since_midnight + wanted;
+        // Correct for a daylight-saving change between midnight and the wanted time.
+        let at = local_time(start.max(0) as u64);
+        let drift = wanted - i64::from(at.hour * 3600 + at.minute * 60);
+        start += (drift + 43_200).rem_euclid(86_400) - 43_200;
+        u64::try_from(start).ok()
+    })
+}
+
+const ICS_WEEKDAYS: [&str; 7] = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Format a Unix timestamp as an RFC 5322 date in UTC, e.g. `Thu, 15 Oct 2026 15:29:52 +0000`.
+pub fn rfc5322_date(timestamp: u64) -> String {
+    const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let days = timestamp / 86_400;
+    let seconds = timestamp % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {day} {} {year:04} {:02}:{:02}:{:02} +0000",
+        DAYS[usize::from(weekday_of(days) - 1)],
+        MONTHS[month as usize - 1],
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn weekday_of(days_since_epoch: u64) -> u8 {
+    // 1970-01-01 was a Thursday.
+    ((days_since_epoch + 3) % 7 + 1) as u8
+}
+
+/// Convert days since the Unix epoch into a proleptic Gregorian date.
+fn civil_from_days(days_since_epoch: u64) -> (i64, u32, u32) {
+    let z = days_since_epoch as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn format_date(days_since_epoch: u64) -> String {
+    let (year, month, day) = civil_from_days(days_since_epoch);
+    format!("{year:04}{month:02}{day:02}")
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    let seconds = timestamp % 86_400;
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        format_date(timestamp / 86_400),
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
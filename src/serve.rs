@@ -0,0 +1,256 @@
+use crate::api::{ApiError, Category, TelldusApi, method_name};
+use crate::cache;
+use crate::schedule::{local_time, unix_now};
+use crate::service;
+use percent_encoding::percent_decode_str;
+use serde_json::{Value, json};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Clients are served one at a time, so a stalled one must not hold the server for long.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BODY: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ServeError {
+    #[error("cannot listen on {0}: {1}")]
+    Bind(SocketAddr, #[source] io::Error),
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    authorization: Option<String>,
+}
+
+struct Response {
+    status: u16,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.into() }),
+        }
+    }
+}
+
+/// Answer REST requests on `address` with the shared API session until shutdown.
+///
+/// Requests are handled sequentially, so every call goes through the one rate limiter.
+pub fn run(api: &TelldusApi, address: SocketAddr, token: &str) -> Result<(), ServeError> {
+    let listener = TcpListener::bind(address).map_err(|err| ServeError::Bind(address, err))?;
+    // Non-blocking accepts let the loop notice SIGTERM and Ctrl-C promptly.
+    listener
+        .set_nonblocking(true)
+        .map_err(|err| ServeError::Bind(address, err))?;
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(err) = serve_client(api, stream, token) {
+                    eprintln!("warning: client connection failed: {err}");
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if !service::sleep(Duration::from_millis(50)) {
+                    return Ok(());
+                }
+            }
+            Err(err) => eprintln!("warning: cannot accept connection: {err}"),
+        }
+    }
+}
+
+fn serve_client(api: &TelldusApi, stream: TcpStream, token: &str) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let response = match read_request(&mut reader)? {
+        Some(request) => {
+            let response = if authorized(&request, token) {
+                route(api, &request)
+            } else {
+                Response::error(401, "missing or invalid bearer token")
+            };
+            println!(
+                "[{}] {} {} {}",
+                local_time(unix_now()),
+                request.method,
+                request.path,
+                response.status
+            );
+            response
+        }
+        None => Response::error(400, "malformed request"),
+    };
+    write_response(reader.get_mut(), &response)
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<HttpRequest>> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut authorization = None;
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().unwrap_or(0);
+            }
+        }
+    }
+    // Parameters travel in the query string; any body is read only to keep the stream in sync.
+    if content_length > MAX_BODY {
+        return Ok(None);
+    }
+    reader.read_exact(&mut vec![0; content_length])?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    Ok(Some(HttpRequest {
+        method,
+        path: path.to_string(),
+        query: serde_urlencoded::from_str(query).unwrap_or_default(),
+        authorization,
+    }))
+}
+
+fn authorized(request: &HttpRequest, token: &str) -> bool {
+    let Some(given) = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // Compare in constant time so response timing does not leak the token.
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn route(api: &TelldusApi, request: &HttpRequest) -> Response {
+    let segments: Vec<String> = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let result = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["devices"]) => list_devices(api),
+        ("GET", ["devices", device]) => device_state(api, device),
+        ("POST", ["devices", device, command]) => device_command(api, device, command, request),
+        (_, ["devices"] | ["devices", _] | ["devices", _, _]) => {
+            return Response::error(405, "method not allowed");
+        }
+        _ => return Response::error(404, "not found"),
+    };
+    result.unwrap_or_else(|err| Response::error(502, err.to_string()))
+}
+
+fn list_devices(api: &TelldusApi) -> Result<Response, ApiError> {
+    let devices: Vec<Value> = api
+        .list_devices()?
+        .into_iter()
+        .map(|entry| {
+            json!({
+                "id": entry.id,
+                "name": entry.name,
+                "type": entry.category.as_str(),
+                "details": entry.details,
+            })
+        })
+        .collect();
+    Ok(Response::ok(Value::Array(devices)))
+}
+
+fn device_state(api: &TelldusApi, device: &str) -> Result<Response, ApiError> {
+    let Some(id) = cache::resolve(api, Category::Device, device)? else {
+        return Ok(Response::error(404, format!("no device named '{device}'")));
+    };
+    let state = api.device_state(&id)?;
+    Ok(Response::ok(json!({
+        "id": id,
+        "state": method_name(state.method),
+        "method": state.method,
+        "level": state.level,
+    })))
+}
+
+fn device_command(
+    api: &TelldusApi,
+    device: &str,
+    command: &str,
+    request: &HttpRequest,
+) -> Result<Response, ApiError> {
+    let Some(id) = cache::resolve(api, Category::Device, device)? else {
+        return Ok(Response::error(404, format!("no device named '{device}'")));
+    };
+    match command {
+        "on" => api.device_turn_on(&id)?,
+        "off" => api.device_turn_off(&id)?,
+        "bell" => api.device_bell(&id)?,
+        "up" => api.device_up(&id)?,
+        "down" => api.device_down(&id)?,
+        "stop" => api.device_stop(&id)?,
+        "dim" => {
+            let level = request
+                .query
+                .iter()
+                .find(|(key, _)| key == "level")
+                .and_then(|(_, value)| value.parse::<u8>().ok());
+            let Some(level) = level else {
+                return Ok(Response::error(400, "dim needs ?level=0-255"));
+            };
+            api.device_dim(&id, level)?
+        }
+        _ => return Ok(Response::error(404, format!("unknown command '{command}'"))),
+    }
+    Ok(Response::ok(
+        json!({ "id": id, "command": command, "status": "success" }),
+    ))
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> io::Result<()> {
+    let body = response.body.to_string();
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Bad Gateway",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        response.status,
+        body.len()
+    )?;
+    stream.flush()
+}
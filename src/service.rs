@@ -0,0 +1,141 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often long sleeps wake up to check for a shutdown request.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(250);
+
+/// Exit status of a run cut short by Ctrl-C, as a shell reports a process killed by SIGINT.
+pub const INTERRUPTED_EXIT: u8 = 130;
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Turn SIGTERM and SIGINT into a shutdown request instead of killing the process, so
+/// long operations can stop between requests. A second signal exits at once.
+#[cfg(unix)]
+pub fn handle_signals() {
+    extern "C" fn request_shutdown(_signal: libc::c_int) {
+        if SHUTDOWN.swap(true, Ordering::SeqCst) {
+            // SAFETY: _exit is async-signal-safe.
+            unsafe { libc::_exit(INTERRUPTED_EXIT.into()) };
+        }
+    }
+    let handler = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only touches an atomic and calls _exit, both async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn handle_signals() {}
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// Sleep for `duration`, returning early (with `false`) once shutdown is requested.
+pub fn sleep(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while !shutdown_requested() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        thread::sleep(remaining.min(SHUTDOWN_POLL));
+    }
+    false
+}
+
+/// Report a state such as `READY=1` to systemd when running as a `Type=notify` service.
+///
+/// Outside systemd `NOTIFY_SOCKET` is unset and this does nothing.
+pub fn sd_notify(state: &str) {
+    #[cfg(unix)]
+    if let Some(socket) = env::var_os("NOTIFY_SOCKET")
+        && let Err(err) = send_notify(Path::new(&socket), state)
+    {
+        eprintln!("warning: cannot notify systemd: {err}");
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+#[cfg(unix)]
+fn send_notify(socket: &Path, state: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let sender = UnixDatagram::unbound()?;
+    match socket.to_str().and_then(|path| path.strip_prefix('@')) {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            let address = SocketAddr::from_abstract_name(name)?;
+            sender.send_to_addr(state.as_bytes(), &address)?;
+        }
+        _ => {
+            sender.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+/// A PID file that is removed again when dropped.
+pub struct PidFile(PathBuf);
+
+impl PidFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        fs::write(path, format!("{}\n", process::id()))?;
+        Ok(Self(path.to_path_buf()))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// A systemd user unit that runs the schedule daemon from `schedules`.
+pub fn systemd_unit(executable: &Path, schedules: &Path) -> String {
+    format!(
+        "[Unit]
+Description=telltales local schedules
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=notify
+ExecStart={} daemon run --file {} --pid-file %t/telltales-daemon.pid
+Restart=on-failure
+RestartSec=30
+TimeoutStopSec=10
+NoNewPrivileges=true
+PrivateTmp=true
+ProtectSystem=full
+
+[Install]
+WantedBy=default.target
+",
+        quote(executable),
+        quote(schedules)
+    )
+}
+
+/// Quote a path for `ExecStart=` when it contains spaces or quotes.
+fn quote(path: &Path) -> String {
+    let text = path.display().to_string();
+    if text.contains([' ', '"', '\\']) {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        text
+    }
+}
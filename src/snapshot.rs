@@ -0,0 +1,235 @@
+use crate::config::{ConfigError, config_dir};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+const SNAPSHOT_SUBDIR: &str = "snapshots";
+
+/// Sections captured in a snapshot, keyed by resource id within each section.
+pub const SECTIONS: [&str; 3] = ["clients", "devices", "sensors"];
+
+/// Fields that change on their own or with every switch (readings, heartbeats, device
+/// states) and would drown out configuration changes in a diff.
+const VOLATILE_FIELDS: [&str; 6] = [
+    "lastUpdated",
+    "lastSeen",
+    "lastseen",
+    "data",
+    "state",
+    "statevalue",
+];
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("snapshot '{0}' does not exist")]
+    NotFound(String),
+    #[error("invalid snapshot name '{0}'; use letters, digits, '-' or '_'")]
+    InvalidName(String),
+    #[error("failed to read snapshot {0}: {1}")]
+    ReadFailed(String, #[source] io::Error),
+    #[error("failed to parse snapshot {0}: {1}")]
+    ParseFailed(String, #[source] serde_json::Error),
+    #[error("failed to serialize snapshot {0}: {1}")]
+    SerializeFailed(String, #[source] serde_json::Error),
+    #[error("failed to write snapshot {0}: {1}")]
+    WriteFailed(String, #[source] io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added {
+        section: String,
+        id: String,
+        name: String,
+    },
+    Removed {
+        section: String,
+        id: String,
+        name: String,
+    },
+    Modified {
+        section: String,
+        id: String,
+        name: String,
+        field: String,
+        before: String,
+        after: String,
+    },
+}
+
+/// Build a snapshot document from raw list payloads, indexing items by id.
+pub fn build(taken_at: u64, sections: Vec<(&str, Vec<Value>)>) -> Value {
+    let mut root = Map::new();
+    root.insert("taken_at".into(), Value::from(taken_at));
+    for (section, items) in sections {
+        let mut indexed = Map::new();
+        for item in items {
+            let id = item
+                .get("id")
+                .map(|id| match id {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                })
+                .unwrap_or_else(|| "?".into());
+            indexed.insert(id, item);
+        }
+        root.insert(section.into(), Value::Object(indexed));
+    }
+    Value::Object(root)
+}
+
+pub fn save(name: &str, snapshot: &Value) -> Result<PathBuf, SnapshotError> {
+    let path = snapshot_path(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|err| SnapshotError::WriteFailed(dir.display().to_string(), err))?;
+    }
+    let text = serde_json::to_string_pretty(snapshot)
+        .map_err(|err| SnapshotError::SerializeFailed(name.into(), err))?;
+    fs::write(&path, text)
+        .map_err(|err| SnapshotError::WriteFailed(path.display().to_string(), err))?;
+    Ok(path)
+}
+
+pub fn load(name: &str) -> Result<Value, SnapshotError> {
+    let path = snapshot_path(name)?;
+    if !path.exists() {
+        return Err(SnapshotError::NotFound(name.into()));
+    }
+    let text = fs::read_to_string(&path)
+        .map_err(|err| SnapshotError::ReadFailed(path.display().to_string(), err))?;
+    serde_json::from_str(&text)
+        .map_err(|err| SnapshotError::ParseFailed(path.display().to_string(), err))
+}
+
+/// List saved snapshot names in alphabetical order.
+pub fn list() -> Result<Vec<String>, SnapshotError> {
+    let dir = config_dir()?.join(SNAPSHOT_SUBDIR);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            } else {
+                None
+            }
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+pub fn diff(before: &Value, after: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let empty = Map::new();
+    for section in SECTIONS {
+        let old = before
+            .get(section)
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        let new = after
+            .get(section)
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+
+        for (id, item) in old {
+            if !new.contains_key(id) {
+                changes.push(Change::Removed {
+                    section: section.into(),
+                    id: id.clone(),
+                    name: display_name(item),
+                });
+            }
+        }
+        for (id, item) in new {
+            let Some(previous) = old.get(id) else {
+                changes.push(Change::Added {
+                    section: section.into(),
+                    id: id.clone(),
+                    name: display_name(item),
+                });
+                continue;
+            };
+            let mut old_fields = BTreeMap::new();
+            let mut new_fields = BTreeMap::new();
+            flatten("", previous, &mut old_fields);
+            flatten("", item, &mut new_fields);
+            let keys: std::collections::BTreeSet<&String> =
+                old_fields.keys().chain(new_fields.keys()).collect();
+            for key in keys {
+                let before = old_fields.get(key).cloned().unwrap_or_else(|| "-".into());
+                let after = new_fields.get(key).cloned().unwrap_or_else(|| "-".into());
+                if before != after {
+                    changes.push(Change::Modified {
+                        section: section.into(),
+                        id: id.clone(),
+                        name: display_name(item),
+                        field: key.clone(),
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+    }
+    changes
+}
+
+fn flatten(prefix: &str, value: &Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                if prefix.is_empty() && VOLATILE_FIELDS.contains(&key.as_str()) {
+                    continue;
+                }
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&path, child, out);
+            }
+        }
+        Value::Array(items) => {
+            for (index, child) in items.iter().enumerate() {
+                flatten(&format!("{prefix}[{index}]"), child, out);
+            }
+        }
+        Value::String(text) => {
+            out.insert(prefix.into(), text.clone());
+        }
+        other => {
+            out.insert(prefix.into(), other.to_string());
+        }
+    }
+}
+
+fn display_name(item: &Value) -> String {
+    item.get("name")
+        .and_then(Value::as_str)
+        .unwrap_or("(unnamed)")
+        .to_string()
+}
+
+fn snapshot_path(name: &str) -> Result<PathBuf, SnapshotError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_');
+    if !valid {
+        return Err(SnapshotError::InvalidName(name.into()));
+    }
+    Ok(config_dir()?
+        .join(SNAPSHOT_SUBDIR)
+        .join(format!("{name}.json")))
+}
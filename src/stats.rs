@@ -0,0 +1,133 @@
+use crate::api::SchedulerJob;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Telldus method bit for dimming, as in `supportedMethods`.
+const METHOD_DIM: u64 = 16;
+
+/// Account-wide counts for `telltales stats`, computed from the raw list payloads so one
+/// pass over each list answers every question.
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub controllers: ControllerStats,
+    pub devices: DeviceStats,
+    pub sensors: SensorStats,
+    /// When sensors last reported a reading.
+    pub last_seen: LastSeen,
+    pub schedules: ScheduleStats,
+    pub events: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ControllerStats {
+    pub total: usize,
+    pub online: usize,
+    pub offline: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DeviceStats {
+    pub total: usize,
+    pub groups: usize,
+    pub dimmable: usize,
+    pub battery: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SensorStats {
+    pub total: usize,
+    pub ignored: usize,
+    pub battery: usize,
+}
+
+/// How many sensors were last heard from within each span of time.
+#[derive(Debug, Default, Serialize)]
+pub struct LastSeen {
+    pub hour: usize,
+    pub day: usize,
+    pub week: usize,
+    pub older: usize,
+    pub never: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ScheduleStats {
+    pub total: usize,
+    pub active: usize,
+}
+
+impl LastSeen {
+    fn add(&mut self, timestamp: Option<u64>, now: u64) {
+        let Some(timestamp) = timestamp.filter(|timestamp| *timestamp > 0) else {
+            self.never += 1;
+            return;
+        };
+        match now.saturating_sub(timestamp) {
+            age if age < 3_600 => self.hour += 1,
+            age if age < 86_400 => self.day += 1,
+            age if age < 7 * 86_400 => self.week += 1,
+            _ => self.older += 1,
+        }
+    }
+}
+
+/// Count `clients`, `devices` (listed with `supportedMethods` and `extras=battery`) and
+/// `sensors` (listed with `includeIgnored`) as of `now`.
+pub fn collect(
+    clients: &[Value],
+    devices: &[Value],
+    sensors: &[Value],
+    jobs: &[SchedulerJob],
+    events: usize,
+    now: u64,
+) -> Stats {
+    let mut stats = Stats {
+        events,
+        ..Stats::default()
+    };
+    for client in clients {
+        stats.controllers.total += 1;
+        match text(client, "online").as_deref() {
+            Some("1" | "true") => stats.controllers.online += 1,
+            Some("0" | "false") => stats.controllers.offline += 1,
+            _ => {}
+        }
+    }
+    for device in devices {
+        stats.devices.total += 1;
+        if text(device, "type").is_some_and(|kind| kind.eq_ignore_ascii_case("group")) {
+            stats.devices.groups += 1;
+        }
+        if number(device, "methods").is_some_and(|methods| methods & METHOD_DIM != 0) {
+            stats.devices.dimmable += 1;
+        }
+        if text(device, "battery").is_some() {
+            stats.devices.battery += 1;
+        }
+    }
+    for sensor in sensors {
+        stats.sensors.total += 1;
+        if text(sensor, "ignored").is_some_and(|flag| matches!(flag.as_str(), "1" | "true")) {
+            stats.sensors.ignored += 1;
+        }
+        if text(sensor, "battery").is_some() {
+            stats.sensors.battery += 1;
+        }
+        stats.last_seen.add(number(sensor, "lastUpdated"), now);
+    }
+    stats.schedules.total = jobs.len();
+    stats.schedules.active = jobs.iter().filter(|job| job.active).count();
+    stats
+}
+
+fn text(value: &Value, key: &str) -> Option<String> {
+    match value.get(key)? {
+        Value::String(text) => Some(text.trim().to_lowercase()).filter(|text| !text.is_empty()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+fn number(value: &Value, key: &str) -> Option<u64> {
+    text(value, key)?.parse().ok()
+}
@@ -0,0 +1,522 @@
+use std::cell::Cell;
+
+/// Most steps one `apply` may take before the pattern is given up as too costly, so a
+/// pattern such as `(a*)*b` cannot backtrack for hours.
+const MAX_STEPS: usize = 1_000_000;
+
+/// A parsed sed-style `s/pattern/replacement/flags` expression.
+///
+/// The pattern is a small backtracking subset of POSIX extended regular expressions:
+/// literals, `.`, bracket classes, `\d` `\w` `\s` (and their negations), `^` `$`, groups
+/// with `|`, and the `*` `+` `?` `{n,m}` quantifiers, lazy when followed by `?`. The
+/// replacement may refer to the whole match as `&` or `\0` and to groups as `\1` to `\9`.
+/// Flags are `g` to replace every match and `i` to ignore case.
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    pattern: Node,
+    groups: usize,
+    replacement: Vec<Piece>,
+    global: bool,
+    ignore_case: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    Group(Box<Node>, Option<usize>),
+    Alternation(Vec<Node>),
+    Sequence(Vec<Node>),
+    Repeat(Repeat),
+}
+
+/// `inner` matched `min` to `max` times, as many as possible unless `lazy`.
+#[derive(Debug, Clone)]
+struct Repeat {
+    inner: Box<Node>,
+    min: usize,
+    max: Option<usize>,
+    lazy: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Text(String),
+    Group(usize),
+}
+
+type Captures = Vec<Option<(usize, usize)>>;
+
+impl Substitution {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let mut chars = expression.chars();
+        if chars.next() != Some('s') {
+            return Err("expected s/pattern/replacement/".into());
+        }
+        let delimiter = chars
+            .next()
+            .filter(|ch| !ch.is_alphanumeric() && *ch != '\\')
+            .ok_or("expected a delimiter such as / after s")?;
+        let parts = split_unescaped(chars.as_str(), delimiter);
+        let [pattern, replacement, flags] = parts.as_slice() else {
+            return Err(format!(
+                "expected s{delimiter}pattern{delimiter}replacement{delimiter}"
+            ));
+        };
+        let (mut global, mut ignore_case) = (false, false);
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' => ignore_case = true,
+                other => return Err(format!("unknown flag '{other}'; use g or i")),
+            }
+        }
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err("unmatched ')'".into());
+        }
+        let groups = parser.groups;
+        let replacement = parse_replacement(replacement, groups)?;
+        Ok(Self {
+            pattern: node,
+            groups,
+            replacement,
+            global,
+            ignore_case,
+        })
+    }
+
+    /// The text with the substitution applied, or `None` when the pattern does not match.
+    ///
+    /// Fails when matching would take more than [`MAX_STEPS`] steps of backtracking.
+    pub fn apply(&self, text: &str) -> Result<Option<String>, String> {
+        let chars: Vec<char> = text.chars().collect();
+        let matcher = Matcher {
+            text: &chars,
+            ignore_case: self.ignore_case,
+            steps: Cell::new(0),
+        };
+        let mut result = String::new();
+        let (mut copied, mut start, mut replaced) = (0, 0, false);
+        while start <= chars.len() {
+            let captures = matcher.find_at(&self.pattern, self.groups, start);
+            if matcher.steps.get() > MAX_STEPS {
+                return Err(format!(
+                    "the pattern needs too much backtracking to match '{text}'"
+                ));
+            }
+            let Some(captures) = captures else {
+                break;
+            };
+            let (match_start, match_end) = captures[0].unwrap_or((start, start));
+            result.extend(&chars[copied..match_start]);
+            for piece in &self.replacement {
+                match piece {
+                    Piece::Text(text) => result.push_str(text),
+                    Piece::Group(index) => {
+                        if let Some((from, to)) = captures[*index] {
+                            result.extend(&chars[from..to]);
+                        }
+                    }
+                }
+            }
+            copied = match_end;
+            replaced = true;
+            if !self.global {
+                break;
+            }
+            // An empty match would be found again at the same place.
+            start = if match_end == match_start {
+                match_end + 1
+            } else {
+                match_end
+            };
+        }
+        if !replaced {
+            return Ok(None);
+        }
+        result.extend(&chars[copied.min(chars.len())..]);
+        Ok(Some(result))
+    }
+}
+
+/// Split on `delimiter` where it is not escaped, turning `\<delimiter>` into the delimiter.
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        let part = parts.last_mut().expect("parts is never empty");
+        match ch {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => part.push('\\'),
+            },
+            _ if ch == delimiter => parts.push(String::new()),
+            _ => part.push(ch),
+        }
+    }
+    parts
+}
+
+fn parse_replacement(text: &str, groups: usize) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut chars = text.chars();
+    let push_group = |literal: &mut String, pieces: &mut Vec<Piece>, index: usize| {
+        if !literal.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(literal)));
+        }
+        pieces.push(Piece::Group(index));
+    };
+    while let Some(ch) = chars.next() {
+        match ch {
+            '&' => push_group(&mut literal, &mut pieces, 0),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    let index = digit as usize - '0' as usize;
+                    if index > groups {
+                        return Err(format!("\\{index} refers to a missing group"));
+                    }
+                    push_group(&mut literal, &mut pieces, index);
+                }
+                Some(other) => literal.push(other),
+                None => literal.push('\\'),
+            },
+            _ => literal.push(ch),
+        }
+    }
+    if !literal.is_empty() {
+        pieces.push(Piece::Text(literal));
+    }
+    Ok(pieces)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += 1;
+        Some(ch)
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut options = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            options.push(self.sequence()?);
+        }
+        Ok(if options.len() == 1 {
+            options.remove(0)
+        } else {
+            Node::Alternation(options)
+        })
+    }
+
+    fn sequence(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(ch) = self.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(Node::Sequence(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let ch = self.next().ok_or("pattern ends unexpectedly")?;
+        Ok(match ch {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '*' | '+' | '?' => return Err(format!("'{ch}' has nothing to repeat")),
+            '(' => {
+                let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.alternation()?;
+                if self.next() != Some(')') {
+                    return Err("unmatched '('".into());
+                }
+                Node::Group(Box::new(inner), index)
+            }
+            '[' => self.class()?,
+            '\\' => {
+                let escaped = self.next().ok_or("pattern ends with '\\'")?;
+                match shorthand_class(escaped) {
+                    Some((ranges, negated)) => Node::Class(ranges, negated),
+                    None => Node::Char(escaped),
+                }
+            }
+            _ => Node::Char(ch),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let ch = self.next().ok_or("unmatched '['")?;
+            if ch == ']' && !first {
+                break;
+            }
+            first = false;
+            let low = if ch == '\\' {
+                let escaped = self.next().ok_or("unmatched '['")?;
+                if let Some((shorthand, false)) = shorthand_class(escaped) {
+                    ranges.extend(shorthand);
+                    continue;
+                }
+                escaped
+            } else {
+                ch
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|ch| *ch != ']')
+            {
+                self.pos += 1;
+                let high = self.next().ok_or("unmatched '['")?;
+                if high < low {
+                    return Err(format!("range {low}-{high} is out of order"));
+                }
+                ranges.push((low, high));
+            } else {
+                ranges.push((low, low));
+            }
+        }
+        Ok(Node::Class(ranges, negated))
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.bounds() {
+                Some(bounds) => bounds,
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        // Step past the quantifier, or the closing brace of its bounds.
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End) {
+            return Err("anchors cannot be repeated".into());
+        }
+        let lazy = self.peek() == Some('?');
+        if lazy {
+            self.pos += 1;
+        }
+        Ok(Node::Repeat(Repeat {
+            inner: Box::new(atom),
+            min,
+            max,
+            lazy,
+        }))
+    }
+
+    /// Parse `{n}`, `{n,}` or `{n,m}`, leaving the position on the closing brace; a brace
+    /// that does not start valid bounds is a literal.
+    fn bounds(&mut self) -> Option<(usize, Option<usize>)> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let close = rest.find('}')?;
+        let inside = &rest[..close];
+        let (min, max) = match inside.split_once(',') {
+            None => {
+                let count = inside.parse().ok()?;
+                (count, Some(count))
+            }
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+        };
+        if max.is_some_and(|max| max < min) {
+            return None;
+        }
+        self.pos += inside.chars().count() + 1;
+        Some((min, max))
+    }
+}
+
+fn shorthand_class(ch: char) -> Option<(Vec<(char, char)>, bool)> {
+    let digits = vec![('0', '9')];
+    // Latin letters beyond ASCII too, so `\w` spans names like "Köksfönster".
+    let word = vec![
+        ('a', 'z'),
+        ('A', 'Z'),
+        ('0', '9'),
+        ('_', '_'),
+        ('\u{c0}', '\u{d6}'),
+        ('\u{d8}', '\u{f6}'),
+        ('\u{f8}', '\u{24f}'),
+    ];
+    let space = vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')];
+    Some(match ch {
+        'd' => (digits, false),
+        'D' => (digits, true),
+        'w' => (word, false),
+        'W' => (word, true),
+        's' => (space, false),
+        'S' => (space, true),
+        _ => return None,
+    })
+}
+
+struct Matcher<'t> {
+    text: &'t [char],
+    ignore_case: bool,
+    /// Nodes tried so far; matching fails once this passes [`MAX_STEPS`].
+    steps: Cell<usize>,
+}
+
+impl Matcher<'_> {
+    /// Leftmost match starting at or after `start`, with group 0 spanning the whole match.
+    fn find_at(&self, pattern: &Node, groups: usize, start: usize) -> Option<Captures> {
+        (start..=self.text.len()).find_map(|from| {
+            let mut captures: Captures = vec![None; groups + 1];
+            let mut end = None;
+            self.matches(pattern, from, &mut captures, &mut |to, _| {
+                end = Some(to);
+                true
+            })
+            .then(|| {
+                captures[0] = end.map(|to| (from, to));
+                captures
+            })
+        })
+    }
+
+    /// Match `node` at `pos`, calling `next` with the end position of each way it can
+    /// match until `next` accepts one.
+    fn matches(
+        &self,
+        node: &Node,
+        pos: usize,
+        captures: &mut Captures,
+        next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        self.steps.set(self.steps.get() + 1);
+        if self.steps.get() > MAX_STEPS {
+            return false;
+        }
+        match node {
+            Node::Char(expected) => {
+                self.text
+                    .get(pos)
+                    .is_some_and(|ch| self.same(*ch, *expected))
+                    && next(pos + 1, captures)
+            }
+            Node::Any => pos < self.text.len() && next(pos + 1, captures),
+            Node::Class(ranges, negated) => {
+                self.text
+                    .get(pos)
+                    .is_some_and(|ch| self.in_class(*ch, ranges) != *negated)
+                    && next(pos + 1, captures)
+            }
+            Node::Start => pos == 0 && next(pos, captures),
+            Node::End => pos == self.text.len() && next(pos, captures),
+            Node::Group(inner, None) => self.matches(inner, pos, captures, next),
+            Node::Group(inner, Some(index)) => {
+                let index = *index;
+                self.matches(inner, pos, captures, &mut |end, captures| {
+                    let previous = captures[index].replace((pos, end));
+                    next(end, captures) || {
+                        captures[index] = previous;
+                        false
+                    }
+                })
+            }
+            Node::Alternation(options) => options
+                .iter()
+                .any(|option| self.matches(option, pos, captures, next)),
+            Node::Sequence(nodes) => self.sequence(nodes, pos, captures, next),
+            Node::Repeat(repeat) => self.repeat(repeat, 0, pos, captures, next),
+        }
+    }
+
+    fn sequence(
+        &self,
+        nodes: &[Node],
+        pos: usize,
+        captures: &mut Captures,
+        next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        match nodes.split_first() {
+            None => next(pos, captures),
+            Some((first, rest)) => self.matches(first, pos, captures, &mut |end, captures| {
+                self.sequence(rest, end, captures, next)
+            }),
+        }
+    }
+
+    /// Repetition after `count` matches of the inner node: greedy takes as many as
+    /// possible, backing off one at a time, while lazy takes as few as possible.
+    fn repeat(
+        &self,
+        repeat: &Repeat,
+        count: usize,
+        pos: usize,
+        captures: &mut Captures,
+        next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        let done = count >= repeat.min;
+        if repeat.lazy && done && next(pos, captures) {
+            return true;
+        }
+        if repeat.max.is_none_or(|max| count < max)
+            && self.matches(&repeat.inner, pos, captures, &mut |end, captures| {
+                // Stop an empty match from repeating forever.
+                end != pos && self.repeat(repeat, count + 1, end, captures, next)
+            })
+        {
+            return true;
+        }
+        !repeat.lazy && done && next(pos, captures)
+    }
+
+    fn same(&self, a: char, b: char) -> bool {
+        a == b || (self.ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    fn in_class(&self, ch: char, ranges: &[(char, char)]) -> bool {
+        let within = |ch: char| {
+            ranges
+                .iter()
+                .any(|(low, high)| (*low..=*high).contains(&ch))
+        };
+        within(ch)
+            || (self.ignore_case
+                && (ch.to_lowercase().any(within) || ch.to_uppercase().any(within)))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,154 @@
+use super::*;
+use std::time::{Duration, Instant};
+
+fn substitute(expression: &str, text: &str) -> Option<String> {
+    Substitution::parse(expression)
+        .unwrap_or_else(|err| panic!("{expression}: {err}"))
+        .apply(text)
+        .unwrap_or_else(|err| panic!("{expression}: {err}"))
+}
+
+#[test]
+fn literals_replace_the_first_match_unless_global() {
+    assert_eq!(
+        substitute("s/Lamp/Light/", "Lamp Lamp").as_deref(),
+        Some("Light Lamp")
+    );
+    assert_eq!(
+        substitute("s/Lamp/Light/g", "Lamp Lamp").as_deref(),
+        Some("Light Light")
+    );
+    assert_eq!(substitute("s/Lamp/Light/", "Porch"), None);
+}
+
+#[test]
+fn ignore_case_covers_literals_and_classes() {
+    assert_eq!(
+        substitute("s/lamp/Light/i", "LAMP 1").as_deref(),
+        Some("Light 1")
+    );
+    assert_eq!(substitute("s/[a-c]+/x/i", "ABCd").as_deref(), Some("xd"));
+    assert_eq!(substitute("s/lamp/Light/", "LAMP"), None);
+}
+
+#[test]
+fn alternation_takes_the_first_option_that_lets_the_rest_match() {
+    assert_eq!(
+        substitute("s/Kitchen|Hall/Room/g", "Kitchen and Hall").as_deref(),
+        Some("Room and Room")
+    );
+    // "ab" would match first, but only "abc" lets the trailing "d" follow.
+    assert_eq!(substitute("s/(ab|abc)d/X/", "abcd").as_deref(), Some("X"));
+}
+
+#[test]
+fn groups_are_numbered_by_their_opening_parenthesis() {
+    assert_eq!(
+        substitute(r"s/((\w+) (\w+))/\3 \2 [\1]/", "Bedroom lamp").as_deref(),
+        Some("lamp Bedroom [Bedroom lamp]")
+    );
+    assert_eq!(
+        substitute(r"s/(?:Lamp) (\d+)/Light \1/", "Lamp 12").as_deref(),
+        Some("Light 12")
+    );
+    assert_eq!(
+        substitute("s/Lamp/<&>/", "Lamp 1").as_deref(),
+        Some("<Lamp> 1")
+    );
+}
+
+#[test]
+fn a_group_that_did_not_take_part_is_replaced_by_nothing() {
+    assert_eq!(
+        substitute(r"s/(a)|(b)/[\1\2]/g", "ab").as_deref(),
+        Some("[a][b]")
+    );
+}
+
+#[test]
+fn anchors_only_match_at_the_ends() {
+    assert_eq!(
+        substitute("s/^Lamp/Light/g", "Lamp Lamp").as_deref(),
+        Some("Light Lamp")
+    );
+    assert_eq!(
+        substitute("s/Lamp$/Light/g", "Lamp Lamp").as_deref(),
+        Some("Lamp Light")
+    );
+    assert_eq!(substitute("s/^Lamp$/Light/", "Lamp 1"), None);
+    assert_eq!(substitute("s/^/Old /", "Lamp").as_deref(), Some("Old Lamp"));
+}
+
+#[test]
+fn greedy_quantifiers_take_the_longest_match() {
+    assert_eq!(substitute("s/<.*>/X/", "<a> and <b>").as_deref(), Some("X"));
+    assert_eq!(substitute(r"s/\d{2,3}/N/", "12345").as_deref(), Some("N45"));
+    // Greedy repetition still backs off so the rest of the pattern can match.
+    assert_eq!(
+        substitute(r"s/(.*)(\d)/\1-\2/", "Lamp 12").as_deref(),
+        Some("Lamp 1-2")
+    );
+}
+
+#[test]
+fn lazy_quantifiers_take_the_shortest_match() {
+    assert_eq!(
+        substitute("s/<.*?>/X/g", "<a> and <b>").as_deref(),
+        Some("X and X")
+    );
+    assert_eq!(
+        substitute(r"s/\d{2,3}?/N/", "12345").as_deref(),
+        Some("N345")
+    );
+    assert_eq!(
+        substitute(r"s/(.+?)(\d+)/\1-\2/", "Lamp 12").as_deref(),
+        Some("Lamp -12")
+    );
+}
+
+#[test]
+fn empty_matches_advance_through_the_text() {
+    assert_eq!(substitute("s/x*/-/g", "ab").as_deref(), Some("-a-b-"));
+}
+
+#[test]
+fn nested_repetition_backtracks_into_a_match() {
+    assert_eq!(substitute("s/(a+)+b/X/", "aaaab").as_deref(), Some("X"));
+}
+
+#[test]
+fn pathological_backtracking_gives_up_quickly() {
+    let substitution = Substitution::parse("s/(a*)*b/X/").unwrap();
+    let text = "a".repeat(40);
+    let started = Instant::now();
+
+    let result = substitution.apply(&text);
+
+    assert!(result.unwrap_err().contains("too much backtracking"));
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn malformed_expressions_are_rejected() {
+    for (expression, message) in [
+        ("Lamp/Light/", "expected s/"),
+        ("s/Lamp/Light", "expected s/pattern/replacement/"),
+        ("s/Lamp/Light/x", "unknown flag 'x'"),
+        ("s/(Lamp/Light/", "unmatched '('"),
+        ("s/Lamp)/Light/", "unmatched ')'"),
+        ("s/[Lamp/Light/", "unmatched '['"),
+        ("s/*Lamp/Light/", "nothing to repeat"),
+        ("s/^*/Light/", "anchors cannot be repeated"),
+        ("s/[z-a]/Light/", "out of order"),
+        (r"s/(Lamp)/\2/", "missing group"),
+    ] {
+        let err = Substitution::parse(expression).unwrap_err();
+        assert!(err.contains(message), "{expression}: {err}");
+    }
+}
+
+#[test]
+fn other_delimiters_and_escaped_delimiters_are_accepted() {
+    assert_eq!(substitute("s|a/b|c|", "a/b").as_deref(), Some("c"));
+    assert_eq!(substitute(r"s/a\/b/c/", "a/b").as_deref(), Some("c"));
+}
@@ -0,0 +1,103 @@
+use crate::schedule::{ScheduleTime, local_time};
+use serde::Deserialize;
+
+const UNIX_EPOCH_JULIAN: f64 = 2_440_587.5;
+const J2000: f64 = 2_451_545.0;
+
+/// A place on Earth in degrees, north and east positive.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    pub fn validate(self) -> Result<Self, String> {
+        if !(-90.0..=90.0).contains(&self.latitude) {
+            return Err(format!(
+                "latitude {} is not between -90 and 90",
+                self.latitude
+            ));
+        }
+        if !(-180.0..=180.0).contains(&self.longitude) {
+            return Err(format!(
+                "longitude {} is not between -180 and 180",
+                self.longitude
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// Sunrise and sunset as Unix timestamps, `None` when the sun stays above or below the
+/// horizon all day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SunTimes {
+    pub sunrise: Option<u64>,
+    pub sunset: Option<u64>,
+}
+
+/// Sunrise and sunset at `location` on the local day containing `timestamp`.
+///
+/// This is the sunrise equation NOAA publishes, good to a minute or two away from the
+/// polar circles.
+pub fn sun_times(location: Location, timestamp: u64) -> SunTimes {
+    let local = local_time(timestamp);
+    let since_midnight = u64::from(local.hour * 3600 + local.minute * 60) + timestamp % 60;
+    let noon = timestamp.saturating_sub(since_midnight) + 43_200;
+    let julian = noon as f64 / 86_400.0 + UNIX_EPOCH_JULIAN;
+    // Days since J2000 of the solar noon nearest local noon.
+    let day = (julian - J2000 + location.longitude / 360.0).round();
+    let mean_noon = day - location.longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * mean_noon)
+        .rem_euclid(360.0)
+        .to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic = (anomaly.to_degrees() + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + mean_noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic).sin();
+    let declination = (ecliptic.sin() * 23.4397_f64.to_radians().sin()).asin();
+    let latitude = location.latitude.to_radians();
+    // -0.833° allows for refraction and the size of the sun's disc.
+    let cos_hour_angle = ((-0.833_f64).to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return SunTimes {
+            sunrise: None,
+            sunset: None,
+        };
+    }
+    let half_day = cos_hour_angle.acos().to_degrees() / 360.0;
+    let unix = |julian: f64| ((julian - UNIX_EPOCH_JULIAN) * 86_400.0).round().max(0.0) as u64;
+    SunTimes {
+        sunrise: Some(unix(transit - half_day)),
+        sunset: Some(unix(transit + half_day)),
+    }
+}
+
+/// Local minutes past midnight at which `time` falls on the day containing `timestamp`.
+///
+/// Sunrise and sunset need a `location`, and have no time on days the sun does not rise
+/// or set.
+pub fn minute_of_day(
+    time: ScheduleTime,
+    location: Option<Location>,
+    timestamp: u64,
+) -> Option<u32> {
+    let (event, offset) = match time {
+        ScheduleTime::Clock { hour, minute } => {
+            return Some(u32::from(hour) * 60 + u32::from(minute));
+        }
+        ScheduleTime::Sunrise { offset } => (sun_times(location?, timestamp).sunrise?, offset),
+        ScheduleTime::Sunset { offset } => (sun_times(location?, timestamp).sunset?, offset),
+    };
+    let at = local_time(event.saturating_add_signed(i64::from(offset) * 60));
+    Some(at.hour * 60 + at.minute)
+}
+
+/// Whether `time` needs a location to be computed locally.
+pub fn needs_location(time: ScheduleTime) -> bool {
+    !matches!(time, ScheduleTime::Clock { .. })
+}
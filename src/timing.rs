@@ -0,0 +1,69 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RECORDS: Mutex<Vec<RequestTiming>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone)]
+pub struct RequestTiming {
+    pub method: &'static str,
+    pub path: String,
+    /// Time spent sleeping in the rate limiter before the request was sent.
+    pub waited: Duration,
+    /// Time from sending the request until the response body was read.
+    pub elapsed: Duration,
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn record(method: &'static str, path: &str, waited: Duration, elapsed: Duration) {
+    if !is_enabled() {
+        return;
+    }
+    if let Ok(mut records) = RECORDS.lock() {
+        records.push(RequestTiming {
+            method,
+            path: path.to_string(),
+            waited,
+            elapsed,
+        });
+    }
+}
+
+/// Print collected timings to standard error, keeping command output clean.
+pub fn print_summary() {
+    if !is_enabled() {
+        return;
+    }
+    let records = match RECORDS.lock() {
+        Ok(records) => records.clone(),
+        Err(_) => return,
+    };
+
+    eprintln!();
+    eprintln!("{:>10} {:>10}  REQUEST", "LATENCY", "WAITED");
+    for record in &records {
+        eprintln!(
+            "{:>7} ms {:>7} ms  {} {}",
+            record.elapsed.as_millis(),
+            record.waited.as_millis(),
+            record.method,
+            record.path
+        );
+    }
+    let latency: Duration = records.iter().map(|record| record.elapsed).sum();
+    let waited: Duration = records.iter().map(|record| record.waited).sum();
+    eprintln!(
+        "{} request(s): {} ms waiting on Telldus Live, {} ms in the rate limiter.",
+        records.len(),
+        latency.as_millis(),
+        waited.as_millis()
+    );
+}
@@ -0,0 +1,144 @@
+use crate::api::ApiError;
+use crate::config::TelldusCredentials;
+use reqwest_oauth1::Secrets;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+impl Method {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+        }
+    }
+}
+
+/// An unsigned request; GET parameters go in the query string, POST parameters in a form body.
+pub struct Request<'a> {
+    pub method: Method,
+    pub url: String,
+    pub params: &'a [(&'a str, &'a str)],
+    pub credentials: &'a TelldusCredentials,
+    /// Overrides the client's own timeout for this request.
+    pub timeout: Option<Duration>,
+    /// Validators of a cached response, sent as `If-None-Match` and `If-Modified-Since`
+    /// by [`Transport::execute_conditional`].
+    pub etag: Option<&'a str>,
+    pub last_modified: Option<&'a str>,
+}
+
+impl<'a> Request<'a> {
+    fn secrets(&self) -> Secrets<'a> {
+        Secrets::new(&self.credentials.public_key, &self.credentials.private_key)
+            .token(&self.credentials.token, &self.credentials.token_secret)
+    }
+}
+
+/// The answer to a conditional request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conditional {
+    /// 304: the cached response is still current.
+    NotModified,
+    Modified {
+        body: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+/// Sends a request with OAuth 1.0a signing and returns the body of a successful response;
+/// building requests and parsing responses is left to `TelldusApi`.
+///
+/// A 401 response is reported as [`ApiError::Unauthorized`] so callers can re-authorize.
+pub trait Transport {
+    fn execute(&self, request: &Request<'_>) -> Result<String, ApiError>;
+
+    /// Like [`Transport::execute`], but also sends the request's validators and reports
+    /// the response's own. Transports without conditional requests answer every request
+    /// as modified, without validators.
+    fn execute_conditional(&self, request: &Request<'_>) -> Result<Conditional, ApiError> {
+        Ok(Conditional::Modified {
+            body: self.execute(request)?,
+            etag: None,
+            last_modified: None,
+        })
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for &T {
+    fn execute(&self, request: &Request<'_>) -> Result<String, ApiError> {
+        (**self).execute(request)
+    }
+
+    fn execute_conditional(&self, request: &Request<'_>) -> Result<Conditional, ApiError> {
+        (**self).execute_conditional(request)
+    }
+}
+
+impl Transport for reqwest::blocking::Client {
+    fn execute(&self, request: &Request<'_>) -> Result<String, ApiError> {
+        Ok(send_blocking(self, request, false)?.text()?)
+    }
+
+    fn execute_conditional(&self, request: &Request<'_>) -> Result<Conditional, ApiError> {
+        let response = send_blocking(self, request, true)?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(Conditional::NotModified);
+        }
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let (etag, last_modified) = (
+            header(reqwest::header::ETAG),
+            header(reqwest::header::LAST_MODIFIED),
+        );
+        Ok(Conditional::Modified {
+            body: response.text()?,
+            etag,
+            last_modified,
+        })
+    }
+}
+
+/// Sign and send `request`, with its validators when `conditional` is set, failing on
+/// any unsuccessful status other than 304.
+fn send_blocking(
+    client: &reqwest::blocking::Client,
+    request: &Request<'_>,
+    conditional: bool,
+) -> Result<reqwest::blocking::Response, ApiError> {
+    use reqwest::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+    use reqwest_oauth1::OAuthClientProvider;
+
+    let signed = client.clone().oauth1(request.secrets());
+    let mut builder = match request.method {
+        Method::Get if request.params.is_empty() => signed.get(&request.url),
+        Method::Get => signed.get(&request.url).query(&request.params),
+        Method::Post => signed.post(&request.url).form(&request.params),
+    };
+    if let Some(timeout) = request.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if conditional {
+        if let Some(etag) = request.etag {
+            builder = builder.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = request.last_modified {
+            builder = builder.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = builder.send()?;
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        return Err(ApiError::Unauthorized);
+    }
+    Ok(response.error_for_status()?)
+}
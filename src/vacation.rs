@@ -0,0 +1,229 @@
+use crate::schedule::{ScheduleTime, local_date, local_time};
+use crate::sun::{self, Location};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use std::fmt;
+
+const MINUTES_PER_DAY: u32 = 1440;
+
+/// The `vacation:` section of schedules.yaml.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VacationConfig {
+    lights: Vec<LightConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightConfig {
+    /// Device id or name.
+    device: String,
+    /// Window to turn the light on in, such as `sunset..sunset+45m`.
+    on: String,
+    /// Window to turn the light off in, such as `22:30..23:45`.
+    off: String,
+}
+
+/// The lights vacation mode switches, and the windows it picks random times from so an
+/// empty house looks lived in.
+///
+/// Times are drawn afresh every local day, so the pattern does not repeat.
+#[derive(Debug, Clone)]
+pub struct Vacation {
+    pub lights: Vec<Light>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Light {
+    pub device: String,
+    pub on: Window,
+    pub off: Window,
+}
+
+/// A span of the day a switch is picked from; it may wrap past midnight.
+#[derive(Debug, Clone, Copy)]
+pub struct Window {
+    start: ScheduleTime,
+    end: ScheduleTime,
+}
+
+/// A planned switch, in minutes past the midnight starting the day it was planned for;
+/// switches after the next midnight are at 1440 or more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Switch {
+    pub minute: u32,
+    pub device: String,
+    pub on: bool,
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+impl Window {
+    fn parse(text: &str) -> Result<Self, String> {
+        let (start, end) = text
+            .split_once("..")
+            .ok_or_else(|| format!("window '{text}' must look like '22:30..23:45'"))?;
+        Ok(Self {
+            start: ScheduleTime::parse(start)?,
+            end: ScheduleTime::parse(end)?,
+        })
+    }
+
+    /// A random minute within the window on the day containing `timestamp`, or `None` when
+    /// the sun does not rise or set that day.
+    fn pick(self, location: Option<Location>, timestamp: u64) -> Option<u32> {
+        let start = sun::minute_of_day(self.start, location, timestamp)?;
+        let end = sun::minute_of_day(self.end, location, timestamp)?;
+        let length = (end + MINUTES_PER_DAY - start) % MINUTES_PER_DAY;
+        Some(start + random_below(length + 1))
+    }
+
+    fn needs_location(self) -> bool {
+        sun::needs_location(self.start) || sun::needs_location(self.end)
+    }
+}
+
+impl Vacation {
+    pub fn from_config(config: VacationConfig) -> Result<Self, String> {
+        if config.lights.is_empty() {
+            return Err("no lights configured".into());
+        }
+        let lights = config
+            .lights
+            .into_iter()
+            .map(|light| {
+                let window = |text: &str| {
+                    Window::parse(text).map_err(|err| format!("{}: {err}", light.device))
+                };
+                Ok(Light {
+                    on: window(&light.on)?,
+                    off: window(&light.off)?,
+                    device: light.device,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { lights })
+    }
+
+    /// Whether some window follows sunrise or sunset and so needs a location.
+    pub fn needs_location(&self) -> bool {
+        self.lights
+            .iter()
+            .any(|light| light.on.needs_location() || light.off.needs_location())
+    }
+
+    /// Random switches for the local day containing `timestamp`, in time order. A light
+    /// goes off after it went on, on the next day when its off window comes first.
+    fn plan(&self, location: Option<Location>, timestamp: u64) -> Vec<Switch> {
+        let mut switches = Vec::new();
+        for light in &self.lights {
+            let (Some(on), Some(mut off)) = (
+                light.on.pick(location, timestamp),
+                light.off.pick(location, timestamp),
+            ) else {
+                continue;
+            };
+            if off <= on {
+                off += MINUTES_PER_DAY;
+            }
+            for (minute, on) in [(on, true), (off, false)] {
+                switches.push(Switch {
+                    minute,
+                    device: light.device.clone(),
+                    on,
+                });
+            }
+        }
+        switches.sort_by_key(|switch| switch.minute);
+        switches
+    }
+}
+
+/// The switches still to come, planned each local day as it starts.
+#[derive(Debug, Default)]
+pub struct Plan {
+    date: String,
+    switches: Vec<Switch>,
+}
+
+impl Plan {
+    /// Plan the day containing `timestamp` unless it already is, keeping switches left over
+    /// from the day before. Returns the new day's switches, for logging.
+    pub fn update(
+        &mut self,
+        vacation: &Vacation,
+        location: Option<Location>,
+        timestamp: u64,
+    ) -> Option<&[Switch]> {
+        let date = local_date(timestamp as i64);
+        if date == self.date {
+            return None;
+        }
+        let first_day = self.date.is_empty();
+        self.date = date;
+        let mut switches: Vec<Switch> = self
+            .switches
+            .drain(..)
+            .filter(|switch| !first_day && switch.minute >= MINUTES_PER_DAY)
+            .map(|switch| Switch {
+                minute: switch.minute - MINUTES_PER_DAY,
+                ..switch
+            })
+            .collect();
+        let now = minute_of(timestamp);
+        // Starting mid-day, switches that would already have happened are left out.
+        let planned = vacation.plan(location, timestamp);
+        let start = switches.len();
+        switches.extend(planned.into_iter().filter(|switch| switch.minute >= now));
+        self.switches = switches;
+        Some(&self.switches[start..])
+    }
+
+    /// Remove and return the switches due by `timestamp`.
+    pub fn due(&mut self, timestamp: u64) -> Vec<Switch> {
+        let now = minute_of(timestamp);
+        let (due, later) = self
+            .switches
+            .drain(..)
+            .partition(|switch| switch.minute <= now);
+        self.switches = later;
+        due
+    }
+}
+
+impl fmt::Display for Switch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minute = self.minute % MINUTES_PER_DAY;
+        write!(
+            f,
+            "{} {} at {:02}:{:02}",
+            self.device,
+            if self.on { "on" } else { "off" },
+            minute / 60,
+            minute % 60
+        )?;
+        if self.minute >= MINUTES_PER_DAY {
+            f.write_str(" tomorrow")?;
+        }
+        Ok(())
+    }
+}
+
+fn minute_of(timestamp: u64) -> u32 {
+    let time = local_time(timestamp);
+    time.hour * 60 + time.minute
+}
+
+/// A random number below `count`, from the system generator as protocol house codes are.
+fn random_below(count: u32) -> u32 {
+    let mut bytes = [0u8; 4];
+    // The system generator only fails when the OS has no entropy source at all.
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random number generator");
+    u32::from_le_bytes(bytes) % count
+}
@@ -0,0 +1,59 @@
+use serde_json::{Value, json};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A scratch home directory with a `--replay` fixture for `devices list`.
+fn scratch_home(name: &str) -> PathBuf {
+    let home = std::env::temp_dir().join(format!("telltales-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&home);
+    let fixtures = home.join("fixtures");
+    fs::create_dir_all(&fixtures).expect("create fixture directory");
+    let devices = json!({
+        "device": [
+            {"id": "1", "name": "Kitchen", "state": "1", "methods": "3", "type": "device"},
+            {"id": "2", "name": "Porch", "state": "2", "methods": "3", "type": "device"}
+        ]
+    });
+    fs::write(
+        fixtures.join("get_json_devices_list.json"),
+        devices.to_string(),
+    )
+    .expect("write fixture");
+    home
+}
+
+/// Nothing but JSON objects may reach stdout, so the stream can be piped into jq.
+#[test]
+fn ndjson_stdout_holds_only_json_objects() {
+    let home = scratch_home("ndjson");
+    let output = Command::new(env!("CARGO_BIN_EXE_telltales"))
+        .env("HOME", &home)
+        .env_remove("TELLTALES_CONFIG")
+        .env_remove("TELLTALES_CACHE_TTL")
+        .env_remove("TELLTALES_FAVORITES")
+        .arg("--replay")
+        .arg(home.join("fixtures"))
+        .args(["devices", "list", "--kind", "devices", "--output", "ndjson"])
+        .output()
+        .expect("run telltales");
+    let _ = fs::remove_dir_all(&home);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).expect("stdout is UTF-8");
+    let records: Vec<Value> = stdout
+        .lines()
+        .map(|line| {
+            serde_json::from_str(line).unwrap_or_else(|err| panic!("not JSON: {line:?}: {err}"))
+        })
+        .collect();
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(Value::is_object));
+    assert_eq!(records[0]["name"], "Kitchen");
+    // The credentials banner still reaches the user, on stderr.
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Using credentials file"));
+}